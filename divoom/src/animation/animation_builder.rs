@@ -1,6 +1,15 @@
-use crate::animation::animation_frame_builder::DivoomAnimationFrameBuilder;
+use crate::animation::animation_color_processing::DivoomColorProcessingOptions;
+use crate::animation::animation_frame_builder::{DivoomAnimationFrameBuilder, DivoomScaleFilter};
+use crate::animation::animation_keyframe::DivoomAnimationKeyframeLayer;
+use crate::animation::animation_layer::DivoomAnimationLayer;
+use crate::animation::animation_marquee::*;
+use crate::animation::animation_pixel_font::DivoomPixelFont;
+use crate::animation::animation_pixel_text::*;
+use crate::animation::animation_resource_loader::DivoomAnimationFrameSequence;
 use crate::animation::DivoomDrawFitMode;
 use crate::dto::*;
+use rgb::RGB8;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::iter::once;
 use std::time::Duration;
@@ -8,10 +17,17 @@ use tiny_skia::{BlendMode, Pixmap};
 
 /// ## Animation builder
 /// This class holds a series of tiny_skia::Pixmap inside with the same size to provide an interface for building animations.
+///
+/// Besides drawing on the frames directly, named layers can also be added to the builder. The frames of the builder work as
+/// the base layer, and all visible layers are drawn on top of it when building the animation.
 pub struct DivoomAnimationBuilder {
     canvas_size: u32,
     speed: Duration,
     frames: Vec<Pixmap>,
+    layers: BTreeMap<String, DivoomAnimationLayer>,
+    scale_filter: DivoomScaleFilter,
+    color_processing: Option<DivoomColorProcessingOptions>,
+    max_frame_count: Option<usize>,
 }
 
 // Ctor and basic functions
@@ -23,6 +39,19 @@ impl DivoomAnimationBuilder {
         DivoomAnimationBuilder::with_frames(canvas_size, speed, vec![])
     }
 
+    /// Create a new animation builder targeting the native resolution of the device model, which also limits the
+    /// number of frames to what the device supports.
+    pub fn for_device_model(
+        device_model: DivoomDeviceModel,
+        speed: Duration,
+    ) -> DivoomAPIResult<DivoomAnimationBuilder> {
+        let profile = device_model.profile();
+        Ok(
+            DivoomAnimationBuilder::new(profile.native_resolution, speed)?
+                .with_max_frame_count(profile.max_animation_frame_count),
+        )
+    }
+
     pub(crate) fn with_frames(
         canvas_size: u32,
         speed: Duration,
@@ -39,14 +68,42 @@ impl DivoomAnimationBuilder {
             canvas_size,
             speed,
             frames,
+            layers: BTreeMap::new(),
+            scale_filter: DivoomScaleFilter::Bicubic,
+            color_processing: None,
+            max_frame_count: None,
         })
     }
 
+    /// Set the filter for scaling the images when drawing them onto the frames. By default, bicubic filter is used.
+    pub fn with_scale_filter(mut self, scale_filter: DivoomScaleFilter) -> Self {
+        self.scale_filter = scale_filter;
+        self
+    }
+
+    /// Set the post-processing options for the colors, which will be applied to all frames when building the animation.
+    pub fn with_color_processing(mut self, options: DivoomColorProcessingOptions) -> Self {
+        self.color_processing = Some(options);
+        self
+    }
+
+    /// Limit the number of frames when drawing frame sequences with variable delays or scrolling text. Please see
+    /// `draw_frame_sequence_fit` and `draw_text_scroll` for more details.
+    pub fn with_max_frame_count(mut self, max_frame_count: usize) -> Self {
+        self.max_frame_count = Some(max_frame_count);
+        self
+    }
+
     /// Return canvas size
     pub fn canvas_size(&self) -> u32 {
         self.canvas_size
     }
 
+    /// Return the speed of the animation, which is the delay of every frame.
+    pub fn speed(&self) -> Duration {
+        self.speed
+    }
+
     /// Return a builder for the specified frame.
     ///
     /// If the frame is not created or any previous frame is not created for the specified index, we will create all of them and return the builder
@@ -58,11 +115,102 @@ impl DivoomAnimationBuilder {
         }
 
         DivoomAnimationFrameBuilder::new(&mut self.frames[index])
+            .with_scale_filter(self.scale_filter)
     }
 
     pub fn new_frame(&mut self) -> DivoomAnimationFrameBuilder {
         self.build_frame(self.frames.len())
     }
+
+    /// Return the number of frames in the final animation, which covers both the frames and all visible layers.
+    pub fn frame_count(&self) -> usize {
+        self.layers
+            .values()
+            .map(|x| x.frame_count())
+            .fold(self.frames.len(), std::cmp::max)
+    }
+}
+
+// Layer functions
+impl DivoomAnimationBuilder {
+    /// Add a layer with the name, or replace the existing layer with the same name.
+    ///
+    /// The content of the layer must have the same canvas size as this builder.
+    pub fn set_layer(&mut self, name: &str, layer: DivoomAnimationLayer) -> DivoomAPIResult<()> {
+        if layer.content().canvas_size() != self.canvas_size {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid layer canvas size: {}. Layer must have the same canvas size as the animation: {}.",
+                layer.content().canvas_size(),
+                self.canvas_size
+            )));
+        }
+
+        self.layers.insert(name.to_string(), layer);
+        Ok(())
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&DivoomAnimationLayer> {
+        self.layers.get(name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut DivoomAnimationLayer> {
+        self.layers.get_mut(name)
+    }
+
+    pub fn remove_layer(&mut self, name: &str) -> Option<DivoomAnimationLayer> {
+        self.layers.remove(name)
+    }
+
+    /// Return the names of all layers, ordered from bottom to top.
+    pub fn layer_names(&self) -> Vec<&str> {
+        self.sorted_layers()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn sorted_layers(&self) -> Vec<(&str, &DivoomAnimationLayer)> {
+        let mut layers: Vec<(&str, &DivoomAnimationLayer)> = self
+            .layers
+            .iter()
+            .map(|(name, layer)| (name.as_str(), layer))
+            .collect();
+
+        // Sort is stable, so layers with the same z-order are kept in the order of their names.
+        layers.sort_by_key(|(_, layer)| layer.z_order());
+        layers
+    }
+
+    /// Flatten the frames and all visible layers into the final frames.
+    pub(crate) fn compose_frames(&self) -> Cow<'_, [Pixmap]> {
+        if self.layers.is_empty() {
+            return Cow::Borrowed(&self.frames);
+        }
+
+        let layers: Vec<(&DivoomAnimationLayer, Cow<[Pixmap]>)> = self
+            .sorted_layers()
+            .into_iter()
+            .filter(|(_, layer)| layer.visible())
+            .map(|(_, layer)| (layer, layer.content().compose_frames()))
+            .collect();
+
+        let frame_count = self.frame_count();
+        let mut frames = Vec::with_capacity(frame_count);
+        for frame_index in 0..frame_count {
+            let mut frame = match self.frames.get(frame_index) {
+                Some(v) => v.clone(),
+                None => Pixmap::new(self.canvas_size, self.canvas_size).unwrap(),
+            };
+
+            for (layer, layer_frames) in &layers {
+                layer.draw(&mut frame, layer_frames, frame_index);
+            }
+
+            frames.push(frame);
+        }
+
+        Cow::Owned(frames)
+    }
 }
 
 // Draw functions
@@ -146,19 +294,210 @@ impl DivoomAnimationBuilder {
     }
 }
 
+// Frame sequence functions
+impl DivoomAnimationBuilder {
+    /// Draw a series of frames with variable delays, such as GIF, APNG or WebP, to the canvas with fit options and other
+    /// options, starting from the specified frame.
+    ///
+    /// The device plays all frames with the same speed, so the frames are resampled onto the speed of the animation: long
+    /// frames are duplicated and short frames are merged, so the animation is played with the original timing. Frames
+    /// without delay are treated as one frame of the animation.
+    ///
+    /// If the max frame count is set and the resampled frames cannot fit in, the frames exceeding the max frame count are
+    /// dropped. The speed of the animation is never changed.
+    pub fn draw_frame_sequence_fit(
+        self,
+        sequence: &DivoomAnimationFrameSequence,
+        start_frame_index: usize,
+        fit: DivoomDrawFitMode,
+        rotation: f32,
+        opacity: f32,
+        blend: BlendMode,
+    ) -> Self {
+        let frames = self.resample_frame_sequence(sequence, start_frame_index);
+        self.draw_frames_fit(&frames, start_frame_index, fit, rotation, opacity, blend)
+    }
+
+    fn resample_frame_sequence(
+        &self,
+        sequence: &DivoomAnimationFrameSequence,
+        start_frame_index: usize,
+    ) -> Vec<Pixmap> {
+        let delays: Vec<Duration> = sequence
+            .delays
+            .iter()
+            .map(|x| if x.is_zero() { self.speed } else { *x })
+            .collect();
+        let total_duration: Duration = delays.iter().sum();
+        if sequence.frames.is_empty() || total_duration.is_zero() {
+            return sequence.frames.clone();
+        }
+
+        let frame_count =
+            ((total_duration.as_secs_f64() / self.speed.as_secs_f64()).round() as usize).max(1);
+        let output_frame_count = match self.max_frame_count {
+            Some(max_frame_count) => {
+                frame_count.min(max_frame_count.saturating_sub(start_frame_index).max(1))
+            }
+            None => frame_count,
+        };
+
+        // Pick the source frame that is shown at the middle of each output frame.
+        let total_duration = total_duration.as_nanos();
+        let mut frames = Vec::with_capacity(output_frame_count);
+        let (mut source_index, mut source_end_time) = (0, delays[0].as_nanos());
+        for index in 0..output_frame_count {
+            let time = (2 * index as u128 + 1) * total_duration / (2 * frame_count as u128);
+            while time >= source_end_time && source_index + 1 < delays.len() {
+                source_index += 1;
+                source_end_time += delays[source_index].as_nanos();
+            }
+
+            frames.push(sequence.frames[source_index].clone());
+        }
+
+        frames
+    }
+}
+
+// Keyframe functions
+impl DivoomAnimationBuilder {
+    /// Render the keyframe layers into the specified number of frames, starting from the specified frame.
+    ///
+    /// The frame indexes in the keyframes are relative to the start frame, and the layers are drawn in order, so the later
+    /// layers are drawn on top of the earlier ones.
+    pub fn draw_keyframe_layers(
+        mut self,
+        layers: &[DivoomAnimationKeyframeLayer],
+        frame_count: usize,
+        start_frame_index: usize,
+    ) -> Self {
+        for frame_offset in 0..frame_count {
+            let mut frame_builder = self.build_frame(start_frame_index + frame_offset);
+            for layer in layers {
+                layer.draw(frame_builder.canvas_mut(), frame_offset);
+            }
+        }
+
+        self
+    }
+}
+
+// Text functions
+impl DivoomAnimationBuilder {
+    /// Scroll the text through the canvas, one pixel per frame, starting from the specified frame.
+    ///
+    /// The text enters from one side of the canvas and scrolls until it fully leaves the other side, so the number of frames
+    /// is the canvas size plus the text size on the scrolling direction. If the max frame count is set, the frames exceeding
+    /// it are dropped.
+    pub fn draw_text_scroll(
+        mut self,
+        text: &str,
+        font: &DivoomPixelFont,
+        color: RGB8,
+        direction: DivoomPixelTextScrollDirection,
+        start_frame_index: usize,
+    ) -> Self {
+        let canvas_size = self.canvas_size;
+        let (lines, text_size) = match direction {
+            DivoomPixelTextScrollDirection::Horizontal => {
+                let line = text.lines().collect::<Vec<&str>>().join(" ");
+                let text_width = font.measure_text(&line);
+                (vec![line], text_width)
+            }
+            DivoomPixelTextScrollDirection::Vertical => {
+                let lines = font.wrap_text(text, Some(canvas_size));
+                let text_height = lines.len() as u32 * font.line_height();
+                (lines, text_height)
+            }
+        };
+
+        let mut frame_count = (canvas_size + text_size) as usize;
+        if let Some(max_frame_count) = self.max_frame_count {
+            frame_count = frame_count.min(max_frame_count.saturating_sub(start_frame_index).max(1));
+        }
+
+        for offset in 0..frame_count {
+            let position = canvas_size as i32 - offset as i32;
+            let text_box = match direction {
+                DivoomPixelTextScrollDirection::Horizontal => DivoomPixelTextBox {
+                    x: position,
+                    y: 0,
+                    width: text_size,
+                    height: canvas_size,
+                },
+                DivoomPixelTextScrollDirection::Vertical => DivoomPixelTextBox {
+                    x: 0,
+                    y: position,
+                    width: canvas_size,
+                    height: text_size,
+                },
+            };
+
+            let mut frame_builder = self.build_frame(start_frame_index + offset);
+            draw_pixel_text_lines(
+                frame_builder.canvas_mut(),
+                &lines,
+                font,
+                text_box,
+                color,
+                DivoomPixelTextAlign::Left,
+                DivoomPixelTextVerticalAlign::Middle,
+            );
+        }
+
+        self
+    }
+
+    /// Draw a seamless looping marquee, starting from the specified frame. Please see `DivoomMarqueeConfig` for more details.
+    pub fn draw_marquee(mut self, config: &DivoomMarqueeConfig, start_frame_index: usize) -> Self {
+        let renderer = DivoomMarqueeRenderer::new(
+            config,
+            self.canvas_size,
+            config.max_frame_count.saturating_sub(start_frame_index),
+        );
+        for frame_offset in 0..renderer.frame_count() {
+            let mut frame_builder = self.build_frame(start_frame_index + frame_offset);
+            renderer.draw_frame(frame_offset, frame_builder.canvas_mut());
+        }
+
+        self
+    }
+
+    /// Draw a seamless looping marquee of the text with default settings, starting from the first frame.
+    pub fn draw_marquee_text(
+        self,
+        text: &str,
+        font: &DivoomPixelFont,
+        color: RGB8,
+        direction: DivoomMarqueeDirection,
+    ) -> Self {
+        let config = DivoomMarqueeConfig::new(text, font, color, direction);
+        self.draw_marquee(&config, 0)
+    }
+}
+
 // Export function
 impl DivoomAnimationBuilder {
     /// Create the final animation that is used for being sent to the device, and one animation builder can be reused to create multiple animations.
     pub fn build(&self) -> DivoomImageAnimation {
+        let frames = self.compose_frames();
         let mut animation = DivoomImageAnimation {
             size: self.canvas_size,
-            frame_count: self.frames.len(),
+            frame_count: frames.len(),
             speed_in_ms: self.speed.as_millis() as i32,
             frames: BTreeMap::new(),
         };
 
-        for (index, frame) in self.frames.iter().enumerate() {
-            let frame_buffer = DivoomAnimationBuilder::build_divoom_animation_frame_buffer(frame);
+        let mut frame_buffers: Vec<DivoomImageAnimationFrameData> = frames
+            .iter()
+            .map(DivoomAnimationBuilder::build_divoom_animation_frame_buffer)
+            .collect();
+        if let Some(color_processing) = &self.color_processing {
+            color_processing.apply(self.canvas_size, &mut frame_buffers);
+        }
+
+        for (index, frame_buffer) in frame_buffers.into_iter().enumerate() {
             animation.frames.entry(index as u32).or_insert(frame_buffer);
         }
 
@@ -181,7 +520,10 @@ impl DivoomAnimationBuilder {
 #[cfg(test)]
 mod tests {
     use crate::animation::*;
-    use crate::{test_utils, DivoomAPIError};
+    use crate::{
+        test_utils, DivoomAPIError, DivoomImageAnimation, DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT,
+    };
+    use rgb::RGB8;
     use std::time::Duration;
     use tiny_skia::{BlendMode, Pixmap};
 
@@ -503,4 +845,634 @@ mod tests {
             "test_data/animation_builder_tests/expected_downscaled_animation.gif",
         );
     }
+
+    #[test]
+    fn divoom_animation_builder_can_downscale_animation_with_scale_filters() {
+        let frames = DivoomAnimationResourceLoader::from_gif_file(
+            "test_data/animation_builder_tests/input/logo.gif",
+        )
+        .unwrap();
+
+        for (scale_filter, expected_file) in [
+            (
+                DivoomScaleFilter::Box,
+                "expected_downscaled_animation_box.gif",
+            ),
+            (
+                DivoomScaleFilter::Lanczos,
+                "expected_downscaled_animation_lanczos.gif",
+            ),
+        ] {
+            let animation = DivoomAnimationBuilder::new(16, Duration::from_millis(100))
+                .unwrap()
+                .with_scale_filter(scale_filter)
+                .draw_frames_fit(
+                    &frames,
+                    0,
+                    DivoomDrawFitMode::Stretch,
+                    0.0,
+                    1.0,
+                    BlendMode::default(),
+                )
+                .build();
+            test_utils::assert_animation_equal_with_baseline(
+                &animation,
+                &format!("test_data/animation_builder_tests/{}", expected_file),
+            );
+        }
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_flip_frame_with_scale_filters() {
+        let mut frame = Pixmap::new(2, 1).unwrap();
+        DivoomAnimationFrameBuilder::new(&mut frame)
+            .set_pixel(0, 0, RGB8::new(250, 0, 0))
+            .set_pixel(1, 0, RGB8::new(0, 0, 250));
+
+        for scale_filter in [
+            DivoomScaleFilter::Nearest,
+            DivoomScaleFilter::Box,
+            DivoomScaleFilter::Lanczos,
+        ] {
+            let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100))
+                .unwrap()
+                .with_scale_filter(scale_filter);
+            builder.build_frame(0).draw_frame_scaled(
+                &frame,
+                -2,
+                0,
+                -2.0,
+                2.0,
+                0.0,
+                1.0,
+                BlendMode::default(),
+            );
+
+            let animation = builder.build();
+            let first_pixel = &animation.frames[&0][0..3];
+            let last_pixel = &animation.frames[&0][9..12];
+            assert!(first_pixel[2] > first_pixel[0], "{:?}", scale_filter);
+            assert!(last_pixel[0] > last_pixel[2], "{:?}", scale_filter);
+            assert_eq!(
+                animation.frames[&0][12..15],
+                [0, 0, 0],
+                "{:?}",
+                scale_filter
+            );
+        }
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_animation_with_color_processing() {
+        let frame = DivoomAnimationResourceLoader::from_image_file(
+            "test_data/animation_builder_tests/input/logo_rgb.jpg",
+        )
+        .unwrap();
+
+        for (dither, expected_file) in [
+            (
+                DivoomDitherMode::Ordered,
+                "expected_color_processed_animation_ordered.gif",
+            ),
+            (
+                DivoomDitherMode::FloydSteinberg,
+                "expected_color_processed_animation_floyd_steinberg.gif",
+            ),
+        ] {
+            let animation = DivoomAnimationBuilder::new(32, Duration::from_millis(100))
+                .unwrap()
+                .with_scale_filter(DivoomScaleFilter::Box)
+                .with_color_processing(
+                    DivoomColorProcessingOptions::led_panel()
+                        .with_palette(DivoomColorPalette::pico8(), dither),
+                )
+                .draw_frames_fit(
+                    std::slice::from_ref(&frame),
+                    0,
+                    DivoomDrawFitMode::Stretch,
+                    0.0,
+                    1.0,
+                    BlendMode::default(),
+                )
+                .build();
+            test_utils::assert_animation_equal_with_baseline(
+                &animation,
+                &format!("test_data/animation_builder_tests/{}", expected_file),
+            );
+        }
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_share_median_cut_palette_across_frames() {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100))
+            .unwrap()
+            .with_color_processing(
+                DivoomColorProcessingOptions::default()
+                    .with_palette(DivoomColorPalette::MedianCut(2), DivoomDitherMode::None),
+            );
+        builder
+            .build_frame(0)
+            .fill_rect(0, 0, 16, 16, RGB8::new(250, 0, 0));
+        builder
+            .build_frame(1)
+            .fill_rect(0, 0, 16, 16, RGB8::new(0, 0, 250))
+            .set_pixel(0, 0, RGB8::new(240, 0, 0));
+
+        let animation = builder.build();
+        assert_eq!(animation.frames[&0][0..3], [250, 0, 0]);
+        assert_eq!(animation.frames[&1][0..3], [250, 0, 0]);
+        assert_eq!(animation.frames[&1][3..6], [0, 0, 250]);
+    }
+
+    fn new_frame_sequence(delays_in_ms: &[u64]) -> DivoomAnimationFrameSequence {
+        let frames = (0..delays_in_ms.len())
+            .map(|index| {
+                let mut frame = Pixmap::new(16, 16).unwrap();
+                DivoomAnimationFrameBuilder::new(&mut frame).set_pixel(
+                    0,
+                    0,
+                    RGB8::new(index as u8 + 1, 0, 0),
+                );
+                frame
+            })
+            .collect();
+
+        DivoomAnimationFrameSequence {
+            frames,
+            delays: delays_in_ms
+                .iter()
+                .map(|x| Duration::from_millis(*x))
+                .collect(),
+            loop_count: 0,
+        }
+    }
+
+    fn frame_sequence_indexes(animation: &DivoomImageAnimation) -> Vec<u8> {
+        animation.frames.values().map(|x| x[0] - 1).collect()
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_resample_frame_sequence_with_variable_delays() {
+        let sequence = new_frame_sequence(&[100, 300, 50, 50, 0]);
+        let animation = DivoomAnimationBuilder::new(16, Duration::from_millis(100))
+            .unwrap()
+            .draw_frame_sequence_fit(
+                &sequence,
+                0,
+                DivoomDrawFitMode::Center,
+                0.0,
+                1.0,
+                BlendMode::default(),
+            )
+            .build();
+        assert_eq!(frame_sequence_indexes(&animation), vec![0, 1, 1, 1, 3, 4]);
+        assert_eq!(animation.speed_in_ms, 100);
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_drop_frame_sequence_frames_exceeding_max_frame_count() {
+        let sequence = new_frame_sequence(&[100, 300, 50, 50]);
+        let animation = DivoomAnimationBuilder::new(16, Duration::from_millis(100))
+            .unwrap()
+            .with_max_frame_count(3)
+            .draw_frame_sequence_fit(
+                &sequence,
+                0,
+                DivoomDrawFitMode::Center,
+                0.0,
+                1.0,
+                BlendMode::default(),
+            )
+            .build();
+        assert_eq!(frame_sequence_indexes(&animation), vec![0, 1, 1]);
+        assert_eq!(animation.speed_in_ms, 100);
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_set_pixel() {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        builder
+            .build_frame(0)
+            .set_pixel(1, 2, RGB8::new(255, 0, 0))
+            .set_pixel(-1, 16, RGB8::new(255, 0, 0));
+
+        let animation = builder.build();
+        let frame = &animation.frames[&0];
+        let index = (2 * 16 + 1) * 3;
+        assert_eq!(frame[index..index + 3], [255, 0, 0]);
+        assert_eq!(frame.iter().filter(|x| **x != 0).count(), 1);
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_fill_rect_without_anti_aliasing() {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        builder
+            .build_frame(0)
+            .fill_rect(2, 3, 4, 5, RGB8::new(0, 255, 0));
+
+        let animation = builder.build();
+        let frame = &animation.frames[&0];
+        for y in 0..16 {
+            for x in 0..16 {
+                let index = (y * 16 + x) * 3;
+                let expected = if (2..6).contains(&x) && (3..8).contains(&y) {
+                    [0, 255, 0]
+                } else {
+                    [0, 0, 0]
+                };
+                assert_eq!(frame[index..index + 3], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_animation_with_primitives() {
+        let mut builder = DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap();
+        builder
+            .build_frame(0)
+            .fill_rect_gradient(
+                1,
+                1,
+                30,
+                4,
+                RGB8::new(255, 0, 0),
+                RGB8::new(0, 0, 255),
+                DivoomDrawGradientDirection::Horizontal,
+            )
+            .draw_rect(0, 0, 32, 6, RGB8::new(255, 255, 255))
+            .draw_line(0, 8, 31, 8, 1, RGB8::new(255, 255, 0))
+            .draw_line(2, 10, 12, 20, 1, RGB8::new(0, 255, 255))
+            .draw_line(4, 30, 4, 30, 1, RGB8::new(0, 255, 255))
+            .draw_circle(20, 16, 5, RGB8::new(0, 255, 0))
+            .fill_circle(20, 16, 2, RGB8::new(255, 0, 255))
+            .fill_polygon(&[(2, 30), (8, 22), (14, 30)], RGB8::new(255, 128, 0))
+            .set_pixel(31, 31, RGB8::new(255, 255, 255));
+
+        builder.build_frame(1).fill_rect_gradient(
+            0,
+            0,
+            32,
+            32,
+            RGB8::new(0, 0, 0),
+            RGB8::new(0, 255, 0),
+            DivoomDrawGradientDirection::Vertical,
+        );
+
+        let animation = builder.build();
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_animation_with_primitives.gif",
+        );
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_animation_with_pixel_text() {
+        let mut builder = DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap();
+        builder
+            .build_frame(0)
+            .draw_text(
+                "Hi!",
+                DivoomPixelFont::builtin(DivoomPixelFontType::Font3x5),
+                0,
+                0,
+                RGB8::new(255, 255, 255),
+            )
+            .draw_text_in_box(
+                "12:34",
+                DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6),
+                0,
+                6,
+                32,
+                8,
+                RGB8::new(0, 255, 0),
+                DivoomPixelTextAlign::Right,
+                DivoomPixelTextVerticalAlign::Middle,
+            )
+            .draw_text_in_box(
+                "Rust Pixoo",
+                DivoomPixelFont::builtin(DivoomPixelFontType::Font5x7),
+                0,
+                14,
+                32,
+                18,
+                RGB8::new(255, 128, 0),
+                DivoomPixelTextAlign::Center,
+                DivoomPixelTextVerticalAlign::Top,
+            );
+
+        let animation = builder.build();
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_animation_with_pixel_text.gif",
+        );
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_animation_with_scrolling_text() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let animation = builder
+            .draw_text_scroll(
+                "Hello",
+                font,
+                RGB8::new(255, 255, 0),
+                DivoomPixelTextScrollDirection::Horizontal,
+                0,
+            )
+            .build();
+        assert_eq!(animation.frame_count, 16 + 20);
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_animation_with_horizontal_scrolling_text.gif",
+        );
+
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let animation = builder
+            .draw_text_scroll(
+                "Hello world",
+                font,
+                RGB8::new(0, 255, 255),
+                DivoomPixelTextScrollDirection::Vertical,
+                0,
+            )
+            .build();
+        assert_eq!(animation.frame_count, 16 + 4 * 6);
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_animation_with_vertical_scrolling_text.gif",
+        );
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_limit_scrolling_text_frame_count() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100))
+            .unwrap()
+            .with_max_frame_count(20);
+        let animation = builder
+            .draw_text_scroll(
+                "Hello",
+                font,
+                RGB8::new(255, 255, 0),
+                DivoomPixelTextScrollDirection::Horizontal,
+                5,
+            )
+            .build();
+        assert_eq!(animation.frame_count, 20);
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_marquee_animation() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let animation = builder
+            .draw_marquee_text(
+                "Hi",
+                font,
+                RGB8::new(255, 0, 0),
+                DivoomMarqueeDirection::Left,
+            )
+            .build();
+
+        // "Hi" is 8 pixels wide, plus 8 pixels gap, and it moves 1 pixel per frame.
+        assert_eq!(animation.frame_count, 16);
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_marquee_animation.gif",
+        );
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_marquee_animation_with_icons() {
+        let mut icon = Pixmap::new(6, 6).unwrap();
+        icon.fill(tiny_skia::Color::from_rgba8(0, 255, 0, 255));
+
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font3x5);
+        let mut config = DivoomMarqueeConfig::new(
+            "Up1",
+            font,
+            RGB8::new(255, 255, 255),
+            DivoomMarqueeDirection::Up,
+        );
+        config.items.insert(0, DivoomMarqueeItem::Icon(icon));
+        config.background_color = Some(RGB8::new(0, 0, 64));
+        config.step_in_pixels = 2;
+        config.pause_frame_count = 3;
+
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let animation = builder.draw_marquee(&config, 0).build();
+
+        // 6 pixels icon + 6 pixels text + 8 pixels gap, moves 2 pixels per frame, and pauses 3 frames at the start.
+        assert_eq!(animation.frame_count, 10 + 3);
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_marquee_animation_with_icons.gif",
+        );
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_limit_marquee_frame_count() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        let mut config = DivoomMarqueeConfig::new(
+            "Hello world, this is a long marquee",
+            font,
+            RGB8::new(255, 255, 255),
+            DivoomMarqueeDirection::Right,
+        );
+        config.pause_frame_count = 5;
+
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let animation = builder.draw_marquee(&config, 0).build();
+        assert_eq!(
+            animation.frame_count,
+            DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT
+        );
+
+        // The loop should still be seamless, which means the first frame is the next frame of the last one.
+        let expected_first_frame_builder =
+            DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        config.pause_frame_count = 0;
+        config.max_frame_count = 1000;
+        config.step_in_pixels = 3;
+        let expected_animation = expected_first_frame_builder
+            .draw_marquee(&config, 0)
+            .build();
+        assert_eq!(expected_animation.frames[&0], animation.frames[&0]);
+        assert_eq!(expected_animation.frame_count, 50);
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_limit_marquee_frame_count_after_start_frame() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        let mut config = DivoomMarqueeConfig::new(
+            "Hello world, this is a long marquee",
+            font,
+            RGB8::new(255, 255, 255),
+            DivoomMarqueeDirection::Left,
+        );
+        config.max_frame_count = 20;
+
+        let builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let animation = builder.draw_marquee(&config, 5).build();
+        assert_eq!(animation.frame_count, 20);
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_keyframe_animation() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font3x5);
+        let layers = vec![
+            DivoomAnimationKeyframeLayer::from_rect(32, 4, RGB8::new(0, 0, 255))
+                .unwrap()
+                .with_keyframe(DivoomAnimationKeyframe::new(0, 0.0, 28.0).with_opacity(0.2))
+                .with_keyframe(DivoomAnimationKeyframe::new(7, 0.0, 28.0).with_opacity(1.0)),
+            DivoomAnimationKeyframeLayer::from_circle(3, RGB8::new(255, 0, 0))
+                .unwrap()
+                .with_keyframe(
+                    DivoomAnimationKeyframe::new(0, 0.0, 0.0)
+                        .with_easing(DivoomAnimationEasing::EaseInOut),
+                )
+                .with_keyframe(DivoomAnimationKeyframe::new(7, 25.0, 0.0).with_scale(0.5, 0.5)),
+            DivoomAnimationKeyframeLayer::from_text("GO", font, RGB8::new(255, 255, 0))
+                .unwrap()
+                .with_keyframe(DivoomAnimationKeyframe::new(0, 12.0, 14.0))
+                .with_keyframe(DivoomAnimationKeyframe::new(7, 12.0, 14.0).with_rotation(90.0)),
+        ];
+
+        let builder = DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap();
+        let animation = builder.draw_keyframe_layers(&layers, 8, 0).build();
+        assert_eq!(animation.frame_count, 8);
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_keyframe_animation.gif",
+        );
+    }
+
+    fn new_solid_layer_content(frame_colors: &[RGB8]) -> DivoomAnimationBuilder {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        for (index, color) in frame_colors.iter().enumerate() {
+            builder.build_frame(index).fill_rect(0, 0, 4, 4, *color);
+        }
+        builder
+    }
+
+    fn pixel_at(animation: &DivoomImageAnimation, frame_index: u32, x: usize, y: usize) -> [u8; 3] {
+        let index = (y * animation.size as usize + x) * 3;
+        let frame = &animation.frames[&frame_index];
+        [frame[index], frame[index + 1], frame[index + 2]]
+    }
+
+    #[test]
+    fn divoom_animation_builder_can_build_layered_animation() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font3x5);
+        let mut builder = DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap();
+        for index in 0..4 {
+            builder.build_frame(index).fill_rect(
+                0,
+                0,
+                32,
+                32,
+                RGB8::new(0, 0, 63 + 64 * index as u8),
+            );
+        }
+
+        let mut overlay = DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap();
+        overlay
+            .build_frame(0)
+            .draw_text("HI", font, 2, 2, RGB8::new(255, 255, 255));
+        builder
+            .set_layer(
+                "overlay",
+                DivoomAnimationLayer::new(overlay)
+                    .with_repeat(true)
+                    .with_frame_range(0, Some(4)),
+            )
+            .unwrap();
+
+        let mut badge = DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap();
+        badge
+            .build_frame(0)
+            .fill_circle(26, 26, 4, RGB8::new(255, 0, 0));
+        builder
+            .set_layer(
+                "badge",
+                DivoomAnimationLayer::new(badge)
+                    .with_repeat(true)
+                    .with_opacity(0.5)
+                    .with_frame_range(1, Some(3)),
+            )
+            .unwrap();
+
+        let animation = builder.build();
+        assert_eq!(animation.frame_count, 4);
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_builder_tests/expected_layered_animation.gif",
+        );
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_draw_layers_in_z_order() {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        builder
+            .set_layer(
+                "a",
+                DivoomAnimationLayer::new(new_solid_layer_content(&[RGB8::new(255, 0, 0)]))
+                    .with_z_order(1),
+            )
+            .unwrap();
+        builder
+            .set_layer(
+                "b",
+                DivoomAnimationLayer::new(new_solid_layer_content(&[RGB8::new(0, 255, 0)])),
+            )
+            .unwrap();
+        assert_eq!(builder.layer_names(), vec!["b", "a"]);
+        assert_eq!(pixel_at(&builder.build(), 0, 0, 0), [255, 0, 0]);
+
+        builder.layer_mut("b").unwrap().set_z_order(2);
+        assert_eq!(builder.layer_names(), vec!["a", "b"]);
+        assert_eq!(pixel_at(&builder.build(), 0, 0, 0), [0, 255, 0]);
+
+        builder.layer_mut("b").unwrap().set_visible(false);
+        assert_eq!(pixel_at(&builder.build(), 0, 0, 0), [255, 0, 0]);
+
+        builder.remove_layer("a");
+        assert_eq!(builder.build().frame_count, 0);
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_extend_frames_to_cover_layers() {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        builder.build_frame(0).set_pixel(8, 8, RGB8::new(0, 0, 255));
+        builder
+            .set_layer(
+                "layer",
+                DivoomAnimationLayer::new(new_solid_layer_content(&[
+                    RGB8::new(255, 0, 0),
+                    RGB8::new(0, 255, 0),
+                ]))
+                .with_frame_range(1, None),
+            )
+            .unwrap();
+
+        let animation = builder.build();
+        assert_eq!(animation.frame_count, 3);
+        assert_eq!(pixel_at(&animation, 0, 0, 0), [0, 0, 0]);
+        assert_eq!(pixel_at(&animation, 0, 8, 8), [0, 0, 255]);
+        assert_eq!(pixel_at(&animation, 1, 0, 0), [255, 0, 0]);
+        assert_eq!(pixel_at(&animation, 2, 0, 0), [0, 255, 0]);
+        assert_eq!(pixel_at(&animation, 2, 8, 8), [0, 0, 0]);
+    }
+
+    #[test]
+    fn divoom_animation_builder_should_reject_layer_with_different_canvas_size() {
+        let mut builder = DivoomAnimationBuilder::new(16, Duration::from_millis(100)).unwrap();
+        let layer = DivoomAnimationLayer::new(
+            DivoomAnimationBuilder::new(32, Duration::from_millis(100)).unwrap(),
+        );
+        assert!(matches!(
+            builder.set_layer("layer", layer),
+            Err(DivoomAPIError::ParameterError(_))
+        ));
+    }
 }
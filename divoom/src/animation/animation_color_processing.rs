@@ -0,0 +1,470 @@
+use crate::dto::*;
+use rgb::RGB8;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Dithering mode when reducing the colors to a palette
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomDitherMode {
+    /// Map every pixel to the closest palette color.
+    None,
+
+    /// Use 4x4 Bayer matrix, which creates stable patterns across frames, so it works better for animations.
+    Ordered,
+
+    /// Diffuse the error to the neighbour pixels, which looks smoother on still images.
+    FloydSteinberg,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomDitherMode, None: "none", Ordered: "ordered", FloydSteinberg: "floyd-steinberg");
+
+/// Palette that the colors of the animation will be reduced to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivoomColorPalette {
+    /// Use the specified colors.
+    Fixed(Vec<RGB8>),
+
+    /// Generate a palette with at most the specified number of colors from all frames with median-cut algorithm.
+    MedianCut(usize),
+}
+
+impl DivoomColorPalette {
+    /// The 16 colors palette of PICO-8, which is designed for tiny pixel art.
+    pub fn pico8() -> DivoomColorPalette {
+        DivoomColorPalette::Fixed(
+            [
+                0x000000, 0x1d2b53, 0x7e2553, 0x008751, 0xab5236, 0x5f574f, 0xc2c3c7, 0xfff1e8,
+                0xff004d, 0xffa300, 0xffec27, 0x00e436, 0x29adff, 0x83769c, 0xff77a8, 0xffccaa,
+            ]
+            .iter()
+            .map(|x| RGB8::new((x >> 16) as u8, (x >> 8) as u8, *x as u8))
+            .collect(),
+        )
+    }
+
+    /// Gray colors with the specified number of levels from black to white.
+    pub fn grayscale(levels: u8) -> DivoomColorPalette {
+        DivoomColorPalette::Fixed(
+            color_levels(levels)
+                .into_iter()
+                .map(|x| RGB8::new(x, x, x))
+                .collect(),
+        )
+    }
+
+    /// All combinations of the specified number of levels on each channel, e.g. 6 levels create the 216 web safe colors.
+    pub fn rgb_levels(levels: u8) -> DivoomColorPalette {
+        let levels = color_levels(levels);
+        let mut colors = vec![];
+        for r in &levels {
+            for g in &levels {
+                for b in &levels {
+                    colors.push(RGB8::new(*r, *g, *b));
+                }
+            }
+        }
+
+        DivoomColorPalette::Fixed(colors)
+    }
+
+    fn build_colors(&self, frames: &[DivoomImageAnimationFrameData]) -> Vec<RGB8> {
+        match self {
+            DivoomColorPalette::Fixed(colors) => colors.clone(),
+            DivoomColorPalette::MedianCut(color_count) => median_cut(frames, *color_count),
+        }
+    }
+}
+
+fn color_levels(levels: u8) -> Vec<u8> {
+    let levels = levels.max(2) as u32;
+    (0..levels)
+        .map(|x| (x * 255 / (levels - 1)) as u8)
+        .collect()
+}
+
+/// ## Color processing options
+/// Post-processing options that are applied to the final frames when building the animation.
+///
+/// The colors are adjusted first, then reduced to the palette if any palette is specified. The dither mode only takes
+/// effect when a palette is specified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DivoomColorProcessingOptions {
+    /// Output = input ^ gamma, so gamma larger than 1.0 darkens the mid tones.
+    pub gamma: f32,
+
+    /// 1.0 keeps the original contrast, larger value increases the contrast and 0.0 makes everything gray.
+    pub contrast: f32,
+
+    /// 1.0 keeps the original saturation, larger value makes the colors more vivid and 0.0 makes everything gray.
+    pub saturation: f32,
+
+    pub palette: Option<DivoomColorPalette>,
+    pub dither: DivoomDitherMode,
+}
+
+impl Default for DivoomColorProcessingOptions {
+    fn default() -> Self {
+        DivoomColorProcessingOptions {
+            gamma: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            palette: None,
+            dither: DivoomDitherMode::None,
+        }
+    }
+}
+
+impl DivoomColorProcessingOptions {
+    /// Options tuned for the LED panel of Pixoo devices.
+    ///
+    /// The LEDs look much brighter than a monitor on dark colors, which washes out the photos, so we darken the mid tones and
+    /// slightly increase the contrast and saturation to make the content easier to read.
+    pub fn led_panel() -> DivoomColorProcessingOptions {
+        DivoomColorProcessingOptions {
+            gamma: 1.6,
+            contrast: 1.1,
+            saturation: 1.2,
+            palette: None,
+            dither: DivoomDitherMode::None,
+        }
+    }
+
+    pub fn with_gamma(mut self, gamma: f32) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn with_contrast(mut self, contrast: f32) -> Self {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn with_saturation(mut self, saturation: f32) -> Self {
+        self.saturation = saturation;
+        self
+    }
+
+    pub fn with_palette(mut self, palette: DivoomColorPalette, dither: DivoomDitherMode) -> Self {
+        self.palette = Some(palette);
+        self.dither = dither;
+        self
+    }
+
+    /// Apply the options to the frames in place. All frames share the same palette, so the colors won't flicker between frames.
+    pub(crate) fn apply(&self, canvas_size: u32, frames: &mut [DivoomImageAnimationFrameData]) {
+        let color_lut = self.build_color_lut();
+        for frame in frames.iter_mut() {
+            for pixel in frame.chunks_exact_mut(3) {
+                let adjusted = self.adjust_color(RGB8::new(pixel[0], pixel[1], pixel[2]));
+                pixel[0] = color_lut[adjusted.0 as usize];
+                pixel[1] = color_lut[adjusted.1 as usize];
+                pixel[2] = color_lut[adjusted.2 as usize];
+            }
+        }
+
+        let colors = match &self.palette {
+            None => return,
+            Some(palette) => palette.build_colors(frames),
+        };
+        if colors.is_empty() {
+            return;
+        }
+
+        for frame in frames.iter_mut() {
+            match self.dither {
+                DivoomDitherMode::None => quantize(frame, &colors),
+                DivoomDitherMode::Ordered => quantize_ordered(frame, canvas_size, &colors),
+                DivoomDitherMode::FloydSteinberg => {
+                    quantize_floyd_steinberg(frame, canvas_size, &colors)
+                }
+            }
+        }
+    }
+
+    fn build_color_lut(&self) -> Vec<u8> {
+        (0..=255u32)
+            .map(|x| {
+                let contrasted = (x as f32 - 127.5) * self.contrast + 127.5;
+                let normalized = (contrasted / 255.0).clamp(0.0, 1.0);
+                (normalized.powf(self.gamma) * 255.0).round() as u8
+            })
+            .collect()
+    }
+
+    /// Adjust the saturation, and return the colors in (r, g, b).
+    fn adjust_color(&self, color: RGB8) -> (u8, u8, u8) {
+        if self.saturation == 1.0 {
+            return (color.r, color.g, color.b);
+        }
+
+        let luma = color_luma(color);
+        let saturate = |x: u8| {
+            (luma + (x as f32 - luma) * self.saturation)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+        (saturate(color.r), saturate(color.g), saturate(color.b))
+    }
+}
+
+fn color_luma(color: RGB8) -> f32 {
+    0.299 * color.r as f32 + 0.587 * color.g as f32 + 0.114 * color.b as f32
+}
+
+fn color_distance(r: f32, g: f32, b: f32, color: &RGB8) -> f32 {
+    let (dr, dg, db) = (r - color.r as f32, g - color.g as f32, b - color.b as f32);
+
+    // Weighted with the sensitivity of human eyes on each channel.
+    2.0 * dr * dr + 4.0 * dg * dg + 3.0 * db * db
+}
+
+fn closest_color(r: f32, g: f32, b: f32, colors: &[RGB8]) -> RGB8 {
+    *colors
+        .iter()
+        .min_by(|x, y| {
+            color_distance(r, g, b, x)
+                .partial_cmp(&color_distance(r, g, b, y))
+                .unwrap()
+        })
+        .unwrap()
+}
+
+fn quantize(frame: &mut [u8], colors: &[RGB8]) {
+    let mut cache: HashMap<(u8, u8, u8), RGB8> = HashMap::new();
+    for pixel in frame.chunks_exact_mut(3) {
+        let color = *cache
+            .entry((pixel[0], pixel[1], pixel[2]))
+            .or_insert_with(|| {
+                closest_color(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32, colors)
+            });
+        pixel.copy_from_slice(&[color.r, color.g, color.b]);
+    }
+}
+
+const BAYER_MATRIX_4X4: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+fn quantize_ordered(frame: &mut [u8], canvas_size: u32, colors: &[RGB8]) {
+    // The spread roughly matches the distance between 2 colors in the palette, assuming they are evenly distributed.
+    let spread = 255.0 / (colors.len() as f32).cbrt();
+    for (index, pixel) in frame.chunks_exact_mut(3).enumerate() {
+        let (x, y) = (index % canvas_size as usize, index / canvas_size as usize);
+        let offset = ((BAYER_MATRIX_4X4[y % 4][x % 4] + 0.5) / 16.0 - 0.5) * spread;
+        let color = closest_color(
+            pixel[0] as f32 + offset,
+            pixel[1] as f32 + offset,
+            pixel[2] as f32 + offset,
+            colors,
+        );
+        pixel.copy_from_slice(&[color.r, color.g, color.b]);
+    }
+}
+
+fn quantize_floyd_steinberg(frame: &mut [u8], canvas_size: u32, colors: &[RGB8]) {
+    let width = canvas_size as usize;
+    let mut values: Vec<f32> = frame.iter().map(|x| *x as f32).collect();
+    let pixel_count = values.len() / 3;
+
+    for index in 0..pixel_count {
+        let (r, g, b) = (
+            values[index * 3].clamp(0.0, 255.0),
+            values[index * 3 + 1].clamp(0.0, 255.0),
+            values[index * 3 + 2].clamp(0.0, 255.0),
+        );
+        let color = closest_color(r, g, b, colors);
+        frame[index * 3..index * 3 + 3].copy_from_slice(&[color.r, color.g, color.b]);
+
+        let errors = [r - color.r as f32, g - color.g as f32, b - color.b as f32];
+        let x = index % width;
+        let mut diffuse = |target: usize, weight: f32| {
+            for (channel, error) in errors.iter().enumerate() {
+                values[target * 3 + channel] += error * weight;
+            }
+        };
+
+        if x + 1 < width {
+            diffuse(index + 1, 7.0 / 16.0);
+        }
+        if index + width < pixel_count {
+            if x > 0 {
+                diffuse(index + width - 1, 3.0 / 16.0);
+            }
+            diffuse(index + width, 5.0 / 16.0);
+            if x + 1 < width {
+                diffuse(index + width + 1, 1.0 / 16.0);
+            }
+        }
+    }
+}
+
+fn median_cut(frames: &[DivoomImageAnimationFrameData], color_count: usize) -> Vec<RGB8> {
+    let mut histogram: HashMap<RGB8, u32> = HashMap::new();
+    for frame in frames {
+        for pixel in frame.chunks_exact(3) {
+            *histogram
+                .entry(RGB8::new(pixel[0], pixel[1], pixel[2]))
+                .or_insert(0) += 1;
+        }
+    }
+
+    let mut boxes: Vec<Vec<(RGB8, u32)>> = vec![histogram.into_iter().collect()];
+    boxes.retain(|x| !x.is_empty());
+
+    while boxes.len() < color_count {
+        // Split the box with the largest range on any channel, so the colors are spread as even as possible.
+        let (box_index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (index, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap();
+
+        if range == 0 {
+            break;
+        }
+
+        let mut colors = boxes.swap_remove(box_index);
+        colors.sort_by_key(|(color, _)| (channel_value(color, channel), color.r, color.g, color.b));
+
+        // Split at the weighted median, but never between 2 colors with the same value on the channel, otherwise the result
+        // will depend on the order of the colors with the same value.
+        let total: u32 = colors.iter().map(|(_, count)| count).sum();
+        let mut accumulated = 0;
+        let mut median_index = 0;
+        for (index, (_, count)) in colors.iter().enumerate() {
+            accumulated += count;
+            if accumulated * 2 >= total {
+                median_index = index;
+                break;
+            }
+        }
+
+        let median_value = channel_value(&colors[median_index].0, channel);
+        let split_index = match colors
+            .iter()
+            .position(|(color, _)| channel_value(color, channel) > median_value)
+        {
+            Some(index) => index,
+            None => colors
+                .iter()
+                .position(|(color, _)| channel_value(color, channel) == median_value)
+                .unwrap(),
+        };
+
+        let upper = colors.split_off(split_index);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette: Vec<RGB8> = boxes.iter().map(|x| average_color(x)).collect();
+    palette.sort_by_key(|x| (x.r, x.g, x.b));
+    palette
+}
+
+fn channel_value(color: &RGB8, channel: usize) -> u8 {
+    match channel {
+        0 => color.r,
+        1 => color.g,
+        _ => color.b,
+    }
+}
+
+fn widest_channel(colors: &[(RGB8, u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors
+                .iter()
+                .map(|(color, _)| channel_value(color, channel));
+            let range = values.clone().max().unwrap() - values.min().unwrap();
+            (channel, range)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn average_color(colors: &[(RGB8, u32)]) -> RGB8 {
+    let total: u64 = colors.iter().map(|(_, count)| *count as u64).sum();
+    let average = |channel: usize| {
+        let sum: u64 = colors
+            .iter()
+            .map(|(color, count)| channel_value(color, channel) as u64 * *count as u64)
+            .sum();
+        ((sum + total / 2) / total) as u8
+    };
+
+    RGB8::new(average(0), average(1), average(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_color_processing_options_should_keep_colors() {
+        let mut frames = vec![vec![0, 10, 20, 127, 128, 255]];
+        DivoomColorProcessingOptions::default().apply(2, &mut frames);
+        assert_eq!(frames[0], vec![0, 10, 20, 127, 128, 255]);
+    }
+
+    #[test]
+    fn color_processing_can_adjust_gamma_and_saturation() {
+        let mut frames = vec![vec![128, 128, 128, 200, 100, 100]];
+        DivoomColorProcessingOptions::default()
+            .with_gamma(2.0)
+            .apply(2, &mut frames);
+        assert_eq!(frames[0][0..3], [64, 64, 64]);
+
+        let mut frames = vec![vec![200, 100, 100]];
+        DivoomColorProcessingOptions::default()
+            .with_saturation(0.0)
+            .apply(1, &mut frames);
+        assert_eq!(frames[0], vec![130, 130, 130]);
+    }
+
+    #[test]
+    fn median_cut_should_generate_palette_from_frames() {
+        let frames = vec![
+            vec![255, 0, 0, 250, 0, 0, 0, 0, 255],
+            vec![0, 0, 250, 255, 0, 0, 0, 0, 255],
+        ];
+        assert_eq!(
+            median_cut(&frames, 2),
+            vec![RGB8::new(0, 0, 253), RGB8::new(253, 0, 0)]
+        );
+        assert_eq!(median_cut(&frames, 16).len(), 4);
+    }
+
+    #[test]
+    fn dithering_should_only_use_palette_colors() {
+        let gradient: Vec<u8> = (0..16u8).flat_map(|x| [x * 17, x * 17, x * 17]).collect();
+        for dither in [
+            DivoomDitherMode::None,
+            DivoomDitherMode::Ordered,
+            DivoomDitherMode::FloydSteinberg,
+        ] {
+            let mut frames = vec![gradient.repeat(16)];
+            DivoomColorProcessingOptions::default()
+                .with_palette(DivoomColorPalette::grayscale(2), dither)
+                .apply(16, &mut frames);
+            assert!(frames[0].iter().all(|x| *x == 0 || *x == 255));
+
+            // Half of the pixels in the gradient should be white no matter which dithering mode is used.
+            let white_count = frames[0].iter().filter(|x| **x == 255).count() / 3;
+            assert!(
+                (96..=160).contains(&white_count),
+                "{}: {}",
+                dither,
+                white_count
+            );
+        }
+    }
+}
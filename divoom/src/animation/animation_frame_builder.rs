@@ -1,10 +1,18 @@
+use crate::animation::animation_pixel_font::DivoomPixelFont;
+use crate::animation::animation_pixel_text::*;
+use rgb::RGB8;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
 use std::fmt;
 use std::str::FromStr;
-use tiny_skia::{BlendMode, FilterQuality, Pixmap, PixmapPaint, Transform};
+use tiny_skia::{
+    BlendMode, Color, FillRule, FilterQuality, GradientStop, LinearGradient, Paint, Path,
+    PathBuilder, Pixmap, PixmapPaint, Point, PremultipliedColorU8, Rect, Shader, SpreadMode,
+    Stroke, Transform,
+};
 
 /// Fit mode when drawing the animation frame
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum DivoomDrawFitMode {
     /// Draw the image in the center
     Center,
@@ -21,15 +29,55 @@ pub enum DivoomDrawFitMode {
 
 impl_divoom_dto_enum_traits_without_raw!(DivoomDrawFitMode, Center: "center", Stretch: "stretch", FitX: "fitX", FitY: "fixY");
 
+/// Direction of the gradient when filling the shapes
+#[derive(Debug, Copy, Clone)]
+pub enum DivoomDrawGradientDirection {
+    /// Gradient from left to right
+    Horizontal,
+
+    /// Gradient from top to bottom
+    Vertical,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomDrawGradientDirection, Horizontal: "horizontal", Vertical: "vertical");
+
+/// Filter that is used for scaling the images when drawing them onto the canvas
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomScaleFilter {
+    /// Pick the closest pixel, which keeps the pixel art sharp.
+    Nearest,
+
+    /// Bicubic interpolation, which is the default filter.
+    Bicubic,
+
+    /// Average all pixels covered by the target pixel, which is good for downscaling photos a lot without losing details.
+    Box,
+
+    /// Lanczos with window size 3, which keeps the images sharp when downscaling, but it is slower.
+    Lanczos,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomScaleFilter, Nearest: "nearest", Bicubic: "bicubic", Box: "box", Lanczos: "lanczos");
+
 /// Builder of each animation frame
 pub struct DivoomAnimationFrameBuilder<'a> {
     frame: &'a mut Pixmap,
+    scale_filter: DivoomScaleFilter,
 }
 
 impl DivoomAnimationFrameBuilder<'_> {
     /// Create an new builder on top of a mutable Pixmap reference.
     pub fn new(frame: &mut Pixmap) -> DivoomAnimationFrameBuilder {
-        DivoomAnimationFrameBuilder { frame }
+        DivoomAnimationFrameBuilder {
+            frame,
+            scale_filter: DivoomScaleFilter::Bicubic,
+        }
+    }
+
+    /// Set the filter for scaling the images when drawing them onto the canvas.
+    pub fn with_scale_filter(mut self, scale_filter: DivoomScaleFilter) -> Self {
+        self.scale_filter = scale_filter;
+        self
     }
 
     /// Return the internal Pixmap
@@ -140,6 +188,40 @@ impl DivoomAnimationFrameBuilder<'_> {
         opacity: f32,
         blend: BlendMode,
     ) -> Self {
+        // tiny_skia only supports nearest, bilinear and bicubic filters, so for other filters, we resample the image to the
+        // target size first and only keep the sign of the scale to flip it. Scaling is applied after positioning, so the
+        // position is scaled too.
+        let (frame, x, y, scale_x, scale_y, quality) = match self.scale_filter {
+            DivoomScaleFilter::Nearest => (
+                Cow::Borrowed(frame),
+                x,
+                y,
+                scale_x,
+                scale_y,
+                FilterQuality::Nearest,
+            ),
+            DivoomScaleFilter::Bicubic => (
+                Cow::Borrowed(frame),
+                x,
+                y,
+                scale_x,
+                scale_y,
+                FilterQuality::Bicubic,
+            ),
+            DivoomScaleFilter::Box | DivoomScaleFilter::Lanczos => {
+                let width = ((frame.width() as f32 * scale_x.abs()).round() as u32).max(1);
+                let height = ((frame.height() as f32 * scale_y.abs()).round() as u32).max(1);
+                (
+                    Cow::Owned(resample_pixmap(frame, width, height, self.scale_filter)),
+                    (x as f32 * scale_x.abs()).round() as i32,
+                    (y as f32 * scale_y.abs()).round() as i32,
+                    scale_x.signum(),
+                    scale_y.signum(),
+                    FilterQuality::Nearest,
+                )
+            }
+        };
+
         let transform = Transform::from_rotate_at(
             rotation,
             x as f32 + (frame.width() as f32 / 2.0),
@@ -150,12 +232,355 @@ impl DivoomAnimationFrameBuilder<'_> {
         let paint = PixmapPaint {
             opacity,
             blend_mode: blend,
-            quality: FilterQuality::Bicubic,
+            quality,
         };
 
         self.frame
-            .draw_pixmap(x, y, frame.as_ref(), &paint, transform, None);
+            .draw_pixmap(x, y, frame.as_ref().as_ref(), &paint, transform, None);
+
+        self
+    }
+}
+
+fn resample_pixmap(frame: &Pixmap, width: u32, height: u32, filter: DivoomScaleFilter) -> Pixmap {
+    let data = match filter {
+        DivoomScaleFilter::Lanczos => {
+            let image =
+                image::RgbaImage::from_raw(frame.width(), frame.height(), frame.data().to_vec())
+                    .unwrap();
+            image::imageops::resize(&image, width, height, image::imageops::FilterType::Lanczos3)
+                .into_raw()
+        }
+        _ => box_resample(frame, width, height),
+    };
+
+    let mut resampled = Pixmap::new(width, height).unwrap();
+    for (target, source) in resampled
+        .data_mut()
+        .chunks_exact_mut(4)
+        .zip(data.chunks_exact(4))
+    {
+        // Lanczos filter can overshoot, so the colors need to be clamped to keep them premultiplied.
+        let alpha = source[3];
+        target.copy_from_slice(&[
+            source[0].min(alpha),
+            source[1].min(alpha),
+            source[2].min(alpha),
+            alpha,
+        ]);
+    }
+
+    resampled
+}
+
+fn box_resample(frame: &Pixmap, width: u32, height: u32) -> Vec<u8> {
+    let (source_width, source_height) = (frame.width() as usize, frame.height() as usize);
+    let (width, height) = (width as usize, height as usize);
+    let source = frame.data();
+
+    let mut data = Vec::with_capacity(width * height * 4);
+    for target_y in 0..height {
+        let y0 = target_y * source_height / height;
+        let y1 = ((target_y + 1) * source_height)
+            .div_ceil(height)
+            .max(y0 + 1);
+        for target_x in 0..width {
+            let x0 = target_x * source_width / width;
+            let x1 = ((target_x + 1) * source_width).div_ceil(width).max(x0 + 1);
+
+            let mut sum = [0u32; 4];
+            for source_y in y0..y1 {
+                for source_x in x0..x1 {
+                    let index = (source_y * source_width + source_x) * 4;
+                    for (channel, value) in sum.iter_mut().enumerate() {
+                        *value += source[index + channel] as u32;
+                    }
+                }
+            }
+
+            let count = ((y1 - y0) * (x1 - x0)) as u32;
+            data.extend(sum.iter().map(|x| ((x + count / 2) / count) as u8));
+        }
+    }
+
+    data
+}
+
+// Drawing primitives
+//
+// All primitives are drawn without anti-aliasing, because every pixel is an LED on the device and blurry edges look bad on them.
+// The coordinates are pixel coordinates, so (0, 0) is the top-left pixel of the canvas, and anything outside the canvas is clipped.
+impl DivoomAnimationFrameBuilder<'_> {
+    /// Set the color of a single pixel.
+    pub fn set_pixel(self, x: i32, y: i32, color: RGB8) -> Self {
+        if x < 0 || y < 0 || x >= self.frame.width() as i32 || y >= self.frame.height() as i32 {
+            return self;
+        }
+
+        let index = (y as u32 * self.frame.width() + x as u32) as usize;
+        self.frame.pixels_mut()[index] =
+            PremultipliedColorU8::from_rgba(color.r, color.g, color.b, 255).unwrap();
 
         self
     }
+
+    /// Draw a line from (x0, y0) to (x1, y1), both ends included.
+    pub fn draw_line(self, x0: i32, y0: i32, x1: i32, y1: i32, width: u32, color: RGB8) -> Self {
+        let mut path_builder = PathBuilder::new();
+        path_builder.move_to(x0 as f32 + 0.5, y0 as f32 + 0.5);
+        path_builder.line_to(x1 as f32 + 0.5, y1 as f32 + 0.5);
+
+        // Single point line will not be drawn by tiny_skia, hence we draw it as a square instead.
+        match path_builder.finish() {
+            Some(path) => self.stroke_path(&path, width, color),
+            None => {
+                let offset = (width as i32 - 1) / 2;
+                self.fill_rect(x0 - offset, y0 - offset, width, width, color)
+            }
+        }
+    }
+
+    /// Draw the outline of a rectangle with 1 pixel width.
+    pub fn draw_rect(self, x: i32, y: i32, width: u32, height: u32, color: RGB8) -> Self {
+        if width <= 2 || height <= 2 {
+            return self.fill_rect(x, y, width, height, color);
+        }
+
+        let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+        self.draw_polygon(&[(x, y), (right, y), (right, bottom), (x, bottom)], color)
+    }
+
+    /// Fill a rectangle with the color.
+    pub fn fill_rect(self, x: i32, y: i32, width: u32, height: u32, color: RGB8) -> Self {
+        self.fill_rect_with_shader(
+            x,
+            y,
+            width,
+            height,
+            Shader::SolidColor(to_skia_color(color)),
+        )
+    }
+
+    /// Fill a rectangle with a linear gradient from the start color to the end color.
+    pub fn fill_rect_gradient(
+        self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        start_color: RGB8,
+        end_color: RGB8,
+        direction: DivoomDrawGradientDirection,
+    ) -> Self {
+        // The gradient goes through the centers of the first and last pixels, so both of them get the exact colors.
+        let start = Point::from_xy(x as f32 + 0.5, y as f32 + 0.5);
+        let end = match direction {
+            DivoomDrawGradientDirection::Horizontal => {
+                Point::from_xy((x + width as i32) as f32 - 0.5, start.y)
+            }
+            DivoomDrawGradientDirection::Vertical => {
+                Point::from_xy(start.x, (y + height as i32) as f32 - 0.5)
+            }
+        };
+
+        let shader = LinearGradient::new(
+            start,
+            end,
+            vec![
+                GradientStop::new(0.0, to_skia_color(start_color)),
+                GradientStop::new(1.0, to_skia_color(end_color)),
+            ],
+            SpreadMode::Pad,
+            Transform::identity(),
+        )
+        .unwrap_or(Shader::SolidColor(to_skia_color(start_color)));
+
+        self.fill_rect_with_shader(x, y, width, height, shader)
+    }
+
+    /// Draw the outline of a circle with 1 pixel width.
+    pub fn draw_circle(self, center_x: i32, center_y: i32, radius: u32, color: RGB8) -> Self {
+        match PathBuilder::from_circle(center_x as f32 + 0.5, center_y as f32 + 0.5, radius as f32)
+        {
+            Some(path) => self.stroke_path(&path, 1, color),
+            None => self.set_pixel(center_x, center_y, color),
+        }
+    }
+
+    /// Fill a circle with the color.
+    pub fn fill_circle(self, center_x: i32, center_y: i32, radius: u32, color: RGB8) -> Self {
+        match PathBuilder::from_circle(
+            center_x as f32 + 0.5,
+            center_y as f32 + 0.5,
+            radius as f32 + 0.5,
+        ) {
+            Some(path) => self.fill_path(&path, color),
+            None => self.set_pixel(center_x, center_y, color),
+        }
+    }
+
+    /// Draw the outline of a closed polygon with 1 pixel width.
+    pub fn draw_polygon(self, points: &[(i32, i32)], color: RGB8) -> Self {
+        match build_polygon_path(points) {
+            Some(path) => self.stroke_path(&path, 1, color),
+            None => self,
+        }
+    }
+
+    /// Fill a closed polygon with the color. Self-intersecting polygons are filled with the non-zero rule.
+    pub fn fill_polygon(self, points: &[(i32, i32)], color: RGB8) -> Self {
+        // Pixels are only filled when their centers are inside the path, which leaves the pixels on the edges half-drawn.
+        // So we stroke the outline as well, to make sure every pixel on the edges and vertices is covered.
+        match build_polygon_path(points) {
+            Some(path) => self.fill_path(&path, color).stroke_path(&path, 1, color),
+            None => self,
+        }
+    }
+
+    fn stroke_path(self, path: &Path, width: u32, color: RGB8) -> Self {
+        let stroke = Stroke {
+            width: width as f32,
+            line_cap: tiny_skia::LineCap::Square,
+            ..Stroke::default()
+        };
+
+        self.frame.stroke_path(
+            path,
+            &new_solid_paint(color),
+            &stroke,
+            Transform::identity(),
+            None,
+        );
+
+        self
+    }
+
+    fn fill_path(self, path: &Path, color: RGB8) -> Self {
+        self.frame.fill_path(
+            path,
+            &new_solid_paint(color),
+            FillRule::Winding,
+            Transform::identity(),
+            None,
+        );
+
+        self
+    }
+
+    fn fill_rect_with_shader(
+        self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        shader: Shader,
+    ) -> Self {
+        let rect = match Rect::from_xywh(x as f32, y as f32, width as f32, height as f32) {
+            Some(rect) => rect,
+            None => return self,
+        };
+
+        let paint = Paint {
+            shader,
+            anti_alias: false,
+            ..Paint::default()
+        };
+
+        self.frame
+            .fill_rect(rect, &paint, Transform::identity(), None);
+
+        self
+    }
+}
+
+// Text functions
+impl DivoomAnimationFrameBuilder<'_> {
+    /// Draw the text with pixel font, and the top-left corner of the text is at (x, y). Lines are split on new line chars.
+    pub fn draw_text(
+        self,
+        text: &str,
+        font: &DivoomPixelFont,
+        x: i32,
+        y: i32,
+        color: RGB8,
+    ) -> Self {
+        let (width, height) = (self.frame.width(), self.frame.height());
+        let lines = font.wrap_text(text, None);
+        for (line_index, line) in lines.iter().enumerate() {
+            draw_pixel_text_line(
+                self.frame,
+                line,
+                font,
+                x,
+                y + (line_index as u32 * font.line_height()) as i32,
+                DivoomPixelTextBox {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+                color,
+            );
+        }
+
+        self
+    }
+
+    /// Draw the text with pixel font into a box with alignment. Long lines are wrapped by words to fit into the box width,
+    /// and anything outside of the box will be clipped.
+    pub fn draw_text_in_box(
+        self,
+        text: &str,
+        font: &DivoomPixelFont,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: RGB8,
+        align: DivoomPixelTextAlign,
+        vertical_align: DivoomPixelTextVerticalAlign,
+    ) -> Self {
+        let lines = font.wrap_text(text, Some(width));
+        draw_pixel_text_lines(
+            self.frame,
+            &lines,
+            font,
+            DivoomPixelTextBox {
+                x,
+                y,
+                width,
+                height,
+            },
+            color,
+            align,
+            vertical_align,
+        );
+
+        self
+    }
+}
+
+fn to_skia_color(color: RGB8) -> Color {
+    Color::from_rgba8(color.r, color.g, color.b, 255)
+}
+
+fn new_solid_paint<'a>(color: RGB8) -> Paint<'a> {
+    Paint {
+        shader: Shader::SolidColor(to_skia_color(color)),
+        anti_alias: false,
+        ..Paint::default()
+    }
+}
+
+fn build_polygon_path(points: &[(i32, i32)]) -> Option<Path> {
+    let (first, rest) = points.split_first()?;
+
+    let mut path_builder = PathBuilder::new();
+    path_builder.move_to(first.0 as f32 + 0.5, first.1 as f32 + 0.5);
+    for point in rest {
+        path_builder.line_to(point.0 as f32 + 0.5, point.1 as f32 + 0.5);
+    }
+    path_builder.close();
+
+    path_builder.finish()
 }
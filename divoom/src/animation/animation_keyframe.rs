@@ -0,0 +1,328 @@
+use crate::animation::animation_frame_builder::DivoomAnimationFrameBuilder;
+use crate::animation::animation_pixel_font::DivoomPixelFont;
+use crate::animation::animation_pixel_text::*;
+use crate::dto::*;
+use rgb::RGB8;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use tiny_skia::{BlendMode, FilterQuality, Pixmap, PixmapPaint, Transform};
+
+/// Easing function that is used for interpolating the properties between 2 keyframes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomAnimationEasing {
+    /// Change in constant speed.
+    Linear,
+
+    /// Start slow and speed up.
+    EaseIn,
+
+    /// Start fast and slow down.
+    EaseOut,
+
+    /// Start slow, speed up in the middle and slow down again at the end.
+    EaseInOut,
+
+    /// Hold the properties until the next keyframe.
+    Step,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomAnimationEasing, Linear: "linear", EaseIn: "ease-in", EaseOut: "ease-out", EaseInOut: "ease-in-out", Step: "step");
+
+impl DivoomAnimationEasing {
+    /// Map the progress between 2 keyframes (0.0 to 1.0) to the interpolation ratio.
+    pub fn apply(&self, progress: f32) -> f32 {
+        let t = progress.clamp(0.0, 1.0);
+        match self {
+            DivoomAnimationEasing::Linear => t,
+            DivoomAnimationEasing::EaseIn => t * t,
+            DivoomAnimationEasing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            DivoomAnimationEasing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - 2.0 * (1.0 - t) * (1.0 - t)
+                }
+            }
+            DivoomAnimationEasing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+/// Properties of a layer at a specific frame.
+///
+/// The position is the top-left corner of the layer before it is scaled and rotated, while scaling and rotation are done
+/// around the center of the layer. Rotation is in degrees.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DivoomAnimationKeyframe {
+    pub frame_index: usize,
+    pub x: f32,
+    pub y: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+    pub rotation: f32,
+    pub opacity: f32,
+
+    /// Easing that is used from this keyframe to the next one.
+    pub easing: DivoomAnimationEasing,
+}
+
+impl DivoomAnimationKeyframe {
+    /// Create a keyframe at the position with no scaling, no rotation and full opacity.
+    pub fn new(frame_index: usize, x: f32, y: f32) -> DivoomAnimationKeyframe {
+        DivoomAnimationKeyframe {
+            frame_index,
+            x,
+            y,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            rotation: 0.0,
+            opacity: 1.0,
+            easing: DivoomAnimationEasing::Linear,
+        }
+    }
+
+    pub fn with_scale(mut self, scale_x: f32, scale_y: f32) -> Self {
+        self.scale_x = scale_x;
+        self.scale_y = scale_y;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn with_easing(mut self, easing: DivoomAnimationEasing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    fn interpolate(&self, next: &DivoomAnimationKeyframe, frame_index: usize) -> Self {
+        let progress =
+            (frame_index - self.frame_index) as f32 / (next.frame_index - self.frame_index) as f32;
+        let ratio = self.easing.apply(progress);
+        let lerp = |from: f32, to: f32| from + (to - from) * ratio;
+
+        DivoomAnimationKeyframe {
+            frame_index,
+            x: lerp(self.x, next.x),
+            y: lerp(self.y, next.y),
+            scale_x: lerp(self.scale_x, next.scale_x),
+            scale_y: lerp(self.scale_y, next.scale_y),
+            rotation: lerp(self.rotation, next.rotation),
+            opacity: lerp(self.opacity, next.opacity),
+            easing: self.easing,
+        }
+    }
+}
+
+/// ## Keyframe layer
+/// A keyframe layer holds an image and a list of keyframes. When rendering, the properties of every frame are interpolated
+/// from the keyframes around it, and before the first or after the last keyframe, the properties of that keyframe are kept.
+pub struct DivoomAnimationKeyframeLayer {
+    content: Pixmap,
+    keyframes: Vec<DivoomAnimationKeyframe>,
+}
+
+impl DivoomAnimationKeyframeLayer {
+    /// Create a layer with an image.
+    pub fn new(content: Pixmap) -> DivoomAnimationKeyframeLayer {
+        DivoomAnimationKeyframeLayer {
+            content,
+            keyframes: vec![],
+        }
+    }
+
+    /// Create a layer with a single line of pixel text.
+    pub fn from_text(
+        text: &str,
+        font: &DivoomPixelFont,
+        color: RGB8,
+    ) -> DivoomAPIResult<DivoomAnimationKeyframeLayer> {
+        let (width, height) = (font.measure_text(text), font.line_height());
+        let mut content = new_layer_pixmap(width, height)?;
+        draw_pixel_text_line(
+            &mut content,
+            text,
+            font,
+            0,
+            0,
+            DivoomPixelTextBox {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+            color,
+        );
+
+        Ok(DivoomAnimationKeyframeLayer::new(content))
+    }
+
+    /// Create a layer with a filled rectangle.
+    pub fn from_rect(
+        width: u32,
+        height: u32,
+        color: RGB8,
+    ) -> DivoomAPIResult<DivoomAnimationKeyframeLayer> {
+        let mut content = new_layer_pixmap(width, height)?;
+        DivoomAnimationFrameBuilder::new(&mut content).fill_rect(0, 0, width, height, color);
+        Ok(DivoomAnimationKeyframeLayer::new(content))
+    }
+
+    /// Create a layer with a filled circle.
+    pub fn from_circle(radius: u32, color: RGB8) -> DivoomAPIResult<DivoomAnimationKeyframeLayer> {
+        let size = radius * 2 + 1;
+        let mut content = new_layer_pixmap(size, size)?;
+        DivoomAnimationFrameBuilder::new(&mut content).fill_circle(
+            radius as i32,
+            radius as i32,
+            radius,
+            color,
+        );
+        Ok(DivoomAnimationKeyframeLayer::new(content))
+    }
+
+    /// Add a keyframe. Keyframes don't need to be added in order, and the keyframe on the same frame will be replaced.
+    pub fn with_keyframe(mut self, keyframe: DivoomAnimationKeyframe) -> Self {
+        match self
+            .keyframes
+            .binary_search_by_key(&keyframe.frame_index, |x| x.frame_index)
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+
+        self
+    }
+
+    /// Return the properties of the layer at the specified frame. When no keyframe is added, None will be returned.
+    pub fn keyframe_at(&self, frame_index: usize) -> Option<DivoomAnimationKeyframe> {
+        let next_index = self
+            .keyframes
+            .partition_point(|x| x.frame_index <= frame_index);
+
+        if next_index == 0 {
+            return self
+                .keyframes
+                .first()
+                .map(|x| DivoomAnimationKeyframe { frame_index, ..*x });
+        }
+
+        let previous = &self.keyframes[next_index - 1];
+        match self.keyframes.get(next_index) {
+            None => Some(DivoomAnimationKeyframe {
+                frame_index,
+                ..*previous
+            }),
+            Some(next) => Some(previous.interpolate(next, frame_index)),
+        }
+    }
+
+    /// Draw the layer onto the canvas with the properties at the specified frame.
+    pub(crate) fn draw(&self, canvas: &mut Pixmap, frame_index: usize) {
+        let keyframe = match self.keyframe_at(frame_index) {
+            None => return,
+            Some(v) => v,
+        };
+
+        // Position is rounded to whole pixels, otherwise the layer will be blurred when it is moving.
+        let (half_width, half_height) = (
+            self.content.width() as f32 / 2.0,
+            self.content.height() as f32 / 2.0,
+        );
+        let transform = Transform::from_translate(
+            keyframe.x.round() + half_width,
+            keyframe.y.round() + half_height,
+        )
+        .pre_concat(Transform::from_rotate(keyframe.rotation))
+        .pre_scale(keyframe.scale_x, keyframe.scale_y)
+        .pre_translate(-half_width, -half_height);
+
+        let paint = PixmapPaint {
+            opacity: keyframe.opacity.clamp(0.0, 1.0),
+            blend_mode: BlendMode::default(),
+            quality: FilterQuality::Bicubic,
+        };
+
+        canvas.draw_pixmap(0, 0, self.content.as_ref(), &paint, transform, None);
+    }
+}
+
+fn new_layer_pixmap(width: u32, height: u32) -> DivoomAPIResult<Pixmap> {
+    Pixmap::new(width, height).ok_or_else(|| {
+        DivoomAPIError::ParameterError(format!(
+            "Invalid layer size: {}x{}. Layer cannot be empty.",
+            width, height
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyframe_layer_can_interpolate_keyframes() {
+        let layer = DivoomAnimationKeyframeLayer::from_rect(2, 2, RGB8::new(255, 0, 0))
+            .unwrap()
+            .with_keyframe(DivoomAnimationKeyframe::new(10, 10.0, 0.0).with_opacity(0.0))
+            .with_keyframe(DivoomAnimationKeyframe::new(0, 0.0, 0.0));
+
+        assert_eq!(layer.keyframe_at(0).unwrap().x, 0.0);
+        assert_eq!(layer.keyframe_at(5).unwrap().x, 5.0);
+        assert_eq!(layer.keyframe_at(5).unwrap().opacity, 0.5);
+        assert_eq!(layer.keyframe_at(10).unwrap().x, 10.0);
+        assert_eq!(layer.keyframe_at(20).unwrap().x, 10.0);
+    }
+
+    #[test]
+    fn keyframe_layer_without_keyframe_should_not_be_drawn() {
+        let layer = DivoomAnimationKeyframeLayer::from_circle(2, RGB8::new(255, 0, 0)).unwrap();
+        assert_eq!(layer.keyframe_at(0), None);
+    }
+
+    #[test]
+    fn keyframe_layer_should_keep_first_keyframe_before_it_starts() {
+        let layer = DivoomAnimationKeyframeLayer::from_rect(2, 2, RGB8::new(255, 0, 0))
+            .unwrap()
+            .with_keyframe(DivoomAnimationKeyframe::new(5, 3.0, 4.0))
+            .with_keyframe(DivoomAnimationKeyframe::new(10, 8.0, 4.0));
+
+        let keyframe = layer.keyframe_at(2).unwrap();
+        assert_eq!(
+            (keyframe.frame_index, keyframe.x, keyframe.y),
+            (2, 3.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn easing_functions_should_start_at_0_and_end_at_1() {
+        for easing in [
+            DivoomAnimationEasing::Linear,
+            DivoomAnimationEasing::EaseIn,
+            DivoomAnimationEasing::EaseOut,
+            DivoomAnimationEasing::EaseInOut,
+            DivoomAnimationEasing::Step,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+
+        assert_eq!(DivoomAnimationEasing::EaseIn.apply(0.5), 0.25);
+        assert_eq!(DivoomAnimationEasing::EaseOut.apply(0.5), 0.75);
+        assert_eq!(DivoomAnimationEasing::Step.apply(0.9), 0.0);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::animation::animation_builder::DivoomAnimationBuilder;
+use tiny_skia::{BlendMode, FilterQuality, Pixmap, PixmapPaint, Transform};
+
+/// ## Animation layer
+/// A layer wraps an animation builder as its content, so everything that can be drawn with the animation builder can be drawn
+/// into a layer. Layers are only flattened into the final frames when the animation is built, hence each layer can be updated
+/// or replaced independently.
+///
+/// The speed of the content builder is ignored, and the frames of the layer are played with the speed of the animation that
+/// holds it.
+pub struct DivoomAnimationLayer {
+    content: DivoomAnimationBuilder,
+    z_order: i32,
+    visible: bool,
+    blend_mode: BlendMode,
+    opacity: f32,
+    start_frame_index: usize,
+    end_frame_index: Option<usize>,
+    repeat: bool,
+}
+
+impl DivoomAnimationLayer {
+    /// Create a visible layer with z-order 0, full opacity and default blend mode, which starts from the first frame.
+    pub fn new(content: DivoomAnimationBuilder) -> DivoomAnimationLayer {
+        DivoomAnimationLayer {
+            content,
+            z_order: 0,
+            visible: true,
+            blend_mode: BlendMode::default(),
+            opacity: 1.0,
+            start_frame_index: 0,
+            end_frame_index: None,
+            repeat: false,
+        }
+    }
+
+    /// Layers with higher z-order are drawn on top of the ones with lower z-order. Layers with the same z-order are drawn
+    /// in the order of their names.
+    pub fn with_z_order(mut self, z_order: i32) -> Self {
+        self.z_order = z_order;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    /// Show the layer only from the start frame to the end frame (exclusive). When the end frame is not specified, the layer
+    /// will be shown until its content runs out of frames.
+    pub fn with_frame_range(
+        mut self,
+        start_frame_index: usize,
+        end_frame_index: Option<usize>,
+    ) -> Self {
+        self.start_frame_index = start_frame_index;
+        self.end_frame_index = end_frame_index;
+        self
+    }
+
+    /// Loop the frames of the content to fill the frame range, e.g. a single frame overlay on top of a multi-frame background.
+    pub fn with_repeat(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn content(&self) -> &DivoomAnimationBuilder {
+        &self.content
+    }
+
+    pub fn content_mut(&mut self) -> &mut DivoomAnimationBuilder {
+        &mut self.content
+    }
+
+    pub fn z_order(&self) -> i32 {
+        self.z_order
+    }
+
+    pub fn set_z_order(&mut self, z_order: i32) {
+        self.z_order = z_order;
+    }
+
+    pub fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+    }
+
+    pub fn set_frame_range(&mut self, start_frame_index: usize, end_frame_index: Option<usize>) {
+        self.start_frame_index = start_frame_index;
+        self.end_frame_index = end_frame_index;
+    }
+
+    pub fn set_repeat(&mut self, repeat: bool) {
+        self.repeat = repeat;
+    }
+
+    /// Number of frames needed to show this layer, which is the end of its frame range.
+    pub(crate) fn frame_count(&self) -> usize {
+        if !self.visible {
+            return 0;
+        }
+
+        match self.end_frame_index {
+            Some(end_frame_index) => end_frame_index,
+            None => self.start_frame_index + self.content.frame_count(),
+        }
+    }
+
+    /// Draw the layer frame onto the canvas of the specified frame in the final animation.
+    pub(crate) fn draw(&self, canvas: &mut Pixmap, content_frames: &[Pixmap], frame_index: usize) {
+        if !self.visible
+            || content_frames.is_empty()
+            || frame_index < self.start_frame_index
+            || frame_index >= self.end_frame_index.unwrap_or(usize::MAX)
+        {
+            return;
+        }
+
+        let mut layer_frame_index = frame_index - self.start_frame_index;
+        if layer_frame_index >= content_frames.len() {
+            if !self.repeat {
+                return;
+            }
+
+            layer_frame_index %= content_frames.len();
+        }
+
+        let paint = PixmapPaint {
+            opacity: self.opacity.clamp(0.0, 1.0),
+            blend_mode: self.blend_mode,
+            quality: FilterQuality::Nearest,
+        };
+
+        canvas.draw_pixmap(
+            0,
+            0,
+            content_frames[layer_frame_index].as_ref(),
+            &paint,
+            Transform::identity(),
+            None,
+        );
+    }
+}
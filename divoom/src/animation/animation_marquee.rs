@@ -0,0 +1,294 @@
+use crate::animation::animation_pixel_font::DivoomPixelFont;
+use crate::animation::animation_pixel_text::*;
+use crate::dto::*;
+use rgb::RGB8;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use tiny_skia::{Pixmap, PixmapPaint, Transform};
+
+/// Direction that the marquee content moves towards
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomMarqueeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomMarqueeDirection, Left: "left", Right: "right", Up: "up", Down: "down");
+
+/// Content of the marquee. Items are placed one after another in the scrolling direction.
+pub enum DivoomMarqueeItem {
+    /// Text drawn with the marquee font and the specified color.
+    Text(String, RGB8),
+
+    /// Image drawn as it is, such as weather or status icons.
+    Icon(Pixmap),
+}
+
+/// ## Marquee config
+/// Marquee scrolls its content through the canvas and loops seamlessly, which means the last frame connects back to the
+/// first one, so the device can play it repeatedly without any jump.
+pub struct DivoomMarqueeConfig<'a> {
+    /// Font for all text items.
+    pub font: &'a DivoomPixelFont,
+
+    /// Items to scroll.
+    pub items: Vec<DivoomMarqueeItem>,
+
+    /// Direction that the content moves towards.
+    pub direction: DivoomMarqueeDirection,
+
+    /// Background color of every frame. When not set, the content is drawn on top of the existing frames.
+    pub background_color: Option<RGB8>,
+
+    /// Pixels that the content moves in each frame.
+    pub step_in_pixels: u32,
+
+    /// Empty pixels between the end of the content and the start of its next loop.
+    pub gap_in_pixels: u32,
+
+    /// Frames to hold when the start of the content reaches the canvas edge, and also when the end of the content
+    /// reaches the opposite edge if the content is longer than the canvas.
+    pub pause_frame_count: usize,
+
+    /// Max number of frames in the animation, including the frames before the start frame of the marquee. When the marquee
+    /// needs more frames than this, the step will be increased and the pauses will be shortened to fit.
+    pub max_frame_count: usize,
+}
+
+impl<'a> DivoomMarqueeConfig<'a> {
+    /// Create a marquee config with a single text item and default settings.
+    pub fn new(
+        text: &str,
+        font: &'a DivoomPixelFont,
+        color: RGB8,
+        direction: DivoomMarqueeDirection,
+    ) -> DivoomMarqueeConfig<'a> {
+        DivoomMarqueeConfig {
+            font,
+            items: vec![DivoomMarqueeItem::Text(text.to_string(), color)],
+            direction,
+            background_color: None,
+            step_in_pixels: 1,
+            gap_in_pixels: 8,
+            pause_frame_count: 0,
+            max_frame_count: DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT,
+        }
+    }
+
+    fn is_horizontal(&self) -> bool {
+        self.direction == DivoomMarqueeDirection::Left
+            || self.direction == DivoomMarqueeDirection::Right
+    }
+}
+
+/// Pre-rendered marquee content with all the frame positions calculated.
+pub(crate) struct DivoomMarqueeRenderer<'a> {
+    config: &'a DivoomMarqueeConfig<'a>,
+    canvas_size: u32,
+    max_frame_count: usize,
+    content: Option<Pixmap>,
+    content_length: i32,
+    period: i32,
+    offsets: Vec<i32>,
+}
+
+impl DivoomMarqueeRenderer<'_> {
+    pub fn new<'a>(
+        config: &'a DivoomMarqueeConfig<'a>,
+        canvas_size: u32,
+        max_frame_count: usize,
+    ) -> DivoomMarqueeRenderer<'a> {
+        let content = render_marquee_content(config, canvas_size);
+        let content_length = match &content {
+            None => 0,
+            Some(v) if config.is_horizontal() => v.width() as i32,
+            Some(v) => v.height() as i32,
+        };
+
+        let mut renderer = DivoomMarqueeRenderer {
+            config,
+            canvas_size,
+            max_frame_count,
+            content,
+            content_length,
+            period: content_length + config.gap_in_pixels as i32,
+            offsets: vec![],
+        };
+        renderer.offsets = renderer.calculate_offsets();
+
+        renderer
+    }
+
+    /// Number of frames that the marquee generates.
+    pub fn frame_count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Draw the specified marquee frame onto the canvas.
+    pub fn draw_frame(&self, frame_index: usize, canvas: &mut Pixmap) {
+        if let Some(color) = self.config.background_color {
+            canvas.fill(tiny_skia::Color::from_rgba8(color.r, color.g, color.b, 255));
+        }
+
+        let content = match &self.content {
+            None => return,
+            Some(v) => v,
+        };
+
+        let canvas_size = self.canvas_size as i32;
+        let offset = self.offsets[frame_index];
+        let position = match self.config.direction {
+            DivoomMarqueeDirection::Left | DivoomMarqueeDirection::Up => -offset,
+            DivoomMarqueeDirection::Right | DivoomMarqueeDirection::Down => {
+                canvas_size - self.content_length + offset
+            }
+        };
+
+        // Draw the content repeatedly, so the next loop shows up right after the gap.
+        let mut copy_position = position;
+        while copy_position > -self.content_length {
+            copy_position -= self.period;
+        }
+
+        while copy_position < canvas_size {
+            let (x, y) = if self.config.is_horizontal() {
+                (copy_position, 0)
+            } else {
+                (0, copy_position)
+            };
+
+            canvas.draw_pixmap(
+                x,
+                y,
+                content.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            );
+
+            copy_position += self.period;
+        }
+    }
+
+    fn calculate_offsets(&mut self) -> Vec<i32> {
+        if self.content.is_none() || self.period <= 0 {
+            return vec![0];
+        }
+
+        let canvas_size = self.canvas_size as i32;
+        let has_end_pause = self.content_length > canvas_size;
+        let pause_count = if has_end_pause { 2 } else { 1 };
+        let max_frame_count = std::cmp::max(self.max_frame_count, 1);
+
+        // Keep at least half of the frames for moving the content.
+        let mut pause_frame_count = self.config.pause_frame_count;
+        if pause_frame_count * pause_count > max_frame_count / 2 {
+            pause_frame_count = max_frame_count / 2 / pause_count;
+        }
+
+        let moving_frame_count = max_frame_count - pause_frame_count * pause_count;
+        let step = std::cmp::max(
+            std::cmp::max(self.config.step_in_pixels as i32, 1),
+            (self.period + moving_frame_count as i32 - 1) / moving_frame_count as i32,
+        );
+
+        // Extend the gap to make the period a multiple of the step, otherwise the loop will jump at the end.
+        self.period = (self.period + step - 1) / step * step;
+
+        let end_offset = (self.content_length - canvas_size + step / 2) / step * step;
+        let mut offsets = Vec::new();
+        for offset in (0..self.period).step_by(step as usize) {
+            offsets.push(offset);
+            if offset == 0 || (has_end_pause && offset == end_offset) {
+                // `iter::repeat_n` is only available since Rust 1.82.
+                #[allow(clippy::manual_repeat_n)]
+                offsets.extend(std::iter::repeat(offset).take(pause_frame_count));
+            }
+        }
+
+        offsets
+    }
+}
+
+fn render_marquee_content(config: &DivoomMarqueeConfig, canvas_size: u32) -> Option<Pixmap> {
+    let font = config.font;
+    let is_horizontal = config.is_horizontal();
+
+    // Measure all items first, horizontal marquee puts everything in a single line, while vertical marquee wraps the text by
+    // the canvas width and stacks all items.
+    let item_layouts: Vec<(Vec<String>, u32, u32)> = config
+        .items
+        .iter()
+        .map(|item| match item {
+            DivoomMarqueeItem::Text(text, _) if is_horizontal => {
+                let line = text.lines().collect::<Vec<&str>>().join(" ");
+                let width = font.measure_text(&line);
+                (vec![line], width, font.line_height())
+            }
+            DivoomMarqueeItem::Text(text, _) => {
+                let lines = font.wrap_text(text, Some(canvas_size));
+                let height = lines.len() as u32 * font.line_height();
+                (lines, canvas_size, height)
+            }
+            DivoomMarqueeItem::Icon(icon) => (vec![], icon.width(), icon.height()),
+        })
+        .collect();
+
+    let content_length: u32 = item_layouts
+        .iter()
+        .map(|(_, width, height)| if is_horizontal { *width } else { *height })
+        .sum();
+    let (width, height) = if is_horizontal {
+        (content_length, canvas_size)
+    } else {
+        (canvas_size, content_length)
+    };
+    let mut content = Pixmap::new(width, height)?;
+
+    let mut cursor = 0i32;
+    for (item, (lines, item_width, item_height)) in config.items.iter().zip(item_layouts.iter()) {
+        let (x, y, box_width, box_height) = if is_horizontal {
+            (cursor, 0, *item_width, canvas_size)
+        } else {
+            (0, cursor, canvas_size, *item_height)
+        };
+
+        match item {
+            DivoomMarqueeItem::Text(_, color) => draw_pixel_text_lines(
+                &mut content,
+                lines,
+                font,
+                DivoomPixelTextBox {
+                    x,
+                    y,
+                    width: box_width,
+                    height: box_height,
+                },
+                *color,
+                DivoomPixelTextAlign::Center,
+                DivoomPixelTextVerticalAlign::Middle,
+            ),
+            DivoomMarqueeItem::Icon(icon) => {
+                content.draw_pixmap(
+                    x + (box_width as i32 - icon.width() as i32) / 2,
+                    y + (box_height as i32 - icon.height() as i32) / 2,
+                    icon.as_ref(),
+                    &PixmapPaint::default(),
+                    Transform::identity(),
+                    None,
+                );
+            }
+        }
+
+        cursor += if is_horizontal {
+            *item_width as i32
+        } else {
+            *item_height as i32
+        };
+    }
+
+    Some(content)
+}
@@ -0,0 +1,413 @@
+use crate::dto::*;
+use lazy_static::lazy_static;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Bitmap pixel fonts that are bundled with the library.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomPixelFontType {
+    /// 3x5 pixel font. Only capital letters are provided, and lowercase letters are drawn as capital letters.
+    Font3x5,
+
+    /// 4x6 pixel font from X11 misc-fixed fonts.
+    Font4x6,
+
+    /// 5x7 pixel font from X11 misc-fixed fonts.
+    Font5x7,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomPixelFontType, Font3x5: "3x5", Font4x6: "4x6", Font5x7: "5x7");
+
+lazy_static! {
+    static ref BUILTIN_FONT_3X5: DivoomPixelFont =
+        DivoomPixelFont::from_bdf(include_str!("fonts/3x5.bdf")).unwrap();
+    static ref BUILTIN_FONT_4X6: DivoomPixelFont =
+        DivoomPixelFont::from_bdf(include_str!("fonts/4x6.bdf")).unwrap();
+    static ref BUILTIN_FONT_5X7: DivoomPixelFont =
+        DivoomPixelFont::from_bdf(include_str!("fonts/5x7.bdf")).unwrap();
+}
+
+/// A single glyph in the bitmap pixel font.
+#[derive(Debug, Clone)]
+pub struct DivoomPixelFontGlyph {
+    advance: i32,
+    width: u32,
+    height: u32,
+    offset_x: i32,
+    offset_y: i32,
+    bitmap: Vec<bool>,
+}
+
+impl DivoomPixelFontGlyph {
+    /// How many pixels the cursor moves after this glyph is drawn.
+    pub fn advance(&self) -> i32 {
+        self.advance
+    }
+
+    /// Width of the glyph bitmap.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the glyph bitmap.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Offset of the glyph bitmap from the cursor on X axis.
+    pub fn offset_x(&self) -> i32 {
+        self.offset_x
+    }
+
+    /// Offset of the bottom of the glyph bitmap from the baseline on Y axis, and positive value means upward.
+    pub fn offset_y(&self) -> i32 {
+        self.offset_y
+    }
+
+    /// Return true if the pixel at (x, y) in the glyph bitmap is set.
+    pub fn is_set(&self, x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+
+        self.bitmap[(y * self.width + x) as usize]
+    }
+}
+
+/// ## Bitmap pixel font
+/// Pixel font renders every glyph as a set of crisp pixels, which is much more readable on the device than the antialiased vector fonts.
+///
+/// Besides the builtin fonts, any BDF font can be loaded with `from_bdf` or `from_bdf_file`.
+#[derive(Debug, Clone)]
+pub struct DivoomPixelFont {
+    ascent: i32,
+    descent: i32,
+    default_glyph: Option<char>,
+    glyphs: HashMap<char, DivoomPixelFontGlyph>,
+}
+
+impl DivoomPixelFont {
+    /// Return the builtin font.
+    pub fn builtin(font_type: DivoomPixelFontType) -> &'static DivoomPixelFont {
+        match font_type {
+            DivoomPixelFontType::Font3x5 => &BUILTIN_FONT_3X5,
+            DivoomPixelFontType::Font4x6 => &BUILTIN_FONT_4X6,
+            DivoomPixelFontType::Font5x7 => &BUILTIN_FONT_5X7,
+        }
+    }
+
+    /// Load font from BDF file.
+    pub fn from_bdf_file<P: AsRef<Path>>(file_path: P) -> DivoomAPIResult<DivoomPixelFont> {
+        let font_text = fs::read_to_string(file_path)?;
+        DivoomPixelFont::from_bdf(&font_text)
+    }
+
+    /// Load font from BDF text.
+    pub fn from_bdf(font_text: &str) -> DivoomAPIResult<DivoomPixelFont> {
+        DivoomPixelFontBdfParser::new(font_text).parse()
+    }
+
+    /// Pixels above the baseline.
+    pub fn ascent(&self) -> i32 {
+        self.ascent
+    }
+
+    /// Pixels below the baseline.
+    pub fn descent(&self) -> i32 {
+        self.descent
+    }
+
+    /// Height of each line.
+    pub fn line_height(&self) -> u32 {
+        (self.ascent + self.descent) as u32
+    }
+
+    /// Return the glyph of the char. If the glyph is not found in the font, the default glyph will be returned.
+    pub fn glyph(&self, c: char) -> Option<&DivoomPixelFontGlyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.default_glyph.and_then(|x| self.glyphs.get(&x)))
+    }
+
+    /// Return the width of the text in pixels, when it is drawn in a single line.
+    pub fn measure_text(&self, text: &str) -> u32 {
+        let width: i32 = text
+            .chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|g| g.advance)
+            .sum();
+
+        std::cmp::max(width, 0) as u32
+    }
+
+    /// Split the text into lines. Lines are split on new line chars, and when `max_width` is specified, long lines will also
+    /// be wrapped by words to fit into the width. Words that are longer than the width will be broken into multiple lines.
+    pub fn wrap_text(&self, text: &str, max_width: Option<u32>) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for paragraph in text.lines() {
+            let max_width = match max_width {
+                None => {
+                    lines.push(paragraph.to_string());
+                    continue;
+                }
+                Some(v) => v,
+            };
+
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{} {}", line, word)
+                };
+
+                if self.measure_text(&candidate) <= max_width {
+                    line = candidate;
+                    continue;
+                }
+
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+
+                line = String::new();
+                for c in word.chars() {
+                    line.push(c);
+                    if line.chars().count() > 1 && self.measure_text(&line) > max_width {
+                        line.pop();
+                        lines.push(line);
+                        line = c.to_string();
+                    }
+                }
+            }
+
+            lines.push(line);
+        }
+
+        lines
+    }
+}
+
+struct DivoomPixelFontBdfParser<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+}
+
+impl DivoomPixelFontBdfParser<'_> {
+    fn new(font_text: &str) -> DivoomPixelFontBdfParser<'_> {
+        DivoomPixelFontBdfParser {
+            lines: font_text.lines().enumerate(),
+        }
+    }
+
+    fn parse(&mut self) -> DivoomAPIResult<DivoomPixelFont> {
+        let mut font = DivoomPixelFont {
+            ascent: 0,
+            descent: 0,
+            default_glyph: None,
+            glyphs: HashMap::new(),
+        };
+
+        let mut font_bounding_box_height: Option<(i32, i32)> = None;
+
+        while let Some((line_index, line)) = self.lines.next() {
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("FONTBOUNDINGBOX") => {
+                    let values = parse_bdf_numbers(line_index, tokens, 4)?;
+                    font_bounding_box_height = Some((values[1], values[3]));
+                }
+                Some("FONT_ASCENT") => font.ascent = parse_bdf_numbers(line_index, tokens, 1)?[0],
+                Some("FONT_DESCENT") => font.descent = parse_bdf_numbers(line_index, tokens, 1)?[0],
+                Some("DEFAULT_CHAR") => {
+                    font.default_glyph =
+                        char::from_u32(parse_bdf_numbers(line_index, tokens, 1)?[0] as u32)
+                }
+                Some("STARTCHAR") => {
+                    if let Some((c, glyph)) = self.parse_glyph()? {
+                        font.glyphs.insert(c, glyph);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // FONT_ASCENT and FONT_DESCENT are optional properties, so fallback to the font bounding box when they are missing.
+        if font.ascent == 0 && font.descent == 0 {
+            if let Some((height, offset_y)) = font_bounding_box_height {
+                font.ascent = height + offset_y;
+                font.descent = -offset_y;
+            }
+        }
+
+        if font.glyphs.is_empty() || font.ascent + font.descent <= 0 {
+            return Err(DivoomAPIError::ResourceDecodeError(
+                "Invalid BDF font: No glyph or font height found.".into(),
+            ));
+        }
+
+        Ok(font)
+    }
+
+    fn parse_glyph(&mut self) -> DivoomAPIResult<Option<(char, DivoomPixelFontGlyph)>> {
+        let mut encoding: Option<char> = None;
+        let mut glyph = DivoomPixelFontGlyph {
+            advance: 0,
+            width: 0,
+            height: 0,
+            offset_x: 0,
+            offset_y: 0,
+            bitmap: vec![],
+        };
+
+        while let Some((line_index, line)) = self.lines.next() {
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("ENCODING") => {
+                    let code = parse_bdf_numbers(line_index, tokens, 1)?[0];
+                    encoding = if code < 0 {
+                        None
+                    } else {
+                        char::from_u32(code as u32)
+                    };
+                }
+                Some("DWIDTH") => glyph.advance = parse_bdf_numbers(line_index, tokens, 1)?[0],
+                Some("BBX") => {
+                    let values = parse_bdf_numbers(line_index, tokens, 4)?;
+                    if values[0] < 0 || values[1] < 0 {
+                        return Err(new_bdf_error(line_index, "Invalid glyph bounding box"));
+                    }
+
+                    glyph.width = values[0] as u32;
+                    glyph.height = values[1] as u32;
+                    glyph.offset_x = values[2];
+                    glyph.offset_y = values[3];
+                }
+                Some("BITMAP") => self.parse_glyph_bitmap(&mut glyph)?,
+                Some("ENDCHAR") => return Ok(encoding.map(|c| (c, glyph))),
+                _ => (),
+            }
+        }
+
+        Err(DivoomAPIError::ResourceDecodeError(
+            "Invalid BDF font: ENDCHAR is missing.".into(),
+        ))
+    }
+
+    fn parse_glyph_bitmap(&mut self, glyph: &mut DivoomPixelFontGlyph) -> DivoomAPIResult<()> {
+        glyph.bitmap = vec![false; (glyph.width * glyph.height) as usize];
+
+        for row in 0..glyph.height {
+            let (line_index, line) = self.lines.next().ok_or_else(|| {
+                DivoomAPIError::ResourceDecodeError("Invalid BDF font: Bitmap is too short.".into())
+            })?;
+
+            // Each row is padded to bytes in hex, and the leftmost pixel is the most significant bit.
+            let row_text = line.trim();
+            let row_bits = u64::from_str_radix(row_text, 16)
+                .map_err(|_| new_bdf_error(line_index, "Invalid bitmap row"))?;
+            let row_bit_count = row_text.len() as u32 * 4;
+            if row_bit_count > 64 || row_bit_count < glyph.width {
+                return Err(new_bdf_error(line_index, "Invalid bitmap row size"));
+            }
+
+            for column in 0..glyph.width {
+                glyph.bitmap[(row * glyph.width + column) as usize] =
+                    (row_bits >> (row_bit_count - 1 - column)) & 1 == 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_bdf_numbers<'a>(
+    line_index: usize,
+    tokens: impl Iterator<Item = &'a str>,
+    count: usize,
+) -> DivoomAPIResult<Vec<i32>> {
+    let values = tokens
+        .take(count)
+        .map(|x| x.parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|_| new_bdf_error(line_index, "Invalid number"))?;
+
+    if values.len() != count {
+        return Err(new_bdf_error(line_index, "Missing number"));
+    }
+
+    Ok(values)
+}
+
+fn new_bdf_error(line_index: usize, message: &str) -> DivoomAPIError {
+    DivoomAPIError::ResourceDecodeError(format!(
+        "Invalid BDF font: {} at line {}.",
+        message,
+        line_index + 1
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_font_can_load_builtin_fonts() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font3x5);
+        assert_eq!(font.line_height(), 6);
+        assert_eq!(font.measure_text("ABC"), 12);
+
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        assert_eq!(font.line_height(), 6);
+        assert_eq!(font.measure_text("abc"), 12);
+
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font5x7);
+        assert_eq!(font.line_height(), 7);
+        assert_eq!(font.measure_text("abc"), 15);
+    }
+
+    #[test]
+    fn pixel_font_can_parse_bdf_glyph() {
+        let font = DivoomPixelFont::from_bdf(
+            "STARTFONT 2.1\nFONTBOUNDINGBOX 3 3 0 0\nSTARTCHAR A\nENCODING 65\nDWIDTH 4 0\nBBX 3 3 0 0\nBITMAP\n40\nA0\nE0\nENDCHAR\nENDFONT\n",
+        )
+        .unwrap();
+
+        assert_eq!(font.line_height(), 3);
+
+        let glyph = font.glyph('A').unwrap();
+        assert_eq!(glyph.advance(), 4);
+        assert!(!glyph.is_set(0, 0) && glyph.is_set(1, 0) && !glyph.is_set(2, 0));
+        assert!(glyph.is_set(0, 1) && !glyph.is_set(1, 1) && glyph.is_set(2, 1));
+        assert!(glyph.is_set(0, 2) && glyph.is_set(1, 2) && glyph.is_set(2, 2));
+    }
+
+    #[test]
+    fn pixel_font_should_fail_on_invalid_bdf() {
+        let result = DivoomPixelFont::from_bdf(
+            "STARTFONT 2.1\nFONTBOUNDINGBOX 3 3 0 0\nSTARTCHAR A\nENCODING 65\nBBX 3 3 0 0\nBITMAP\nXY\n",
+        );
+
+        match result {
+            Err(DivoomAPIError::ResourceDecodeError(e)) => assert!(e.contains("line 7")),
+            _ => panic!("Invalid BDF font should not be loaded."),
+        }
+    }
+
+    #[test]
+    fn pixel_font_can_wrap_text() {
+        let font = DivoomPixelFont::builtin(DivoomPixelFontType::Font4x6);
+        assert_eq!(
+            font.wrap_text("Hello world\nABCDEFGHIJ", Some(16)),
+            vec!["Hell", "o", "worl", "d", "ABCD", "EFGH", "IJ"]
+        );
+        assert_eq!(
+            font.wrap_text("Hi all, bye", Some(28)),
+            vec!["Hi all,", "bye"]
+        );
+    }
+}
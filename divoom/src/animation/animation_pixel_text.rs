@@ -0,0 +1,128 @@
+use crate::animation::animation_pixel_font::DivoomPixelFont;
+use rgb::RGB8;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use tiny_skia::{Pixmap, PremultipliedColorU8};
+
+/// Horizontal alignment of the pixel text
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomPixelTextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomPixelTextAlign, Left: "left", Center: "center", Right: "right");
+
+/// Vertical alignment of the pixel text
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomPixelTextVerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomPixelTextVerticalAlign, Top: "top", Middle: "middle", Bottom: "bottom");
+
+/// Direction of the scrolling text
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DivoomPixelTextScrollDirection {
+    /// Text is drawn in a single line and scrolls from right to left.
+    Horizontal,
+
+    /// Text is wrapped by the canvas width and scrolls from bottom to top.
+    Vertical,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomPixelTextScrollDirection, Horizontal: "horizontal", Vertical: "vertical");
+
+/// Rectangle that the text is drawn in. Pixels outside of it are clipped.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct DivoomPixelTextBox {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Draw the text lines into the box with alignment.
+pub(crate) fn draw_pixel_text_lines(
+    frame: &mut Pixmap,
+    lines: &[String],
+    font: &DivoomPixelFont,
+    text_box: DivoomPixelTextBox,
+    color: RGB8,
+    align: DivoomPixelTextAlign,
+    vertical_align: DivoomPixelTextVerticalAlign,
+) {
+    let text_height = (lines.len() as u32 * font.line_height()) as i32;
+    let mut y = match vertical_align {
+        DivoomPixelTextVerticalAlign::Top => text_box.y,
+        DivoomPixelTextVerticalAlign::Middle => {
+            text_box.y + (text_box.height as i32 - text_height) / 2
+        }
+        DivoomPixelTextVerticalAlign::Bottom => text_box.y + text_box.height as i32 - text_height,
+    };
+
+    for line in lines {
+        let line_width = font.measure_text(line) as i32;
+        let x = match align {
+            DivoomPixelTextAlign::Left => text_box.x,
+            DivoomPixelTextAlign::Center => text_box.x + (text_box.width as i32 - line_width) / 2,
+            DivoomPixelTextAlign::Right => text_box.x + text_box.width as i32 - line_width,
+        };
+
+        draw_pixel_text_line(frame, line, font, x, y, text_box, color);
+        y += font.line_height() as i32;
+    }
+}
+
+/// Draw a single line of text with its top-left corner at (x, y).
+pub(crate) fn draw_pixel_text_line(
+    frame: &mut Pixmap,
+    line: &str,
+    font: &DivoomPixelFont,
+    x: i32,
+    y: i32,
+    clip: DivoomPixelTextBox,
+    color: RGB8,
+) {
+    let clip_left = std::cmp::max(clip.x, 0);
+    let clip_top = std::cmp::max(clip.y, 0);
+    let clip_right = std::cmp::min(clip.x + clip.width as i32, frame.width() as i32);
+    let clip_bottom = std::cmp::min(clip.y + clip.height as i32, frame.height() as i32);
+
+    let frame_width = frame.width() as i32;
+    let pixel_color = PremultipliedColorU8::from_rgba(color.r, color.g, color.b, 255).unwrap();
+    let pixels = frame.pixels_mut();
+
+    let baseline = y + font.ascent();
+    let mut cursor = x;
+    for c in line.chars() {
+        let glyph = match font.glyph(c) {
+            None => continue,
+            Some(v) => v,
+        };
+
+        let glyph_left = cursor + glyph.offset_x();
+        let glyph_top = baseline - glyph.offset_y() - glyph.height() as i32;
+        for row in 0..glyph.height() {
+            for column in 0..glyph.width() {
+                let (pixel_x, pixel_y) = (glyph_left + column as i32, glyph_top + row as i32);
+                if pixel_x < clip_left
+                    || pixel_x >= clip_right
+                    || pixel_y < clip_top
+                    || pixel_y >= clip_bottom
+                    || !glyph.is_set(column, row)
+                {
+                    continue;
+                }
+
+                pixels[(pixel_y * frame_width + pixel_x) as usize] = pixel_color;
+            }
+        }
+
+        cursor += glyph.advance();
+    }
+}
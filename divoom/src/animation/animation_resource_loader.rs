@@ -1,11 +1,30 @@
 use crate::{DivoomAPIError, DivoomAPIResult};
 use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
 use image::io::Reader as ImageReader;
 use image::AnimationDecoder;
 use std::fs::File;
 use std::io::{BufReader, Cursor, Read};
+use std::time::Duration;
 use tiny_skia::Pixmap;
 
+/// A series of frames with the delay of each frame, which is loaded from the animated resources.
+pub struct DivoomAnimationFrameSequence {
+    pub frames: Vec<Pixmap>,
+    pub delays: Vec<Duration>,
+
+    /// Number of times the animation is played, and 0 means it is looped forever.
+    pub loop_count: u16,
+}
+
+impl DivoomAnimationFrameSequence {
+    /// Total duration of playing all frames once.
+    pub fn duration(&self) -> Duration {
+        self.delays.iter().sum()
+    }
+}
+
 /// Load resources into a series of `tiny_skia::Pixmap`, so we can use them to build the animations.
 pub struct DivoomAnimationResourceLoader {}
 
@@ -53,10 +72,202 @@ impl DivoomAnimationResourceLoader {
 
     /// Load gif resource from Read trait
     pub fn from_gif<R: Read>(reader: R) -> DivoomAPIResult<Vec<Pixmap>> {
+        let decoder = GifDecoder::new(reader)?;
+        Ok(DivoomAnimationResourceLoader::from_animation_decoder(decoder, 0)?.frames)
+    }
+
+    /// Load gif resource from local file, with the delay of each frame and the loop count.
+    pub fn from_gif_sequence_file(
+        file_path: &str,
+    ) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let input = File::open(file_path)?;
+        DivoomAnimationResourceLoader::from_gif_sequence(input)
+    }
+
+    /// Load gif resource from Read trait, with the delay of each frame and the loop count.
+    pub fn from_gif_sequence<R: Read>(reader: R) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let buf = DivoomAnimationResourceLoader::read_all(reader)?;
+        DivoomAnimationResourceLoader::from_gif_sequence_buf(&buf)
+    }
+
+    /// Load gif resource from a memory buffer, with the delay of each frame and the loop count.
+    pub fn from_gif_sequence_buf(buf: &[u8]) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        // Loop count is saved in NETSCAPE2.0 application extension as the number of repeats, and GIF without it is only
+        // played once.
+        let loop_count = match gif::DecodeOptions::new()
+            .read_info(Cursor::new(buf))?
+            .repeat()
+        {
+            gif::Repeat::Infinite => 0,
+            gif::Repeat::Finite(repeat_count) => repeat_count.saturating_add(1),
+        };
+
+        let decoder = GifDecoder::new(Cursor::new(buf))?;
+        DivoomAnimationResourceLoader::from_animation_decoder(decoder, loop_count)
+    }
+
+    /// Load APNG from local file. Static PNG will be loaded as a single frame with zero delay.
+    pub fn from_apng_file(file_path: &str) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let input = File::open(file_path)?;
+        DivoomAnimationResourceLoader::from_apng(input)
+    }
+
+    /// Load APNG from Read trait
+    pub fn from_apng<R: Read>(reader: R) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let buf = DivoomAnimationResourceLoader::read_all(reader)?;
+        DivoomAnimationResourceLoader::from_apng_buf(&buf)
+    }
+
+    /// Load APNG from a memory buffer
+    pub fn from_apng_buf(buf: &[u8]) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let decoder = PngDecoder::new(Cursor::new(buf))?;
+        if !decoder.is_apng() {
+            return DivoomAnimationResourceLoader::from_static_image_buf(buf);
+        }
+
+        let loop_count = match png::Decoder::new(Cursor::new(buf))
+            .read_info()?
+            .info()
+            .animation_control()
+        {
+            Some(animation_control) => animation_control.num_plays.min(u16::MAX as u32) as u16,
+            None => 0,
+        };
+
+        DivoomAnimationResourceLoader::from_animation_decoder(decoder.apng(), loop_count)
+    }
+
+    /// Load animated WebP from local file. Static WebP will be loaded as a single frame with zero delay.
+    pub fn from_webp_file(file_path: &str) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let input = File::open(file_path)?;
+        DivoomAnimationResourceLoader::from_webp(input)
+    }
+
+    /// Load animated WebP from Read trait
+    pub fn from_webp<R: Read>(reader: R) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let buf = DivoomAnimationResourceLoader::read_all(reader)?;
+        DivoomAnimationResourceLoader::from_webp_buf(&buf)
+    }
+
+    /// Load animated WebP from a memory buffer
+    pub fn from_webp_buf(buf: &[u8]) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        // ANIM chunk: background color (u32) and loop count (u16) in little endian.
+        let loop_count = match find_webp_chunk_data(buf, b"ANIM") {
+            Some([_, _, _, _, low, high, ..]) => u16::from_le_bytes([*low, *high]),
+            _ => 0,
+        };
+
+        let decoder = WebPDecoder::new(Cursor::new(buf))?;
+        let sequence = DivoomAnimationResourceLoader::from_animation_decoder(decoder, loop_count)?;
+        if sequence.frames.is_empty() {
+            return DivoomAnimationResourceLoader::from_static_image_buf(buf);
+        }
+
+        Ok(sequence)
+    }
+
+    /// Load sprite sheet from local file. Please see `from_sprite_sheet_buf` for more details.
+    pub fn from_sprite_sheet_file(
+        file_path: &str,
+        frame_width: u32,
+        frame_height: u32,
+        frame_count: Option<usize>,
+    ) -> DivoomAPIResult<Vec<Pixmap>> {
+        let input = File::open(file_path)?;
+        DivoomAnimationResourceLoader::from_sprite_sheet(
+            input,
+            frame_width,
+            frame_height,
+            frame_count,
+        )
+    }
+
+    /// Load sprite sheet from Read trait. Please see `from_sprite_sheet_buf` for more details.
+    pub fn from_sprite_sheet<R: Read>(
+        reader: R,
+        frame_width: u32,
+        frame_height: u32,
+        frame_count: Option<usize>,
+    ) -> DivoomAPIResult<Vec<Pixmap>> {
+        let buf = DivoomAnimationResourceLoader::read_all(reader)?;
+        DivoomAnimationResourceLoader::from_sprite_sheet_buf(
+            &buf,
+            frame_width,
+            frame_height,
+            frame_count,
+        )
+    }
+
+    /// Load sprite sheet from a memory buffer.
+    ///
+    /// The sprite sheet is a grid of frames with the same size in one image, and the frames are sliced from left to right,
+    /// then top to bottom. The cells that don't fully fit in the image are ignored. When the frame count is specified, only
+    /// the first few frames will be returned, which helps skipping the empty cells at the end of the sheet.
+    pub fn from_sprite_sheet_buf(
+        buf: &[u8],
+        frame_width: u32,
+        frame_height: u32,
+        frame_count: Option<usize>,
+    ) -> DivoomAPIResult<Vec<Pixmap>> {
+        let sheet = DivoomAnimationResourceLoader::from_image_buf(buf)?;
+        if frame_width == 0
+            || frame_height == 0
+            || frame_width > sheet.width()
+            || frame_height > sheet.height()
+        {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid sprite sheet frame size: {}x{}. Frame must not be empty or larger than the sheet: {}x{}.",
+                frame_width,
+                frame_height,
+                sheet.width(),
+                sheet.height()
+            )));
+        }
+
+        let (column_count, row_count) =
+            (sheet.width() / frame_width, sheet.height() / frame_height);
+        let frame_count = frame_count
+            .unwrap_or(usize::MAX)
+            .min((column_count * row_count) as usize);
+
         let mut frames = vec![];
+        for index in 0..frame_count as u32 {
+            let (x, y) = (
+                (index % column_count) * frame_width,
+                (index / column_count) * frame_height,
+            );
+
+            let mut frame = Pixmap::new(frame_width, frame_height).unwrap();
+            let row_size = (frame_width * 4) as usize;
+            for row in 0..frame_height {
+                let sheet_offset = (((y + row) * sheet.width() + x) * 4) as usize;
+                let frame_offset = row as usize * row_size;
+                frame.data_mut()[frame_offset..frame_offset + row_size]
+                    .copy_from_slice(&sheet.data()[sheet_offset..sheet_offset + row_size]);
+            }
+
+            frames.push(frame);
+        }
+
+        Ok(frames)
+    }
+
+    fn from_animation_decoder<'a, D: AnimationDecoder<'a>>(
+        decoder: D,
+        loop_count: u16,
+    ) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let mut sequence = DivoomAnimationFrameSequence {
+            frames: vec![],
+            delays: vec![],
+            loop_count,
+        };
 
-        let decoder = GifDecoder::new(reader)?;
         for frame in decoder.into_frames().collect_frames()? {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            sequence.delays.push(Duration::from_micros(
+                numer as u64 * 1000 / denom.max(1) as u64,
+            ));
+
             let mut frame_pixmap = Pixmap::new(
                 frame.buffer().width() as u32,
                 frame.buffer().height() as u32,
@@ -66,15 +277,207 @@ impl DivoomAnimationResourceLoader {
             frame_pixmap
                 .data_mut()
                 .copy_from_slice(frame.into_buffer().as_raw());
-            frames.push(frame_pixmap);
+            sequence.frames.push(frame_pixmap);
         }
 
-        Ok(frames)
+        Ok(sequence)
+    }
+
+    fn from_static_image_buf(buf: &[u8]) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        Ok(DivoomAnimationFrameSequence {
+            frames: vec![DivoomAnimationResourceLoader::from_image_buf(buf)?],
+            delays: vec![Duration::ZERO],
+            loop_count: 1,
+        })
+    }
+
+    pub(crate) fn read_all<R: Read>(reader: R) -> DivoomAPIResult<Vec<u8>> {
+        let mut buf_reader = BufReader::new(reader);
+        let mut buf: Vec<u8> = Vec::new();
+        buf_reader.read_to_end(&mut buf)?;
+        Ok(buf)
     }
 }
 
+/// Find the chunk by its FourCC in the WebP RIFF container, and return its data.
+///
+/// Each chunk has a FourCC and data size (u32, little endian) before its data, and the data is padded to even size.
+fn find_webp_chunk_data<'a>(buf: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    if buf.get(0..4)? != b"RIFF" || buf.get(8..12)? != b"WEBP" {
+        return None;
+    }
+
+    let mut chunks = &buf[12..];
+    while chunks.len() >= 8 {
+        let data_size = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+        let data = chunks[8..].get(..data_size)?;
+        if &chunks[..4] == fourcc {
+            return Some(data);
+        }
+
+        chunks = chunks[8..]
+            .get(data_size + data_size % 2..)
+            .unwrap_or_default();
+    }
+
+    None
+}
+
 impl From<image::ImageError> for DivoomAPIError {
     fn from(err: image::ImageError) -> Self {
         DivoomAPIError::ResourceDecodeError(err.to_string())
     }
 }
+
+impl From<gif::DecodingError> for DivoomAPIError {
+    fn from(err: gif::DecodingError) -> Self {
+        DivoomAPIError::ResourceDecodeError(err.to_string())
+    }
+}
+
+impl From<png::DecodingError> for DivoomAPIError {
+    fn from(err: png::DecodingError) -> Self {
+        DivoomAPIError::ResourceDecodeError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_frame_color(frame: &Pixmap, expected: [u8; 4]) {
+        let pixel = frame.pixel(frame.width() - 1, frame.height() - 1).unwrap();
+        assert_eq!(
+            [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()],
+            expected
+        );
+    }
+
+    #[test]
+    fn resource_loader_can_load_apng_with_delays() {
+        let sequence = DivoomAnimationResourceLoader::from_apng_file(
+            "test_data/animation_builder_tests/input/animated.png",
+        )
+        .unwrap();
+        assert_eq!(sequence.frames.len(), 3);
+        assert_eq!(
+            sequence.delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(300)
+            ]
+        );
+        assert_eq!(sequence.loop_count, 0);
+        assert_frame_color(&sequence.frames[0], [255, 0, 0, 255]);
+        assert_frame_color(&sequence.frames[2], [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn resource_loader_can_load_gif_with_delays_and_loop_count() {
+        let sequence = DivoomAnimationResourceLoader::from_gif_sequence_file(
+            "test_data/animation_builder_tests/input/logo-16-rotate-4-frames.gif",
+        )
+        .unwrap();
+        assert_eq!(sequence.frames.len(), 4);
+        assert_eq!(sequence.delays, vec![Duration::from_millis(500); 4]);
+        assert_eq!(sequence.duration(), Duration::from_secs(2));
+        assert_eq!(sequence.loop_count, 0);
+
+        let sequence = DivoomAnimationResourceLoader::from_gif_sequence_file(
+            "test_data/animation_builder_tests/input/logo-16-0.gif",
+        )
+        .unwrap();
+        assert_eq!(sequence.delays, vec![Duration::from_millis(200)]);
+        assert_eq!(sequence.loop_count, 1);
+    }
+
+    #[test]
+    fn resource_loader_should_read_gif_loop_count_from_netscape_extension_only() {
+        let encode_gif = |repeat: Option<gif::Repeat>| {
+            let mut buf = vec![];
+            let mut encoder = gif::Encoder::new(&mut buf, 1, 1, &[0, 0, 0, 255, 255, 255]).unwrap();
+            encoder
+                .write_raw_extension(
+                    gif::Extension::Comment.into(),
+                    &[b"NETSCAPE2.0\x03\x01\x05\x00"],
+                )
+                .unwrap();
+            if let Some(repeat) = repeat {
+                encoder.set_repeat(repeat).unwrap();
+            }
+            encoder
+                .write_frame(&gif::Frame::from_indexed_pixels(1, 1, vec![1], None))
+                .unwrap();
+            drop(encoder);
+            buf
+        };
+
+        for (repeat, expected_loop_count) in [
+            (None, 1),
+            (Some(gif::Repeat::Finite(2)), 3),
+            (Some(gif::Repeat::Infinite), 0),
+        ] {
+            let sequence =
+                DivoomAnimationResourceLoader::from_gif_sequence_buf(&encode_gif(repeat)).unwrap();
+            assert_eq!(sequence.loop_count, expected_loop_count);
+        }
+    }
+
+    #[test]
+    fn resource_loader_can_load_animated_webp_with_delays() {
+        let sequence = DivoomAnimationResourceLoader::from_webp_file(
+            "test_data/animation_builder_tests/input/animated.webp",
+        )
+        .unwrap();
+        assert_eq!(sequence.frames.len(), 3);
+        assert_eq!(sequence.delays[1], Duration::from_millis(200));
+        assert_eq!(sequence.loop_count, 0);
+        assert_frame_color(&sequence.frames[1], [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn resource_loader_should_find_webp_chunk_by_walking_chunks() {
+        let mut buf = b"RIFF\0\0\0\0WEBP".to_vec();
+        buf.extend_from_slice(b"EXIF\x07\0\0\0ANIM\x01\x02\x03\0");
+        buf.extend_from_slice(b"ANIM\x06\0\0\0\0\0\0\0\x05\0");
+        assert_eq!(
+            find_webp_chunk_data(&buf, b"ANIM"),
+            Some(&[0, 0, 0, 0, 5, 0][..])
+        );
+        assert_eq!(find_webp_chunk_data(&buf, b"VP8X"), None);
+        assert_eq!(find_webp_chunk_data(&buf[12..], b"ANIM"), None);
+    }
+
+    #[test]
+    fn resource_loader_can_load_static_png_as_single_frame() {
+        let sequence = DivoomAnimationResourceLoader::from_apng_file(
+            "test_data/animation_builder_tests/input/logo.png",
+        )
+        .unwrap();
+        assert_eq!(sequence.frames.len(), 1);
+        assert_eq!(sequence.delays, vec![Duration::ZERO]);
+    }
+
+    #[test]
+    fn resource_loader_can_slice_sprite_sheet() {
+        let file_path = "test_data/animation_builder_tests/input/sprite_sheet.png";
+        let frames =
+            DivoomAnimationResourceLoader::from_sprite_sheet_file(file_path, 8, 8, None).unwrap();
+        assert_eq!(frames.len(), 6);
+        assert_frame_color(&frames[1], [0, 255, 0, 255]);
+        assert_frame_color(&frames[3], [255, 255, 0, 255]);
+        assert_frame_color(&frames[5], [0, 0, 0, 0]);
+
+        let frames =
+            DivoomAnimationResourceLoader::from_sprite_sheet_file(file_path, 8, 8, Some(5))
+                .unwrap();
+        assert_eq!(frames.len(), 5);
+        assert_frame_color(&frames[4], [0, 255, 255, 255]);
+
+        assert!(matches!(
+            DivoomAnimationResourceLoader::from_sprite_sheet_file(file_path, 32, 8, None),
+            Err(DivoomAPIError::ParameterError(_))
+        ));
+    }
+}
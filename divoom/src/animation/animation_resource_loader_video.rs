@@ -0,0 +1,217 @@
+use crate::animation::animation_resource_loader::*;
+use crate::{DivoomAPIError, DivoomAPIResult};
+use mp4::{MediaType, Mp4Reader, Mp4Track};
+use openh264::decoder::Decoder;
+use openh264::formats::YUVSource;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::time::Duration;
+use tiny_skia::Pixmap;
+
+// Video loading functions
+//
+// Only H.264 video in MP4 container is supported for now, and it is decoded by the bundled OpenH264 decoder, so no
+// external tool or library is needed.
+impl DivoomAnimationResourceLoader {
+    /// Load frames from video file. Please see `from_video_buf` for more details.
+    pub fn from_video_file(
+        file_path: &str,
+        frame_interval: Duration,
+        max_frame_count: usize,
+    ) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let input = File::open(file_path)?;
+        DivoomAnimationResourceLoader::from_video(input, frame_interval, max_frame_count)
+    }
+
+    /// Load frames from video in Read trait. Please see `from_video_buf` for more details.
+    pub fn from_video<R: Read>(
+        reader: R,
+        frame_interval: Duration,
+        max_frame_count: usize,
+    ) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let buf = DivoomAnimationResourceLoader::read_all(reader)?;
+        DivoomAnimationResourceLoader::from_video_buf(&buf, frame_interval, max_frame_count)
+    }
+
+    /// Load frames from video in a memory buffer.
+    ///
+    /// Videos usually have a lot more frames than the device can play, so we only pick one frame in every frame interval,
+    /// and stop after the max frame count is reached. The delay of every frame is the frame interval.
+    pub fn from_video_buf(
+        buf: &[u8],
+        frame_interval: Duration,
+        max_frame_count: usize,
+    ) -> DivoomAPIResult<DivoomAnimationFrameSequence> {
+        let mut reader = Mp4Reader::read_header(Cursor::new(buf), buf.len() as u64)?;
+        let track = reader
+            .tracks()
+            .values()
+            .find(|x| matches!(x.media_type(), Ok(MediaType::H264)))
+            .ok_or_else(|| {
+                DivoomAPIError::ResourceDecodeError(
+                    "No H.264 video track is found in the video.".into(),
+                )
+            })?;
+
+        let (track_id, timescale) = (track.track_id(), track.timescale().max(1) as u128);
+        let parameter_sets = build_parameter_sets(track)?;
+        let length_size = nal_length_size(track);
+
+        let mut decoder = Decoder::new()?;
+        let mut sequence = DivoomAnimationFrameSequence {
+            frames: vec![],
+            delays: vec![],
+            loop_count: 1,
+        };
+
+        let mut packet = vec![];
+        let mut next_frame_time = Duration::ZERO;
+        for sample_id in 1..=reader.sample_count(track_id)? {
+            if sequence.frames.len() >= max_frame_count {
+                break;
+            }
+
+            let sample = match reader.read_sample(track_id, sample_id)? {
+                None => continue,
+                Some(v) => v,
+            };
+
+            // MP4 stores the NAL units with length prefix, and parameter sets in the track header, but the decoder expects
+            // Annex B stream with start codes, so we need to convert it and insert the parameter sets before key frames.
+            packet.clear();
+            if sample.is_sync {
+                packet.extend_from_slice(&parameter_sets);
+            }
+            convert_to_annex_b(&sample.bytes, length_size, &mut packet)?;
+
+            let image = match decoder.decode(&packet)? {
+                None => continue,
+                Some(v) => v,
+            };
+
+            let sample_time = Duration::from_nanos(
+                (sample.start_time as u128 * 1_000_000_000 / timescale) as u64,
+            );
+            if sample_time < next_frame_time {
+                continue;
+            }
+
+            let (width, height) = image.dimensions();
+            let mut frame = Pixmap::new(width as u32, height as u32).unwrap();
+            image.write_rgba8(frame.data_mut());
+            sequence.frames.push(frame);
+            sequence.delays.push(frame_interval);
+
+            next_frame_time = sample_time + frame_interval;
+        }
+
+        Ok(sequence)
+    }
+}
+
+fn build_parameter_sets(track: &Mp4Track) -> DivoomAPIResult<Vec<u8>> {
+    let mut parameter_sets = vec![];
+    for parameter_set in [
+        track.sequence_parameter_set()?,
+        track.picture_parameter_set()?,
+    ] {
+        parameter_sets.extend_from_slice(&[0, 0, 0, 1]);
+        parameter_sets.extend_from_slice(parameter_set);
+    }
+
+    Ok(parameter_sets)
+}
+
+fn nal_length_size(track: &Mp4Track) -> usize {
+    match &track.trak.mdia.minf.stbl.stsd.avc1 {
+        Some(avc1) => avc1.avcc.length_size_minus_one as usize + 1,
+        None => 4,
+    }
+}
+
+fn convert_to_annex_b(
+    sample: &[u8],
+    length_size: usize,
+    packet: &mut Vec<u8>,
+) -> DivoomAPIResult<()> {
+    let mut remaining = sample;
+    while !remaining.is_empty() {
+        if remaining.len() < length_size {
+            return Err(DivoomAPIError::ResourceDecodeError(
+                "Invalid H.264 sample: NAL unit length is truncated.".into(),
+            ));
+        }
+
+        let nal_size = remaining[..length_size]
+            .iter()
+            .fold(0usize, |size, x| (size << 8) | *x as usize);
+        remaining = &remaining[length_size..];
+        if remaining.len() < nal_size {
+            return Err(DivoomAPIError::ResourceDecodeError(
+                "Invalid H.264 sample: NAL unit is truncated.".into(),
+            ));
+        }
+
+        packet.extend_from_slice(&[0, 0, 0, 1]);
+        packet.extend_from_slice(&remaining[..nal_size]);
+        remaining = &remaining[nal_size..];
+    }
+
+    Ok(())
+}
+
+impl From<mp4::Error> for DivoomAPIError {
+    fn from(err: mp4::Error) -> Self {
+        DivoomAPIError::ResourceDecodeError(err.to_string())
+    }
+}
+
+impl From<openh264::Error> for DivoomAPIError {
+    fn from(err: openh264::Error) -> Self {
+        DivoomAPIError::ResourceDecodeError(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_loader_can_load_video_frames() {
+        let file_path = "test_data/animation_builder_tests/input/video.mp4";
+        let sequence =
+            DivoomAnimationResourceLoader::from_video_file(file_path, Duration::ZERO, usize::MAX)
+                .unwrap();
+        assert_eq!(sequence.frames.len(), 4);
+        assert_eq!(sequence.frames[0].width(), 32);
+
+        // Video is lossy, so we only check the main color of each frame.
+        let expected_colors = [
+            [true, false, false],
+            [false, true, false],
+            [false, false, true],
+        ];
+        for (frame, expected_color) in sequence.frames.iter().zip(expected_colors) {
+            let pixel = frame.pixel(16, 16).unwrap();
+            let color = [pixel.red() > 128, pixel.green() > 128, pixel.blue() > 128];
+            assert_eq!(color, expected_color);
+        }
+    }
+
+    #[test]
+    fn resource_loader_can_sample_video_frames() {
+        let file_path = "test_data/animation_builder_tests/input/video.mp4";
+        let sequence = DivoomAnimationResourceLoader::from_video_file(
+            file_path,
+            Duration::from_millis(500),
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(sequence.frames.len(), 2);
+        assert_eq!(sequence.delays, vec![Duration::from_millis(500); 2]);
+
+        let sequence =
+            DivoomAnimationResourceLoader::from_video_file(file_path, Duration::ZERO, 3).unwrap();
+        assert_eq!(sequence.frames.len(), 3);
+    }
+}
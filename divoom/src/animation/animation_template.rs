@@ -1,5 +1,8 @@
-use crate::{DivoomAPIError, DivoomAPIResult};
+use crate::animation::animation_template_jinja::new_jinja_environment;
+use crate::animation::animation_template_parameter::*;
+use crate::{DivoomAPIError, DivoomAPIResult, DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT};
 use lazy_static::lazy_static;
+use minijinja::{Environment, Value};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -10,6 +13,31 @@ lazy_static! {
     static ref TEMPLATE_PARAM_REGEX: Regex = Regex::new(r"\{(\w+)\}").unwrap();
 }
 
+/// Names of the frame variables, which cannot be used as template parameter names.
+pub(crate) const DIVOOM_ANIMATION_FRAME_VARIABLE_NAMES: [&str; 4] = [
+    "frame_index",
+    "frame_count",
+    "frame_progress",
+    "frame_time_in_ms",
+];
+
+/// Name of the Jinja template that evaluates the frame count expression. It is not a valid file path, so it never
+/// conflicts with the frame templates.
+const FRAME_COUNT_TEMPLATE_NAME: &str = "<frame-count>";
+
+/// Engines for evaluating the SVG frames in animation templates.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivoomAnimationTemplateEngine {
+    /// Replace every `{name}` in the SVG with the parameter value.
+    #[default]
+    Simple,
+
+    /// Evaluate the SVG with Jinja template engine, which supports default values, conditionals, loops, filters and
+    /// arithmetic.
+    Jinja,
+}
+
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DivoomAnimationTemplateConfig {
@@ -21,6 +49,71 @@ pub struct DivoomAnimationTemplateConfig {
 
     /// SVG file paths that used to describe each animation frame
     pub frames: Vec<String>,
+
+    /// Expression for generating frames from a single SVG file, e.g. `"12"` or `"seconds | int"`. When set, `frames`
+    /// must contain exactly one SVG file, which is evaluated once per generated frame.
+    ///
+    /// The expression is evaluated in Jinja syntax with the template parameters, regardless of the template engine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_count: Option<String>,
+
+    /// Template engine for evaluating the SVG frames
+    #[serde(default)]
+    pub engine: DivoomAnimationTemplateEngine,
+
+    /// Parameters that the template accepts. They are validated before rendering.
+    #[serde(default)]
+    pub parameters: Vec<DivoomAnimationTemplateParameterConfig>,
+}
+
+/// Information of loaded animation template, which helps callers to find out what parameters to pass in.
+#[derive(Debug, PartialOrd, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DivoomAnimationTemplateInfo {
+    pub name: String,
+    pub canvas_size: u32,
+    pub speed_in_ms: u64,
+    pub frame_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_count_expression: Option<String>,
+    pub parameters: Vec<DivoomAnimationTemplateParameterConfig>,
+}
+
+/// Variables that are passed into every frame, besides the template parameters.
+///
+/// - `frame_index`: Index of the frame, starting from 0.
+/// - `frame_count`: Total number of frames.
+/// - `frame_progress`: `frame_index / frame_count`, which goes from 0 to 1 (exclusive), so looping animations like
+///   spinners don't repeat the first frame at the end.
+/// - `frame_time_in_ms`: Time offset of the frame from the start of the animation.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub(crate) struct DivoomAnimationFrameVariables {
+    pub frame_index: usize,
+    pub frame_count: usize,
+    pub frame_progress: f64,
+    pub frame_time_in_ms: u64,
+}
+
+impl DivoomAnimationFrameVariables {
+    pub fn new(frame_index: usize, frame_count: usize, speed: Duration) -> Self {
+        DivoomAnimationFrameVariables {
+            frame_index,
+            frame_count,
+            frame_progress: frame_index as f64 / frame_count as f64,
+            frame_time_in_ms: frame_index as u64 * speed.as_millis() as u64,
+        }
+    }
+
+    pub fn to_values(self) -> [(&'static str, Value); 4] {
+        let [frame_index, frame_count, frame_progress, frame_time_in_ms] =
+            DIVOOM_ANIMATION_FRAME_VARIABLE_NAMES;
+        [
+            (frame_index, Value::from(self.frame_index)),
+            (frame_count, Value::from(self.frame_count)),
+            (frame_progress, Value::from(self.frame_progress)),
+            (frame_time_in_ms, Value::from(self.frame_time_in_ms)),
+        ]
+    }
 }
 
 pub(crate) struct DivoomAnimationTemplate {
@@ -28,6 +121,10 @@ pub(crate) struct DivoomAnimationTemplate {
     canvas_size: u32,
     speed: Duration,
     frames: Vec<DivoomAnimationFrameTemplate>,
+    frame_count_expression: Option<String>,
+    engine: DivoomAnimationTemplateEngine,
+    jinja_env: Option<Environment<'static>>,
+    parameters: Vec<DivoomAnimationTemplateParameterConfig>,
 }
 
 pub(crate) struct DivoomEvaluatedAnimationTemplate {
@@ -48,11 +145,73 @@ impl DivoomAnimationTemplate {
             .map(|v| DivoomAnimationFrameTemplate::from_file(format!("{}/{}", resource_dir, v)))
             .collect();
 
+        if let Some(parameter) = config
+            .parameters
+            .iter()
+            .find(|x| DIVOOM_ANIMATION_FRAME_VARIABLE_NAMES.contains(&x.name.as_str()))
+        {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Template parameter name is reserved for frame variables: Name = {}, Parameter = {}",
+                name, parameter.name
+            )));
+        }
+
+        // Default values are used without validation when rendering, so they are validated here to fail the template
+        // loading instead.
+        for declaration in &config.parameters {
+            if let Some(default) = &declaration.default {
+                declaration.validate(default).map_err(|e| match e {
+                    DivoomAPIError::ParameterError(message) => DivoomAPIError::ParameterError(
+                        format!("Invalid default value in template {}: {}", name, message),
+                    ),
+                    e => e,
+                })?;
+            }
+        }
+
+        let frames = parsed_templates_result?;
+        if config.frame_count.is_some() && frames.len() != 1 {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Template with frame count expression must have exactly 1 frame: Name = {}, Frame count = {}",
+                name,
+                frames.len()
+            )));
+        }
+
+        // Jinja templates and frame count expression are parsed when loading, so syntax errors can be found as early as
+        // possible. The expression is kept as a template in the environment, so it is compiled only once.
+        let jinja_env = match (config.engine, &config.frame_count) {
+            (DivoomAnimationTemplateEngine::Simple, None) => None,
+            _ => {
+                let mut env = new_jinja_environment();
+                if let Some(frame_count) = &config.frame_count {
+                    env.compile_expression(frame_count)?;
+                    env.add_template_owned(
+                        FRAME_COUNT_TEMPLATE_NAME,
+                        format!("{{% set frame_count = ({}) %}}", frame_count),
+                    )?;
+                }
+                if config.engine == DivoomAnimationTemplateEngine::Jinja {
+                    for frame in &frames {
+                        env.add_template_owned(
+                            frame.file_path.clone(),
+                            frame.file_content.clone(),
+                        )?;
+                    }
+                }
+                Some(env)
+            }
+        };
+
         Ok(DivoomAnimationTemplate {
             name,
             canvas_size: config.canvas_size,
             speed: Duration::from_millis(config.speed_in_ms),
-            frames: parsed_templates_result?,
+            frames,
+            frame_count_expression: config.frame_count.clone(),
+            engine: config.engine,
+            jinja_env,
+            parameters: config.parameters.clone(),
         })
     }
 
@@ -60,16 +219,44 @@ impl DivoomAnimationTemplate {
         &self.name
     }
 
+    pub fn info(&self) -> DivoomAnimationTemplateInfo {
+        DivoomAnimationTemplateInfo {
+            name: self.name.clone(),
+            canvas_size: self.canvas_size,
+            speed_in_ms: self.speed.as_millis() as u64,
+            frame_count: self.frames.len(),
+            frame_count_expression: self.frame_count_expression.clone(),
+            parameters: self.parameters.clone(),
+        }
+    }
+
     pub fn eval(
         &self,
         parameters: &HashMap<String, String>,
         per_frame_parameters: &HashMap<usize, HashMap<String, String>>,
     ) -> DivoomAPIResult<DivoomEvaluatedAnimationTemplate> {
-        let evaled_template_result: DivoomAPIResult<Vec<String>> =
-            self.frames.iter().enumerate().map(|(index, f)| {
+        let parameters =
+            resolve_template_parameters(&self.parameters, parameters, per_frame_parameters)?;
+        let frame_count = match &self.frame_count_expression {
+            None => self.frames.len(),
+            Some(expression) => self.eval_frame_count(expression, &parameters)?,
+        };
+
+        let evaled_template_result: DivoomAPIResult<Vec<String>> = (0..frame_count)
+            .map(|index| {
+                // Generated frames always come from the only SVG file.
+                let f = self.frames.get(index).unwrap_or(&self.frames[0]);
+                let frame_variables =
+                    DivoomAnimationFrameVariables::new(index, frame_count, self.speed);
                 let frame_only_parameters = per_frame_parameters.get(&index);
-                f.eval(parameters, frame_only_parameters)
-            }).collect();
+                match (self.engine, &self.jinja_env) {
+                    (DivoomAnimationTemplateEngine::Jinja, Some(env)) => {
+                        f.eval_jinja(env, &parameters, frame_only_parameters, frame_variables)
+                    }
+                    _ => f.eval(&parameters, frame_only_parameters, frame_variables),
+                }
+            })
+            .collect();
 
         Ok(DivoomEvaluatedAnimationTemplate {
             canvas_size: self.canvas_size,
@@ -77,6 +264,34 @@ impl DivoomAnimationTemplate {
             frames: evaled_template_result?,
         })
     }
+
+    fn eval_frame_count(
+        &self,
+        expression: &str,
+        parameters: &HashMap<String, String>,
+    ) -> DivoomAPIResult<usize> {
+        let env = self.jinja_env.as_ref().unwrap();
+        let value = env
+            .get_template(FRAME_COUNT_TEMPLATE_NAME)?
+            .render_captured(parameters)?
+            .state()
+            .lookup("frame_count")
+            .unwrap_or_default();
+
+        // Parameters are passed in as strings, so we also accept strings like "12" here.
+        let frame_count = match value.as_str() {
+            Some(text) => text.trim().parse::<usize>().ok(),
+            None => usize::try_from(value.clone()).ok(),
+        };
+
+        match frame_count {
+            Some(v) if (1..=DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT).contains(&v) => Ok(v),
+            _ => Err(DivoomAPIError::ParameterError(format!(
+                "Invalid frame count: Template = {}, Expression = {}, Value = {}, Expected range = 1..={}",
+                self.name, expression, value, DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT
+            ))),
+        }
+    }
 }
 
 pub(crate) struct DivoomAnimationFrameTemplate {
@@ -106,14 +321,40 @@ impl DivoomAnimationFrameTemplate {
     }
 
     #[allow(dead_code)]
-    pub fn file_path(&self) -> &str { self.file_path.as_ref() }
+    pub fn file_path(&self) -> &str {
+        self.file_path.as_ref()
+    }
 
-    pub fn eval(&self, parameters: &HashMap<String, String>, frame_only_parameters: Option<&HashMap<String, String>>) -> DivoomAPIResult<String> {
+    pub fn eval(
+        &self,
+        parameters: &HashMap<String, String>,
+        frame_only_parameters: Option<&HashMap<String, String>>,
+        frame_variables: DivoomAnimationFrameVariables,
+    ) -> DivoomAPIResult<String> {
         let mut evaled_file_content = self.file_content.clone();
 
+        // Frame variables are passed in as strings like other parameters.
+        let mut frame_only_parameters_with_variables: HashMap<String, String> = frame_variables
+            .to_values()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        if let Some(frame_only_parameters) = frame_only_parameters {
+            frame_only_parameters_with_variables.extend(
+                frame_only_parameters
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+        }
+        let frame_only_parameters = Some(&frame_only_parameters_with_variables);
+
         for template_parameter_definition in &self.param_name_to_pattern_map {
             if frame_only_parameters.is_some() {
-                if let Some(parameter) = frame_only_parameters.as_ref().unwrap().get(template_parameter_definition.0) {
+                if let Some(parameter) = frame_only_parameters
+                    .as_ref()
+                    .unwrap()
+                    .get(template_parameter_definition.0)
+                {
                     evaled_file_content =
                         evaled_file_content.replace(template_parameter_definition.1, parameter);
 
@@ -127,7 +368,7 @@ impl DivoomAnimationFrameTemplate {
                         "Missing template parameter: {}",
                         template_parameter_definition.0
                     )));
-                },
+                }
 
                 Some(parameter) => {
                     evaled_file_content =
@@ -138,4 +379,31 @@ impl DivoomAnimationFrameTemplate {
 
         Ok(evaled_file_content)
     }
+
+    pub fn eval_jinja(
+        &self,
+        env: &Environment<'static>,
+        parameters: &HashMap<String, String>,
+        frame_only_parameters: Option<&HashMap<String, String>>,
+        frame_variables: DivoomAnimationFrameVariables,
+    ) -> DivoomAPIResult<String> {
+        // Unlike parameters, frame variables are passed in as numbers, so they can be used in math directly.
+        let mut merged_parameters: HashMap<&str, Value> = parameters
+            .iter()
+            .map(|(k, v)| (k.as_str(), Value::from(v.as_str())))
+            .collect();
+        merged_parameters.extend(frame_variables.to_values());
+        if let Some(frame_only_parameters) = frame_only_parameters {
+            merged_parameters.extend(
+                frame_only_parameters
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), Value::from(v.as_str()))),
+            );
+        }
+
+        let evaled_file_content = env
+            .get_template(&self.file_path)?
+            .render(merged_parameters)?;
+        Ok(evaled_file_content)
+    }
 }
@@ -0,0 +1,330 @@
+use crate::animation::animation_template::DivoomEvaluatedAnimationTemplate;
+use crate::{DivoomAPIResult, DivoomImageAnimation};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+/// Options of the rendered animation cache in template manager.
+#[derive(Debug, PartialOrd, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DivoomAnimationTemplateCacheOptions {
+    /// Max number of rendered animations kept in memory. The least recently used ones are evicted first.
+    pub capacity: usize,
+
+    /// Folder to persist the rendered animations, so they can be reused after restart. Disk cache is disabled when not set.
+    pub disk_cache_dir: Option<String>,
+}
+
+impl Default for DivoomAnimationTemplateCacheOptions {
+    fn default() -> Self {
+        DivoomAnimationTemplateCacheOptions {
+            capacity: 64,
+            disk_cache_dir: None,
+        }
+    }
+}
+
+/// Statistics of the rendered animation cache.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DivoomAnimationTemplateCacheStats {
+    /// Number of renders served from memory.
+    pub memory_hit_count: u64,
+
+    /// Number of renders served from disk.
+    pub disk_hit_count: u64,
+
+    /// Number of renders that are not found in cache and rendered from scratch.
+    pub miss_count: u64,
+
+    /// Number of animations in memory.
+    pub entry_count: usize,
+
+    /// Max number of animations in memory.
+    pub capacity: usize,
+}
+
+impl DivoomAnimationTemplateCacheStats {
+    /// Ratio of the renders served from cache, either memory or disk. 0 when nothing is rendered yet.
+    pub fn hit_rate(&self) -> f64 {
+        let hit_count = self.memory_hit_count + self.disk_hit_count;
+        let total_count = hit_count + self.miss_count;
+        if total_count == 0 {
+            return 0.0;
+        }
+
+        hit_count as f64 / total_count as f64
+    }
+}
+
+/// Content-addressed cache of rendered animations.
+///
+/// The cache key is the hash of the template name and the evaluated template, which already has all parameters filled,
+/// so the same template rendered with the same parameters always hits the same entry. Please note that the resources
+/// referenced by the SVG, e.g. images, are not part of the key, so the cache needs to be cleared when they are changed.
+pub(crate) struct DivoomAnimationTemplateCache {
+    options: DivoomAnimationTemplateCacheOptions,
+    entries: HashMap<String, (u64, DivoomImageAnimation)>,
+    lru: BTreeMap<u64, String>,
+    last_used_tick: u64,
+    stats: DivoomAnimationTemplateCacheStats,
+}
+
+impl DivoomAnimationTemplateCache {
+    pub fn new(options: DivoomAnimationTemplateCacheOptions) -> DivoomAPIResult<Self> {
+        if let Some(disk_cache_dir) = &options.disk_cache_dir {
+            fs::create_dir_all(disk_cache_dir)?;
+        }
+
+        Ok(DivoomAnimationTemplateCache {
+            stats: DivoomAnimationTemplateCacheStats {
+                capacity: options.capacity,
+                ..Default::default()
+            },
+            options,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            last_used_tick: 0,
+        })
+    }
+
+    pub fn cache_key(template_name: &str, template: &DivoomEvaluatedAnimationTemplate) -> String {
+        // The key is also used as file name in disk cache, so we need a hash that is stable across builds, which the
+        // default hasher doesn't guarantee. Hence we use 128-bit FNV-1a here. Every part is prefixed with its length, so
+        // different parts cannot be mixed up.
+        let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+        let mut hash_bytes = |bytes: &[u8]| {
+            for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+                hash ^= *byte as u128;
+                hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+            }
+        };
+
+        hash_bytes(template_name.as_bytes());
+        hash_bytes(&template.canvas_size.to_le_bytes());
+        hash_bytes(&(template.speed.as_millis() as u64).to_le_bytes());
+        for frame in &template.frames {
+            hash_bytes(frame.as_bytes());
+        }
+
+        format!("{:032x}", hash)
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<DivoomImageAnimation> {
+        if let Some((last_used_tick, animation)) = self.entries.get_mut(key) {
+            self.last_used_tick += 1;
+            self.lru.remove(last_used_tick);
+            self.lru.insert(self.last_used_tick, key.to_string());
+            *last_used_tick = self.last_used_tick;
+
+            self.stats.memory_hit_count += 1;
+            return Some(animation.clone());
+        }
+
+        if let Some(animation) = self.load_from_disk(key) {
+            self.stats.disk_hit_count += 1;
+            self.insert_to_memory(key, animation.clone());
+            return Some(animation);
+        }
+
+        self.stats.miss_count += 1;
+        None
+    }
+
+    pub fn insert(&mut self, key: &str, animation: &DivoomImageAnimation) {
+        self.save_to_disk(key, animation);
+        self.insert_to_memory(key, animation.clone());
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+
+        if let Some(disk_cache_dir) = &self.options.disk_cache_dir {
+            if let Err(e) =
+                fs::remove_dir_all(disk_cache_dir).and_then(|_| fs::create_dir_all(disk_cache_dir))
+            {
+                warn!(
+                    "Failed to clear animation disk cache: Dir = {}, Error = {:?}",
+                    disk_cache_dir, e
+                );
+            }
+        }
+    }
+
+    pub fn stats(&self) -> DivoomAnimationTemplateCacheStats {
+        DivoomAnimationTemplateCacheStats {
+            entry_count: self.entries.len(),
+            ..self.stats
+        }
+    }
+
+    fn insert_to_memory(&mut self, key: &str, animation: DivoomImageAnimation) {
+        if self.options.capacity == 0 {
+            return;
+        }
+
+        self.last_used_tick += 1;
+        if let Some((last_used_tick, _)) = self
+            .entries
+            .insert(key.to_string(), (self.last_used_tick, animation))
+        {
+            self.lru.remove(&last_used_tick);
+        }
+        self.lru.insert(self.last_used_tick, key.to_string());
+
+        while self.entries.len() > self.options.capacity {
+            let (_, evicted_key) = self.lru.pop_first().unwrap();
+            debug!(
+                "Evicting rendered animation from cache: Key = {}",
+                evicted_key
+            );
+            self.entries.remove(&evicted_key);
+        }
+    }
+
+    fn disk_cache_file_path(&self, key: &str) -> Option<PathBuf> {
+        self.options
+            .disk_cache_dir
+            .as_ref()
+            .map(|disk_cache_dir| PathBuf::from(disk_cache_dir).join(format!("{}.json", key)))
+    }
+
+    fn load_from_disk(&self, key: &str) -> Option<DivoomImageAnimation> {
+        let file_path = self.disk_cache_file_path(key)?;
+        if !file_path.is_file() {
+            return None;
+        }
+
+        // Disk cache is only an optimization, so broken cache files are treated as cache miss and will be overwritten.
+        match fs::read(&file_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| serde_json::from_slice(&content).map_err(anyhow::Error::from))
+        {
+            Ok(animation) => Some(animation),
+            Err(e) => {
+                warn!(
+                    "Failed to load rendered animation from disk cache: Path = {:?}, Error = {:?}",
+                    file_path, e
+                );
+                None
+            }
+        }
+    }
+
+    fn save_to_disk(&self, key: &str, animation: &DivoomImageAnimation) {
+        let file_path = match self.disk_cache_file_path(key) {
+            None => return,
+            Some(v) => v,
+        };
+
+        if let Err(e) = serde_json::to_vec(animation)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| fs::write(&file_path, content).map_err(anyhow::Error::from))
+        {
+            warn!(
+                "Failed to save rendered animation to disk cache: Path = {:?}, Error = {:?}",
+                file_path, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time::Duration;
+
+    fn new_test_template(text: &str) -> DivoomEvaluatedAnimationTemplate {
+        DivoomEvaluatedAnimationTemplate {
+            canvas_size: 16,
+            speed: Duration::from_millis(100),
+            frames: vec![text.to_string()],
+        }
+    }
+
+    fn new_test_animation(frame_count: usize) -> DivoomImageAnimation {
+        DivoomImageAnimation {
+            size: 16,
+            frame_count,
+            speed_in_ms: 100,
+            frames: (0..frame_count as u32)
+                .map(|x| (x, vec![0; 16 * 16 * 3]))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn animation_template_cache_key_should_be_stable() {
+        let key = DivoomAnimationTemplateCache::cache_key("foo", &new_test_template("bar"));
+        assert_eq!(
+            key,
+            DivoomAnimationTemplateCache::cache_key("foo", &new_test_template("bar"))
+        );
+        assert_ne!(
+            key,
+            DivoomAnimationTemplateCache::cache_key("foo", &new_test_template("baz"))
+        );
+        assert_ne!(
+            key,
+            DivoomAnimationTemplateCache::cache_key("foob", &new_test_template("ar"))
+        );
+    }
+
+    #[test]
+    fn animation_template_cache_should_evict_least_recently_used_entries() {
+        let mut cache = DivoomAnimationTemplateCache::new(DivoomAnimationTemplateCacheOptions {
+            capacity: 2,
+            disk_cache_dir: None,
+        })
+        .unwrap();
+
+        cache.insert("a", &new_test_animation(1));
+        cache.insert("b", &new_test_animation(2));
+        assert_eq!(cache.get("a").unwrap().frame_count, 1);
+
+        cache.insert("c", &new_test_animation(3));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a").unwrap().frame_count, 1);
+        assert_eq!(cache.get("c").unwrap().frame_count, 3);
+
+        let stats = cache.stats();
+        assert_eq!(
+            stats,
+            DivoomAnimationTemplateCacheStats {
+                memory_hit_count: 3,
+                disk_hit_count: 0,
+                miss_count: 1,
+                entry_count: 2,
+                capacity: 2,
+            }
+        );
+        assert_eq!(stats.hit_rate(), 0.75);
+    }
+
+    #[test]
+    fn animation_template_cache_can_load_entries_from_disk() {
+        let disk_cache_dir = env::temp_dir().join("divoom_animation_template_cache_tests");
+        let options = DivoomAnimationTemplateCacheOptions {
+            capacity: 1,
+            disk_cache_dir: Some(disk_cache_dir.to_string_lossy().to_string()),
+        };
+
+        let mut cache = DivoomAnimationTemplateCache::new(options.clone()).unwrap();
+        cache.clear();
+        cache.insert("a", &new_test_animation(1));
+        cache.insert("b", &new_test_animation(2));
+
+        let mut cache = DivoomAnimationTemplateCache::new(options).unwrap();
+        assert_eq!(cache.get("a").unwrap(), new_test_animation(1));
+        assert_eq!(cache.get("a").unwrap(), new_test_animation(1));
+        assert_eq!(cache.stats().disk_hit_count, 1);
+        assert_eq!(cache.stats().memory_hit_count, 1);
+
+        cache.clear();
+        assert!(cache.get("b").is_none());
+    }
+}
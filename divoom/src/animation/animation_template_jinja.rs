@@ -0,0 +1,160 @@
+use crate::DivoomAPIError;
+use chrono::{DateTime, Local, TimeZone};
+use minijinja::{AutoEscape, Environment, Error, ErrorKind, UndefinedBehavior, Value};
+
+/// Create the environment for rendering SVG frames with Jinja template engine.
+///
+/// Besides the builtin filters and functions in minijinja, we also provide a few helpers that are commonly used in
+/// templates:
+///
+/// - `format_number(decimals=0, separator="")`: Format number with fixed decimals and optional thousands separator.
+/// - `datetime_format(format="%H:%M")`: Format RFC3339 time string or unix timestamp in seconds with strftime format.
+/// - `now()`: Current local time in RFC3339 format, which can be used with `datetime_format` filter.
+///
+/// Missing parameters are treated as error, unless `default` filter or `is defined` test is used. And all outputs are
+/// escaped, so text like "R&D" will not break the SVG.
+pub(crate) fn new_jinja_environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_auto_escape_callback(|_| AutoEscape::Html);
+    env.add_filter("format_number", format_number);
+    env.add_filter("datetime_format", datetime_format);
+    env.add_function("now", now);
+    env
+}
+
+fn format_number(
+    value: Value,
+    decimals: Option<usize>,
+    separator: Option<String>,
+) -> Result<String, Error> {
+    let number = match value.as_str() {
+        Some(text) => text.trim().parse::<f64>().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidOperation,
+                format!("Cannot format \"{}\" as number", text),
+            )
+        })?,
+        None => f64::try_from(value)?,
+    };
+
+    let formatted = format!("{:.*}", decimals.unwrap_or(0), number);
+    let separator = match separator {
+        Some(v) if !v.is_empty() => v,
+        _ => return Ok(formatted),
+    };
+
+    let (sign, unsigned) = match formatted.strip_prefix('-') {
+        Some(v) => ("-", v),
+        None => ("", formatted.as_str()),
+    };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(index) => unsigned.split_at(index),
+        None => (unsigned, ""),
+    };
+
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index != 0 && (integer.len() - index) % 3 == 0 {
+            grouped.push_str(&separator);
+        }
+        grouped.push(digit);
+    }
+
+    Ok(format!("{}{}{}", sign, grouped, fraction))
+}
+
+fn datetime_format(value: Value, format: Option<String>) -> Result<String, Error> {
+    let time: DateTime<Local> = match value.as_str() {
+        Some(text) => DateTime::parse_from_rfc3339(text)
+            .map(|x| x.with_timezone(&Local))
+            .map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("Cannot parse \"{}\" as RFC3339 time: {}", text, e),
+                )
+            })?,
+        None => {
+            let timestamp = i64::try_from(value)?;
+            Local.timestamp_opt(timestamp, 0).single().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("Invalid unix timestamp: {}", timestamp),
+                )
+            })?
+        }
+    };
+
+    Ok(time
+        .format(format.as_deref().unwrap_or("%H:%M"))
+        .to_string())
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339()
+}
+
+impl From<Error> for DivoomAPIError {
+    fn from(err: Error) -> Self {
+        DivoomAPIError::ParameterError(format!("Failed to render template: {:#}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: Value) -> String {
+        new_jinja_environment().render_str(source, context).unwrap()
+    }
+
+    #[test]
+    fn jinja_environment_can_format_numbers() {
+        let context = minijinja::context! { value => "1234567.891", negative => -1234 };
+        assert_eq!(
+            render("{{ value | format_number }}", context.clone()),
+            "1234568"
+        );
+        assert_eq!(
+            render("{{ value | format_number(2, ',') }}", context.clone()),
+            "1,234,567.89"
+        );
+        assert_eq!(
+            render("{{ negative | format_number(0, ',') }}", context),
+            "-1,234"
+        );
+    }
+
+    #[test]
+    fn jinja_environment_can_format_datetime() {
+        let context = minijinja::context! { time => "2022-08-01T13:04:05+00:00" };
+        let expected = DateTime::parse_from_rfc3339("2022-08-01T13:04:05+00:00")
+            .unwrap()
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        assert_eq!(
+            render("{{ time | datetime_format('%Y-%m-%d %H:%M:%S') }}", context),
+            expected
+        );
+
+        let context = minijinja::context! { time => 1659359045 };
+        assert_eq!(
+            render("{{ time | datetime_format('%Y-%m-%d %H:%M:%S') }}", context),
+            expected
+        );
+    }
+
+    #[test]
+    fn jinja_environment_should_fail_on_missing_parameters_without_default() {
+        let env = new_jinja_environment();
+        assert!(env
+            .render_str("{{ text }}", minijinja::context! {})
+            .is_err());
+        assert_eq!(
+            env.render_str("{{ text | default('R&D') }}", minijinja::context! {})
+                .unwrap(),
+            "R&amp;D"
+        );
+    }
+}
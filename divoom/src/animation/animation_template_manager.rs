@@ -1,5 +1,9 @@
 use crate::animation::animation_template::{
-    DivoomAnimationTemplate, DivoomAnimationTemplateConfig,
+    DivoomAnimationTemplate, DivoomAnimationTemplateConfig, DivoomAnimationTemplateInfo,
+};
+use crate::animation::animation_template_cache::{
+    DivoomAnimationTemplateCache, DivoomAnimationTemplateCacheOptions,
+    DivoomAnimationTemplateCacheStats,
 };
 use crate::animation::animation_template_renderer::DivoomAnimationTemplateRenderer;
 use crate::{
@@ -12,12 +16,14 @@ use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use tiny_skia::BlendMode;
 
 pub struct DivoomAnimationTemplateManager {
     resource_dir: String,
     templates: HashMap<String, DivoomAnimationTemplate>,
     renderer: DivoomAnimationTemplateRenderer,
+    cache: Option<Mutex<DivoomAnimationTemplateCache>>,
 }
 
 impl DivoomAnimationTemplateManager {
@@ -26,6 +32,7 @@ impl DivoomAnimationTemplateManager {
             resource_dir: resource_dir.to_string(),
             templates: HashMap::new(),
             renderer: DivoomAnimationTemplateRenderer::new(resource_dir.to_string()),
+            cache: None,
         })
     }
 
@@ -96,6 +103,19 @@ impl DivoomAnimationTemplateManager {
         Ok(())
     }
 
+    /// List all loaded templates with their parameters, sorted by name.
+    pub fn templates(&self) -> Vec<DivoomAnimationTemplateInfo> {
+        let mut templates: Vec<DivoomAnimationTemplateInfo> =
+            self.templates.values().map(|x| x.info()).collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates
+    }
+
+    /// Get the template with its parameters by name.
+    pub fn template(&self, template_name: &str) -> Option<DivoomAnimationTemplateInfo> {
+        self.templates.get(template_name).map(|x| x.info())
+    }
+
     pub fn render_template(
         &self,
         template_name: &str,
@@ -117,6 +137,17 @@ impl DivoomAnimationTemplateManager {
         };
 
         let evaled_template = template.eval(parameters, per_frame_parameters)?;
+        let cache_key = self
+            .cache
+            .as_ref()
+            .map(|_| DivoomAnimationTemplateCache::cache_key(template_name, &evaled_template));
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key) {
+            if let Some(animation) = cache.lock().unwrap().get(cache_key) {
+                debug!("Rendered animation is found in cache: Key = {}", cache_key);
+                return Ok(animation);
+            }
+        }
+
         let mut animation_builder =
             DivoomAnimationBuilder::new(evaled_template.canvas_size, evaled_template.speed)?;
         for evaled_frame in evaled_template.frames {
@@ -131,14 +162,50 @@ impl DivoomAnimationTemplateManager {
         }
 
         let animation = animation_builder.build();
+        if let (Some(cache), Some(cache_key)) = (&self.cache, &cache_key) {
+            cache.lock().unwrap().insert(cache_key, &animation);
+        }
+
         Ok(animation)
     }
 }
 
+/// Rendered animation cache functions
+///
+/// Rasterizing SVG is slow on low-end devices, e.g. Raspberry Pi, so we can enable the cache to reuse the animations that
+/// are rendered with the same template and parameters before.
+impl DivoomAnimationTemplateManager {
+    pub fn enable_cache(
+        &mut self,
+        options: DivoomAnimationTemplateCacheOptions,
+    ) -> DivoomAPIResult<()> {
+        self.cache = Some(Mutex::new(DivoomAnimationTemplateCache::new(options)?));
+        Ok(())
+    }
+
+    /// Get the cache statistics. Returns `None` when cache is not enabled.
+    pub fn cache_stats(&self) -> Option<DivoomAnimationTemplateCacheStats> {
+        self.cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap().stats())
+    }
+
+    /// Remove all rendered animations from cache, including the ones on disk. Statistics are kept.
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().clear();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils;
+    use crate::{
+        DivoomAnimationTemplateEngine, DivoomAnimationTemplateParameterConfig,
+        DivoomAnimationTemplateParameterType,
+    };
 
     #[test]
     fn animation_template_manager_can_render_simple_graph_without_parameter() {
@@ -204,12 +271,28 @@ mod tests {
             .into_iter()
             .collect();
         let per_frame_parameters = [
-            (0, [("text".to_string(), "Foo".to_string())].into_iter().collect()),
-            (1, [("text".to_string(), "Bar".to_string())].into_iter().collect()),
-        ].into_iter().collect();
+            (
+                0,
+                [("text".to_string(), "Foo".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            (
+                1,
+                [("text".to_string(), "Bar".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+        ]
+        .into_iter()
+        .collect();
 
         let animation = manager
-            .render_template("template_multi_frame_text", &parameters, &per_frame_parameters)
+            .render_template(
+                "template_multi_frame_text",
+                &parameters,
+                &per_frame_parameters,
+            )
             .unwrap();
         test_utils::assert_animation_equal_with_baseline(
             &animation,
@@ -217,6 +300,183 @@ mod tests {
         );
     }
 
+    #[test]
+    fn animation_template_manager_can_render_jinja_template() {
+        let mut manager =
+            DivoomAnimationTemplateManager::new("test_data/animation_template_tests/input")
+                .unwrap();
+        manager
+            .add_template_file(
+                &"test_data/animation_template_tests/input/template_bar_chart.yaml".into(),
+            )
+            .unwrap();
+
+        let result =
+            manager.render_template("template_bar_chart", &HashMap::new(), &HashMap::new());
+        assert!(result.is_err());
+
+        let parameters = [
+            ("values".to_string(), "3,5,7,9".to_string()),
+            ("color".to_string(), "blue".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let animation = manager
+            .render_template("template_bar_chart", &parameters, &HashMap::new())
+            .unwrap();
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_template_tests/expected_generated_bar_chart.gif",
+        );
+    }
+
+    #[test]
+    fn animation_template_manager_can_generate_frames_from_frame_count_expression() {
+        let mut manager =
+            DivoomAnimationTemplateManager::new("test_data/animation_template_tests/input")
+                .unwrap();
+        manager
+            .add_template_file(
+                &"test_data/animation_template_tests/input/template_progress.yaml".into(),
+            )
+            .unwrap();
+
+        let template = manager.template("template_progress").unwrap();
+        assert_eq!(template.frame_count, 1);
+        assert_eq!(
+            template.frame_count_expression,
+            Some("steps | int".to_string())
+        );
+
+        let animation = manager
+            .render_template("template_progress", &HashMap::new(), &HashMap::new())
+            .unwrap();
+        test_utils::assert_animation_equal_with_baseline(
+            &animation,
+            "test_data/animation_template_tests/expected_generated_progress.gif",
+        );
+
+        for (steps, expected_frame_count) in
+            [("1", Some(1)), ("60", Some(60)), ("0", None), ("61", None)]
+        {
+            let parameters = [("steps".to_string(), steps.to_string())]
+                .into_iter()
+                .collect();
+            let result = manager.render_template("template_progress", &parameters, &HashMap::new());
+            assert_eq!(result.ok().map(|x| x.frame_count), expected_frame_count);
+        }
+
+        let config = DivoomAnimationTemplateConfig {
+            canvas_size: 32,
+            speed_in_ms: 100,
+            frames: vec!["svgs/progress.svg".into()],
+            frame_count: Some("4".into()),
+            engine: DivoomAnimationTemplateEngine::Jinja,
+            parameters: vec![DivoomAnimationTemplateParameterConfig {
+                name: "frame_index".into(),
+                parameter_type: DivoomAnimationTemplateParameterType::Integer,
+                default: None,
+                allowed_values: vec![],
+                description: "".into(),
+            }],
+        };
+        assert!(manager
+            .add_template_config("template_reserved_parameter".into(), &config)
+            .is_err());
+    }
+
+    #[test]
+    fn animation_template_manager_should_validate_declared_parameters() {
+        let mut manager =
+            DivoomAnimationTemplateManager::from_dir("test_data/animation_template_tests/input")
+                .unwrap();
+
+        let template_names: Vec<String> = manager.templates().into_iter().map(|x| x.name).collect();
+        assert_eq!(
+            template_names,
+            vec![
+                "template_bar_chart",
+                "template_image",
+                "template_multi_frame_text",
+                "template_progress",
+                "template_simple",
+                "template_simple_text"
+            ]
+        );
+
+        let template = manager.template("template_bar_chart").unwrap();
+        assert_eq!(template.frame_count, 1);
+        assert_eq!(template.parameters.len(), 3);
+        assert_eq!(template.parameters[2].default, Some("6".to_string()));
+
+        let parameters = [("values".to_string(), "3,5".to_string())]
+            .into_iter()
+            .collect();
+        assert!(manager
+            .render_template("template_bar_chart", &parameters, &HashMap::new())
+            .is_ok());
+        assert!(manager
+            .render_template("template_bar_chart", &HashMap::new(), &HashMap::new())
+            .is_err());
+
+        let config = DivoomAnimationTemplateConfig {
+            canvas_size: 32,
+            speed_in_ms: 100,
+            frames: vec!["svgs/progress.svg".into()],
+            frame_count: Some("4".into()),
+            engine: DivoomAnimationTemplateEngine::Jinja,
+            parameters: vec![DivoomAnimationTemplateParameterConfig {
+                name: "steps".into(),
+                parameter_type: DivoomAnimationTemplateParameterType::Integer,
+                default: Some("six".into()),
+                allowed_values: vec![],
+                description: "".into(),
+            }],
+        };
+        assert!(manager
+            .add_template_config("template_invalid_default".into(), &config)
+            .is_err());
+        assert!(manager.template("template_invalid_default").is_none());
+    }
+
+    #[test]
+    fn animation_template_manager_can_reuse_rendered_animation_from_cache() {
+        let mut manager =
+            DivoomAnimationTemplateManager::new("test_data/animation_template_tests/input")
+                .unwrap();
+        manager
+            .add_template_file(
+                &"test_data/animation_template_tests/input/template_simple_text.yaml".into(),
+            )
+            .unwrap();
+        assert!(manager.cache_stats().is_none());
+
+        manager
+            .enable_cache(DivoomAnimationTemplateCacheOptions::default())
+            .unwrap();
+
+        let render = |text: &str| {
+            let parameters = [("text".to_string(), text.to_string())]
+                .into_iter()
+                .collect();
+            manager
+                .render_template("template_simple_text", &parameters, &HashMap::new())
+                .unwrap()
+        };
+
+        let animation = render("Foo");
+        assert_eq!(render("Foo"), animation);
+        render("Bar");
+
+        let stats = manager.cache_stats().unwrap();
+        assert_eq!(stats.memory_hit_count, 1);
+        assert_eq!(stats.miss_count, 2);
+        assert_eq!(stats.entry_count, 2);
+
+        manager.clear_cache();
+        assert_eq!(manager.cache_stats().unwrap().entry_count, 0);
+    }
+
     #[test]
     fn animation_template_manager_can_render_image() {
         let mut manager =
@@ -0,0 +1,221 @@
+use crate::animation::animation_template::DIVOOM_ANIMATION_FRAME_VARIABLE_NAMES;
+use crate::{DivoomAPIError, DivoomAPIResult};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Types of animation template parameters.
+///
+/// All parameters are passed into templates as strings, and the type is only used for validating the value before
+/// rendering, as well as helping UI to generate the input form.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivoomAnimationTemplateParameterType {
+    #[default]
+    String,
+
+    /// 64-bit signed integer, e.g. "-42".
+    Integer,
+
+    /// Floating point number, e.g. "3.14".
+    Number,
+
+    /// "true" or "false".
+    Boolean,
+
+    /// Color in any format that SVG accepts, e.g. "#RRGGBB", "#RGB" or named colors like "blue".
+    Color,
+}
+
+/// Declaration of animation template parameter.
+#[derive(Debug, PartialOrd, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DivoomAnimationTemplateParameterConfig {
+    /// Parameter name
+    pub name: String,
+
+    /// Parameter type
+    #[serde(default, rename = "type")]
+    pub parameter_type: DivoomAnimationTemplateParameterType,
+
+    /// Default value. The parameter is required when no default value is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+
+    /// Allowed values. Any value that matches the type is allowed when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_values: Vec<String>,
+
+    /// Description of the parameter
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl DivoomAnimationTemplateParameterConfig {
+    /// Check if the value matches the parameter type and allowed values.
+    pub fn validate(&self, value: &str) -> DivoomAPIResult<()> {
+        let is_valid_type = match self.parameter_type {
+            DivoomAnimationTemplateParameterType::String => true,
+            DivoomAnimationTemplateParameterType::Integer => value.parse::<i64>().is_ok(),
+            DivoomAnimationTemplateParameterType::Number => value.parse::<f64>().is_ok(),
+            DivoomAnimationTemplateParameterType::Boolean => value.parse::<bool>().is_ok(),
+            DivoomAnimationTemplateParameterType::Color => value.parse::<svgtypes::Color>().is_ok(),
+        };
+
+        if !is_valid_type {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid template parameter: Name = {}, Value = {}, Expected type = {:?}",
+                self.name, value, self.parameter_type
+            )));
+        }
+
+        if !self.allowed_values.is_empty() && !self.allowed_values.iter().any(|x| x == value) {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid template parameter: Name = {}, Value = {}, Allowed values = {:?}",
+                self.name, value, self.allowed_values
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Validate the parameters with the declarations, and fill in the default values for the missing ones.
+///
+/// Parameters that are not declared are passed through without validation, so templates without declarations work as
+/// before. Per frame parameters are only validated, because they override the regular parameters, which are already
+/// filled with default values. Frame variables, e.g. `frame_index`, are provided by the renderer, so parameters with
+/// the same names are rejected.
+pub(crate) fn resolve_template_parameters<'a>(
+    declarations: &[DivoomAnimationTemplateParameterConfig],
+    parameters: &'a HashMap<String, String>,
+    per_frame_parameters: &HashMap<usize, HashMap<String, String>>,
+) -> DivoomAPIResult<Cow<'a, HashMap<String, String>>> {
+    let parameter_names = parameters
+        .keys()
+        .chain(per_frame_parameters.values().flat_map(|x| x.keys()));
+    for parameter_name in parameter_names {
+        if DIVOOM_ANIMATION_FRAME_VARIABLE_NAMES.contains(&parameter_name.as_str()) {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Template parameter name is reserved for frame variables: {}",
+                parameter_name
+            )));
+        }
+    }
+
+    let mut resolved_parameters = Cow::Borrowed(parameters);
+    for declaration in declarations {
+        match parameters.get(&declaration.name) {
+            Some(value) => declaration.validate(value)?,
+            None => match &declaration.default {
+                Some(default) => {
+                    resolved_parameters
+                        .to_mut()
+                        .insert(declaration.name.clone(), default.clone());
+                }
+                None => {
+                    return Err(DivoomAPIError::ParameterError(format!(
+                        "Missing template parameter: {}",
+                        declaration.name
+                    )))
+                }
+            },
+        }
+
+        for frame_parameters in per_frame_parameters.values() {
+            if let Some(value) = frame_parameters.get(&declaration.name) {
+                declaration.validate(value)?;
+            }
+        }
+    }
+
+    Ok(resolved_parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_declaration(
+        name: &str,
+        parameter_type: DivoomAnimationTemplateParameterType,
+    ) -> DivoomAnimationTemplateParameterConfig {
+        DivoomAnimationTemplateParameterConfig {
+            name: name.to_string(),
+            parameter_type,
+            default: None,
+            allowed_values: vec![],
+            description: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn template_parameter_should_validate_types_and_allowed_values() {
+        let cases = [
+            (DivoomAnimationTemplateParameterType::Integer, "-42", "4.2"),
+            (DivoomAnimationTemplateParameterType::Number, "4.2", "four"),
+            (DivoomAnimationTemplateParameterType::Boolean, "true", "yes"),
+            (
+                DivoomAnimationTemplateParameterType::Color,
+                "#00ff7F",
+                "#00ff7",
+            ),
+            (DivoomAnimationTemplateParameterType::Color, "#0f7", "bleu"),
+            (DivoomAnimationTemplateParameterType::Color, "blue", "#"),
+        ];
+        for (parameter_type, valid_value, invalid_value) in cases {
+            let declaration = new_declaration("foo", parameter_type);
+            assert!(declaration.validate(valid_value).is_ok());
+            assert!(declaration.validate(invalid_value).is_err());
+        }
+
+        let mut declaration = new_declaration("foo", DivoomAnimationTemplateParameterType::String);
+        declaration.allowed_values = vec!["up".into(), "down".into()];
+        assert!(declaration.validate("down").is_ok());
+        assert!(declaration.validate("left").is_err());
+    }
+
+    #[test]
+    fn template_parameters_should_be_resolved_with_default_values() {
+        let mut color = new_declaration("color", DivoomAnimationTemplateParameterType::Color);
+        color.default = Some("#ff0000".into());
+        let declarations = vec![
+            new_declaration("value", DivoomAnimationTemplateParameterType::Integer),
+            color,
+        ];
+
+        let parameters = [("value".to_string(), "10".to_string())]
+            .into_iter()
+            .collect();
+        let resolved =
+            resolve_template_parameters(&declarations, &parameters, &HashMap::new()).unwrap();
+        assert_eq!(resolved.get("color").unwrap(), "#ff0000");
+        assert_eq!(resolved.get("value").unwrap(), "10");
+
+        let per_frame_parameters = [(
+            1,
+            [("value".to_string(), "ten".to_string())]
+                .into_iter()
+                .collect(),
+        )]
+        .into_iter()
+        .collect();
+        assert!(
+            resolve_template_parameters(&declarations, &parameters, &per_frame_parameters).is_err()
+        );
+        assert!(
+            resolve_template_parameters(&declarations, &HashMap::new(), &HashMap::new()).is_err()
+        );
+    }
+
+    #[test]
+    fn template_parameters_should_not_use_frame_variable_names() {
+        let parameters = [("frame_index".to_string(), "1".to_string())]
+            .into_iter()
+            .collect();
+        assert!(resolve_template_parameters(&[], &parameters, &HashMap::new()).is_err());
+
+        let per_frame_parameters = [(0, parameters)].into_iter().collect();
+        assert!(resolve_template_parameters(&[], &HashMap::new(), &per_frame_parameters).is_err());
+    }
+}
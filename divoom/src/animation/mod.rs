@@ -1,13 +1,40 @@
 mod animation_builder;
+mod animation_color_processing;
 mod animation_frame_builder;
+mod animation_keyframe;
+mod animation_layer;
+mod animation_marquee;
+mod animation_pixel_font;
+mod animation_pixel_text;
 mod animation_resource_loader;
+#[cfg(feature = "animation-video")]
+mod animation_resource_loader_video;
 mod animation_template;
+mod animation_template_cache;
+mod animation_template_jinja;
+mod animation_template_parameter;
 mod animation_template_manager;
 mod animation_template_renderer;
 
 pub use animation_builder::*;
+pub use animation_color_processing::*;
 pub use animation_frame_builder::*;
+pub use animation_keyframe::*;
+pub use animation_layer::*;
+pub use animation_marquee::{DivoomMarqueeConfig, DivoomMarqueeDirection, DivoomMarqueeItem};
+pub use animation_pixel_font::*;
+pub use animation_pixel_text::{
+    DivoomPixelTextAlign, DivoomPixelTextScrollDirection, DivoomPixelTextVerticalAlign,
+};
 pub use animation_resource_loader::*;
-pub use animation_template::DivoomAnimationTemplateConfig;
+pub use animation_template::{
+    DivoomAnimationTemplateConfig, DivoomAnimationTemplateEngine, DivoomAnimationTemplateInfo,
+};
+pub use animation_template_cache::{
+    DivoomAnimationTemplateCacheOptions, DivoomAnimationTemplateCacheStats,
+};
 pub use animation_template_manager::*;
+pub use animation_template_parameter::{
+    DivoomAnimationTemplateParameterConfig, DivoomAnimationTemplateParameterType,
+};
 pub use animation_template_manager::*;
@@ -1,6 +1,8 @@
+mod pixoo_batch_result;
 mod pixoo_client;
 mod pixoo_command_builder;
 mod pixoo_command_store;
 
+pub use pixoo_batch_result::*;
 pub use pixoo_client::*;
 pub use pixoo_command_builder::*;
@@ -0,0 +1,74 @@
+use crate::{DivoomAPIError, DivoomAPIResult};
+
+/// Outcome of a command in a batch.
+#[derive(Debug)]
+pub enum PixooBatchCommandOutcome {
+    Succeeded,
+
+    /// The command is rejected by the device.
+    Failed(DivoomAPIError),
+
+    /// The batch is rejected, but the device only returns a single error code for the whole batch, so we don't know
+    /// if this command failed or not.
+    Unknown,
+
+    /// The command is not sent, because an earlier command failed when sending the commands one by one.
+    NotExecuted,
+}
+
+/// A command queued in the batch and its outcome.
+#[derive(Debug)]
+pub struct PixooBatchCommandResult {
+    /// Index of the command in the batch, starting from 0.
+    pub index: usize,
+
+    /// API name of the command, e.g. "set_device_brightness".
+    pub command: String,
+
+    /// Arguments of the command, e.g. "brightness=30".
+    pub arguments: String,
+
+    pub outcome: PixooBatchCommandOutcome,
+}
+
+/// Result of executing a batch, which maps each queued command to its outcome.
+#[derive(Debug)]
+pub struct PixooBatchExecutionResult {
+    pub commands: Vec<PixooBatchCommandResult>,
+
+    /// Error returned from the device for the whole batch, if the batch is rejected.
+    pub batch_error: Option<DivoomAPIError>,
+}
+
+impl PixooBatchExecutionResult {
+    pub fn is_success(&self) -> bool {
+        self.batch_error.is_none()
+    }
+
+    /// The command that is known to be rejected by the device.
+    pub fn failed_command(&self) -> Option<&PixooBatchCommandResult> {
+        self.commands
+            .iter()
+            .find(|x| matches!(x.outcome, PixooBatchCommandOutcome::Failed(_)))
+    }
+
+    /// Convert to the error of the failed command if it is known, otherwise the error of the whole batch.
+    pub fn into_result(mut self) -> DivoomAPIResult<()> {
+        let batch_error = match self.batch_error.take() {
+            None => return Ok(()),
+            Some(e) => e,
+        };
+
+        for command in self.commands {
+            if let PixooBatchCommandOutcome::Failed(e) = command.outcome {
+                return Err(DivoomAPIError::CommandError {
+                    command_index: command.index,
+                    command: command.command,
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        Err(batch_error)
+    }
+}
@@ -26,8 +26,19 @@ use crate::animation::*;
 /// // let result = pixoo.get_current_channel().await?;
 /// // println!("{:?}", result);
 /// ```
+///
+/// The client targets Pixoo-64 by default. For other models, we can specify the device model, so the commands are
+/// validated against its capability profile before being sent to the device:
+///
+/// ```rust
+/// use divoom::*;
+/// let pixoo = PixooClient::new("192.168.0.123")
+///     .unwrap()
+///     .with_device_model(DivoomDeviceModel::Pixoo16);
+/// ```
 pub struct PixooClient {
     client: Arc<DivoomRestAPIClient>,
+    device_model: DivoomDeviceModel,
 }
 
 macro_rules! impl_pixoo_client_api {
@@ -37,6 +48,7 @@ macro_rules! impl_pixoo_client_api {
         #[doc = include_str!($api_doc_path)]
         pub async fn $api_name(&self) -> DivoomAPIResult<$resp_return_type> {
             let response: $resp_type = PixooCommandBuilder::start(self.client.clone())
+                .with_device_model(self.device_model)
                 .$api_name()
                 .execute_with_parsed_response::<$resp_type>()
                 .await?;
@@ -56,6 +68,7 @@ macro_rules! impl_pixoo_client_api {
         #[doc = include_str!($api_doc_path)]
         pub async fn $api_name(&self, $($api_arg: $api_arg_type),*) -> DivoomAPIResult<$resp_return_type> {
             let response: $resp_type = PixooCommandBuilder::start(self.client.clone())
+                .with_device_model(self.device_model)
                 .$api_name($($api_arg),*)
                 .execute_with_parsed_response::<$resp_type>()
                 .await?;
@@ -93,8 +106,19 @@ impl PixooClient {
                 format!("http://{}", device_address),
                 timeout,
             )),
+            device_model: DivoomDeviceModel::default(),
         })
     }
+
+    /// Set the device model, which is used to validate the commands before sending them to the device.
+    pub fn with_device_model(mut self, device_model: DivoomDeviceModel) -> Self {
+        self.device_model = device_model;
+        self
+    }
+
+    pub fn device_model(&self) -> DivoomDeviceModel {
+        self.device_model
+    }
 }
 
 /// # Chanel API implementations
@@ -377,6 +401,40 @@ impl PixooClient {
         self.send_image_animation(animation).await
     }
 
+    /// Send GIF to the device to play as an animation, keeping the original timing of the GIF.
+    ///
+    /// Unlike `render_gif_as_animation_with_options`, which draws every GIF frame as one frame of the animation, the GIF frames
+    /// are resampled onto the speed, so GIFs with variable delays are played with their original timing. When the GIF is too long
+    /// for the device, the frames exceeding the max animation frame count of the device are dropped.
+    #[cfg(feature = "animation-builder")]
+    pub async fn render_gif_as_animation_with_original_timing(
+        &self,
+        canvas_size: u32,
+        speed: Duration,
+        file_path: &str,
+        fit: DivoomDrawFitMode,
+        rotation: f32,
+        opacity: f32,
+        blend: BlendMode,
+    ) -> DivoomAPIResult<()> {
+        let animation_builder = DivoomAnimationBuilder::new(canvas_size, speed)?
+            .with_max_frame_count(self.device_model.profile().max_animation_frame_count);
+        let gif = DivoomAnimationResourceLoader::from_gif_sequence_file(file_path)?;
+        let animation = animation_builder
+            .draw_frame_sequence_fit(&gif, 0, fit, rotation, opacity, blend)
+            .build();
+        self.send_image_animation(animation).await
+    }
+
+    /// Create an animation builder that targets the native resolution of the device model.
+    #[cfg(feature = "animation-builder")]
+    pub fn new_animation_builder(
+        &self,
+        speed: Duration,
+    ) -> DivoomAPIResult<DivoomAnimationBuilder> {
+        DivoomAnimationBuilder::for_device_model(self.device_model, speed)
+    }
+
     #[doc = include_str!("../../divoom_contracts/pixoo/animation/api_send_image_animation_frame.md")]
     pub async fn send_image_animation(
         &self,
@@ -400,6 +458,7 @@ impl PixooClient {
 
         let response: DivoomPixooCommandBatchExecuteCommandsResponse =
             PixooCommandBuilder::start_batch(self.client.clone())
+                .with_device_model(self.device_model)
                 .send_image_animation(animation_id, animation)
                 .execute_with_parsed_response::<DivoomPixooCommandBatchExecuteCommandsResponse>()
                 .await?;
@@ -454,7 +513,7 @@ impl PixooClient {
     /// ## Batch mode
     /// This function returns the command builder, which allows us to build multiple commands and execute them at once.
     pub fn start_batch(&self) -> PixooCommandBuilder {
-        PixooCommandBuilder::start_batch(self.client.clone())
+        PixooCommandBuilder::start_batch(self.client.clone()).with_device_model(self.device_model)
     }
 }
 
@@ -462,6 +521,7 @@ impl PixooClient {
 impl PixooClient {
     pub async fn send_raw_request(&self, request: String) -> DivoomAPIResult<String> {
         let response: String = PixooCommandBuilder::start(self.client.clone())
+            .with_device_model(self.device_model)
             .send_raw_request(request)
             .execute_with_raw_response()
             .await?;
@@ -473,6 +533,8 @@ impl PixooClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::mock_pixoo_command;
+    use crate::PixooBatchCommandOutcome;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
     async fn pixoo_client_batch_mode_should_work() {
@@ -493,4 +555,110 @@ mod tests {
             .await
             .expect("Request should succeed.");
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
+    async fn pixoo_client_batch_mode_should_report_unknown_outcomes_without_fallback() {
+        let _m = mock_pixoo_command(
+            r#""Brightness":41[,}].*"Brightness":42[,}]"#,
+            "{\"error_code\": 1}",
+            1,
+        );
+
+        let pixoo = PixooClient::new(&mockito::server_address().to_string()).unwrap();
+        let result = pixoo
+            .start_batch()
+            .set_device_brightness(41)
+            .set_device_brightness(42)
+            .execute_with_result(false)
+            .await
+            .expect("Batch should be sent.");
+
+        assert!(!result.is_success());
+        assert!(result.failed_command().is_none());
+        assert_eq!(result.commands[1].command, "set_device_brightness");
+        assert_eq!(result.commands[1].arguments, "brightness=42");
+        assert!(matches!(
+            result.commands[1].outcome,
+            PixooBatchCommandOutcome::Unknown
+        ));
+        match result.into_result() {
+            Err(DivoomAPIError::ServerError(e)) => assert_eq!(e.error_code, 1),
+            result => panic!("Batch should fail: {:?}", result),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 5)]
+    async fn pixoo_client_batch_mode_should_return_server_error_when_single_command_is_rejected() {
+        let _m = mock_pixoo_command(r#""Brightness":43[,}]"#, "{\"error_code\": 1}", 1);
+
+        let pixoo = PixooClient::new(&mockito::server_address().to_string()).unwrap();
+        let result = pixoo
+            .start_batch()
+            .set_device_brightness(43)
+            .execute()
+            .await;
+        match result {
+            Err(DivoomAPIError::ServerError(e)) => assert_eq!(e.error_code, 1),
+            result => panic!("Batch should fail: {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn pixoo_client_should_reject_unsupported_commands_of_device_model() {
+        // Nothing listens on this address, so the error can only come from the client side validation.
+        let pixoo = PixooClient::new("127.0.0.1:1")
+            .unwrap()
+            .with_device_model(DivoomDeviceModel::Pixoo16);
+
+        match pixoo.play_buzzer(100, 50, 50).await {
+            Err(DivoomAPIError::UnsupportedCommandError(message)) => {
+                assert!(message.contains("play_buzzer"), "{}", message)
+            }
+            result => panic!("Command should be rejected: {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn pixoo_client_batch_mode_should_reject_invalid_arguments_of_device_model() {
+        let pixoo = PixooClient::new("127.0.0.1:1").unwrap();
+        let text_animation = DivoomTextAnimation {
+            text_id: 21,
+            ..DivoomTextAnimation::default()
+        };
+
+        let result = pixoo
+            .start_batch()
+            .set_device_brightness(30)
+            .send_text_animation(text_animation)
+            .execute()
+            .await;
+        match result {
+            Err(e) => {
+                assert_eq!(e.failed_command(), Some((1, "send_text_animation")));
+                assert_eq!(e.category(), DivoomErrorCategory::InvalidInput);
+                assert!(e.to_string().contains("text_id"), "{}", e)
+            }
+            result => panic!("Command should be rejected: {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn pixoo_client_should_reject_out_of_range_parameters() {
+        let pixoo = PixooClient::new("127.0.0.1:1").unwrap();
+
+        match pixoo.set_device_brightness(120).await {
+            Err(DivoomAPIError::ParameterError(message)) => assert_eq!(
+                message,
+                "Invalid brightness: 120. It must be between 0 and 100."
+            ),
+            result => panic!("Command should be rejected: {:?}", result),
+        }
+
+        match pixoo.set_device_white_balance(255, 256, 0).await {
+            Err(DivoomAPIError::ParameterError(message)) => {
+                assert!(message.starts_with("Invalid g: 256."), "{}", message)
+            }
+            result => panic!("Command should be rejected: {:?}", result),
+        }
+    }
 }
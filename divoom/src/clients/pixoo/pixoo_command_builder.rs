@@ -12,9 +12,22 @@ use serde::de::DeserializeOwned;
 use std::sync::{Arc, Mutex};
 
 /// Pixoo command builder for creating the JSON payload of Pixoo commands.
+///
+/// The commands are validated against the capability profile of the device model when they are added, and the first
+/// validation error is returned when executing the commands. In batch mode, the error also tells which command failed.
 pub struct PixooCommandBuilder {
     command_store: Arc<Mutex<Option<Box<dyn PixooCommandStore + Send>>>>,
     client: Arc<DivoomRestAPIClient>,
+    device_model: DivoomDeviceModel,
+    commands: Vec<PixooQueuedCommand>,
+    validation_error: Option<DivoomAPIError>,
+}
+
+/// Command added to the builder, which can be sent as multiple requests, e.g. one request per animation frame.
+struct PixooQueuedCommand {
+    name: &'static str,
+    arguments: String,
+    request_count: usize,
 }
 
 /// Constructors, builder and executor
@@ -23,6 +36,9 @@ impl PixooCommandBuilder {
         PixooCommandBuilder {
             command_store: Arc::new(Mutex::new(Some(Box::new(PixooSingleCommandStore::new())))),
             client,
+            device_model: DivoomDeviceModel::default(),
+            commands: vec![],
+            validation_error: None,
         }
     }
 
@@ -30,6 +46,69 @@ impl PixooCommandBuilder {
         PixooCommandBuilder {
             command_store: Arc::new(Mutex::new(Some(Box::new(PixooBatchedCommandStore::new())))),
             client,
+            device_model: DivoomDeviceModel::default(),
+            commands: vec![],
+            validation_error: None,
+        }
+    }
+
+    pub(crate) fn with_device_model(mut self, device_model: DivoomDeviceModel) -> Self {
+        self.device_model = device_model;
+        self
+    }
+
+    /// Return the first validation error of the added commands, or the builder itself if all commands are valid.
+    pub(crate) fn into_validated(mut self) -> DivoomAPIResult<Self> {
+        match self.validation_error.take() {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+
+    fn start_command(&mut self, api_name: &'static str, arguments: String) {
+        self.commands.push(PixooQueuedCommand {
+            name: api_name,
+            arguments,
+            request_count: 0,
+        });
+    }
+
+    fn append_request(&mut self, request: String) {
+        if let Some(command) = self.commands.last_mut() {
+            command.request_count += 1;
+        }
+
+        self.command_store
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .append(request);
+    }
+
+    /// Number of commands added to the builder, which can be different from the number of requests in the payload.
+    pub(crate) fn command_count(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Keep the first validation error. In batch mode, the error is tagged with the command being added.
+    fn validate(&mut self, result: DivoomAPIResult<()>) {
+        if let Err(e) = result {
+            if self.validation_error.is_some() {
+                return;
+            }
+
+            let is_batch_mode = self.command_store.lock().unwrap().as_ref().unwrap().mode()
+                == PixooCommandStoreMode::Batched;
+            let e = match self.commands.last() {
+                Some(command) if is_batch_mode => DivoomAPIError::CommandError {
+                    command_index: self.commands.len() - 1,
+                    command: command.name.to_string(),
+                    source: Box::new(e),
+                },
+                _ => e,
+            };
+            self.validation_error = Some(e);
         }
     }
 
@@ -47,7 +126,7 @@ impl PixooCommandBuilder {
     pub(crate) async fn execute_with_parsed_response<TResp: DeserializeOwned>(
         self,
     ) -> DivoomAPIResult<TResp> {
-        let (client, command_count, request_body) = self.build();
+        let (client, command_count, request_body) = self.into_validated()?.build();
         if command_count == 0 {
             return Err(DivoomAPIError::ParameterError(
                 "No command is built yet!".to_string(),
@@ -60,7 +139,7 @@ impl PixooCommandBuilder {
     }
 
     pub async fn execute_with_raw_response(self) -> DivoomAPIResult<String> {
-        let (client, command_count, request_body) = self.build();
+        let (client, command_count, request_body) = self.into_validated()?.build();
         if command_count == 0 {
             return Err(DivoomAPIError::ParameterError(
                 "No command is built yet!".to_string(),
@@ -72,7 +151,26 @@ impl PixooCommandBuilder {
             .await
     }
 
+    /// Execute the commands. When the device rejects the batch, the error returned from the device is returned as
+    /// `ServerError`, even if the batch only has a single command. Please use `execute_with_result` to find the failed
+    /// command.
     pub async fn execute(self) -> DivoomAPIResult<()> {
+        match self.execute_with_result(false).await?.batch_error {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+
+    /// Execute the commands in batch mode and report the outcome of each command.
+    ///
+    /// Errors before the batch is accepted by the device, such as validation or network errors, are returned as errors.
+    /// When the device rejects the batch, it only returns a single error code, so the outcomes of the commands are
+    /// unknown. With `fallback_to_single_commands` set, the commands are then sent one by one to find the one that the
+    /// device rejects. Please note that the commands before the failed one are sent to the device again.
+    pub async fn execute_with_result(
+        self,
+        fallback_to_single_commands: bool,
+    ) -> DivoomAPIResult<PixooBatchExecutionResult> {
         if self.command_store.lock().unwrap().as_ref().unwrap().mode()
             == PixooCommandStoreMode::Single
         {
@@ -82,40 +180,205 @@ impl PixooCommandBuilder {
             ));
         }
 
-        let response = self
-            .execute_with_parsed_response::<DivoomPixooCommandBatchExecuteCommandsResponse>()
-            .await?;
-        if response.error_code() != 0 {
-            return Err(DivoomAPIError::ServerError(
-                DivoomServerErrorInfo::server_error(response.error_code()),
+        let builder = self.into_validated()?;
+        let requests = builder
+            .command_store
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap()
+            .to_requests();
+        if requests.is_empty() {
+            return Err(DivoomAPIError::ParameterError(
+                "No command is built yet!".to_string(),
             ));
         }
 
+        let mut result = PixooBatchExecutionResult {
+            commands: builder
+                .commands
+                .iter()
+                .enumerate()
+                .map(|(index, command)| PixooBatchCommandResult {
+                    index,
+                    command: command.name.to_string(),
+                    arguments: command.arguments.clone(),
+                    outcome: PixooBatchCommandOutcome::Succeeded,
+                })
+                .collect(),
+            batch_error: None,
+        };
+
+        let batch_error = match send_batch_requests(&builder.client, &requests).await? {
+            None => return Ok(result),
+            Some(e) => e,
+        };
+
+        result.batch_error = Some(DivoomAPIError::ServerError(batch_error.clone()));
+        if result.commands.len() == 1 {
+            result.commands[0].outcome =
+                PixooBatchCommandOutcome::Failed(DivoomAPIError::ServerError(batch_error));
+            return Ok(result);
+        }
+
+        if !fallback_to_single_commands {
+            for command in result.commands.iter_mut() {
+                command.outcome = PixooBatchCommandOutcome::Unknown;
+            }
+            return Ok(result);
+        }
+
+        let mut has_failed_command = false;
+        let mut remaining_requests = &requests[..];
+        for (command, queued_command) in result.commands.iter_mut().zip(builder.commands.iter()) {
+            let (command_requests, rest) =
+                remaining_requests.split_at(queued_command.request_count);
+            remaining_requests = rest;
+
+            if has_failed_command {
+                command.outcome = PixooBatchCommandOutcome::NotExecuted;
+                continue;
+            }
+
+            let command_error = match send_batch_requests(&builder.client, command_requests).await {
+                Ok(None) => None,
+                Ok(Some(e)) => Some(DivoomAPIError::ServerError(e)),
+                Err(e) => Some(e),
+            };
+
+            if let Some(e) = command_error {
+                command.outcome = PixooBatchCommandOutcome::Failed(e);
+                has_failed_command = true;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Send the requests in a batch, and return the error info if the device rejects the batch.
+async fn send_batch_requests(
+    client: &DivoomRestAPIClient,
+    requests: &[String],
+) -> DivoomAPIResult<Option<DivoomServerErrorInfo>> {
+    if requests.is_empty() {
+        return Ok(None);
+    }
+
+    let response = client
+        .send_request_with_body::<DivoomPixooCommandBatchExecuteCommandsResponse>(
+            "/post",
+            PixooBatchedCommandStore::to_batch_payload(requests),
+        )
+        .await?;
+    if response.error_code() != 0 {
+        return Ok(Some(DivoomServerErrorInfo::server_error(
+            response.error_code(),
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Parameter validations, which check the ranges of the parameters first, then the limits of the device model.
+impl PixooCommandBuilder {
+    fn validate_custom_page_index(&self, custom_page_index: &i32) -> DivoomAPIResult<()> {
+        DivoomCustomPageIndex::new(*custom_page_index)?;
+        self.device_model
+            .profile()
+            .validate_custom_page_index(custom_page_index)
+    }
+
+    fn validate_brightness(&self, brightness: &i32) -> DivoomAPIResult<()> {
+        DivoomBrightness::new(*brightness)?;
+        Ok(())
+    }
+
+    fn validate_white_balance(&self, r: &i32, g: &i32, b: &i32) -> DivoomAPIResult<()> {
+        DivoomWhiteBalanceLevel::new_for_field("r", *r)?;
+        DivoomWhiteBalanceLevel::new_for_field("g", *g)?;
+        DivoomWhiteBalanceLevel::new_for_field("b", *b)?;
+        Ok(())
+    }
+
+    fn validate_countdown(
+        &self,
+        minute: &i32,
+        second: &i32,
+        _action: &DivoomToolCountdownAction,
+    ) -> DivoomAPIResult<()> {
+        DivoomCountdownMinute::new(*minute)?;
+        DivoomCountdownSecond::new(*second)?;
+        Ok(())
+    }
+
+    fn validate_text_animation(&self, animation: &DivoomTextAnimation) -> DivoomAPIResult<()> {
+        animation.validate()?;
+        self.device_model
+            .profile()
+            .validate_text_animation(animation)
+    }
+
+    fn validate_buzzer(
+        &self,
+        play_total_time: &i32,
+        active_time_in_cycle: &i32,
+        off_time_in_cycle: &i32,
+    ) -> DivoomAPIResult<()> {
+        DivoomBuzzerTime::new_for_field("play_total_time", *play_total_time)?;
+        DivoomBuzzerTime::new_for_field("active_time_in_cycle", *active_time_in_cycle)?;
+        DivoomBuzzerTime::new_for_field("off_time_in_cycle", *off_time_in_cycle)?;
         Ok(())
     }
 }
 
+macro_rules! format_command_arguments {
+    ($($api_arg:ident),*) => (
+        [$(format!("{}={:?}", stringify!($api_arg), $api_arg)),*].join(", ")
+    );
+}
+
 macro_rules! impl_command_builder {
     ($api_name:ident, $api_doc_path:literal, $req_type:ty) => (
         #[doc = include_str!($api_doc_path)]
-        pub fn $api_name(self) -> PixooCommandBuilder {
+        pub fn $api_name(mut self) -> PixooCommandBuilder {
+            self.start_command(stringify!($api_name), String::new());
+            self.validate(self.device_model.profile().validate_command(stringify!($api_name)));
             let request = <$req_type>::new();
             let serialized_request = serde_json::to_string(&request).expect("Serializing pixoo command failed!");
-            self.command_store.lock().unwrap().as_mut().unwrap().append(serialized_request);
+            self.append_request(serialized_request);
+            self
+        }
+    );
+
+    // Besides checking if the command is supported, the arguments are also validated with the specified function, e.g.
+    // validate_text_animation.
+    ($api_name:ident, $api_doc_path:literal, $req_type:ty, $req_payload_type:ty, validate_with: $validate_fn:ident, $($api_arg:ident: $api_arg_type:ty),*) => (
+        #[doc = include_str!($api_doc_path)]
+        pub fn $api_name(mut self, $($api_arg: $api_arg_type),*) -> PixooCommandBuilder {
+            self.start_command(stringify!($api_name), format_command_arguments!($($api_arg),*));
+            self.validate(self.device_model.profile().validate_command(stringify!($api_name)));
+            self.validate(self.$validate_fn($(&$api_arg),*));
+            let payload = <$req_payload_type>::new($($api_arg),*);
+            let request = <$req_type>::new(payload);
+            let serialized_request = serde_json::to_string(&request).expect("Serializing pixoo command failed!");
+            self.append_request(serialized_request);
             self
         }
     );
 
     ($api_name:ident, $api_doc_path:literal, $req_type:ty, $req_payload_type:ty, $($api_arg:ident: $api_arg_type:ty),*) => (
         #[doc = include_str!($api_doc_path)]
-        pub fn $api_name(self, $($api_arg: $api_arg_type),*) -> PixooCommandBuilder {
+        pub fn $api_name(mut self, $($api_arg: $api_arg_type),*) -> PixooCommandBuilder {
+            self.start_command(stringify!($api_name), format_command_arguments!($($api_arg),*));
+            self.validate(self.device_model.profile().validate_command(stringify!($api_name)));
             let payload = <$req_payload_type>::new($($api_arg),*);
             let request = <$req_type>::new(payload);
             let serialized_request = serde_json::to_string(&request).expect("Serializing pixoo command failed!");
-            self.command_store.lock().unwrap().as_mut().unwrap().append(serialized_request);
+            self.append_request(serialized_request);
             self
         }
-    )
+    );
 }
 
 /// Channel API implementations
@@ -169,6 +432,7 @@ impl PixooCommandBuilder {
         "../../divoom_contracts/pixoo/channel/api_select_custom_page.md",
         DivoomPixooCommandChannelSelectCustomPageRequest,
         DivoomPixooCommandChannelSelectCustomPageRequestPayload,
+        validate_with: validate_custom_page_index,
         custom_page_index: i32
     );
 }
@@ -192,6 +456,7 @@ impl PixooCommandBuilder {
         "../../divoom_contracts/pixoo/system/api_set_device_brightness.md",
         DivoomPixooCommandSystemSetBrightnessRequest,
         DivoomPixooCommandSystemSetBrightnessRequestPayload,
+        validate_with: validate_brightness,
         brightness: i32
     );
 
@@ -273,6 +538,7 @@ impl PixooCommandBuilder {
         "../../divoom_contracts/pixoo/system/api_set_device_white_balance.md",
         DivoomPixooCommandSystemSetWhiteBalanceRequest,
         DivoomPixooCommandSystemSetWhiteBalanceRequestPayload,
+        validate_with: validate_white_balance,
         r: i32,
         g: i32,
         b: i32
@@ -286,6 +552,7 @@ impl PixooCommandBuilder {
         "../../divoom_contracts/pixoo/tool/api_set_countdown_tool.md",
         DivoomPixooCommandToolSetCountdownRequest,
         DivoomPixooCommandToolSetCountdownRequestPayload,
+        validate_with: validate_countdown,
         minute: i32,
         second: i32,
         action: DivoomToolCountdownAction
@@ -342,10 +609,22 @@ impl PixooCommandBuilder {
 
     #[doc = include_str!("../../divoom_contracts/pixoo/animation/api_send_image_animation_frame.md")]
     pub fn send_image_animation(
-        self,
+        mut self,
         id: i32,
         animation: DivoomImageAnimation,
     ) -> PixooCommandBuilder {
+        self.start_command(
+            "send_image_animation",
+            format!(
+                "id={}, size={}, frame_count={}, speed_in_ms={}",
+                id, animation.size, animation.frame_count, animation.speed_in_ms
+            ),
+        );
+        self.validate(
+            self.device_model
+                .profile()
+                .validate_image_animation(&id, &animation),
+        );
         let payloads =
             DivoomPixooCommandAnimationSendImageAnimationFrameRequestPayload::create_frames(
                 id, animation,
@@ -354,12 +633,7 @@ impl PixooCommandBuilder {
             let request = DivoomPixooCommandAnimationSendImageAnimationFrameRequest::new(payload);
             let serialized_request =
                 serde_json::to_string(&request).expect("Serializing pixoo command failed!");
-            self.command_store
-                .lock()
-                .unwrap()
-                .as_mut()
-                .unwrap()
-                .append(serialized_request);
+            self.append_request(serialized_request);
         });
         self
     }
@@ -369,6 +643,7 @@ impl PixooCommandBuilder {
         "../../divoom_contracts/pixoo/animation/api_send_text_animation.md",
         DivoomPixooCommandAnimationSendTextAnimationRequest,
         DivoomPixooCommandAnimationSendTextAnimationRequestPayload,
+        validate_with: validate_text_animation,
         animation: DivoomTextAnimation
     );
 
@@ -383,6 +658,7 @@ impl PixooCommandBuilder {
         "../../divoom_contracts/pixoo/animation/api_play_buzzer.md",
         DivoomPixooCommandAnimationPlayBuzzerRequest,
         DivoomPixooCommandAnimationPlayBuzzerRequestPayload,
+        validate_with: validate_buzzer,
         play_total_time: i32,
         active_time_in_cycle: i32,
         off_time_in_cycle: i32
@@ -402,13 +678,9 @@ impl PixooCommandBuilder {
 
 /// Raw API implementations
 impl PixooCommandBuilder {
-    pub fn send_raw_request(self, request: String) -> PixooCommandBuilder {
-        self.command_store
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .append(request);
+    pub fn send_raw_request(mut self, request: String) -> PixooCommandBuilder {
+        self.start_command("send_raw_request", request.clone());
+        self.append_request(request);
         self
     }
 }
@@ -553,6 +825,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pixoo_command_builder_image_animation_commands_can_be_parsed_back() {
+        let new_image_animation = || DivoomImageAnimation {
+            size: 16,
+            frame_count: 3,
+            speed_in_ms: 150,
+            frames: (0..3u8)
+                .map(|offset| (offset as u32, vec![offset * 50; 16 * 16 * 3]))
+                .collect(),
+        };
+
+        let client = Arc::new(DivoomRestAPIClient::new(
+            "http://192.168.0.123".to_string(),
+            None,
+        ));
+        let builder = PixooCommandBuilder::start_batch(client)
+            .reset_next_animation_id()
+            .send_image_animation(1, new_image_animation())
+            .clear_all_text_area();
+
+        let (_, command_count, request_body) = builder.build();
+        assert_eq!(command_count, 5);
+
+        let parsed = DivoomImageAnimation::from_pixoo_request(&request_body).unwrap();
+        assert_eq!(parsed, new_image_animation());
+    }
+
     #[test]
     fn pixoo_command_builder_should_work_with_batch_commands_in_batch_mode() {
         let client = Arc::new(DivoomRestAPIClient::new(
@@ -8,6 +8,7 @@ pub(crate) trait PixooCommandStore {
     fn mode(&self) -> PixooCommandStoreMode;
     fn append(&mut self, command: String);
     fn to_payload(self: Box<Self>) -> (usize, String);
+    fn to_requests(self: Box<Self>) -> Vec<String>;
 }
 
 /// Command store for storing a single command.
@@ -36,20 +37,32 @@ impl PixooCommandStore for PixooSingleCommandStore {
         let command_count = if self.command.is_empty() { 0 } else { 1 };
         (command_count, self.command)
     }
+
+    fn to_requests(self: Box<Self>) -> Vec<String> {
+        if self.command.is_empty() {
+            return vec![];
+        }
+
+        vec![self.command]
+    }
 }
 
 /// Command store for storing multiple commands and batch execution
 pub(crate) struct PixooBatchedCommandStore {
-    commands: String,
-    command_count: usize,
+    commands: Vec<String>,
 }
 
 impl PixooBatchedCommandStore {
     pub fn new() -> PixooBatchedCommandStore {
-        PixooBatchedCommandStore {
-            commands: "{\"Command\":\"Draw/CommandList\",\"CommandList\": [".to_string(),
-            command_count: 0,
-        }
+        PixooBatchedCommandStore { commands: vec![] }
+    }
+
+    /// Create the payload that runs all requests in a batch.
+    pub fn to_batch_payload(requests: &[String]) -> String {
+        format!(
+            "{{\"Command\":\"Draw/CommandList\",\"CommandList\": [{}]}}",
+            requests.join(",")
+        )
     }
 }
 
@@ -59,18 +72,17 @@ impl PixooCommandStore for PixooBatchedCommandStore {
     }
 
     fn append(&mut self, command: String) {
-        if self.command_count > 0 {
-            self.commands.push(',');
-        }
-
-        self.commands.push_str(&command);
-        self.command_count += 1;
+        self.commands.push(command);
     }
 
     fn to_payload(self: Box<Self>) -> (usize, String) {
-        let mut payload = self.commands;
-        payload.push_str("]}");
+        (
+            self.commands.len(),
+            PixooBatchedCommandStore::to_batch_payload(&self.commands),
+        )
+    }
 
-        (self.command_count, payload)
+    fn to_requests(self: Box<Self>) -> Vec<String> {
+        self.commands
     }
 }
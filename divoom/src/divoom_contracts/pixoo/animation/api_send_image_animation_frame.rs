@@ -1,7 +1,11 @@
 #[doc = include_str!("./api_send_image_animation_frame.md")]
 use crate::divoom_contracts::pixoo::common::*;
-use crate::DivoomImageAnimation;
+use crate::{DivoomAPIError, DivoomAPIResult, DivoomImageAnimation};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const SEND_IMAGE_ANIMATION_FRAME_COMMAND: &str = "Draw/SendHttpGif";
+const EXECUTE_COMMANDS_COMMAND: &str = "Draw/CommandList";
 
 // Request
 define_pixoo_command_request!(
@@ -16,6 +20,9 @@ pub struct DivoomPixooCommandAnimationSendImageAnimationFrameRequestPayload {
     pub pic_num: i32,
     pub pic_width: i32,
     pub pic_offset: i32,
+
+    // Official doc uses "PicID", so we accept it as well when parsing the payloads from other tools.
+    #[serde(alias = "PicID")]
     pub pic_id: i32,
     pub pic_speed: i32,
     pub pic_data: String,
@@ -41,9 +48,187 @@ impl DivoomPixooCommandAnimationSendImageAnimationFrameRequestPayload {
             )
             .collect()
     }
+
+    /// Merge the frames back into an image animation. This is the reverse of `create_frames`.
+    ///
+    /// All frames must belong to the same animation. When the same frame offset shows up multiple times, the last one wins,
+    /// which matches the behavior on the device.
+    pub fn merge_frames(
+        payloads: Vec<DivoomPixooCommandAnimationSendImageAnimationFrameRequestPayload>,
+    ) -> DivoomAPIResult<(i32, DivoomImageAnimation)> {
+        let first = payloads.first().ok_or_else(|| {
+            DivoomAPIError::ParameterError("No image animation frame is found!".into())
+        })?;
+        let (id, frame_count, size, speed_in_ms) = (
+            first.pic_id,
+            first.pic_num,
+            first.pic_width,
+            first.pic_speed,
+        );
+        if frame_count <= 0 || size <= 0 {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid image animation: PicNum = {}, PicWidth = {}",
+                frame_count, size
+            )));
+        }
+
+        let mut frames = BTreeMap::new();
+        for payload in payloads {
+            if (
+                payload.pic_id,
+                payload.pic_num,
+                payload.pic_width,
+                payload.pic_speed,
+            ) != (id, frame_count, size, speed_in_ms)
+            {
+                return Err(DivoomAPIError::ParameterError(format!(
+                    "Frames from different animations are found: PicId = {}, PicNum = {}, PicWidth = {}, PicSpeed = {}. Expected: PicId = {}, PicNum = {}, PicWidth = {}, PicSpeed = {}",
+                    payload.pic_id, payload.pic_num, payload.pic_width, payload.pic_speed, id, frame_count, size, speed_in_ms
+                )));
+            }
+
+            if payload.pic_offset < 0 || payload.pic_offset >= frame_count {
+                return Err(DivoomAPIError::ParameterError(format!(
+                    "Invalid frame offset: PicOffset = {}, PicNum = {}",
+                    payload.pic_offset, frame_count
+                )));
+            }
+
+            let frame_data = base64::decode(&payload.pic_data).map_err(|e| {
+                DivoomAPIError::ResourceDecodeError(format!(
+                    "Invalid frame data: PicOffset = {}, Error = {}",
+                    payload.pic_offset, e
+                ))
+            })?;
+            if frame_data.len() != (size * size * 3) as usize {
+                return Err(DivoomAPIError::ResourceDecodeError(format!(
+                    "Invalid frame data size: PicOffset = {}, Size = {}, Expected = {}",
+                    payload.pic_offset,
+                    frame_data.len(),
+                    size * size * 3
+                )));
+            }
+
+            frames.insert(payload.pic_offset as u32, frame_data);
+        }
+
+        let animation = DivoomImageAnimation {
+            size: size as u32,
+            frame_count: frame_count as usize,
+            speed_in_ms,
+            frames,
+        };
+        Ok((id, animation))
+    }
+}
+
+/// Parsing image animations from the captured requests
+impl DivoomImageAnimation {
+    /// Parse the image animation from a captured request body, which can be either a single `Draw/SendHttpGif` command or
+    /// a `Draw/CommandList` batch. Other commands in the batch are ignored.
+    ///
+    /// This is useful for debugging the payloads generated by other tools, or converting the recorded requests to GIF.
+    pub fn from_pixoo_request(request_body: &str) -> DivoomAPIResult<DivoomImageAnimation> {
+        DivoomImageAnimation::from_pixoo_requests([request_body])
+    }
+
+    /// Parse the image animation from multiple captured request bodies, e.g. when frames are sent in separate requests.
+    /// Please see `from_pixoo_request` for more details.
+    pub fn from_pixoo_requests<'a, I: IntoIterator<Item = &'a str>>(
+        request_bodies: I,
+    ) -> DivoomAPIResult<DivoomImageAnimation> {
+        let mut payloads = vec![];
+        for request_body in request_bodies {
+            let request: serde_json::Value = serde_json::from_str(request_body)?;
+            let commands = match request["Command"].as_str() {
+                Some(EXECUTE_COMMANDS_COMMAND) => match request["CommandList"].as_array() {
+                    Some(command_list) => command_list.iter().collect(),
+                    None => {
+                        return Err(DivoomAPIError::ParameterError(
+                            "CommandList is not found in Draw/CommandList request!".into(),
+                        ))
+                    }
+                },
+                _ => vec![&request],
+            };
+
+            for command in commands {
+                if command["Command"].as_str() != Some(SEND_IMAGE_ANIMATION_FRAME_COMMAND) {
+                    continue;
+                }
+
+                let frame_request =
+                    DivoomPixooCommandAnimationSendImageAnimationFrameRequest::deserialize(
+                        command,
+                    )?;
+                payloads.push(frame_request.payload);
+            }
+        }
+
+        let (_, animation) =
+            DivoomPixooCommandAnimationSendImageAnimationFrameRequestPayload::merge_frames(
+                payloads,
+            )?;
+        Ok(animation)
+    }
 }
 
 // Response
 define_pixoo_command_response_without_payload!(
     DivoomPixooCommandAnimationSendImageAnimationFrameResponse
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_animation_can_be_parsed_from_separated_requests() {
+        let frame_data = base64::encode(vec![255u8; 16 * 16 * 3]);
+        let requests: Vec<String> = [1, 0, 1]
+            .iter()
+            .map(|offset| {
+                format!(
+                    r#"{{"Command":"Draw/SendHttpGif","PicNum":2,"PicWidth":16,"PicOffset":{},"PicID":3,"PicSpeed":100,"PicData":"{}"}}"#,
+                    offset, frame_data
+                )
+            })
+            .collect();
+
+        let animation =
+            DivoomImageAnimation::from_pixoo_requests(requests.iter().map(|x| x.as_str())).unwrap();
+        assert_eq!(animation.size, 16);
+        assert_eq!(animation.frame_count, 2);
+        assert_eq!(animation.speed_in_ms, 100);
+        assert_eq!(
+            animation.frames.keys().copied().collect::<Vec<u32>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn image_animation_parsing_should_fail_on_invalid_frames() {
+        let new_request = |id: i32, offset: i32, frame_size: usize| {
+            format!(
+                r#"{{"Command":"Draw/SendHttpGif","PicNum":2,"PicWidth":16,"PicOffset":{},"PicId":{},"PicSpeed":100,"PicData":"{}"}}"#,
+                offset,
+                id,
+                base64::encode(vec![0u8; frame_size])
+            )
+        };
+
+        for requests in [
+            vec![],
+            vec![
+                new_request(1, 0, 16 * 16 * 3),
+                new_request(2, 1, 16 * 16 * 3),
+            ],
+            vec![new_request(1, 2, 16 * 16 * 3)],
+            vec![new_request(1, 0, 3)],
+        ] {
+            let result =
+                DivoomImageAnimation::from_pixoo_requests(requests.iter().map(|x| x.as_str()));
+            assert!(result.is_err());
+        }
+    }
+}
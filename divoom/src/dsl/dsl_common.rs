@@ -1,6 +1,11 @@
-use crate::dsl::dsl_syntax::DivoomDeviceCommand;
+use crate::dsl::dsl_syntax::{
+    DivoomDeviceChannelCommand, DivoomDeviceCommand, DivoomDeviceSystemCommand,
+};
 use crate::DivoomAPIResult;
-use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
 pub struct DivoomDslOperation {
     pub command: DivoomDeviceCommand,
@@ -17,6 +22,39 @@ impl DivoomDslOperation {
             resource_loader: Arc::new(Mutex::new(resource_loader)),
         }
     }
+
+    /// Name of the command when it reads from device, e.g. "system get-settings". Read commands cannot be batched, so
+    /// they are sent to device right away, with all batched commands sent before them.
+    pub fn read_command_name(&self) -> Option<&'static str> {
+        match &self.command {
+            DivoomDeviceCommand::Channel(DivoomDeviceChannelCommand::Get) => Some("channel get"),
+            DivoomDeviceCommand::Channel(DivoomDeviceChannelCommand::GetClock) => {
+                Some("channel get-clock")
+            }
+            DivoomDeviceCommand::System(DivoomDeviceSystemCommand::GetSettings) => {
+                Some("system get-settings")
+            }
+            DivoomDeviceCommand::System(DivoomDeviceSystemCommand::GetTime) => {
+                Some("system get-time")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Result of a read command, e.g. "system get-settings".
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DivoomDslReadResult {
+    /// Name of the read command.
+    pub command: String,
+
+    /// Name of the script variable that captures the result, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variable: Option<String>,
+
+    /// Value returned from device.
+    pub value: serde_json::Value,
 }
 
 pub struct DivoomDslOperationResource {
@@ -31,8 +69,14 @@ impl DivoomDslOperationResource {
             data,
         }
     }
+
+    /// Check if the resource is a GIF. Resources from URLs might not have file extension, so the content is checked too.
+    pub fn is_gif(&self) -> bool {
+        self.name.ends_with(".gif") || self.data.starts_with(b"GIF8")
+    }
 }
 
+#[async_trait]
 pub trait DivoomDslOperationResourceLoader {
-    fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>>;
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>>;
 }
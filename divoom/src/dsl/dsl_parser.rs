@@ -1,51 +1,405 @@
 use crate::dsl::dsl_resource_loader::*;
 use crate::dsl::dsl_syntax::*;
-use crate::dsl::DivoomDslOperation;
+use crate::dsl::{DivoomDslOperation, DivoomDslOperationResourceLoader};
+use crate::dto::*;
 use crate::{DivoomAPIError, DivoomAPIResult};
+use clap::error::{ContextKind, ContextValue, ErrorKind};
 use clap::Parser;
 
 pub struct DivoomDslParser {}
 
 impl DivoomDslParser {
     pub fn parse(command_input: &str) -> DivoomAPIResult<DivoomDslOperation> {
+        DivoomDslParser::parse_with_error_column(command_input).map_err(|(e, _)| e)
+    }
+
+    /// Parse the command, and return the column (starting from 1) of the word that causes the error on failure, so
+    /// the error can be located in script files. The column points to the end of the command when no word can be
+    /// blamed, e.g. missing arguments.
+    pub(crate) fn parse_with_error_column(
+        command_input: &str,
+    ) -> Result<DivoomDslOperation, (DivoomAPIError, usize)> {
         let words = match shellwords::split(command_input) {
-            Err(e) => return Err(DivoomAPIError::ParameterError(e.to_string())),
+            Err(e) => {
+                return Err((
+                    DivoomAPIError::ParameterError(e.to_string()),
+                    find_unclosed_quote_column(command_input),
+                ))
+            }
             Ok(v) => v,
         };
 
-        let command_with_words = ["divoom".to_string()].into_iter().chain(words);
+        let command_with_words = ["divoom".to_string()].into_iter().chain(words.clone());
         let command: DivoomDeviceCommand =
             match DivoomDeviceCommand::try_parse_from(command_with_words) {
-                Err(e) => return Err(DivoomAPIError::ParameterError(e.to_string())),
+                Err(e) => {
+                    let blamed_word = find_clap_error_word(&words, &e);
+                    let column = match &blamed_word {
+                        Some(word) => find_word_column(command_input, &words, word),
+                        None => command_input.chars().count() + 1,
+                    };
+                    return Err((new_command_parse_error(&e, blamed_word), column));
+                }
                 Ok(v) => v,
             };
 
-        let mut resource_loader = DivoomDslOperationNoOpResourceLoader::new();
+        match validate_command_parameters(&command).and_then(|_| new_resource_loader(&command)) {
+            Err((e, blamed_word)) => {
+                let column = find_word_column(command_input, &words, &blamed_word);
+                Err((e, column))
+            }
+            Ok(resource_loader) => Ok(DivoomDslOperation::new(command, resource_loader)),
+        }
+    }
+}
+
+/// Validate the parameters of the command, so the invalid values are reported when parsing, instead of when running. On
+/// failure, the invalid value is returned as well, so the error can be located in the command.
+fn validate_command_parameters(
+    command: &DivoomDeviceCommand,
+) -> Result<(), (DivoomAPIError, String)> {
+    match command {
+        DivoomDeviceCommand::Channel(DivoomDeviceChannelCommand::SetCustomPage { page_index }) => {
+            blame(
+                DivoomCustomPageIndex::new_for_field("page_index", *page_index),
+                page_index,
+            )?;
+        }
+        DivoomDeviceCommand::System(DivoomDeviceSystemCommand::SetBrightness { brightness }) => {
+            blame(DivoomBrightness::new(*brightness), brightness)?;
+        }
+        DivoomDeviceCommand::System(DivoomDeviceSystemCommand::SetWhiteBalance { r, g, b }) => {
+            blame(DivoomWhiteBalanceLevel::new_for_field("r", *r), r)?;
+            blame(DivoomWhiteBalanceLevel::new_for_field("g", *g), g)?;
+            blame(DivoomWhiteBalanceLevel::new_for_field("b", *b), b)?;
+        }
+        DivoomDeviceCommand::Tool(DivoomDeviceToolCommand::Countdown {
+            minute, second, ..
+        }) => {
+            blame(DivoomCountdownMinute::new(*minute), minute)?;
+            blame(DivoomCountdownSecond::new(*second), second)?;
+        }
+        DivoomDeviceCommand::Tool(DivoomDeviceToolCommand::Buzzer {
+            play_total_time,
+            active_time_in_cycle,
+            off_time_in_cycle,
+        }) => {
+            blame(
+                DivoomBuzzerTime::new_for_field("play_total_time", *play_total_time),
+                play_total_time,
+            )?;
+            blame(
+                DivoomBuzzerTime::new_for_field("active_time_in_cycle", *active_time_in_cycle),
+                active_time_in_cycle,
+            )?;
+            blame(
+                DivoomBuzzerTime::new_for_field("off_time_in_cycle", *off_time_in_cycle),
+                off_time_in_cycle,
+            )?;
+        }
+        DivoomDeviceCommand::Animation(DivoomDeviceAnimationCommand::Text(
+            DivoomDeviceTextAnimationCommand::Set(args),
+        )) => {
+            blame(DivoomTextId::new(args.text_id), &args.text_id)?;
+            blame(DivoomTextFontIndex::new(args.font_index), &args.font_index)?;
+            blame(DivoomTextWidth::new(args.text_width), &args.text_width)?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn blame<T>(result: DivoomAPIResult<T>, value: &i32) -> Result<(), (DivoomAPIError, String)> {
+    result.map(|_| ()).map_err(|e| (e, value.to_string()))
+}
 
-        #[cfg(feature = "animation-builder")]
-        if let DivoomDeviceCommand::Animation(animation_command) = &command {
-            if let DivoomDeviceAnimationCommand::Image(animation_image_command) = animation_command
+/// Create the resource loader for the command. On failure, the word that causes the error is returned as well, so
+/// the error can be located in the command.
+fn new_resource_loader(
+    command: &DivoomDeviceCommand,
+) -> Result<Box<dyn DivoomDslOperationResourceLoader + Send>, (DivoomAPIError, String)> {
+    let mut resource_loader = DivoomDslOperationNoOpResourceLoader::new();
+
+    #[cfg(feature = "animation-builder")]
+    if let DivoomDeviceCommand::Animation(animation_command) = command {
+        if let DivoomDeviceAnimationCommand::Image(animation_image_command) = animation_command {
+            if let DivoomDeviceImageAnimationCommand::RenderGif { file_path, .. } =
+                animation_image_command
+            {
+                resource_loader = match new_url_resource_loader(file_path) {
+                    Err(e) => return Err((e, file_path.clone())),
+                    Ok(Some(v)) => v,
+                    Ok(None) => DivoomDslOperationFileResourceLoader::new(file_path),
+                };
+            } else if let DivoomDeviceImageAnimationCommand::RenderFiles {
+                file_pattern,
+                random,
+                prefetch_count,
+                watch,
+                newest_first,
+                ..
+            } = animation_image_command
             {
-                if let DivoomDeviceImageAnimationCommand::RenderGif { file_path, .. } =
-                    animation_image_command
-                {
-                    resource_loader = DivoomDslOperationFileResourceLoader::new(file_path);
-                } else if let DivoomDeviceImageAnimationCommand::RenderFiles {
-                    file_pattern,
-                    random,
-                    prefetch_count,
-                    ..
-                } = animation_image_command
-                {
-                    resource_loader = DivoomDslOperationGlobResourceLoader::new(
+                resource_loader = match new_url_resource_loader(file_pattern) {
+                    Err(e) => return Err((e, file_pattern.clone())),
+                    Ok(Some(_)) if *watch => {
+                        return Err((
+                            DivoomAPIError::ParameterError(
+                                "--watch only works with local folders, not URLs".into(),
+                            ),
+                            file_pattern.clone(),
+                        ))
+                    }
+                    Ok(Some(v)) => v,
+                    Ok(None) if *watch => Box::new(DivoomDslOperationWatchResourceLoader::new(
+                        file_pattern,
+                        *newest_first,
+                        *prefetch_count,
+                    )),
+                    Ok(None) => DivoomDslOperationGlobResourceLoader::new(
                         file_pattern,
                         *random,
                         *prefetch_count,
-                    );
+                    ),
+                };
+            }
+        }
+    }
+
+    Ok(resource_loader)
+}
+
+/// Convert the clap error to the command parse error, with the first paragraph of the clap message only, since the
+/// usage and the help hints are about the command line tool, not the DSL.
+fn new_command_parse_error(error: &clap::Error, word: Option<String>) -> DivoomAPIError {
+    let kind = match error.kind() {
+        ErrorKind::InvalidSubcommand | ErrorKind::UnrecognizedSubcommand => {
+            DivoomCommandParseErrorKind::UnknownCommand
+        }
+        // Clap reports unknown subcommands as unexpected arguments, when the command expects a subcommand.
+        ErrorKind::UnknownArgument if expects_subcommand(error) => {
+            DivoomCommandParseErrorKind::UnknownCommand
+        }
+        ErrorKind::UnknownArgument => DivoomCommandParseErrorKind::UnknownArgument,
+        ErrorKind::InvalidValue
+        | ErrorKind::ValueValidation
+        | ErrorKind::EmptyValue
+        | ErrorKind::InvalidUtf8 => DivoomCommandParseErrorKind::InvalidValue,
+        ErrorKind::MissingRequiredArgument
+        | ErrorKind::MissingSubcommand
+        | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+            DivoomCommandParseErrorKind::MissingArgument
+        }
+        _ => DivoomCommandParseErrorKind::InvalidArgument,
+    };
+
+    let message = match error.kind() {
+        ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => {
+            "Help and version are not supported in commands".to_string()
+        }
+        ErrorKind::MissingSubcommand | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+            "A subcommand is required but not provided".to_string()
+        }
+        _ => {
+            let rendered = error.to_string();
+            rendered
+                .trim_start_matches("error:")
+                .lines()
+                .map(|line| line.trim())
+                .take_while(|line| !line.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" ")
+        }
+    };
+
+    DivoomAPIError::CommandParseError {
+        kind,
+        word,
+        message,
+    }
+}
+
+fn expects_subcommand(error: &clap::Error) -> bool {
+    error.context().any(|(kind, value)| match (kind, value) {
+        (ContextKind::Usage, ContextValue::String(usage)) => usage.ends_with("<SUBCOMMAND>"),
+        _ => false,
+    })
+}
+
+/// Find the word that causes the clap error, e.g. invalid values, unknown subcommands or arguments, which are carried
+/// in the error context. No word is returned when it cannot be blamed, e.g. missing arguments.
+fn find_clap_error_word(words: &[String], error: &clap::Error) -> Option<String> {
+    let blamed_words: Vec<&str> = error
+        .context()
+        .filter(|(kind, _)| {
+            matches!(
+                kind,
+                ContextKind::InvalidValue
+                    | ContextKind::InvalidSubcommand
+                    | ContextKind::InvalidArg
+            )
+        })
+        .filter_map(|(_, value)| match value {
+            ContextValue::String(v) => Some(v.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    words
+        .iter()
+        .find(|word| blamed_words.iter().any(|blamed| word == blamed))
+        .cloned()
+}
+
+/// Find the start column of the first word that equals to the given one. The column points to the end of the command
+/// when the word is not found.
+fn find_word_column(command_input: &str, words: &[String], word: &str) -> usize {
+    let end_column = command_input.chars().count() + 1;
+    let word_index = match words.iter().position(|x| x == word) {
+        Some(v) => v,
+        None => return end_column,
+    };
+
+    match find_word_columns(command_input).get(word_index) {
+        Some(column) => *column,
+        None => end_column,
+    }
+}
+
+/// Find the start column of each word, using the same rules as shellwords: words are split by whitespace outside of
+/// quotes, and backslash escapes the next char.
+fn find_word_columns(command_input: &str) -> Vec<usize> {
+    let mut columns = Vec::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (index, c) in command_input.chars().enumerate() {
+        if !in_word && !c.is_whitespace() {
+            in_word = true;
+            columns.push(index + 1);
+        }
+
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, c) if c.is_whitespace() => in_word = false,
+            _ => {}
+        }
+    }
+
+    columns
+}
+
+fn find_unclosed_quote_column(command_input: &str) -> usize {
+    let mut quote: Option<(char, usize)> = None;
+    let mut escaped = false;
+
+    for (index, c) in command_input.chars().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match (quote, c) {
+            (Some(('\'', _)), '\'') => quote = None,
+            (Some(('\'', _)), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(('"', _)), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some((c, index + 1)),
+            _ => {}
+        }
+    }
+
+    match quote {
+        Some((_, column)) => column,
+        None => command_input.chars().count() + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn dsl_parser_should_report_error_column() {
+        let cases = [
+            ("channel set unknown", 13),
+            ("channel  unknown clock", 10),
+            ("system set-brightness \"80\" --foo", 28),
+            ("animation text set 0 \"test string", 22),
+            ("channel set-clock", 18),
+            ("animation image render-gif data:image/gif,GIF89a", 28),
+            ("system set-white-balance 255 300 0", 30),
+            ("animation text set 21 hello", 20),
+            (
+                "animation image render-files https://example.com/a.gif 64 --watch",
+                30,
+            ),
+        ];
+
+        for (command, expected_column) in cases {
+            match DivoomDslParser::parse_with_error_column(command) {
+                Err((_, column)) => assert_eq!(column, expected_column, "Command: {}", command),
+                Ok(_) => panic!("Command should fail to parse: {}", command),
+            }
+        }
+    }
+
+    #[test]
+    fn dsl_parser_should_report_structured_parse_errors() {
+        use DivoomCommandParseErrorKind::*;
+        let cases = [
+            ("channel unknown clock", UnknownCommand, Some("unknown"), "Found argument 'unknown' which wasn't expected, or isn't valid in this context"),
+            ("system set-brightness 80 --foo", UnknownArgument, Some("--foo"), "Found argument '--foo' which wasn't expected, or isn't valid in this context"),
+            ("channel set unknown", InvalidValue, Some("unknown"), "Invalid value \"unknown\" for '<CHANNEL_TYPE>': Invalid value for DivoomChannelType: invalid digit found in string"),
+            ("channel set-clock", MissingArgument, None, "The following required arguments were not provided: <CLOCK_ID>"),
+            ("channel", MissingArgument, None, "A subcommand is required but not provided"),
+        ];
+
+        for (command, expected_kind, expected_word, expected_message) in cases {
+            match DivoomDslParser::parse(command) {
+                Err(DivoomAPIError::CommandParseError {
+                    kind,
+                    word,
+                    message,
+                }) => {
+                    assert_eq!(kind, expected_kind, "Command: {}", command);
+                    assert_eq!(word.as_deref(), expected_word, "Command: {}", command);
+                    assert_eq!(message, expected_message, "Command: {}", command);
                 }
+                v => panic!(
+                    "Unexpected parse result for {}: {:?}",
+                    command,
+                    v.map(|x| x.command)
+                ),
             }
         }
+    }
+
+    #[test]
+    fn dsl_syntax_should_have_no_conflicting_arguments() {
+        DivoomDeviceCommand::command().debug_assert();
 
-        Ok(DivoomDslOperation::new(command, resource_loader))
+        let operation = DivoomDslParser::parse(
+            "animation image render-template clock -p '{\"color\":\"red\"}' --per-frame-parameters {}",
+        )
+        .unwrap();
+        assert_eq!(
+            operation.command.to_string(),
+            "animation image render-template clock '--parameters={\"color\":\"red\"}'"
+        );
     }
 }
@@ -0,0 +1,401 @@
+use crate::dsl::dsl_syntax::*;
+use std::fmt;
+
+#[cfg(feature = "animation-builder")]
+use crate::animation::*;
+
+/// Print the command in the canonical DSL form, which can be parsed back by `DivoomDslParser`. Positional arguments are
+/// always printed, while options are only printed when they are different from their default values.
+impl fmt::Display for DivoomDeviceCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words: Vec<String> = command_words(self).iter().map(|x| quote_word(x)).collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+impl DivoomDeviceCommand {
+    /// Name of the command without arguments, e.g. "system set-rotation-angle".
+    pub fn command_name(&self) -> String {
+        let name_word_count = match self {
+            DivoomDeviceCommand::Raw { .. } => 1,
+            DivoomDeviceCommand::Animation(_) => 3,
+            _ => 2,
+        };
+
+        command_words(self)
+            .into_iter()
+            .take(name_word_count)
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+fn command_words(command: &DivoomDeviceCommand) -> Vec<String> {
+    let mut words = Vec::new();
+    match command {
+        DivoomDeviceCommand::Channel(channel_command) => {
+            words.push("channel".into());
+            channel_command_words(channel_command, &mut words);
+        }
+        DivoomDeviceCommand::System(system_command) => {
+            words.push("system".into());
+            system_command_words(system_command, &mut words);
+        }
+        DivoomDeviceCommand::Tool(tool_command) => {
+            words.push("tool".into());
+            tool_command_words(tool_command, &mut words);
+        }
+        DivoomDeviceCommand::Animation(animation_command) => {
+            words.push("animation".into());
+            animation_command_words(animation_command, &mut words);
+        }
+        DivoomDeviceCommand::Batch(batch_command) => {
+            words.push("batch".into());
+            match batch_command {
+                DivoomDeviceBatchCommand::RunUrl { command_url } => {
+                    words.extend(["run-url".into(), command_url.clone()])
+                }
+            }
+        }
+        DivoomDeviceCommand::Raw { request } => words.extend(["raw".into(), request.clone()]),
+    }
+    words
+}
+
+fn channel_command_words(command: &DivoomDeviceChannelCommand, words: &mut Vec<String>) {
+    match command {
+        DivoomDeviceChannelCommand::Get => words.push("get".into()),
+        DivoomDeviceChannelCommand::GetClock => words.push("get-clock".into()),
+        DivoomDeviceChannelCommand::Set { channel_type } => {
+            words.extend(["set".into(), channel_type.to_string()])
+        }
+        DivoomDeviceChannelCommand::SetClock { clock_id } => {
+            words.extend(["set-clock".into(), clock_id.to_string()])
+        }
+        DivoomDeviceChannelCommand::SetCloudChannel { channel_type } => {
+            words.extend(["set-cloud-channel".into(), channel_type.to_string()])
+        }
+        DivoomDeviceChannelCommand::SetCustomPage { page_index } => {
+            words.extend(["set-custom-page".into(), page_index.to_string()])
+        }
+        DivoomDeviceChannelCommand::SetVisualizer { visualizer_index } => {
+            words.extend(["set-visualizer".into(), visualizer_index.to_string()])
+        }
+    }
+}
+
+fn system_command_words(command: &DivoomDeviceSystemCommand, words: &mut Vec<String>) {
+    match command {
+        DivoomDeviceSystemCommand::GetSettings => words.push("get-settings".into()),
+        DivoomDeviceSystemCommand::GetTime => words.push("get-time".into()),
+        DivoomDeviceSystemCommand::SetBrightness { brightness } => {
+            words.extend(["set-brightness".into(), brightness.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetTime { utc } => {
+            words.extend(["set-time".into(), utc.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetHighLightMode { mode } => {
+            words.extend(["set-high-light-mode".into(), mode.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetHourMode { mode } => {
+            words.extend(["set-hour-mode".into(), mode.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetMirrorMode { mode } => {
+            words.extend(["set-mirror-mode".into(), mode.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetRotationAngle { mode } => {
+            words.extend(["set-rotation-angle".into(), mode.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetScreenPowerState { power_state } => {
+            words.extend(["set-screen-power-state".into(), power_state.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetTemperatureUnit { unit } => {
+            words.extend(["set-temperature-unit".into(), unit.to_string()])
+        }
+        DivoomDeviceSystemCommand::SetTimeZone { time_zone } => {
+            words.extend(["set-time-zone".into(), time_zone.clone()])
+        }
+        DivoomDeviceSystemCommand::SetWeatherArea {
+            longitude,
+            latitude,
+        } => words.extend([
+            "set-weather-area".into(),
+            longitude.clone(),
+            latitude.clone(),
+        ]),
+        DivoomDeviceSystemCommand::SetWhiteBalance { r, g, b } => words.extend([
+            "set-white-balance".into(),
+            r.to_string(),
+            g.to_string(),
+            b.to_string(),
+        ]),
+    }
+}
+
+fn tool_command_words(command: &DivoomDeviceToolCommand, words: &mut Vec<String>) {
+    match command {
+        DivoomDeviceToolCommand::Countdown {
+            action,
+            minute,
+            second,
+        } => words.extend([
+            "countdown".into(),
+            action.to_string(),
+            minute.to_string(),
+            second.to_string(),
+        ]),
+        DivoomDeviceToolCommand::Noise { action } => {
+            words.extend(["noise".into(), action.to_string()])
+        }
+        DivoomDeviceToolCommand::Scoreboard {
+            blue_score,
+            red_score,
+        } => words.extend([
+            "scoreboard".into(),
+            blue_score.to_string(),
+            red_score.to_string(),
+        ]),
+        DivoomDeviceToolCommand::Stopwatch { action } => {
+            words.extend(["stopwatch".into(), action.to_string()])
+        }
+        DivoomDeviceToolCommand::Buzzer {
+            play_total_time,
+            active_time_in_cycle,
+            off_time_in_cycle,
+        } => {
+            words.extend(["buzzer".into(), play_total_time.to_string()]);
+            push_option(
+                words,
+                "-a",
+                active_time_in_cycle,
+                &DEFAULT_BUZZER_ACTIVE_TIME_IN_CYCLE,
+            );
+            push_option(
+                words,
+                "-o",
+                off_time_in_cycle,
+                &DEFAULT_BUZZER_OFF_TIME_IN_CYCLE,
+            );
+        }
+    }
+}
+
+fn animation_command_words(command: &DivoomDeviceAnimationCommand, words: &mut Vec<String>) {
+    match command {
+        DivoomDeviceAnimationCommand::Gif(DivoomDeviceGifAnimationCommand::Play(args)) => {
+            words.extend(["gif".into(), "play".into()]);
+            push_optional(words, "--file", &args.file);
+            push_optional(words, "--folder", &args.folder);
+            push_optional(words, "--url", &args.url);
+        }
+        DivoomDeviceAnimationCommand::Image(image_command) => {
+            words.push("image".into());
+            image_animation_command_words(image_command, words);
+        }
+        DivoomDeviceAnimationCommand::Text(DivoomDeviceTextAnimationCommand::Clear) => {
+            words.extend(["text".into(), "clear".into()])
+        }
+        DivoomDeviceAnimationCommand::Text(DivoomDeviceTextAnimationCommand::Set(args)) => {
+            words.extend([
+                "text".into(),
+                "set".into(),
+                args.text_id.to_string(),
+                args.text_string.clone(),
+            ]);
+            push_option(words, "-x", &args.x, &DEFAULT_TEXT_POSITION);
+            push_option(words, "-y", &args.y, &DEFAULT_TEXT_POSITION);
+            push_option(
+                words,
+                "-d",
+                &args.scroll_direction,
+                &DEFAULT_TEXT_SCROLL_DIRECTION,
+            );
+            push_option(words, "-f", &args.font_index, &DEFAULT_TEXT_FONT_INDEX);
+            push_option(words, "--width", &args.text_width, &DEFAULT_TEXT_WIDTH);
+            push_option(
+                words,
+                "--speed",
+                &args.speed_in_ms,
+                &DEFAULT_TEXT_SPEED_IN_MS,
+            );
+            push_option(words, "-r", &args.r, &DEFAULT_TEXT_COLOR);
+            push_option(words, "-g", &args.g, &DEFAULT_TEXT_COLOR);
+            push_option(words, "-b", &args.b, &DEFAULT_TEXT_COLOR);
+            push_option(words, "-a", &args.align, &DEFAULT_TEXT_ALIGN);
+        }
+    }
+}
+
+fn image_animation_command_words(
+    command: &DivoomDeviceImageAnimationCommand,
+    words: &mut Vec<String>,
+) {
+    match command {
+        DivoomDeviceImageAnimationCommand::ResetId => words.push("reset-id".into()),
+
+        #[cfg(feature = "animation-builder")]
+        DivoomDeviceImageAnimationCommand::RenderGif {
+            file_path,
+            size,
+            speed_in_ms,
+            fit,
+            rotation,
+            opacity,
+            keep_timing,
+        } => {
+            words.extend(["render-gif".into(), file_path.clone(), size.to_string()]);
+            push_render_options(words, *speed_in_ms, *fit, *rotation, *opacity);
+            push_flag(words, "--keep-timing", *keep_timing);
+        }
+
+        #[cfg(feature = "animation-builder")]
+        DivoomDeviceImageAnimationCommand::RenderFiles {
+            file_pattern,
+            size,
+            speed_in_ms,
+            fit,
+            rotation,
+            opacity,
+            random,
+            prefetch_count,
+            watch,
+            newest_first,
+            keep_timing,
+        } => {
+            words.extend([
+                "render-files".into(),
+                file_pattern.clone(),
+                size.to_string(),
+            ]);
+            push_render_options(words, *speed_in_ms, *fit, *rotation, *opacity);
+            push_flag(words, "--random", *random);
+            push_option(words, "--prefetch", prefetch_count, &DEFAULT_PREFETCH_COUNT);
+            push_flag(words, "--watch", *watch);
+            push_flag(words, "--newest-first", *newest_first);
+            push_flag(words, "--keep-timing", *keep_timing);
+        }
+
+        #[cfg(feature = "animation-builder")]
+        DivoomDeviceImageAnimationCommand::RenderTemplate {
+            template_name,
+            parameters,
+            per_frame_parameters,
+        } => {
+            words.extend(["render-template".into(), template_name.clone()]);
+            push_option(
+                words,
+                "--parameters",
+                parameters,
+                &DEFAULT_TEMPLATE_PARAMETERS.to_string(),
+            );
+            push_option(
+                words,
+                "--per-frame-parameters",
+                per_frame_parameters,
+                &DEFAULT_TEMPLATE_PARAMETERS.to_string(),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "animation-builder")]
+fn push_render_options(
+    words: &mut Vec<String>,
+    speed_in_ms: u64,
+    fit: DivoomDrawFitMode,
+    rotation: f32,
+    opacity: f32,
+) {
+    push_option(
+        words,
+        "--speed",
+        &speed_in_ms,
+        &DEFAULT_ANIMATION_SPEED_IN_MS,
+    );
+    push_option(words, "--fit", &fit, &DEFAULT_ANIMATION_FIT);
+    push_option(words, "--rotate", &rotation, &DEFAULT_ANIMATION_ROTATION);
+    push_option(words, "--opacity", &opacity, &DEFAULT_ANIMATION_OPACITY);
+}
+
+/// Options are printed with "=", so the values starting with "-", e.g. negative numbers, are not treated as options.
+fn push_option<T: fmt::Display + PartialEq>(
+    words: &mut Vec<String>,
+    name: &str,
+    value: &T,
+    default: &T,
+) {
+    if value != default {
+        words.push(format!("{}={}", name, value));
+    }
+}
+
+fn push_optional(words: &mut Vec<String>, name: &str, value: &Option<String>) {
+    if let Some(value) = value {
+        words.push(format!("{}={}", name, value));
+    }
+}
+
+#[cfg(feature = "animation-builder")]
+fn push_flag(words: &mut Vec<String>, name: &str, value: bool) {
+    if value {
+        words.push(name.to_string());
+    }
+}
+
+/// Quote the word with single quotes when it contains any char that shellwords treats specially, e.g. whitespaces,
+/// quotes and backslashes. Single quotes in the word are closed, escaped and reopened, e.g. it's -> 'it'\''s'.
+fn quote_word(word: &str) -> String {
+    let is_plain_char = |c: char| c.is_alphanumeric() || "_-+=.,:/@%*?[]{}".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain_char) {
+        return word.to_string();
+    }
+
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dsl::DivoomDslParser;
+
+    #[test]
+    fn dsl_printer_should_round_trip_commands() {
+        let commands = [
+            "channel get",
+            "channel set customPage",
+            "channel set-clock 100",
+            "system set-brightness 80",
+            "system set-weather-area -122.33 47.61",
+            "system set-time-zone 'America/Los Angeles'",
+            "tool countdown start 1 30",
+            "tool buzzer 1000 -a=60",
+            "animation gif play --url=https://example.com/a.gif",
+            "animation text set 1 'it'\\''s a \"test\"' -x=-5 --speed=50 -r=0 -a=left",
+            "animation image render-gif a.gif 32 --fit=fitX --rotate=-90 --opacity=0.5 --keep-timing",
+            "animation image render-files /photos/*.jpg 64 --prefetch=5 --watch --newest-first",
+            "animation image render-template clock '--parameters={\"color\":\"red\"}'",
+            "batch run-url https://example.com/commands.txt",
+            "raw '{\"Command\":\"Channel/GetIndex\"}'",
+        ];
+
+        for command in commands {
+            let operation = DivoomDslParser::parse(command).unwrap();
+            assert_eq!(operation.command.to_string(), command);
+        }
+    }
+
+    #[test]
+    fn dsl_printer_should_skip_default_options() {
+        let operation = DivoomDslParser::parse(
+            "animation image render-gif a.gif --speed 100 --fit center -r 0.0 --opacity 1.0",
+        )
+        .unwrap();
+        assert_eq!(
+            operation.command.to_string(),
+            "animation image render-gif a.gif 64"
+        );
+        assert_eq!(
+            operation.command.command_name(),
+            "animation image render-gif"
+        );
+    }
+}
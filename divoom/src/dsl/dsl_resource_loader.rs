@@ -1,13 +1,45 @@
 use crate::dsl::{DivoomDslOperationResource, DivoomDslOperationResourceLoader};
-use crate::{DivoomAPIError, DivoomAPIResult};
+use crate::{DivoomAPIError, DivoomAPIResult, DivoomServerErrorInfo};
+use async_trait::async_trait;
 use log::{debug, warn};
 
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use rand::Rng;
+use reqwest::header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
 use std::cmp::min;
+use std::collections::VecDeque;
 use std::fs;
 use std::mem::swap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const HTTP_RESOURCE_LOADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Max size of the downloaded resources. Larger ones are rejected, so a wrong URL, e.g. a video stream, cannot exhaust
+/// the memory. 16MB is far more than any animation the devices can show.
+const HTTP_RESOURCE_LOADER_MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Create resource loader for URLs, which can be "http://", "https://" or "data:". None is returned for other paths, so
+/// they can be loaded as files.
+pub(crate) fn new_url_resource_loader(
+    path: &str,
+) -> DivoomAPIResult<Option<Box<dyn DivoomDslOperationResourceLoader + Send>>> {
+    if path.starts_with("http://") || path.starts_with("https://") {
+        return Ok(Some(Box::new(DivoomDslOperationHttpResourceLoader::new(
+            path,
+        ))));
+    }
+
+    if path.starts_with("data:") {
+        let loader = DivoomDslOperationDataUrlResourceLoader::new(path)?;
+        return Ok(Some(Box::new(loader)));
+    }
+
+    Ok(None)
+}
 
 /// No op resource loader.
 /// - It doesn't load anything and always return failure when being called.
@@ -19,8 +51,9 @@ impl DivoomDslOperationNoOpResourceLoader {
     }
 }
 
+#[async_trait]
 impl DivoomDslOperationResourceLoader for DivoomDslOperationNoOpResourceLoader {
-    fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
         Err(DivoomAPIError::ResourceLoadError {
             source: std::io::Error::new(
                 std::io::ErrorKind::Unsupported,
@@ -47,8 +80,9 @@ impl DivoomDslOperationFileResourceLoader {
     }
 }
 
+#[async_trait]
 impl DivoomDslOperationResourceLoader for DivoomDslOperationFileResourceLoader {
-    fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
         let mut file_content = self.file_content.lock().unwrap();
 
         if file_content.is_none() {
@@ -172,8 +206,9 @@ impl DivoomDslOperationGlobResourceLoader {
     }
 }
 
+#[async_trait]
 impl DivoomDslOperationResourceLoader for DivoomDslOperationGlobResourceLoader {
-    fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
         let mut guarded_file_resources = self.file_resources.lock().unwrap();
         if guarded_file_resources.is_empty() {
             let mut guarded_file_path_candidates = self.file_path_candidates.lock().unwrap();
@@ -201,12 +236,438 @@ impl DivoomDslOperationResourceLoader for DivoomDslOperationGlobResourceLoader {
     }
 }
 
+/// Folder watching resource loader.
+/// - Lazy scan and start watching the folder when first time being called.
+/// - Newly added or modified files are returned next, even before the prefetched ones, so the files dropped into the
+///   folder show up right away.
+/// - Deleted files are removed from both candidates and prefetched files.
+/// - Files are returned in file name order, or from the newest to the oldest by modified time when `newest_first` is set.
+///   The folder is rescanned after all files are returned, so they are shown in loop.
+pub(crate) struct DivoomDslOperationWatchResourceLoader {
+    file_pattern: String,
+    newest_first: bool,
+    prefetch_count: usize,
+    watcher: Option<(RecommendedWatcher, glob::Pattern)>,
+    state: Arc<Mutex<DivoomDslOperationWatchResourceLoaderState>>,
+}
+
+#[derive(Default)]
+struct DivoomDslOperationWatchResourceLoaderState {
+    newest_first: bool,
+    new_file_paths: VecDeque<PathBuf>,
+    file_path_candidates: VecDeque<PathBuf>,
+    file_resources: VecDeque<Arc<DivoomDslOperationResource>>,
+}
+
+impl DivoomDslOperationWatchResourceLoaderState {
+    fn add_new_file(&mut self, file_path: &Path) {
+        self.remove_file(file_path);
+
+        // The newest file is returned first, otherwise the files are returned in the order they are added.
+        if self.newest_first {
+            self.new_file_paths.push_front(file_path.to_path_buf());
+        } else {
+            self.new_file_paths.push_back(file_path.to_path_buf());
+        }
+    }
+
+    fn remove_file(&mut self, file_path: &Path) {
+        self.new_file_paths.retain(|x| x != file_path);
+        self.file_path_candidates.retain(|x| x != file_path);
+        self.file_resources
+            .retain(|x| Path::new(&x.name) != file_path);
+    }
+}
+
+impl DivoomDslOperationWatchResourceLoader {
+    pub fn new(file_pattern: &str, newest_first: bool, prefetch_count: usize) -> Self {
+        DivoomDslOperationWatchResourceLoader {
+            file_pattern: file_pattern.to_string(),
+            newest_first,
+            prefetch_count,
+            watcher: None,
+            state: Arc::new(Mutex::new(DivoomDslOperationWatchResourceLoaderState {
+                newest_first,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn start_watching(&self) -> DivoomAPIResult<(RecommendedWatcher, glob::Pattern)> {
+        let (watch_dir, recursive_mode, matcher) = resolve_watch_pattern(&self.file_pattern)?;
+        debug!(
+            "Start watching folder: Path = {:?}, Pattern = {}",
+            watch_dir,
+            matcher.as_str()
+        );
+
+        let state = self.state.clone();
+        let event_matcher = matcher.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Err(e) => {
+                        warn!("Failed to watch folder: Error = {:?}", e);
+                        return;
+                    }
+                    Ok(v) => v,
+                };
+
+                let mut state = state.lock().unwrap();
+                for file_path in event.paths.iter().filter(|x| event_matcher.matches_path(x)) {
+                    match event.kind {
+                        EventKind::Create(_)
+                        | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => {
+                            debug!("File is added or modified: Path = {:?}", file_path);
+                            state.add_new_file(file_path);
+                        }
+
+                        // Renaming is reported differently across platforms, so we check the file to see which side it is.
+                        EventKind::Modify(ModifyKind::Name(_)) if file_path.is_file() => {
+                            debug!("File is renamed: Path = {:?}", file_path);
+                            state.add_new_file(file_path);
+                        }
+
+                        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                            debug!("File is removed: Path = {:?}", file_path);
+                            state.remove_file(file_path);
+                        }
+
+                        _ => {}
+                    }
+                }
+            })
+            .map_err(new_watch_error)?;
+
+        watcher
+            .watch(&watch_dir, recursive_mode)
+            .map_err(new_watch_error)?;
+        Ok((watcher, matcher))
+    }
+
+    fn scan_files(&self, matcher: &glob::Pattern) -> DivoomAPIResult<VecDeque<PathBuf>> {
+        debug!("Scanning files: Pattern = {}", matcher.as_str());
+
+        let glob_matches = match glob::glob(matcher.as_str()) {
+            Err(e) => return Err(DivoomAPIError::ParameterError(e.to_string())),
+            Ok(v) => v,
+        };
+
+        let mut file_paths: Vec<PathBuf> = glob_matches
+            .filter_map(|x| x.ok())
+            .filter(|x| x.is_file())
+            .collect();
+        if self.newest_first {
+            file_paths.sort_by_cached_key(|x| {
+                std::cmp::Reverse(fs::metadata(x).and_then(|m| m.modified()).ok())
+            });
+        } else {
+            file_paths.sort();
+        }
+
+        debug!(
+            "{} files found with file pattern: {}",
+            file_paths.len(),
+            matcher.as_str()
+        );
+        Ok(file_paths.into())
+    }
+}
+
+#[async_trait]
+impl DivoomDslOperationResourceLoader for DivoomDslOperationWatchResourceLoader {
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
+        if self.watcher.is_none() {
+            self.watcher = Some(self.start_watching()?);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        while let Some(file_path) = state.new_file_paths.pop_front() {
+            if let Some(resource) = load_watched_file_resource(&file_path) {
+                return Ok(resource);
+            }
+        }
+
+        if state.file_resources.is_empty() {
+            if state.file_path_candidates.is_empty() {
+                state.file_path_candidates = self.scan_files(&self.watcher.as_ref().unwrap().1)?;
+            }
+
+            while state.file_resources.len() < self.prefetch_count.max(1) {
+                let file_path = match state.file_path_candidates.pop_front() {
+                    None => break,
+                    Some(v) => v,
+                };
+
+                if let Some(resource) = load_watched_file_resource(&file_path) {
+                    state.file_resources.push_back(resource);
+                }
+            }
+        }
+
+        match state.file_resources.pop_front() {
+            Some(resource) => Ok(resource),
+            None => Err(DivoomAPIError::ResourceLoadError {
+                source: std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "Unable to load any resources, no files are found.",
+                ),
+            }),
+        }
+    }
+}
+
+/// Split the glob pattern into the folder to watch and the pattern that matches the absolute file paths in the events.
+fn resolve_watch_pattern(
+    file_pattern: &str,
+) -> DivoomAPIResult<(PathBuf, RecursiveMode, glob::Pattern)> {
+    let mut watch_dir = PathBuf::new();
+    let mut sub_pattern = PathBuf::new();
+    for component in Path::new(file_pattern).components() {
+        let is_pattern = component
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '[']);
+        if sub_pattern.as_os_str().is_empty() && !is_pattern {
+            watch_dir.push(component);
+        } else {
+            sub_pattern.push(component);
+        }
+    }
+
+    // Watch the parent folder when the pattern is a single file, so it can be picked up again after being replaced.
+    if sub_pattern.as_os_str().is_empty() {
+        if let Some(file_name) = watch_dir.file_name() {
+            sub_pattern.push(file_name);
+            watch_dir.pop();
+        }
+    }
+    if watch_dir.as_os_str().is_empty() {
+        watch_dir.push(".");
+    }
+
+    let watch_dir = watch_dir.canonicalize()?;
+    let recursive_mode = match sub_pattern.components().count() {
+        1 if !sub_pattern.to_string_lossy().contains("**") => RecursiveMode::NonRecursive,
+        _ => RecursiveMode::Recursive,
+    };
+
+    let matcher = glob::Pattern::new(&format!(
+        "{}{}{}",
+        glob::Pattern::escape(&watch_dir.to_string_lossy()),
+        std::path::MAIN_SEPARATOR,
+        sub_pattern.to_string_lossy()
+    ))
+    .map_err(|e| DivoomAPIError::ParameterError(e.to_string()))?;
+
+    Ok((watch_dir, recursive_mode, matcher))
+}
+
+fn load_watched_file_resource(file_path: &Path) -> Option<Arc<DivoomDslOperationResource>> {
+    // Newly added file can still be in writing, which will be added again when the data is modified.
+    match fs::read(file_path) {
+        Ok(file_content) if !file_content.is_empty() => Some(Arc::new(
+            DivoomDslOperationResource::new(&file_path.to_string_lossy(), file_content),
+        )),
+        Ok(_) => {
+            debug!("Skip loading empty file: Path = {:?}", file_path);
+            None
+        }
+        Err(e) => {
+            warn!("Failed to load file, skip failed and continue loading more: Path = {:?}, Error = {:?}", file_path, e);
+            None
+        }
+    }
+}
+
+fn new_watch_error(e: notify::Error) -> DivoomAPIError {
+    DivoomAPIError::ResourceLoadError {
+        source: std::io::Error::other(e.to_string()),
+    }
+}
+
+/// HTTP(S) URL resource loader.
+/// - Load the resource every time being called, so the latest content is used, e.g. webcam snapshots.
+/// - The last loaded content is cached and revalidated with ETag and Last-Modified headers, so unchanged resources are
+///   not downloaded again.
+/// - The cached content is returned when loading fails, so temporary network issues don't break the schedules.
+/// - Resources larger than 16MB are rejected.
+pub(crate) struct DivoomDslOperationHttpResourceLoader {
+    url: String,
+    http_client: reqwest::Client,
+    max_body_size: usize,
+    cached_resource: Option<DivoomDslOperationHttpCachedResource>,
+}
+
+struct DivoomDslOperationHttpCachedResource {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    resource: Arc<DivoomDslOperationResource>,
+}
+
+impl DivoomDslOperationHttpResourceLoader {
+    pub fn new(url: &str) -> Self {
+        DivoomDslOperationHttpResourceLoader {
+            url: url.to_string(),
+            http_client: reqwest::Client::new(),
+            max_body_size: HTTP_RESOURCE_LOADER_MAX_BODY_SIZE,
+            cached_resource: None,
+        }
+    }
+
+    /// Load the resource from server. None is returned when the cached content is not modified.
+    async fn load(&self) -> DivoomAPIResult<Option<DivoomDslOperationHttpCachedResource>> {
+        debug!("Loading resource: Url = {}", self.url);
+
+        let mut request = self
+            .http_client
+            .get(&self.url)
+            .timeout(HTTP_RESOURCE_LOADER_TIMEOUT);
+        if let Some(cached_resource) = &self.cached_resource {
+            if let Some(etag) = &cached_resource.etag {
+                request = request.header(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = &cached_resource.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED && self.cached_resource.is_some() {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(DivoomAPIError::ServerError(
+                DivoomServerErrorInfo::http_error(response.status().as_u16()),
+            ));
+        }
+
+        let etag = response.headers().get(ETAG).cloned();
+        let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+        let data = self.read_body(response).await?;
+        Ok(Some(DivoomDslOperationHttpCachedResource {
+            etag,
+            last_modified,
+            resource: Arc::new(DivoomDslOperationResource::new(&self.url, data)),
+        }))
+    }
+
+    /// Read the response body in chunks, so the download stops as soon as it goes beyond the max body size, even when
+    /// the server doesn't tell the content length.
+    async fn read_body(&self, mut response: reqwest::Response) -> DivoomAPIResult<Vec<u8>> {
+        if let Some(content_length) = response.content_length() {
+            if content_length > self.max_body_size as u64 {
+                return Err(self.new_body_too_large_error());
+            }
+        }
+
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if data.len() + chunk.len() > self.max_body_size {
+                return Err(self.new_body_too_large_error());
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(data)
+    }
+
+    fn new_body_too_large_error(&self) -> DivoomAPIError {
+        DivoomAPIError::ResourceLoadError {
+            source: std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Resource is larger than {} bytes: Url = {}",
+                    self.max_body_size, self.url
+                ),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl DivoomDslOperationResourceLoader for DivoomDslOperationHttpResourceLoader {
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
+        match self.load().await {
+            Ok(Some(loaded_resource)) => self.cached_resource = Some(loaded_resource),
+            Ok(None) => debug!(
+                "Resource is not modified, using cached content: Url = {}",
+                self.url
+            ),
+            Err(e) => {
+                if self.cached_resource.is_none() {
+                    return Err(e);
+                }
+
+                warn!(
+                    "Failed to load resource, using cached content instead: Url = {}, Error = {:?}",
+                    self.url, e
+                );
+            }
+        }
+
+        Ok(self.cached_resource.as_ref().unwrap().resource.clone())
+    }
+}
+
+/// Inline data URL resource loader, e.g. "data:image/png;base64,iVBORw0KGgo...".
+/// - Only base64 encoded data is supported.
+/// - The data is decoded when the loader is created, so invalid data can be found when the command is parsed.
+pub(crate) struct DivoomDslOperationDataUrlResourceLoader {
+    resource: Arc<DivoomDslOperationResource>,
+}
+
+impl DivoomDslOperationDataUrlResourceLoader {
+    pub fn new(data_url: &str) -> DivoomAPIResult<Self> {
+        let (header, data) = match data_url
+            .strip_prefix("data:")
+            .and_then(|x| x.split_once(','))
+        {
+            Some(v) => v,
+            None => {
+                return Err(DivoomAPIError::ParameterError(
+                    "Invalid data URL, expecting \"data:[<media type>];base64,<data>\"".into(),
+                ))
+            }
+        };
+
+        let media_type = match header.strip_suffix(";base64") {
+            Some(v) => v,
+            None => {
+                return Err(DivoomAPIError::ParameterError(
+                    "Only base64 encoded data URL is supported".into(),
+                ))
+            }
+        };
+
+        let data = base64::decode(data.trim()).map_err(|e| {
+            DivoomAPIError::ParameterError(format!("Invalid base64 data in data URL: {}", e))
+        })?;
+
+        Ok(DivoomDslOperationDataUrlResourceLoader {
+            resource: Arc::new(DivoomDslOperationResource::new(
+                &format!("data:{}", media_type),
+                data,
+            )),
+        })
+    }
+}
+
+#[async_trait]
+impl DivoomDslOperationResourceLoader for DivoomDslOperationDataUrlResourceLoader {
+    async fn next(&mut self) -> DivoomAPIResult<Arc<DivoomDslOperationResource>> {
+        Ok(self.resource.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::create_test_temp_dir;
 
-    #[test]
-    fn dsl_resource_loader_can_load_single_file() {
+    #[tokio::test]
+    async fn dsl_resource_loader_can_load_single_file() {
         let mut loader = DivoomDslOperationFileResourceLoader::new(
             "test_data/dsl_runner_tests/system_commands.json",
         );
@@ -214,11 +675,12 @@ mod tests {
         run_dsl_resource_loader_test(
             &mut loader,
             &vec!["test_data/dsl_runner_tests/system_commands.json".to_string()],
-        );
+        )
+        .await;
     }
 
-    #[test]
-    fn dsl_resource_loader_can_load_file_with_pattern() {
+    #[tokio::test]
+    async fn dsl_resource_loader_can_load_file_with_pattern() {
         let mut loader = DivoomDslOperationGlobResourceLoader::new(
             "test_data/dsl_runner_tests/*.json",
             false,
@@ -236,15 +698,155 @@ mod tests {
                 "test_data/dsl_runner_tests/tool_commands.json".to_string(),
                 "test_data/dsl_runner_tests/animation_commands.json".to_string(),
             ],
-        );
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn dsl_resource_loader_can_watch_folder_for_new_and_deleted_files() {
+        let watch_dir = create_test_temp_dir("divoom_dsl_resource_loader_watch_tests");
+        for file_name in ["a.txt", "b.txt", "c.txt", "ignored.json"] {
+            fs::write(watch_dir.join(file_name), file_name).unwrap();
+        }
+
+        let file_pattern = format!("{}/*.txt", watch_dir.to_string_lossy());
+        let mut loader = DivoomDslOperationWatchResourceLoader::new(&file_pattern, false, 3);
+        assert_eq!(loader.next().await.unwrap().data, b"a.txt");
+
+        // b.txt is prefetched already, but it should be evicted after being deleted.
+        fs::remove_file(watch_dir.join("b.txt")).unwrap();
+        fs::write(watch_dir.join("d.txt"), "d.txt").unwrap();
+        wait_for_watch_events(&loader, |state| {
+            state.new_file_paths.iter().any(|x| x.ends_with("d.txt"))
+                && !state
+                    .file_resources
+                    .iter()
+                    .any(|x| x.name.ends_with("b.txt"))
+        })
+        .await;
+
+        for expected_data in ["d.txt", "c.txt", "a.txt", "c.txt", "d.txt"] {
+            assert_eq!(loader.next().await.unwrap().data, expected_data.as_bytes());
+        }
+
+        fs::remove_dir_all(&watch_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dsl_resource_loader_can_watch_folder_with_newest_files_first() {
+        let watch_dir = create_test_temp_dir("divoom_dsl_resource_loader_watch_newest_tests");
+        for file_name in ["a.txt", "b.txt"] {
+            fs::write(watch_dir.join(file_name), file_name).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let file_pattern = format!("{}/*.txt", watch_dir.to_string_lossy());
+        let mut loader = DivoomDslOperationWatchResourceLoader::new(&file_pattern, true, 1);
+        assert_eq!(loader.next().await.unwrap().data, b"b.txt");
+        assert_eq!(loader.next().await.unwrap().data, b"a.txt");
+
+        fs::remove_dir_all(&watch_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dsl_resource_loader_can_load_data_url() {
+        let mut loader = new_url_resource_loader("data:text/plain;base64,aGVsbG8=")
+            .unwrap()
+            .unwrap();
+        let resource = loader.next().await.unwrap();
+        assert_eq!(resource.name, "data:text/plain");
+        assert_eq!(resource.data, b"hello");
+
+        assert!(new_url_resource_loader("data:text/plain,hello").is_err());
+        assert!(new_url_resource_loader("data:text/plain;base64,!!!").is_err());
+        assert!(new_url_resource_loader("test_data/1.gif")
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn dsl_resource_loader_can_load_http_url_with_etag_revalidation() {
+        let url_path = "/dsl_resource_loader_tests/snapshot.gif";
+        let full_response_mock = mockito::mock("GET", url_path)
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("ETag", "\"v1\"")
+            .with_body("GIF89a")
+            .expect(1)
+            .create();
+        let not_modified_mock = mockito::mock("GET", url_path)
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .expect(2)
+            .create();
+
+        let url = format!("{}{}", mockito::server_url(), url_path);
+        let mut loader = new_url_resource_loader(&url).unwrap().unwrap();
+        for _ in 0..3 {
+            let resource = loader.next().await.unwrap();
+            assert_eq!(resource.name, url);
+            assert_eq!(resource.data, b"GIF89a");
+            assert!(resource.is_gif());
+        }
+
+        full_response_mock.assert();
+        not_modified_mock.assert();
+
+        let broken_url_path = "/dsl_resource_loader_tests/broken.gif";
+        let _broken_mock = mockito::mock("GET", broken_url_path)
+            .with_status(500)
+            .create();
+        let mut loader =
+            new_url_resource_loader(&format!("{}{}", mockito::server_url(), broken_url_path))
+                .unwrap()
+                .unwrap();
+        assert!(loader.next().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn dsl_resource_loader_should_reject_http_url_larger_than_max_body_size() {
+        let url_path = "/dsl_resource_loader_tests/large.gif";
+        let _large_mock = mockito::mock("GET", url_path)
+            .with_status(200)
+            .with_body("GIF89a-large")
+            .create();
+
+        let mut loader = DivoomDslOperationHttpResourceLoader::new(&format!(
+            "{}{}",
+            mockito::server_url(),
+            url_path
+        ));
+        loader.max_body_size = 6;
+        match loader.next().await {
+            Err(DivoomAPIError::ResourceLoadError { source }) => {
+                assert_eq!(source.kind(), std::io::ErrorKind::InvalidData)
+            }
+            Err(e) => panic!("Unexpected error: {:?}", e),
+            Ok(_) => panic!("Resource larger than max body size should be rejected."),
+        }
+    }
+
+    /// Wait until the watcher has handled the file changes, since the events are delivered asynchronously.
+    async fn wait_for_watch_events(
+        loader: &DivoomDslOperationWatchResourceLoader,
+        condition: impl Fn(&DivoomDslOperationWatchResourceLoaderState) -> bool,
+    ) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !condition(&loader.state.lock().unwrap()) {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "Timed out waiting for the watch events."
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
     }
 
-    fn run_dsl_resource_loader_test(
+    async fn run_dsl_resource_loader_test(
         loader: &mut Box<dyn DivoomDslOperationResourceLoader + Send>,
         expected_resource_name_suffixes: &Vec<String>,
     ) {
         for expected_resource_name_suffix in expected_resource_name_suffixes {
-            let resource = loader.next().unwrap();
+            let resource = loader.next().await.unwrap();
             let normalized_resource_name = resource.name.replace('\\', "/");
             assert!(normalized_resource_name.ends_with(expected_resource_name_suffix));
             assert!(!resource.data.is_empty());
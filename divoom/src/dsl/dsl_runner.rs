@@ -1,13 +1,19 @@
+use crate::dsl::dsl_common::DivoomDslReadResult;
+use crate::dsl::dsl_script::*;
 use crate::dsl::dsl_syntax::*;
 use crate::dto::*;
 use crate::{DivoomAPIError, DivoomAPIResult, PixooClient, PixooCommandBuilder};
+use chrono::Local;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
 #[cfg(feature = "animation-builder")]
 use crate::{
     DivoomAnimationBuilder, DivoomAnimationResourceLoader, DivoomAnimationTemplateManager,
+    DivoomDrawFitMode,
 };
 
 use crate::dsl::DivoomDslOperation;
@@ -17,6 +23,18 @@ use tiny_skia::BlendMode;
 pub struct DivoomDslRunner<'a> {
     device_client: &'a PixooClient,
     command_builder: Option<PixooCommandBuilder>,
+    has_pending_commands: bool,
+
+    /// Operations in the current batch, so the failed command can be mapped back to the operation.
+    pending_operations: Vec<DivoomDslPendingOperation>,
+    single_command_fallback: bool,
+
+    /// Payloads of all batches in dry run mode, which are collected instead of being sent to device.
+    dry_run_payloads: Option<Vec<String>>,
+    next_dry_run_animation_id: i32,
+
+    /// Results of all read commands, e.g. "system get-settings".
+    read_results: Vec<DivoomDslReadResult>,
 
     #[cfg(feature = "animation-builder")]
     template_manager: Arc<DivoomAnimationTemplateManager>,
@@ -33,6 +51,12 @@ impl DivoomDslRunner<'_> {
         DivoomDslRunner {
             device_client,
             command_builder: Some(command_builder),
+            has_pending_commands: false,
+            pending_operations: Vec::new(),
+            single_command_fallback: false,
+            dry_run_payloads: None,
+            next_dry_run_animation_id: 1,
+            read_results: Vec::new(),
             template_manager,
         }
     }
@@ -44,22 +68,67 @@ impl DivoomDslRunner<'_> {
         DivoomDslRunner {
             device_client,
             command_builder: Some(command_builder),
+            has_pending_commands: false,
+            pending_operations: Vec::new(),
+            single_command_fallback: false,
+            dry_run_payloads: None,
+            next_dry_run_animation_id: 1,
+            read_results: Vec::new(),
         }
     }
 
+    /// Run in dry run mode, which collects the payloads of all batches in scripts instead of sending them to device.
+    /// The payloads can be retrieved with `dry_run_payloads()` after the script ends.
+    pub fn with_dry_run(mut self) -> Self {
+        self.dry_run_payloads = Some(Vec::new());
+        self
+    }
+
+    /// When a batch is rejected by the device, send the commands in it one by one to find the failed operation. Please
+    /// note that the commands before the failed one are sent to the device again.
+    pub fn with_single_command_fallback(mut self) -> Self {
+        self.single_command_fallback = true;
+        self
+    }
+
+    /// Payloads of all batches sent in dry run mode.
+    pub fn dry_run_payloads(&self) -> &[String] {
+        self.dry_run_payloads.as_deref().unwrap_or_default()
+    }
+
+    /// Results of all read commands in the order they run, including the ones captured by scripts.
+    pub fn read_results(&self) -> &[DivoomDslReadResult] {
+        &self.read_results
+    }
+
     pub async fn batch_operations(
         &mut self,
         operations: &[DivoomDslOperation],
     ) -> DivoomAPIResult<()> {
-        for operation in operations {
-            self.batch_operation(operation).await?;
+        for (index, operation) in operations.iter().enumerate() {
+            self.batch_operation_at_line(operation, index + 1).await?;
         }
 
         Ok(())
     }
 
+    /// Batch a single operation. If it fails, the error reports its position in the current batch as the line number.
     pub async fn batch_operation(&mut self, operation: &DivoomDslOperation) -> DivoomAPIResult<()> {
-        match &operation.command {
+        let line_number = self.pending_operations.len() + 1;
+        self.batch_operation_at_line(operation, line_number).await
+    }
+
+    async fn batch_operation_at_line(
+        &mut self,
+        operation: &DivoomDslOperation,
+        line_number: usize,
+    ) -> DivoomAPIResult<()> {
+        if operation.read_command_name().is_some() {
+            self.read_operation(operation, None).await?;
+            return Ok(());
+        }
+
+        let result = match &operation.command {
             DivoomDeviceCommand::Channel(channel_command) => {
                 self.batch_channel_command(operation, channel_command)
             }
@@ -90,12 +159,339 @@ impl DivoomDslRunner<'_> {
                 );
                 Ok(())
             }
+        };
+
+        if result.is_ok() {
+            self.has_pending_commands = true;
+            self.pending_operations.push(DivoomDslPendingOperation {
+                line_number,
+                name: operation.command.command_name(),
+                command_end: self.command_builder.as_ref().unwrap().command_count(),
+            });
         }
+        result
     }
 
-    #[allow(dead_code)]
+    /// Send all batched operations to device. Like scripts, the error tells which operation failed.
     pub async fn execute(mut self) -> DivoomAPIResult<()> {
-        self.command_builder.take().unwrap().execute().await
+        self.flush().await
+    }
+
+    /// Run the script. Commands are batched and sent to device together, until `sleep`, `wait` or reading device state
+    /// in the script, which sends all batched commands first. The remaining commands are sent when the script ends.
+    pub async fn run_script(&mut self, script: &DivoomDslScript) -> DivoomAPIResult<()> {
+        self.run_script_with_variables(script, HashMap::new()).await
+    }
+
+    /// Run the script with variables set ahead, which can also be used to override device state in dry run mode, e.g.
+    /// `channel`.
+    pub async fn run_script_with_variables(
+        &mut self,
+        script: &DivoomDslScript,
+        mut variables: HashMap<String, String>,
+    ) -> DivoomAPIResult<()> {
+        self.run_script_statements(&script.statements, &mut variables)
+            .await?;
+        self.flush().await
+    }
+
+    fn run_script_statements<'s>(
+        &'s mut self,
+        statements: &'s [DivoomDslStatement],
+        variables: &'s mut HashMap<String, String>,
+    ) -> Pin<Box<dyn Future<Output = DivoomAPIResult<()>> + Send + 's>> {
+        Box::pin(async move {
+            for statement in statements {
+                self.run_script_statement(statement, variables).await?;
+            }
+
+            Ok(())
+        })
+    }
+
+    async fn run_script_statement(
+        &mut self,
+        statement: &DivoomDslStatement,
+        variables: &mut HashMap<String, String>,
+    ) -> DivoomAPIResult<()> {
+        match statement {
+            DivoomDslStatement::Command(command) => match &command.operation {
+                Some(operation) => {
+                    self.batch_operation_at_line(operation, command.line_number)
+                        .await?
+                }
+                None => {
+                    let values = self.get_script_variables(&command.text, variables).await?;
+                    let operation = command.parse_with_variables(&values)?;
+                    self.batch_operation_at_line(&operation, command.line_number)
+                        .await?;
+                }
+            },
+
+            DivoomDslStatement::Set { name, value } => {
+                let value = self.eval_script_text(value, variables).await?;
+                variables.insert(name.clone(), value);
+            }
+
+            DivoomDslStatement::Calculate {
+                name,
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.eval_script_text(left, variables).await?;
+                let right = self.eval_script_text(right, variables).await?;
+                variables.insert(name.clone(), operator.eval(&left, &right)?);
+            }
+
+            DivoomDslStatement::Capture { name, command } => {
+                // Captured values can be set ahead in dry run mode, since the device is not available.
+                let prefix = format!("{}.", name);
+                if self.dry_run_payloads.is_some()
+                    && variables
+                        .keys()
+                        .any(|x| x == name || x.starts_with(&prefix))
+                {
+                    return Ok(());
+                }
+
+                let value = match &command.operation {
+                    Some(operation) => self.read_operation(operation, Some(name)).await?,
+                    None => {
+                        let values = self.get_script_variables(&command.text, variables).await?;
+                        let operation = command.parse_with_variables(&values)?;
+                        self.read_operation(&operation, Some(name)).await?
+                    }
+                };
+                set_captured_variables(variables, name, &value);
+            }
+
+            DivoomDslStatement::If {
+                condition,
+                then_statements,
+                else_statements,
+            } => {
+                let left = self.eval_script_text(&condition.left, variables).await?;
+                let right = self.eval_script_text(&condition.right, variables).await?;
+                let statements = match condition.eval(&left, &right) {
+                    true => then_statements,
+                    false => else_statements,
+                };
+                self.run_script_statements(statements, variables).await?;
+            }
+
+            DivoomDslStatement::Repeat { count, statements } => {
+                let count_text = self.eval_script_text(count, variables).await?;
+                let count = count_text.parse::<u32>().map_err(|_| {
+                    DivoomAPIError::ParameterError(format!("Invalid repeat count: {}", count_text))
+                })?;
+
+                // Restore the index of outer loop after the inner loop ends, so nested loops work as expected.
+                let outer_repeat_index = variables.remove("repeat_index");
+                for repeat_index in 0..count {
+                    variables.insert("repeat_index".into(), repeat_index.to_string());
+                    self.run_script_statements(statements, variables).await?;
+                }
+                variables.remove("repeat_index");
+                if let Some(outer_repeat_index) = outer_repeat_index {
+                    variables.insert("repeat_index".into(), outer_repeat_index);
+                }
+            }
+
+            DivoomDslStatement::Sleep(duration) => {
+                self.flush().await?;
+
+                // Nothing is sent to device in dry run mode, so there is no need to wait for it.
+                if self.dry_run_payloads.is_none() {
+                    tokio::time::sleep(*duration).await;
+                }
+            }
+
+            DivoomDslStatement::Wait => self.flush().await?,
+        }
+
+        Ok(())
+    }
+
+    async fn eval_script_text(
+        &mut self,
+        text: &str,
+        variables: &HashMap<String, String>,
+    ) -> DivoomAPIResult<String> {
+        let values = self.get_script_variables(text, variables).await?;
+        replace_script_variables(text, |name| Ok(values[name].clone()))
+    }
+
+    /// Same as `eval_script_text`, but the values are escaped, so each of them stays in a single word of the command.
+    async fn get_script_variables(
+        &mut self,
+        text: &str,
+        variables: &HashMap<String, String>,
+    ) -> DivoomAPIResult<HashMap<String, String>> {
+        let mut names = script_variable_names(text);
+        names.sort();
+        names.dedup();
+
+        let mut values = HashMap::new();
+        for name in names {
+            let value = self.get_script_variable(&name, variables).await?;
+            values.insert(name, value);
+        }
+
+        Ok(values)
+    }
+
+    async fn get_script_variable(
+        &mut self,
+        name: &str,
+        variables: &HashMap<String, String>,
+    ) -> DivoomAPIResult<String> {
+        if let Some(value) = variables.get(name) {
+            return Ok(value.clone());
+        }
+
+        let now = Local::now();
+        let value = match name {
+            "channel" => {
+                if self.dry_run_payloads.is_some() {
+                    return Err(DivoomAPIError::ParameterError(format!(
+                        "Device state is not available in dry run mode, please set it as variable: {}",
+                        name
+                    )));
+                }
+
+                // The batched commands might change the channel, so we need to send them before reading it.
+                self.flush().await?;
+                self.device_client.get_current_channel().await?.to_string()
+            }
+            "time" => now.format("%H:%M").to_string(),
+            "hour" => now.format("%H").to_string(),
+            "minute" => now.format("%M").to_string(),
+            "weekday" => now.format("%a").to_string().to_lowercase(),
+            _ => {
+                return Err(DivoomAPIError::ParameterError(format!(
+                    "Undefined script variable: {}",
+                    name
+                )))
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Send all batched commands to device, then run the read command and record its result.
+    async fn read_operation(
+        &mut self,
+        operation: &DivoomDslOperation,
+        variable: Option<&str>,
+    ) -> DivoomAPIResult<serde_json::Value> {
+        let command_name = match operation.read_command_name() {
+            Some(v) => v,
+            None => {
+                return Err(DivoomAPIError::ParameterError(
+                    "Only read commands can be captured, e.g. channel get, channel get-clock, system get-settings or system get-time".into(),
+                ))
+            }
+        };
+
+        if self.dry_run_payloads.is_some() {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Device state is not available in dry run mode, please set the captured value as variable: {}",
+                command_name
+            )));
+        }
+
+        // The batched commands might change the device state, so we need to send them before reading it.
+        self.flush().await?;
+        let value = match &operation.command {
+            DivoomDeviceCommand::Channel(DivoomDeviceChannelCommand::Get) => {
+                serde_json::to_value(self.device_client.get_current_channel().await?)?
+            }
+            DivoomDeviceCommand::Channel(DivoomDeviceChannelCommand::GetClock) => {
+                serde_json::to_value(self.device_client.get_selected_clock_info().await?)?
+            }
+            DivoomDeviceCommand::System(DivoomDeviceSystemCommand::GetSettings) => {
+                serde_json::to_value(self.device_client.get_device_settings().await?)?
+            }
+            DivoomDeviceCommand::System(DivoomDeviceSystemCommand::GetTime) => {
+                serde_json::to_value(self.device_client.get_device_time().await?)?
+            }
+            _ => unreachable!("Read command is not handled: {}", command_name),
+        };
+
+        self.read_results.push(DivoomDslReadResult {
+            command: command_name.to_string(),
+            variable: variable.map(|x| x.to_string()),
+            value: value.clone(),
+        });
+        Ok(value)
+    }
+
+    /// Send all batched commands to device and start a new batch. Nothing is sent if no command is batched.
+    async fn flush(&mut self) -> DivoomAPIResult<()> {
+        if !self.has_pending_commands {
+            return Ok(());
+        }
+
+        let command_builder = self
+            .command_builder
+            .replace(self.device_client.start_batch())
+            .unwrap();
+        self.has_pending_commands = false;
+        let pending_operations = std::mem::take(&mut self.pending_operations);
+
+        let result = match &mut self.dry_run_payloads {
+            None => command_builder
+                .execute_with_result(self.single_command_fallback)
+                .await
+                .and_then(|x| x.into_result()),
+            Some(dry_run_payloads) => command_builder.into_validated().map(|x| {
+                let (_, _, payload) = x.build();
+                dry_run_payloads.push(payload);
+            }),
+        };
+
+        result.map_err(|e| new_operation_error(&pending_operations, e))
+    }
+
+    async fn get_next_animation_id(&mut self) -> DivoomAPIResult<i32> {
+        if self.dry_run_payloads.is_some() {
+            self.next_dry_run_animation_id += 1;
+            return Ok(self.next_dry_run_animation_id - 1);
+        }
+
+        self.device_client.get_next_animation_id().await
+    }
+
+    #[cfg(feature = "animation-builder")]
+    fn max_animation_frame_count(&self) -> usize {
+        self.device_client
+            .device_model()
+            .profile()
+            .max_animation_frame_count
+    }
+
+    #[cfg(feature = "animation-builder")]
+    fn draw_gif(
+        &self,
+        animation_builder: DivoomAnimationBuilder,
+        gif_data: &[u8],
+        keep_timing: bool,
+        fit: DivoomDrawFitMode,
+        rotation: f32,
+        opacity: f32,
+    ) -> DivoomAPIResult<DivoomImageAnimation> {
+        let animation_builder = if keep_timing {
+            let gif = DivoomAnimationResourceLoader::from_gif_sequence_buf(gif_data)?;
+            animation_builder
+                .with_max_frame_count(self.max_animation_frame_count())
+                .draw_frame_sequence_fit(&gif, 0, fit, rotation, opacity, BlendMode::default())
+        } else {
+            let gif = DivoomAnimationResourceLoader::from_gif_buf(gif_data)?;
+            animation_builder.draw_frames_fit(&gif, 0, fit, rotation, opacity, BlendMode::default())
+        };
+
+        Ok(animation_builder.build())
     }
 
     #[allow(dead_code)]
@@ -110,6 +506,10 @@ impl DivoomDslRunner<'_> {
         command: &DivoomDeviceChannelCommand,
     ) -> DivoomAPIResult<()> {
         match command {
+            DivoomDeviceChannelCommand::Get | DivoomDeviceChannelCommand::GetClock => {
+                return Err(new_read_command_batched_error())
+            }
+
             DivoomDeviceChannelCommand::Set { channel_type } => {
                 self.command_builder = Some(
                     self.command_builder
@@ -161,6 +561,10 @@ impl DivoomDslRunner<'_> {
         command: &DivoomDeviceSystemCommand,
     ) -> DivoomAPIResult<()> {
         match command {
+            DivoomDeviceSystemCommand::GetSettings | DivoomDeviceSystemCommand::GetTime => {
+                return Err(new_read_command_batched_error())
+            }
+
             DivoomDeviceSystemCommand::SetTime { utc } => {
                 self.command_builder =
                     Some(self.command_builder.take().unwrap().set_device_time(*utc))
@@ -404,17 +808,28 @@ impl DivoomDslRunner<'_> {
                 fit,
                 rotation,
                 opacity,
+                keep_timing,
                 ..
             } => {
                 let animation_builder =
                     DivoomAnimationBuilder::new(*canvas_size, Duration::from_millis(*speed_in_ms))?;
-                let gif_file_data = operation.resource_loader.lock().unwrap().as_mut().next()?;
-                let gif = DivoomAnimationResourceLoader::from_gif_buf(&gif_file_data.data)?;
-                let animation = animation_builder
-                    .draw_frames_fit(&gif, 0, *fit, *rotation, *opacity, BlendMode::default())
-                    .build();
-
-                let animation_id = self.device_client.get_next_animation_id().await?;
+                let gif_file_data = operation
+                    .resource_loader
+                    .lock()
+                    .await
+                    .as_mut()
+                    .next()
+                    .await?;
+                let animation = self.draw_gif(
+                    animation_builder,
+                    &gif_file_data.data,
+                    *keep_timing,
+                    *fit,
+                    *rotation,
+                    *opacity,
+                )?;
+
+                let animation_id = self.get_next_animation_id().await?;
                 self.command_builder = Some(
                     self.command_builder
                         .take()
@@ -430,6 +845,7 @@ impl DivoomDslRunner<'_> {
                 fit,
                 rotation,
                 opacity,
+                keep_timing,
                 ..
             } => {
                 let animation: DivoomImageAnimation;
@@ -437,12 +853,22 @@ impl DivoomDslRunner<'_> {
                 let mut animation_builder =
                     DivoomAnimationBuilder::new(*canvas_size, Duration::from_millis(*speed_in_ms))?;
 
-                let file_resource = operation.resource_loader.lock().unwrap().as_mut().next()?;
-                if file_resource.name.ends_with(".gif") {
-                    let gif = DivoomAnimationResourceLoader::from_gif_buf(&file_resource.data)?;
-                    animation = animation_builder
-                        .draw_frames_fit(&gif, 0, *fit, *rotation, *opacity, BlendMode::default())
-                        .build();
+                let file_resource = operation
+                    .resource_loader
+                    .lock()
+                    .await
+                    .as_mut()
+                    .next()
+                    .await?;
+                if file_resource.is_gif() {
+                    animation = self.draw_gif(
+                        animation_builder,
+                        &file_resource.data,
+                        *keep_timing,
+                        *fit,
+                        *rotation,
+                        *opacity,
+                    )?;
                 } else {
                     let image = DivoomAnimationResourceLoader::from_image_buf(&file_resource.data)?;
                     animation_builder.build_frame(0).draw_frame_fit(
@@ -455,7 +881,7 @@ impl DivoomDslRunner<'_> {
                     animation = animation_builder.build();
                 }
 
-                let animation_id = self.device_client.get_next_animation_id().await?;
+                let animation_id = self.get_next_animation_id().await?;
                 self.command_builder = Some(
                     self.command_builder
                         .take()
@@ -471,13 +897,16 @@ impl DivoomDslRunner<'_> {
                 per_frame_parameters,
             } => {
                 let parsed_parameters: HashMap<String, String> = serde_json::from_str(parameters)?;
-                let parsed_per_frame_parameters: HashMap<usize, HashMap<String, String>> = serde_json::from_str(per_frame_parameters)?;
+                let parsed_per_frame_parameters: HashMap<usize, HashMap<String, String>> =
+                    serde_json::from_str(per_frame_parameters)?;
 
-                let animation = self
-                    .template_manager
-                    .render_template(template_name, &parsed_parameters, &parsed_per_frame_parameters)?;
+                let animation = self.template_manager.render_template(
+                    template_name,
+                    &parsed_parameters,
+                    &parsed_per_frame_parameters,
+                )?;
 
-                let animation_id = self.device_client.get_next_animation_id().await?;
+                let animation_id = self.get_next_animation_id().await?;
                 self.command_builder = Some(
                     self.command_builder
                         .take()
@@ -534,10 +963,65 @@ impl DivoomDslRunner<'_> {
     }
 }
 
+/// Operation batched in the current batch, which owns the commands before `command_end` in the command builder.
+struct DivoomDslPendingOperation {
+    line_number: usize,
+    name: String,
+    command_end: usize,
+}
+
+/// Map the failed command in the batch back to the operation that creates it.
+fn new_operation_error(
+    pending_operations: &[DivoomDslPendingOperation],
+    e: DivoomAPIError,
+) -> DivoomAPIError {
+    let command_index = match e.failed_command() {
+        Some((command_index, _)) => command_index,
+        None => return e,
+    };
+
+    match pending_operations
+        .iter()
+        .find(|x| command_index < x.command_end)
+    {
+        Some(operation) => DivoomAPIError::OperationError {
+            line_number: operation.line_number,
+            operation: operation.name.clone(),
+            source: Box::new(e),
+        },
+        None => e,
+    }
+}
+
+fn new_read_command_batched_error() -> DivoomAPIError {
+    DivoomAPIError::ParameterError("Read commands cannot be batched".into())
+}
+
+/// Save the captured value as variable. Fields of objects are also saved as `name.field`, so they can be used directly.
+fn set_captured_variables(
+    variables: &mut HashMap<String, String>,
+    name: &str,
+    value: &serde_json::Value,
+) {
+    let text = match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(v) => v.clone(),
+        v => v.to_string(),
+    };
+    variables.insert(name.to_string(), text);
+
+    if let serde_json::Value::Object(fields) = value {
+        for (field_name, field_value) in fields {
+            set_captured_variables(variables, &format!("{}.{}", name, field_name), field_value);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::dsl::dsl_parser::DivoomDslParser;
+    use crate::test_utils::mock_pixoo_command;
     use crate::PixooClient;
     use std::{env, fs};
 
@@ -661,7 +1145,262 @@ mod tests {
         run_dsl_runner_parser_test(dsl_runner, "test_data/dsl_runner_tests/raw_commands.json");
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dsl_runner_should_run_script_with_batches_split_by_sleep_and_wait() {
+        let flash_on_mock = mock_pixoo_command(r#""Brightness":100[,}]"#, "{\"error_code\": 0}", 3);
+        let flash_off_mock = mock_pixoo_command(r#""Brightness":10[,}]"#, "{\"error_code\": 0}", 3);
+        let get_channel_mock = mock_pixoo_command(
+            "Channel/GetIndex",
+            "{\"error_code\": 0, \"SelectIndex\": 2}",
+            1,
+        );
+        let set_clock_mock = mock_pixoo_command(
+            r#""ClockId":100[,}].*Tools/SetStopWatch"#,
+            "{\"error_code\": 0}",
+            1,
+        );
+
+        let script = DivoomDslScript::parse(
+            r#"
+            set on 100
+            repeat 3
+                system set-brightness ${on}
+                sleep 10ms
+                system set-brightness 10
+                wait
+            end
+            if ${channel} == visualizer
+                channel set-clock 100
+            else
+                channel set clock
+            end
+            tool stopwatch stop
+            "#,
+        )
+        .unwrap();
+
+        let client = PixooClient::new(&mockito::server_address().to_string()).unwrap();
+        let mut dsl_runner = new_divoom_dsl_runner(&client);
+        dsl_runner.run_script(&script).await.unwrap();
+
+        flash_on_mock.assert();
+        flash_off_mock.assert();
+        get_channel_mock.assert();
+        set_clock_mock.assert();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dsl_runner_should_capture_read_results_into_variables() {
+        let get_settings_mock = mockito::mock("POST", "/post")
+            .match_body(mockito::Matcher::Regex("Channel/GetAllConf".into()))
+            .with_status(200)
+            .with_header("Content-Type", "application/json; charset=UTF-8")
+            .with_body(r#"{"error_code": 0, "Brightness": 50, "RotationFlag": 1, "ClockTime": 60, "GalleryTime": 60, "SingleGalleyTime": 5, "PowerOnChannelId": 1, "GalleryShowTimeFlag": 1, "CurClockId": 182, "Time24Flag": 1, "TemperatureMode": 1, "GyrateAngle": 1, "MirrorFlag": 1, "LightSwitch": 1}"#)
+            .expect(1)
+            .create();
+        let set_brightness_mock = mockito::mock("POST", "/post")
+            .match_body(mockito::Matcher::Regex(r#"Tools/SetStopWatch.*"Brightness":70[,}]|"Brightness":70[,}].*Tools/SetStopWatch"#.into()))
+            .with_status(200)
+            .with_header("Content-Type", "application/json; charset=UTF-8")
+            .with_body("{\"error_code\": 0}")
+            .expect(1)
+            .create();
+
+        let script = DivoomDslScript::parse(
+            r#"
+            capture settings system get-settings
+            set brightness ${settings.brightness} + 20
+            system set-brightness ${brightness}
+            tool stopwatch stop
+            "#,
+        )
+        .unwrap();
+
+        let client = PixooClient::new(&mockito::server_address().to_string()).unwrap();
+        let mut dsl_runner = new_divoom_dsl_runner(&client);
+        dsl_runner.run_script(&script).await.unwrap();
+
+        get_settings_mock.assert();
+        set_brightness_mock.assert();
+        let read_results = dsl_runner.read_results();
+        assert_eq!(read_results.len(), 1);
+        assert_eq!(read_results[0].command, "system get-settings");
+        assert_eq!(read_results[0].variable.as_deref(), Some("settings"));
+        assert_eq!(read_results[0].value["cur-clock-id"], 182);
+
+        // Device is not available in dry run mode, so captured values need to be set ahead.
+        let variables = [("settings.brightness".to_string(), "10".to_string())]
+            .into_iter()
+            .collect();
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_dry_run();
+        dsl_runner
+            .run_script_with_variables(&script, variables)
+            .await
+            .unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&dsl_runner.dry_run_payloads()[0]).unwrap();
+        assert_eq!(payload["CommandList"][0]["Brightness"], 30);
+        assert!(dsl_runner.read_results().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dsl_runner_should_collect_payloads_in_dry_run_mode() {
+        let script = DivoomDslScript::from_file("test_data/dsl_script_tests/alert.divoom").unwrap();
+        let client = PixooClient::new("127.0.0.1").unwrap();
+
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_dry_run();
+        dsl_runner.run_script(&script).await.unwrap();
+
+        let payloads: Vec<serde_json::Value> = dsl_runner
+            .dry_run_payloads()
+            .iter()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(payloads.len(), 7);
+        assert_eq!(payloads[0]["CommandList"][0]["Brightness"], 100);
+        assert_eq!(payloads[1]["CommandList"][0]["Brightness"], 10);
+        assert_eq!(
+            payloads[6]["CommandList"][0]["TextString"],
+            "Time to stand up!"
+        );
+
+        let script =
+            DivoomDslScript::parse("if ${channel} != clock\nchannel set clock\nend").unwrap();
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_dry_run();
+        assert!(dsl_runner.run_script(&script).await.is_err());
+
+        let variables = [("channel".to_string(), "clock".to_string())]
+            .into_iter()
+            .collect();
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_dry_run();
+        dsl_runner
+            .run_script_with_variables(&script, variables)
+            .await
+            .unwrap();
+        assert!(dsl_runner.dry_run_payloads().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dsl_runner_can_render_gif_from_data_url() {
+        let gif =
+            fs::read("test_data/animation_template_tests/expected_generated_simple.gif").unwrap();
+        let operation = DivoomDslParser::parse(&format!(
+            "animation image render-gif data:image/gif;base64,{} 16",
+            base64::encode(gif)
+        ))
+        .unwrap();
+
+        let client = PixooClient::new("127.0.0.1").unwrap();
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_dry_run();
+        dsl_runner.batch_operation(&operation).await.unwrap();
+        dsl_runner.flush().await.unwrap();
+
+        let payload: serde_json::Value =
+            serde_json::from_str(&dsl_runner.dry_run_payloads()[0]).unwrap();
+        assert_eq!(payload["CommandList"][0]["Command"], "Draw/SendHttpGif");
+        assert_eq!(payload["CommandList"][0]["PicWidth"], 16);
+    }
+
     #[cfg(feature = "animation-builder")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dsl_runner_should_report_failed_operation_with_single_command_fallback() {
+        // The whole batch is rejected, then the commands are sent one by one until the rejected one.
+        let batch_mock = mock_pixoo_command(
+            r#""Brightness":31[,}].*SetScreenRotationAngle.*"Brightness":32[,}]"#,
+            "{\"error_code\": 1}",
+            1,
+        );
+        let first_command_mock = mock_pixoo_command(
+            r#"\[\{[^{]*"Brightness":31[,}][^{]*\]\}$"#,
+            "{\"error_code\": 0}",
+            1,
+        );
+        let second_command_mock = mock_pixoo_command(
+            r#"\[\{[^{]*SetScreenRotationAngle","Mode":3[,}][^{]*\]\}$"#,
+            "{\"error_code\": 1}",
+            1,
+        );
+        let third_command_mock = mock_pixoo_command(
+            r#"\[\{[^{]*"Brightness":32[,}][^{]*\]\}$"#,
+            "{\"error_code\": 0}",
+            0,
+        );
+
+        let script = DivoomDslScript::parse(
+            r#"
+            system set-brightness 31
+            system set-rotation-angle 270
+            system set-brightness 32
+            "#,
+        )
+        .unwrap();
+
+        let client = PixooClient::new(&mockito::server_address().to_string()).unwrap();
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_single_command_fallback();
+        let error = dsl_runner.run_script(&script).await.unwrap_err();
+        assert_eq!(
+            error.failed_operation(),
+            Some((3, "system set-rotation-angle"))
+        );
+        assert_eq!(
+            error.failed_command(),
+            Some((1, "set_device_rotation_angle"))
+        );
+        assert_eq!(error.category(), DivoomErrorCategory::Unknown);
+        assert!(
+            error.to_string().starts_with("Operation at line 3 (system set-rotation-angle) failed: Command 1 (set_device_rotation_angle) failed"),
+            "{}",
+            error
+        );
+
+        batch_mock.assert();
+        first_command_mock.assert();
+        second_command_mock.assert();
+        third_command_mock.assert();
+    }
+
+    #[cfg(feature = "animation-builder")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dsl_runner_should_report_failed_operation_when_executing_batched_operations() {
+        let batch_mock = mock_pixoo_command(
+            r#""Brightness":33[,}].*SetScreenRotationAngle","Mode":2[,}]"#,
+            "{\"error_code\": 1}",
+            1,
+        );
+        let first_command_mock = mock_pixoo_command(
+            r#"\[\{[^{]*"Brightness":33[,}][^{]*\]\}$"#,
+            "{\"error_code\": 0}",
+            1,
+        );
+        let second_command_mock = mock_pixoo_command(
+            r#"\[\{[^{]*SetScreenRotationAngle","Mode":2[,}][^{]*\]\}$"#,
+            "{\"error_code\": 1}",
+            1,
+        );
+
+        let operations = vec![
+            DivoomDslParser::parse("system set-brightness 33").unwrap(),
+            DivoomDslParser::parse("system set-rotation-angle 180").unwrap(),
+        ];
+
+        let client = PixooClient::new(&mockito::server_address().to_string()).unwrap();
+        let mut dsl_runner = new_divoom_dsl_runner(&client).with_single_command_fallback();
+        dsl_runner.batch_operations(&operations).await.unwrap();
+        let error = dsl_runner.execute().await.unwrap_err();
+        assert_eq!(
+            error.failed_operation(),
+            Some((2, "system set-rotation-angle"))
+        );
+        assert_eq!(
+            error.failed_command(),
+            Some((1, "set_device_rotation_angle"))
+        );
+
+        batch_mock.assert();
+        first_command_mock.assert();
+        second_command_mock.assert();
+    }
+
     fn new_divoom_dsl_runner(pixoo_client: &PixooClient) -> DivoomDslRunner {
         let template_manager = Arc::new(DivoomAnimationTemplateManager::new(".").unwrap());
         DivoomDslRunner::new(&pixoo_client, template_manager)
@@ -0,0 +1,1093 @@
+use crate::dsl::dsl_parser::DivoomDslParser;
+use crate::dsl::DivoomDslOperation;
+use crate::{DivoomAPIError, DivoomAPIResult};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// DSL script, which runs DSL commands with variables, conditionals, loops and sleeps.
+///
+/// Script is line based, and every line is either a DSL command, e.g. `channel set clock`, or one of the statements
+/// below. Empty lines and lines starting with `#` are ignored.
+///
+/// - `set <name> <value>`: Set variable, which can be used as `${name}` in any line after it. In DSL commands, the value
+///   is escaped and used as literal text, so the spaces or quotes in it cannot split the word or add more arguments.
+/// - `set <name> <left> <operator> <right>`: Set variable to the result of the arithmetic, where operator can be `+`,
+///   `-`, `*`, `/` or `%`, e.g. `set brightness ${settings.brightness} + 20`.
+/// - `capture <name> <read command>`: Run the read command, e.g. `system get-settings`, and save the result as
+///   variable. When the result is an object, each field is saved as `${name.field}` as well, e.g.
+///   `${settings.brightness}`.
+/// - `if <left> <operator> <right>` ... `else` ... `end`: Run the statements based on the condition. Operator can be
+///   `==`, `!=`, `<`, `<=`, `>` or `>=`. Values are compared as numbers when both sides are numbers, otherwise as
+///   strings, so times like `${time} >= 08:30` also work.
+/// - `repeat <count>` ... `end`: Run the statements for `count` times. The current iteration is `${repeat_index}`,
+///   starting from 0.
+/// - `sleep <duration>`: Send all batched commands to device, then sleep for the duration, e.g. `500ms`, `2s` or `1m`.
+/// - `wait`: Send all batched commands to device, so the commands after it will see the updated device state.
+///
+/// Besides the variables set by the script, the device state and the current local time can also be used:
+/// `${channel}`, `${time}` (HH:MM), `${hour}`, `${minute}` and `${weekday}` (mon-sun).
+///
+/// Example of flashing alert 3 times, then returning to clock:
+///
+/// ```text
+/// repeat 3
+///     system set-brightness 100
+///     sleep 500ms
+///     system set-brightness 10
+///     sleep 500ms
+/// end
+/// if ${channel} != clock
+///     channel set clock
+/// end
+/// ```
+///
+/// Example of turning up the brightness based on the current one:
+///
+/// ```text
+/// capture settings system get-settings
+/// set brightness ${settings.brightness} + 20
+/// if ${brightness} > 100
+///     set brightness 100
+/// end
+/// system set-brightness ${brightness}
+/// ```
+#[derive(Debug)]
+pub struct DivoomDslScript {
+    pub statements: Vec<DivoomDslStatement>,
+}
+
+#[derive(Debug)]
+pub enum DivoomDslStatement {
+    Command(DivoomDslScriptCommand),
+    Set {
+        name: String,
+        value: String,
+    },
+    Calculate {
+        name: String,
+        left: String,
+        operator: DivoomDslArithmeticOperator,
+        right: String,
+    },
+    Capture {
+        name: String,
+        command: DivoomDslScriptCommand,
+    },
+    If {
+        condition: DivoomDslCondition,
+        then_statements: Vec<DivoomDslStatement>,
+        else_statements: Vec<DivoomDslStatement>,
+    },
+    Repeat {
+        count: String,
+        statements: Vec<DivoomDslStatement>,
+    },
+    Sleep(Duration),
+    Wait,
+}
+
+pub struct DivoomDslScriptCommand {
+    pub text: String,
+
+    /// Line of the command in the script, starting from 1. For commands in included files, it is the line in the
+    /// included file.
+    pub line_number: usize,
+
+    /// Parsed operation, when the command doesn't use any variable. Parsing it ahead of time helps us finding the errors
+    /// early, as well as keeping the state of resource loaders, e.g. the next file to render, across runs.
+    pub operation: Option<DivoomDslOperation>,
+
+    location: DivoomDslScriptLocation,
+}
+
+impl DivoomDslScriptCommand {
+    /// Parse the command with the variables replaced by their values. Errors are reported at their line and column in
+    /// the script, and the errors in a value are reported at its variable.
+    pub(crate) fn parse_with_variables(
+        &self,
+        values: &HashMap<String, String>,
+    ) -> DivoomAPIResult<DivoomDslOperation> {
+        let (text, variable_spans) =
+            replace_script_command_variables(&self.text, |name| Ok(values[name].clone()))?;
+
+        parse_located_script_command(&self.location, &text, |column| {
+            let offset = text
+                .char_indices()
+                .nth(column - 1)
+                .map_or(text.len(), |(offset, _)| offset);
+
+            let mut original_offset = offset;
+            for span in &variable_spans {
+                if offset < span.replaced_start {
+                    break;
+                }
+
+                if offset < span.replaced_end {
+                    original_offset = span.start;
+                    break;
+                }
+
+                original_offset = span.end + offset - span.replaced_end;
+            }
+
+            self.text[..original_offset].chars().count() + 1
+        })
+    }
+}
+
+impl fmt::Debug for DivoomDslScriptCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DivoomDslScriptCommand")
+            .field(&self.text)
+            .finish()
+    }
+}
+
+#[derive(Debug, PartialOrd, PartialEq, Clone)]
+pub struct DivoomDslCondition {
+    pub left: String,
+    pub operator: DivoomDslConditionOperator,
+    pub right: String,
+}
+
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub enum DivoomDslConditionOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl DivoomDslConditionOperator {
+    fn parse(operator: &str) -> Option<Self> {
+        match operator {
+            "==" => Some(DivoomDslConditionOperator::Equal),
+            "!=" => Some(DivoomDslConditionOperator::NotEqual),
+            "<" => Some(DivoomDslConditionOperator::Less),
+            "<=" => Some(DivoomDslConditionOperator::LessOrEqual),
+            ">" => Some(DivoomDslConditionOperator::Greater),
+            ">=" => Some(DivoomDslConditionOperator::GreaterOrEqual),
+            _ => None,
+        }
+    }
+}
+
+impl DivoomDslCondition {
+    /// Evaluate the condition with the values that have all variables replaced.
+    pub fn eval(&self, left: &str, right: &str) -> bool {
+        let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+            (Ok(l), Ok(r)) => l.partial_cmp(&r),
+            _ => Some(left.cmp(right)),
+        };
+
+        match self.operator {
+            DivoomDslConditionOperator::Equal => ordering == Some(Ordering::Equal),
+            DivoomDslConditionOperator::NotEqual => ordering != Some(Ordering::Equal),
+            DivoomDslConditionOperator::Less => ordering == Some(Ordering::Less),
+            DivoomDslConditionOperator::LessOrEqual => {
+                matches!(ordering, Some(Ordering::Less | Ordering::Equal))
+            }
+            DivoomDslConditionOperator::Greater => ordering == Some(Ordering::Greater),
+            DivoomDslConditionOperator::GreaterOrEqual => {
+                matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub enum DivoomDslArithmeticOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl DivoomDslArithmeticOperator {
+    fn parse(operator: &str) -> Option<Self> {
+        match operator {
+            "+" => Some(DivoomDslArithmeticOperator::Add),
+            "-" => Some(DivoomDslArithmeticOperator::Subtract),
+            "*" => Some(DivoomDslArithmeticOperator::Multiply),
+            "/" => Some(DivoomDslArithmeticOperator::Divide),
+            "%" => Some(DivoomDslArithmeticOperator::Remainder),
+            _ => None,
+        }
+    }
+
+    /// Evaluate the arithmetic with the values that have all variables replaced. Integral results are formatted without
+    /// decimals, so they can be passed to commands that take integers, e.g. `system set-brightness`.
+    pub fn eval(&self, left: &str, right: &str) -> DivoomAPIResult<String> {
+        let parse_number = |value: &str| {
+            value.trim().parse::<f64>().map_err(|_| {
+                DivoomAPIError::ParameterError(format!("Invalid number in arithmetic: {}", value))
+            })
+        };
+        let (l, r) = (parse_number(left)?, parse_number(right)?);
+
+        let result = match self {
+            DivoomDslArithmeticOperator::Add => l + r,
+            DivoomDslArithmeticOperator::Subtract => l - r,
+            DivoomDslArithmeticOperator::Multiply => l * r,
+            DivoomDslArithmeticOperator::Divide | DivoomDslArithmeticOperator::Remainder
+                if r == 0.0 =>
+            {
+                return Err(DivoomAPIError::ParameterError(format!(
+                    "Division by zero: {} {:?} {}",
+                    left, self, right
+                )))
+            }
+            DivoomDslArithmeticOperator::Divide => l / r,
+            DivoomDslArithmeticOperator::Remainder => l % r,
+        };
+
+        if result.fract() == 0.0 && result.abs() < i64::MAX as f64 {
+            return Ok((result as i64).to_string());
+        }
+        Ok(result.to_string())
+    }
+}
+
+/// Location of a line in script, which is used for reporting errors.
+#[derive(Debug, PartialEq, Clone)]
+struct DivoomDslScriptLocation {
+    file: Option<String>,
+    line_number: usize,
+
+    /// Column in the line with continuations joined. Use `physical_position` to find where it is in the file.
+    column: usize,
+
+    /// Continuation lines joined into the line, so the columns can be mapped back to the physical lines.
+    continuations: Vec<DivoomDslScriptContinuation>,
+}
+
+/// Continuation line that is joined into a script line.
+#[derive(Debug, PartialEq, Clone)]
+struct DivoomDslScriptContinuation {
+    /// Column in the joined line, where the text of the continuation line starts.
+    joined_column: usize,
+    line_number: usize,
+    column: usize,
+}
+
+impl DivoomDslScriptLocation {
+    fn with_column(&self, column: usize) -> Self {
+        DivoomDslScriptLocation {
+            column,
+            ..self.clone()
+        }
+    }
+
+    /// Line number and column in the script file, with the line continuations taken into account.
+    fn physical_position(&self) -> (usize, usize) {
+        match self
+            .continuations
+            .iter()
+            .rev()
+            .find(|x| x.joined_column <= self.column)
+        {
+            Some(continuation) => (
+                continuation.line_number,
+                continuation.column + self.column - continuation.joined_column,
+            ),
+            None => (self.line_number, self.column),
+        }
+    }
+}
+
+impl fmt::Display for DivoomDslScriptLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line_number, column) = self.physical_position();
+        write!(f, "line {}, column {}", line_number, column)?;
+        if let Some(file) = &self.file {
+            write!(f, " in {}", file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Line in script with comments removed, continuations joined and includes expanded.
+struct DivoomDslScriptLine {
+    /// Location of the first non-whitespace char in the line.
+    location: DivoomDslScriptLocation,
+    text: String,
+}
+
+/// Statements that end a block, e.g. `else` and `end`.
+#[derive(Debug, PartialEq, Clone)]
+enum DivoomDslBlockTerminator {
+    EndOfScript,
+    Else(DivoomDslScriptLocation),
+    End(DivoomDslScriptLocation),
+}
+
+impl DivoomDslScript {
+    /// Parse script from text. Since there is no file to resolve the path from, `include` is not supported here.
+    pub fn parse(script: &str) -> DivoomAPIResult<DivoomDslScript> {
+        let lines = read_script_lines(script, None, &mut Vec::new())?;
+        DivoomDslScript::from_lines(lines)
+    }
+
+    /// Parse script from file. Besides the syntax of `parse`, script file also supports:
+    ///
+    /// - Line continuation: Lines ending with `\` are joined with the next line.
+    /// - `include <path>`: Insert the content of another script file in place. The path is relative to the current file.
+    pub fn from_file(file_path: &str) -> DivoomAPIResult<DivoomDslScript> {
+        let lines = read_script_file_lines(Path::new(file_path), &mut Vec::new())?;
+        DivoomDslScript::from_lines(lines)
+    }
+
+    fn from_lines(lines: Vec<DivoomDslScriptLine>) -> DivoomAPIResult<DivoomDslScript> {
+        let (statements, terminator) = parse_script_block(&mut lines.iter())?;
+        match terminator {
+            DivoomDslBlockTerminator::EndOfScript => Ok(DivoomDslScript { statements }),
+            DivoomDslBlockTerminator::Else(location) => {
+                Err(new_script_error(&location, "\"else\" without \"if\""))
+            }
+            DivoomDslBlockTerminator::End(location) => Err(new_script_error(
+                &location,
+                "\"end\" without \"if\" or \"repeat\"",
+            )),
+        }
+    }
+}
+
+fn read_script_file_lines(
+    file_path: &Path,
+    include_stack: &mut Vec<PathBuf>,
+) -> DivoomAPIResult<Vec<DivoomDslScriptLine>> {
+    let script = fs::read_to_string(file_path)?;
+    include_stack.push(file_path.canonicalize()?);
+    let lines = read_script_lines(&script, Some(file_path), include_stack)?;
+    include_stack.pop();
+    Ok(lines)
+}
+
+fn read_script_lines(
+    script: &str,
+    file_path: Option<&Path>,
+    include_stack: &mut Vec<PathBuf>,
+) -> DivoomAPIResult<Vec<DivoomDslScriptLine>> {
+    let mut lines = Vec::new();
+    let mut raw_lines = script.lines().enumerate();
+
+    while let Some((index, raw_line)) = raw_lines.next() {
+        let indent = raw_line.chars().take_while(|c| c.is_whitespace()).count();
+        let mut location = DivoomDslScriptLocation {
+            file: file_path.map(|x| x.to_string_lossy().to_string()),
+            line_number: index + 1,
+            column: indent + 1,
+            continuations: Vec::new(),
+        };
+
+        let mut text = raw_line.trim().to_string();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        while let Some(joined_text) = text.strip_suffix('\\') {
+            text = joined_text.trim_end().to_string();
+            if let Some((next_index, next_raw_line)) = raw_lines.next() {
+                text.push(' ');
+                location.continuations.push(DivoomDslScriptContinuation {
+                    joined_column: location.column + text.chars().count(),
+                    line_number: next_index + 1,
+                    column: next_raw_line
+                        .chars()
+                        .take_while(|c| c.is_whitespace())
+                        .count()
+                        + 1,
+                });
+                text.push_str(next_raw_line.trim());
+            }
+        }
+
+        match text.strip_prefix("include ") {
+            None => lines.push(DivoomDslScriptLine { location, text }),
+            Some(include_path) => {
+                let file_path = match file_path {
+                    Some(v) => v,
+                    None => {
+                        return Err(new_script_error(
+                            &location,
+                            "\"include\" is only supported in script files",
+                        ))
+                    }
+                };
+
+                let include_path = file_path
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .join(include_path.trim());
+                if let Ok(canonical_include_path) = include_path.canonicalize() {
+                    if include_stack.contains(&canonical_include_path) {
+                        return Err(new_script_error(
+                            &location,
+                            &format!("Circular include: {}", include_path.to_string_lossy()),
+                        ));
+                    }
+                }
+
+                let included_lines =
+                    read_script_file_lines(&include_path, include_stack).map_err(|e| match e {
+                        DivoomAPIError::ResourceLoadError { source } => new_script_error(
+                            &location,
+                            &format!(
+                                "Failed to include {}: {}",
+                                include_path.to_string_lossy(),
+                                source
+                            ),
+                        ),
+                        e => e,
+                    })?;
+                lines.extend(included_lines);
+            }
+        }
+    }
+
+    Ok(lines)
+}
+
+fn parse_script_block<'a>(
+    lines: &mut impl Iterator<Item = &'a DivoomDslScriptLine>,
+) -> DivoomAPIResult<(Vec<DivoomDslStatement>, DivoomDslBlockTerminator)> {
+    let mut statements = Vec::new();
+
+    while let Some(DivoomDslScriptLine {
+        location,
+        text: line,
+    }) = lines.next()
+    {
+        let (keyword, args) = match line.split_once(char::is_whitespace) {
+            Some((keyword, args)) => (keyword, args.trim()),
+            None => (line.as_str(), ""),
+        };
+
+        let statement = match keyword {
+            "else" | "end" => {
+                if !args.is_empty() {
+                    return Err(new_script_error(
+                        location,
+                        &format!("\"{}\" doesn't take any argument", keyword),
+                    ));
+                }
+
+                let terminator = match keyword {
+                    "else" => DivoomDslBlockTerminator::Else(location.clone()),
+                    _ => DivoomDslBlockTerminator::End(location.clone()),
+                };
+                return Ok((statements, terminator));
+            }
+
+            "set" => {
+                let words = split_script_words(location, args)?;
+                let operator = match words.len() {
+                    4 => DivoomDslArithmeticOperator::parse(&words[2]),
+                    _ => None,
+                };
+                if (words.len() != 2 && operator.is_none()) || !is_valid_variable_name(&words[0]) {
+                    return Err(new_script_error(
+                        location,
+                        "\"set\" requires a variable name and a value, e.g. set color red, or an arithmetic, e.g. set level ${level} + 1",
+                    ));
+                }
+
+                let mut words = words.into_iter();
+                let name = words.next().unwrap();
+                match operator {
+                    None => DivoomDslStatement::Set {
+                        name,
+                        value: words.next().unwrap(),
+                    },
+                    Some(operator) => DivoomDslStatement::Calculate {
+                        name,
+                        left: words.next().unwrap(),
+                        operator,
+                        right: words.nth(1).unwrap(),
+                    },
+                }
+            }
+
+            "capture" => {
+                let (name, command_text) = match args.split_once(char::is_whitespace) {
+                    Some((name, command_text)) if is_valid_variable_name(name) => (name, command_text.trim()),
+                    _ => {
+                        return Err(new_script_error(
+                            location,
+                            "\"capture\" requires a variable name and a read command, e.g. capture settings system get-settings",
+                        ))
+                    }
+                };
+
+                let command_column =
+                    location.column + line.chars().count() - command_text.chars().count();
+                let command =
+                    parse_script_command(&location.with_column(command_column), command_text)?;
+                if let Some(operation) = &command.operation {
+                    if operation.read_command_name().is_none() {
+                        return Err(new_script_error(
+                            &location.with_column(command_column),
+                            "\"capture\" only works with read commands, e.g. channel get, channel get-clock, system get-settings or system get-time",
+                        ));
+                    }
+                }
+
+                DivoomDslStatement::Capture {
+                    name: name.to_string(),
+                    command,
+                }
+            }
+
+            "if" => {
+                let words = split_script_words(location, args)?;
+                let operator = match words
+                    .get(1)
+                    .and_then(|x| DivoomDslConditionOperator::parse(x))
+                {
+                    Some(v) if words.len() == 3 => v,
+                    _ => {
+                        return Err(new_script_error(
+                            location,
+                            "\"if\" requires a condition, e.g. if ${channel} == clock",
+                        ))
+                    }
+                };
+                let mut words = words.into_iter();
+                let condition = DivoomDslCondition {
+                    left: words.next().unwrap(),
+                    operator,
+                    right: words.nth(1).unwrap(),
+                };
+
+                let (then_statements, terminator) = parse_script_block(lines)?;
+                let else_statements = match terminator {
+                    DivoomDslBlockTerminator::Else(_) => {
+                        let (else_statements, terminator) = parse_script_block(lines)?;
+                        expect_block_end(location, "if", terminator)?;
+                        else_statements
+                    }
+                    _ => {
+                        expect_block_end(location, "if", terminator)?;
+                        Vec::new()
+                    }
+                };
+
+                DivoomDslStatement::If {
+                    condition,
+                    then_statements,
+                    else_statements,
+                }
+            }
+
+            "repeat" => {
+                let words = split_script_words(location, args)?;
+                if words.len() != 1 {
+                    return Err(new_script_error(
+                        location,
+                        "\"repeat\" requires the repeat count, e.g. repeat 3",
+                    ));
+                }
+
+                let (statements, terminator) = parse_script_block(lines)?;
+                expect_block_end(location, "repeat", terminator)?;
+                DivoomDslStatement::Repeat {
+                    count: words.into_iter().next().unwrap(),
+                    statements,
+                }
+            }
+
+            "sleep" => match parse_script_duration(args) {
+                Some(duration) => DivoomDslStatement::Sleep(duration),
+                None => {
+                    return Err(new_script_error(
+                        location,
+                        "\"sleep\" requires a duration, e.g. sleep 500ms, sleep 2s or sleep 1m",
+                    ))
+                }
+            },
+
+            "wait" => {
+                if !args.is_empty() {
+                    return Err(new_script_error(
+                        location,
+                        "\"wait\" doesn't take any argument, please use \"sleep\" to wait for a duration",
+                    ));
+                }
+                DivoomDslStatement::Wait
+            }
+
+            _ => DivoomDslStatement::Command(parse_script_command(location, line)?),
+        };
+
+        statements.push(statement);
+    }
+
+    Ok((statements, DivoomDslBlockTerminator::EndOfScript))
+}
+
+fn parse_script_command(
+    location: &DivoomDslScriptLocation,
+    text: &str,
+) -> DivoomAPIResult<DivoomDslScriptCommand> {
+    let operation = if text.contains("${") {
+        None
+    } else {
+        Some(parse_located_script_command(location, text, |column| {
+            column
+        })?)
+    };
+
+    Ok(DivoomDslScriptCommand {
+        text: text.to_string(),
+        line_number: location.line_number,
+        operation,
+        location: location.clone(),
+    })
+}
+
+/// Parse the command at the location. The column of a parse error is mapped to the column in the command text by the
+/// map function, since the parsed text might have its variables replaced.
+fn parse_located_script_command(
+    location: &DivoomDslScriptLocation,
+    text: &str,
+    map_column: impl FnOnce(usize) -> usize,
+) -> DivoomAPIResult<DivoomDslOperation> {
+    DivoomDslParser::parse_with_error_column(text).map_err(|(e, column)| {
+        let error_location = location.with_column(location.column + map_column(column) - 1);
+        match e {
+            DivoomAPIError::ParameterError(message)
+            | DivoomAPIError::CommandParseError { message, .. } => {
+                new_script_error(&error_location, &message)
+            }
+            e => e,
+        }
+    })
+}
+
+fn expect_block_end(
+    location: &DivoomDslScriptLocation,
+    keyword: &str,
+    terminator: DivoomDslBlockTerminator,
+) -> DivoomAPIResult<()> {
+    match terminator {
+        DivoomDslBlockTerminator::End(_) => Ok(()),
+        DivoomDslBlockTerminator::Else(else_location) => Err(new_script_error(
+            &else_location,
+            &format!(
+                "Unexpected \"else\" in \"{}\" at line {}",
+                keyword, location.line_number
+            ),
+        )),
+        DivoomDslBlockTerminator::EndOfScript => Err(new_script_error(
+            location,
+            &format!("\"{}\" is not closed with \"end\"", keyword),
+        )),
+    }
+}
+
+fn split_script_words(
+    location: &DivoomDslScriptLocation,
+    text: &str,
+) -> DivoomAPIResult<Vec<String>> {
+    shellwords::split(text).map_err(|e| new_script_error(location, &e.to_string()))
+}
+
+fn is_valid_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_script_duration(text: &str) -> Option<Duration> {
+    let unit_start = text.find(|c: char| !c.is_ascii_digit())?;
+    let value = text[..unit_start].parse::<u64>().ok()?;
+    match &text[unit_start..] {
+        "ms" => Some(Duration::from_millis(value)),
+        "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value.checked_mul(60)?)),
+        _ => None,
+    }
+}
+
+fn new_script_error(location: &DivoomDslScriptLocation, message: &str) -> DivoomAPIError {
+    DivoomAPIError::ParameterError(format!("Invalid script at {}: {}", location, message))
+}
+
+/// Replace all `${name}` in the text with the values returned by the lookup function.
+pub(crate) fn replace_script_variables(
+    text: &str,
+    mut lookup: impl FnMut(&str) -> DivoomAPIResult<String>,
+) -> DivoomAPIResult<String> {
+    replace_script_variables_with_offset(text, |name, _| lookup(name))
+}
+
+/// Replace all `${name}` in the DSL command with the values returned by the lookup function. The values are escaped
+/// based on the quotes around the variable, so they are always used as literal text, e.g. the spaces or quotes in the
+/// values cannot split the word or add more arguments.
+///
+/// Where each variable is before and after it is replaced is also returned, so the errors found in the replaced command
+/// can be located in the original one.
+fn replace_script_command_variables(
+    text: &str,
+    mut lookup: impl FnMut(&str) -> DivoomAPIResult<String>,
+) -> DivoomAPIResult<(String, Vec<DivoomDslScriptVariableSpan>)> {
+    let mut quote = None;
+    let mut scanned_len = 0;
+    let mut spans: Vec<DivoomDslScriptVariableSpan> = Vec::new();
+    let result = replace_script_variables_with_offset(text, |name, offset| {
+        quote = find_quote_after(quote, &text[scanned_len..offset]);
+        scanned_len = offset;
+
+        let value = escape_script_command_value(&lookup(name)?, quote);
+        let replaced_start = match spans.last() {
+            Some(span) => span.replaced_end + offset - span.end,
+            None => offset,
+        };
+        spans.push(DivoomDslScriptVariableSpan {
+            start: offset,
+            end: offset + name.len() + 3,
+            replaced_start,
+            replaced_end: replaced_start + value.len(),
+        });
+        Ok(value)
+    })?;
+
+    Ok((result, spans))
+}
+
+/// Byte range of a variable in the command text, before and after it is replaced.
+struct DivoomDslScriptVariableSpan {
+    start: usize,
+    end: usize,
+    replaced_start: usize,
+    replaced_end: usize,
+}
+
+/// Replace all `${name}` in the text, with the byte offset of each variable passed to the lookup function.
+fn replace_script_variables_with_offset(
+    text: &str,
+    mut lookup: impl FnMut(&str, usize) -> DivoomAPIResult<String>,
+) -> DivoomAPIResult<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(v) => start + v,
+            None => {
+                return Err(DivoomAPIError::ParameterError(format!(
+                    "Variable is not closed with \"}}\": {}",
+                    text
+                )))
+            }
+        };
+
+        let offset = text.len() - rest.len() + start;
+        result.push_str(&rest[..start]);
+        result.push_str(&lookup(&rest[start + 2..end], offset)?);
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Find the quote that is still open after the text, using the same rules as shellwords: backslash escapes the next
+/// char outside of quotes and in double quotes, and nothing is escaped in single quotes.
+fn find_quote_after(mut quote: Option<char>, text: &str) -> Option<char> {
+    let mut escaped = false;
+    for c in text.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+    }
+    quote
+}
+
+fn escape_script_command_value(value: &str, quote: Option<char>) -> String {
+    match quote {
+        None => shellwords::escape(value),
+        Some('"') => {
+            let mut escaped_value = String::with_capacity(value.len());
+            for c in value.chars() {
+                if matches!(c, '$' | '`' | '"' | '\\') {
+                    escaped_value.push('\\');
+                }
+                escaped_value.push(c);
+            }
+            escaped_value
+        }
+
+        // Nothing can be escaped in single quotes, so we close the quotes, then reopen them after the value.
+        Some(_) => format!("'{}'", shellwords::escape(value)),
+    }
+}
+
+/// Get the names of all variables used in the text.
+pub(crate) fn script_variable_names(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let _ = replace_script_variables(text, |name| {
+        names.push(name.to_string());
+        Ok(String::new())
+    });
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dsl_script_can_be_parsed() {
+        let script = DivoomDslScript::parse(
+            r#"
+            # Flash alert, then return to clock.
+            set brightness 100
+            repeat 3
+                system set-brightness ${brightness}
+                sleep 500ms
+                system set-brightness 10
+                wait
+            end
+            if ${channel} != clock
+                channel set clock
+            else
+                tool buzzer
+            end
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            format!("{:?}", script.statements),
+            "[Set { name: \"brightness\", value: \"100\" }, \
+            Repeat { count: \"3\", statements: [\
+                Command(DivoomDslScriptCommand(\"system set-brightness ${brightness}\")), \
+                Sleep(500ms), \
+                Command(DivoomDslScriptCommand(\"system set-brightness 10\")), \
+                Wait] }, \
+            If { condition: DivoomDslCondition { left: \"${channel}\", operator: NotEqual, right: \"clock\" }, \
+                then_statements: [Command(DivoomDslScriptCommand(\"channel set clock\"))], \
+                else_statements: [Command(DivoomDslScriptCommand(\"tool buzzer\"))] }]"
+        );
+
+        match &script.statements[1] {
+            DivoomDslStatement::Repeat { statements, .. } => match &statements[..] {
+                [DivoomDslStatement::Command(with_variable), _, DivoomDslStatement::Command(without_variable), _] =>
+                {
+                    assert!(with_variable.operation.is_none());
+                    assert!(without_variable.operation.is_some());
+                }
+                _ => panic!("Unexpected statements in repeat: {:?}", statements),
+            },
+            v => panic!("Unexpected statement: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn dsl_script_should_report_errors_with_line_number() {
+        let cases = [
+            ("repeat 3\nsleep 1s", "Invalid script at line 1, column 1: \"repeat\" is not closed with \"end\""),
+            ("sleep 1s\n  end", "Invalid script at line 2, column 3: \"end\" without \"if\" or \"repeat\""),
+            ("if 1 == 1\nelse\nelse\nend", "Invalid script at line 3, column 1: Unexpected \"else\" in \"if\" at line 1"),
+            ("if ${channel} is clock\nend", "Invalid script at line 1, column 1: \"if\" requires a condition, e.g. if ${channel} == clock"),
+            ("sleep 2h", "Invalid script at line 1, column 1: \"sleep\" requires a duration, e.g. sleep 500ms, sleep 2s or sleep 1m"),
+            ("sleep 307445734561825861m", "Invalid script at line 1, column 1: \"sleep\" requires a duration, e.g. sleep 500ms, sleep 2s or sleep 1m"),
+            ("set color-name red", "Invalid script at line 1, column 1: \"set\" requires a variable name and a value, e.g. set color red, or an arithmetic, e.g. set level ${level} + 1"),
+            ("set level ${level} ^ 2", "Invalid script at line 1, column 1: \"set\" requires a variable name and a value, e.g. set color red, or an arithmetic, e.g. set level ${level} + 1"),
+            ("capture settings", "Invalid script at line 1, column 1: \"capture\" requires a variable name and a read command, e.g. capture settings system get-settings"),
+            ("capture settings  system set-brightness 10", "Invalid script at line 1, column 19: \"capture\" only works with read commands, e.g. channel get, channel get-clock, system get-settings or system get-time"),
+            ("include common.divoom", "Invalid script at line 1, column 1: \"include\" is only supported in script files"),
+        ];
+
+        for (script, expected_error) in cases {
+            match DivoomDslScript::parse(script) {
+                Err(DivoomAPIError::ParameterError(message)) => assert_eq!(message, expected_error),
+                v => panic!("Unexpected parse result: {:?}", v),
+            }
+        }
+
+        match DivoomDslScript::parse("tool buzzer\nchannel \\\n  set \\\n\tunknown") {
+            Err(DivoomAPIError::ParameterError(message)) => {
+                assert!(
+                    message.starts_with("Invalid script at line 4, column 2: "),
+                    "{}",
+                    message
+                )
+            }
+            v => panic!("Unexpected parse result: {:?}", v),
+        }
+
+        match DivoomDslScript::parse("tool buzzer\n    channel set unknown") {
+            Err(DivoomAPIError::ParameterError(message)) => {
+                assert!(
+                    message.starts_with("Invalid script at line 2, column 17: "),
+                    "{}",
+                    message
+                )
+            }
+            v => panic!("Unexpected parse result: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn dsl_script_should_report_errors_in_commands_with_variables_at_their_location() {
+        let script =
+            DivoomDslScript::parse("tool buzzer\n  channel set ${name}\nchannel ${verb} unknown")
+                .unwrap();
+        let values = HashMap::from([
+            ("name".to_string(), "unknown".to_string()),
+            ("verb".to_string(), "set".to_string()),
+        ]);
+
+        let expected_error_prefixes = [
+            "Invalid script at line 2, column 15: ",
+            "Invalid script at line 3, column 17: ",
+        ];
+        for (statement, expected_error_prefix) in
+            script.statements[1..].iter().zip(expected_error_prefixes)
+        {
+            let command = match statement {
+                DivoomDslStatement::Command(command) => command,
+                v => panic!("Unexpected statement: {:?}", v),
+            };
+
+            match command.parse_with_variables(&values) {
+                Err(DivoomAPIError::ParameterError(message)) => {
+                    assert!(message.starts_with(expected_error_prefix), "{}", message)
+                }
+                Err(e) => panic!("Unexpected error: {:?}", e),
+                Ok(_) => panic!("Command should fail to parse: {}", command.text),
+            }
+        }
+    }
+
+    #[test]
+    fn dsl_script_can_be_loaded_from_file_with_includes_and_line_continuations() {
+        let script = DivoomDslScript::from_file("test_data/dsl_script_tests/alert.divoom").unwrap();
+        assert_eq!(
+            format!("{:?}", script.statements),
+            "[Set { name: \"alert\", value: \"Time to stand up!\" }, \
+            Repeat { count: \"3\", statements: [\
+                Command(DivoomDslScriptCommand(\"system set-brightness 100\")), \
+                Sleep(500ms), \
+                Command(DivoomDslScriptCommand(\"system set-brightness 10\")), \
+                Sleep(500ms)] }, \
+            Command(DivoomDslScriptCommand(\"animation text set 1 \\\"${alert}\\\" -x 0 -y 20 -r 255 -g 0 -b 0\"))]"
+        );
+
+        match DivoomDslScript::from_file("test_data/dsl_script_tests/invalid.divoom") {
+            Err(DivoomAPIError::ParameterError(message)) => assert!(
+                message.starts_with("Invalid script at line 2, column 13 in test_data/dsl_script_tests/common/invalid_channel.divoom: "),
+                "{}",
+                message
+            ),
+            v => panic!("Unexpected parse result: {:?}", v),
+        }
+
+        match DivoomDslScript::from_file("test_data/dsl_script_tests/circular.divoom") {
+            Err(DivoomAPIError::ParameterError(message)) => assert_eq!(
+                message,
+                "Invalid script at line 2, column 1 in test_data/dsl_script_tests/circular.divoom: Circular include: test_data/dsl_script_tests/circular.divoom"
+            ),
+            v => panic!("Unexpected parse result: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn dsl_script_condition_should_compare_numbers_and_strings() {
+        let condition = |operator| DivoomDslCondition {
+            left: "".into(),
+            operator,
+            right: "".into(),
+        };
+
+        assert!(condition(DivoomDslConditionOperator::Less).eval("9", "10"));
+        assert!(!condition(DivoomDslConditionOperator::Greater).eval("9", "10.5"));
+        assert!(condition(DivoomDslConditionOperator::GreaterOrEqual).eval("17:30", "08:30"));
+        assert!(condition(DivoomDslConditionOperator::Equal).eval("clock", "clock"));
+        assert!(condition(DivoomDslConditionOperator::NotEqual).eval("clock", "cloud"));
+        assert!(condition(DivoomDslConditionOperator::LessOrEqual).eval("1.0", "1"));
+    }
+
+    #[test]
+    fn dsl_script_arithmetic_should_keep_integers_as_integers() {
+        assert_eq!(
+            DivoomDslArithmeticOperator::Add.eval("50", "20").unwrap(),
+            "70"
+        );
+        assert_eq!(
+            DivoomDslArithmeticOperator::Subtract
+                .eval("5", "20")
+                .unwrap(),
+            "-15"
+        );
+        assert_eq!(
+            DivoomDslArithmeticOperator::Multiply
+                .eval("1.5", "2")
+                .unwrap(),
+            "3"
+        );
+        assert_eq!(
+            DivoomDslArithmeticOperator::Divide.eval("5", "2").unwrap(),
+            "2.5"
+        );
+        assert_eq!(
+            DivoomDslArithmeticOperator::Remainder
+                .eval("7", "3")
+                .unwrap(),
+            "1"
+        );
+        assert!(DivoomDslArithmeticOperator::Divide.eval("5", "0").is_err());
+        assert!(DivoomDslArithmeticOperator::Add.eval("clock", "1").is_err());
+    }
+
+    #[test]
+    fn dsl_script_variables_can_be_replaced() {
+        let result = replace_script_variables("a ${x} b ${yy}", |name| Ok(name.to_uppercase()));
+        assert_eq!(result.unwrap(), "a X b YY");
+        assert!(replace_script_variables("a ${x", |name| Ok(name.to_string())).is_err());
+        assert_eq!(script_variable_names("${a}${b} c"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn dsl_script_command_variables_should_be_escaped() {
+        let value = "it's \"a\" \\ $test; --foo";
+        let cases = [
+            "text ${x} end",
+            "text \"${x}\" end",
+            "text '${x}' end",
+            "text \"pre ${x}\" end",
+        ];
+
+        for (index, command) in cases.iter().enumerate() {
+            let (result, _) =
+                replace_script_command_variables(command, |_| Ok(value.to_string())).unwrap();
+            let expected_word = match index {
+                3 => format!("pre {}", value),
+                _ => value.to_string(),
+            };
+            assert_eq!(
+                shellwords::split(&result).unwrap(),
+                vec!["text".to_string(), expected_word, "end".to_string()],
+                "Command: {}, Result: {}",
+                command,
+                result
+            );
+        }
+
+        let (result, _) =
+            replace_script_command_variables("text ${x} end", |_| Ok("".into())).unwrap();
+        assert_eq!(shellwords::split(&result).unwrap(), vec!["text", "", "end"]);
+    }
+}
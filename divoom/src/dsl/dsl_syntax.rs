@@ -1,10 +1,12 @@
 use crate::dto::*;
 use clap::{Args, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "animation-builder")]
 use crate::animation::*;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 #[clap(rename_all = "kebab-case")]
 pub enum DivoomDeviceCommand {
     #[clap(subcommand, about = "Channel related APIs")]
@@ -31,9 +33,16 @@ pub enum DivoomDeviceCommand {
     },
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 #[clap(rename_all = "kebab-case")]
 pub enum DivoomDeviceChannelCommand {
+    #[clap(about = "Get current selected channel type")]
+    Get,
+
+    #[clap(about = "Get current selected clock type")]
+    GetClock,
+
     #[clap(about = "Set current channel")]
     Set {
         #[clap(help = "Channel type. It can be clock, cloud, visualizer and customPage.")]
@@ -65,8 +74,15 @@ pub enum DivoomDeviceChannelCommand {
     },
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceSystemCommand {
+    #[clap(about = "Get all settings")]
+    GetSettings,
+
+    #[clap(about = "Get device time")]
+    GetTime,
+
     #[clap(about = "Set device brightness")]
     SetBrightness {
         #[clap(help = "Brightness (0-100)")]
@@ -143,16 +159,19 @@ pub enum DivoomDeviceSystemCommand {
     },
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceToolCommand {
     #[clap(about = "Countdown tool")]
     Countdown {
         #[clap(help = "Action, can be start, stop")]
         action: DivoomToolCountdownAction,
 
+        #[serde(default)]
         #[clap(value_parser, default_value_t = 0, help = "Number of minutes, 0-59")]
         minute: i32,
 
+        #[serde(default)]
         #[clap(value_parser, default_value_t = 0, help = "Number of seconds, 0-59")]
         second: i32,
     },
@@ -180,26 +199,33 @@ pub enum DivoomDeviceToolCommand {
 
     #[clap(about = "Play buzzer")]
     Buzzer {
-        #[clap(default_value = "1000", help = "Total time to play in milliseconds")]
+        #[serde(default = "default_buzzer_play_total_time")]
+        #[clap(
+            default_value_t = DEFAULT_BUZZER_PLAY_TOTAL_TIME,
+            help = "Total time to play in milliseconds"
+        )]
         play_total_time: i32,
 
+        #[serde(default = "default_buzzer_active_time_in_cycle")]
         #[clap(
             short,
-            default_value = "50",
+            default_value_t = DEFAULT_BUZZER_ACTIVE_TIME_IN_CYCLE,
             help = "Time to play in every buzz cycle in milliseconds"
         )]
         active_time_in_cycle: i32,
 
+        #[serde(default = "default_buzzer_off_time_in_cycle")]
         #[clap(
             short,
-            default_value = "100",
+            default_value_t = DEFAULT_BUZZER_OFF_TIME_IN_CYCLE,
             help = "Time to off after every buzz in milliseconds"
         )]
         off_time_in_cycle: i32,
     },
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceAnimationCommand {
     #[clap(subcommand, about = "Play GIF from Internet")]
     Gif(DivoomDeviceGifAnimationCommand),
@@ -211,26 +237,31 @@ pub enum DivoomDeviceAnimationCommand {
     Text(DivoomDeviceTextAnimationCommand),
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceGifAnimationCommand {
     #[clap(about = "Play gif file. Only supports 16x16, 32x32, 64x64 gifs")]
     Play(DivoomDevicePlayGifAnimationCommandArgs),
 }
 
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct DivoomDevicePlayGifAnimationCommandArgs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[clap(
         long,
         help = "Specify a local file on *pixoo device*. Only supports 16x16, 32x32, 64x64 gifs"
     )]
     pub file: Option<String>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[clap(
         long,
         help = "Specify a local folder on *pixoo device*. Only supports 16x16, 32x32, 64x64 gifs"
     )]
     pub folder: Option<String>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[clap(
         long,
         help = "Specify a URL from Internet. Only supports 16x16, 32x32, 64x64 gifs"
@@ -238,7 +269,8 @@ pub struct DivoomDevicePlayGifAnimationCommandArgs {
     pub url: Option<String>,
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceImageAnimationCommand {
     #[clap(about = "Reset next animation id")]
     ResetId,
@@ -248,46 +280,60 @@ pub enum DivoomDeviceImageAnimationCommand {
         about = "Render gif as animation. This is different from \"gif play\" command, which is provided directly by Divoom device. This command will create a regular animation and render the specified file, then send to the device to play."
     )]
     RenderGif {
-        #[clap(help = "Gif file path")]
+        #[clap(
+            help = "Gif file path, http(s) URL or base64 data URL, e.g. data:image/gif;base64,R0lGODlh..."
+        )]
         file_path: String,
 
+        #[serde(default = "default_animation_size")]
         #[clap(
-            default_value = "64",
+            default_value_t = DEFAULT_ANIMATION_SIZE,
             help = "Animation size in pixels. Only 16 and 32 and 64 are allowed."
         )]
         size: u32,
 
+        #[serde(default = "default_animation_speed_in_ms")]
         #[clap(
             short,
             long = "speed",
-            default_value = "100",
+            default_value_t = DEFAULT_ANIMATION_SPEED_IN_MS,
             help = "Animation play speed in milliseconds"
         )]
         speed_in_ms: u64,
 
+        #[serde(default = "default_animation_fit")]
         #[clap(
             short,
             long = "fit",
-            default_value = "center",
+            default_value_t = DEFAULT_ANIMATION_FIT,
             help = "Animation fit mode. Can be center, stretch, fitX and fitY"
         )]
         fit: DivoomDrawFitMode,
 
+        #[serde(default = "default_animation_rotation")]
         #[clap(
             short,
             long = "rotate",
-            default_value = "0.0",
+            default_value_t = DEFAULT_ANIMATION_ROTATION,
             help = "Animation rotate angle"
         )]
         rotation: f32,
 
+        #[serde(default = "default_animation_opacity")]
         #[clap(
             short,
             long = "opacity",
-            default_value = "1.0",
+            default_value_t = DEFAULT_ANIMATION_OPACITY,
             help = "Animation opacity"
         )]
         opacity: f32,
+
+        #[serde(default)]
+        #[clap(
+            long = "keep-timing",
+            help = "Resample the GIF frames onto the speed to keep the original timing of GIFs with variable delays. Frames exceeding the max frame count of the device are dropped"
+        )]
+        keep_timing: bool,
     },
 
     #[cfg(feature = "animation-builder")]
@@ -295,58 +341,90 @@ pub enum DivoomDeviceImageAnimationCommand {
         about = "Render file as animation. This is different from \"gif play\" command, which is provided directly by Divoom device. This command will create a regular animation and render the specified files one at a time, then send to the device to play."
     )]
     RenderFiles {
-        #[clap(help = "File glob")]
+        #[clap(
+            help = "File glob, or http(s) URL and base64 data URL of a single image or GIF, e.g. a webcam snapshot"
+        )]
         file_pattern: String,
 
+        #[serde(default = "default_animation_size")]
         #[clap(
-            default_value = "64",
+            default_value_t = DEFAULT_ANIMATION_SIZE,
             help = "Animation size in pixels. Only 16 and 32 and 64 are allowed."
         )]
         size: u32,
 
+        #[serde(default = "default_animation_speed_in_ms")]
         #[clap(
             short,
             long = "speed",
-            default_value = "100",
+            default_value_t = DEFAULT_ANIMATION_SPEED_IN_MS,
             help = "Animation play speed in milliseconds"
         )]
         speed_in_ms: u64,
 
+        #[serde(default = "default_animation_fit")]
         #[clap(
             short,
             long = "fit",
-            default_value = "center",
+            default_value_t = DEFAULT_ANIMATION_FIT,
             help = "Animation fit mode. Can be center, stretch, fitX and fitY"
         )]
         fit: DivoomDrawFitMode,
 
+        #[serde(default = "default_animation_rotation")]
         #[clap(
             short,
             long = "rotate",
-            default_value = "0.0",
+            default_value_t = DEFAULT_ANIMATION_ROTATION,
             help = "Animation rotate angle"
         )]
         rotation: f32,
 
+        #[serde(default = "default_animation_opacity")]
         #[clap(
             short,
             long = "opacity",
-            default_value = "1.0",
+            default_value_t = DEFAULT_ANIMATION_OPACITY,
             help = "Animation opacity"
         )]
         opacity: f32,
 
+        #[serde(default)]
         #[clap(long = "random", help = "Render in random order")]
         random: bool,
 
+        #[serde(default = "default_prefetch_count")]
         #[clap(
             short,
             long = "prefetch",
             value_parser,
-            default_value_t = 10,
+            default_value_t = DEFAULT_PREFETCH_COUNT,
             help = "Number of gifs to prefetch to memory."
         )]
         prefetch_count: usize,
+
+        #[serde(default)]
+        #[clap(
+            long = "watch",
+            conflicts_with = "random",
+            help = "Watch the folder, so newly added files are rendered next and deleted files are skipped"
+        )]
+        watch: bool,
+
+        #[serde(default)]
+        #[clap(
+            long = "newest-first",
+            requires = "watch",
+            help = "Render files from the newest to the oldest by modified time, when watching the folder"
+        )]
+        newest_first: bool,
+
+        #[serde(default)]
+        #[clap(
+            long = "keep-timing",
+            help = "Resample the GIF frames onto the speed to keep the original timing of GIFs with variable delays. Frames exceeding the max frame count of the device are dropped"
+        )]
+        keep_timing: bool,
     },
 
     #[cfg(feature = "animation-builder")]
@@ -357,25 +435,28 @@ pub enum DivoomDeviceImageAnimationCommand {
         #[clap(help = "Template name")]
         template_name: String,
 
+        #[serde(default = "default_template_parameters")]
         #[clap(
             short,
             long,
-            default_value = "{}",
+            default_value = DEFAULT_TEMPLATE_PARAMETERS,
             help = "Template parameters. We use a string to string json dictionary as the format. E.g. {\"foo\":\"bar\"}"
         )]
         parameters: String,
 
+        // No short flag here, because "-p" is taken by the parameters above, and clap panics on duplicated short flags.
+        #[serde(default = "default_template_parameters")]
         #[clap(
-            short,
             long,
-            default_value = "{}",
+            default_value = DEFAULT_TEMPLATE_PARAMETERS,
             help = "Per frame template parameters. We use a 0-indexed int to string to string json dictionary as the format. E.g. {0:{\"foo\":\"bar\"},1:{\"foo\":\"bar2\"}}"
         )]
         per_frame_parameters: String,
     },
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceTextAnimationCommand {
     #[clap(about = "Clear all text area")]
     Clear,
@@ -384,43 +465,50 @@ pub enum DivoomDeviceTextAnimationCommand {
     Set(DivoomDeviceTextAnimationCommandArgs),
 }
 
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct DivoomDeviceTextAnimationCommandArgs {
     #[clap(help = "Text id to create/update. Must be <= 20.")]
     pub text_id: i32,
 
-    #[clap(short, default_value = "0", help = "Start position x.")]
+    #[serde(default = "default_text_position")]
+    #[clap(short, default_value_t = DEFAULT_TEXT_POSITION, help = "Start position x.")]
     pub x: i32,
 
-    #[clap(short, default_value = "0", help = "Start position y.")]
+    #[serde(default = "default_text_position")]
+    #[clap(short, default_value_t = DEFAULT_TEXT_POSITION, help = "Start position y.")]
     pub y: i32,
 
+    #[serde(default = "default_text_scroll_direction")]
     #[clap(
         short = 'd',
-        default_value = "left",
+        default_value_t = DEFAULT_TEXT_SCROLL_DIRECTION,
         help = "Scroll direction, can be left, right."
     )]
     pub scroll_direction: DivoomTextAnimationScrollDirection,
 
+    #[serde(default = "default_text_font_index")]
     #[clap(
         short,
-        default_value = "0",
+        default_value_t = DEFAULT_TEXT_FONT_INDEX,
         help = "0-7: font id in app. Divoom only has 8 fonts."
     )]
     pub font_index: i32,
 
+    #[serde(default = "default_text_width")]
     #[clap(
         short = 'w',
         long = "width",
-        default_value = "16",
+        default_value_t = DEFAULT_TEXT_WIDTH,
         help = "Text size. Must be >= 16 and <= 64."
     )]
     pub text_width: i32,
 
+    #[serde(default = "default_text_speed_in_ms")]
     #[clap(
         short = 's',
         long = "speed",
-        default_value = "100",
+        default_value_t = DEFAULT_TEXT_SPEED_IN_MS,
         help = "Speed of each animation step (scroll) in milliseconds."
     )]
     pub speed_in_ms: i32,
@@ -428,18 +516,22 @@ pub struct DivoomDeviceTextAnimationCommandArgs {
     #[clap(help = "Text data")]
     pub text_string: String,
 
-    #[clap(short, default_value = "255", help = "Font color, red.")]
+    #[serde(default = "default_text_color")]
+    #[clap(short, default_value_t = DEFAULT_TEXT_COLOR, help = "Font color, red.")]
     pub r: u8,
 
-    #[clap(short, default_value = "255", help = "Font color, green.")]
+    #[serde(default = "default_text_color")]
+    #[clap(short, default_value_t = DEFAULT_TEXT_COLOR, help = "Font color, green.")]
     pub g: u8,
 
-    #[clap(short, default_value = "255", help = "Font color, blue.")]
+    #[serde(default = "default_text_color")]
+    #[clap(short, default_value_t = DEFAULT_TEXT_COLOR, help = "Font color, blue.")]
     pub b: u8,
 
+    #[serde(default = "default_text_align")]
     #[clap(
         short = 'a',
-        default_value = "middle",
+        default_value_t = DEFAULT_TEXT_ALIGN,
         help = "Text align. Can be left, middle, right."
     )]
     pub align: DivoomTextAnimationAlign,
@@ -462,7 +554,8 @@ impl DivoomDeviceTextAnimationCommandArgs {
     }
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DivoomDeviceBatchCommand {
     #[clap(about = "Run commands from a URL")]
     RunUrl {
@@ -470,3 +563,107 @@ pub enum DivoomDeviceBatchCommand {
         command_url: String,
     },
 }
+
+// Default values of the options, which are shared by the clap attributes above, the structured objects that are
+// deserialized by serde, and the printer that skips the default options, so all forms of the same command run in the same way.
+pub(crate) const DEFAULT_BUZZER_PLAY_TOTAL_TIME: i32 = 1000;
+pub(crate) const DEFAULT_BUZZER_ACTIVE_TIME_IN_CYCLE: i32 = 50;
+pub(crate) const DEFAULT_BUZZER_OFF_TIME_IN_CYCLE: i32 = 100;
+pub(crate) const DEFAULT_TEXT_POSITION: i32 = 0;
+pub(crate) const DEFAULT_TEXT_SCROLL_DIRECTION: DivoomTextAnimationScrollDirection =
+    DivoomTextAnimationScrollDirection::Left;
+pub(crate) const DEFAULT_TEXT_FONT_INDEX: i32 = 0;
+pub(crate) const DEFAULT_TEXT_WIDTH: i32 = 16;
+pub(crate) const DEFAULT_TEXT_SPEED_IN_MS: i32 = 100;
+pub(crate) const DEFAULT_TEXT_COLOR: u8 = 255;
+pub(crate) const DEFAULT_TEXT_ALIGN: DivoomTextAnimationAlign = DivoomTextAnimationAlign::Middle;
+
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_ANIMATION_SIZE: u32 = 64;
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_ANIMATION_SPEED_IN_MS: u64 = 100;
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_ANIMATION_FIT: DivoomDrawFitMode = DivoomDrawFitMode::Center;
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_ANIMATION_ROTATION: f32 = 0.0;
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_ANIMATION_OPACITY: f32 = 1.0;
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_PREFETCH_COUNT: usize = 10;
+#[cfg(feature = "animation-builder")]
+pub(crate) const DEFAULT_TEMPLATE_PARAMETERS: &str = "{}";
+
+fn default_buzzer_play_total_time() -> i32 {
+    DEFAULT_BUZZER_PLAY_TOTAL_TIME
+}
+
+fn default_buzzer_active_time_in_cycle() -> i32 {
+    DEFAULT_BUZZER_ACTIVE_TIME_IN_CYCLE
+}
+
+fn default_buzzer_off_time_in_cycle() -> i32 {
+    DEFAULT_BUZZER_OFF_TIME_IN_CYCLE
+}
+
+fn default_text_position() -> i32 {
+    DEFAULT_TEXT_POSITION
+}
+
+fn default_text_scroll_direction() -> DivoomTextAnimationScrollDirection {
+    DEFAULT_TEXT_SCROLL_DIRECTION
+}
+
+fn default_text_font_index() -> i32 {
+    DEFAULT_TEXT_FONT_INDEX
+}
+
+fn default_text_width() -> i32 {
+    DEFAULT_TEXT_WIDTH
+}
+
+fn default_text_speed_in_ms() -> i32 {
+    DEFAULT_TEXT_SPEED_IN_MS
+}
+
+fn default_text_color() -> u8 {
+    DEFAULT_TEXT_COLOR
+}
+
+fn default_text_align() -> DivoomTextAnimationAlign {
+    DEFAULT_TEXT_ALIGN
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_animation_size() -> u32 {
+    DEFAULT_ANIMATION_SIZE
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_animation_speed_in_ms() -> u64 {
+    DEFAULT_ANIMATION_SPEED_IN_MS
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_animation_fit() -> DivoomDrawFitMode {
+    DEFAULT_ANIMATION_FIT
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_animation_rotation() -> f32 {
+    DEFAULT_ANIMATION_ROTATION
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_animation_opacity() -> f32 {
+    DEFAULT_ANIMATION_OPACITY
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_prefetch_count() -> usize {
+    DEFAULT_PREFETCH_COUNT
+}
+
+#[cfg(feature = "animation-builder")]
+fn default_template_parameters() -> String {
+    DEFAULT_TEMPLATE_PARAMETERS.to_string()
+}
@@ -1,10 +1,13 @@
 mod dsl_common;
 mod dsl_parser;
+mod dsl_printer;
 mod dsl_resource_loader;
 mod dsl_runner;
+mod dsl_script;
 mod dsl_syntax;
 
 pub use dsl_common::*;
 pub use dsl_parser::DivoomDslParser;
 pub use dsl_runner::DivoomDslRunner;
+pub use dsl_script::*;
 pub use dsl_syntax::*;
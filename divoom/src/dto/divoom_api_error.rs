@@ -1,8 +1,12 @@
+use super::divoom_error_code::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::io;
+use std::str::FromStr;
 use thiserror::Error;
 
 /// This represents the error that returned from Divoom online service or Divoom devices.
-#[derive(Debug, PartialOrd, PartialEq)]
+#[derive(Debug, PartialOrd, PartialEq, Clone)]
 pub struct DivoomServerErrorInfo {
     /// HTTP status code returned from Divoom service or device.
     pub http_status_code: u16,
@@ -46,22 +50,91 @@ impl DivoomServerErrorInfo {
             error_message,
         }
     }
+
+    /// Known meaning of the error, looked up with the Divoom error code first, then the HTTP status code.
+    pub fn error_code_info(&self) -> Option<&'static DivoomErrorCodeInfo> {
+        if self.error_code != 0 {
+            return DivoomErrorCodeInfo::lookup(DivoomErrorCodeSource::Divoom, self.error_code);
+        }
+
+        DivoomErrorCodeInfo::lookup(DivoomErrorCodeSource::Http, self.http_status_code as i32)
+    }
+
+    pub fn category(&self) -> DivoomErrorCategory {
+        self.error_code_info()
+            .map_or(DivoomErrorCategory::Unknown, |x| x.category)
+    }
+}
+
+impl fmt::Display for DivoomServerErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.error_code != 0 {
+            write!(f, "Error code = {}", self.error_code)?;
+        } else {
+            write!(f, "HTTP status code = {}", self.http_status_code)?;
+        }
+
+        if let Some(info) = self.error_code_info() {
+            write!(f, " ({}: {})", info.name, info.description)?;
+        }
+
+        if !self.error_message.is_empty() {
+            write!(f, ", Message = {}", self.error_message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Kind of the DSL command parse errors, so callers can tell what is wrong with the command without parsing the message.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub enum DivoomCommandParseErrorKind {
+    /// The command or subcommand doesn't exist, e.g. "channel unknown".
+    UnknownCommand,
+
+    /// The argument or option doesn't exist, e.g. "system set-brightness 80 --foo".
+    UnknownArgument,
+
+    /// The value is not valid for the argument, e.g. "channel set unknown".
+    InvalidValue,
+
+    /// The required argument or subcommand is missing, e.g. "channel set-clock".
+    MissingArgument,
+
+    /// Other invalid usages, e.g. too many values or conflicting arguments.
+    InvalidArgument,
 }
 
+impl_divoom_dto_enum_traits_without_raw!(DivoomCommandParseErrorKind, UnknownCommand: "unknown-command", UnknownArgument: "unknown-argument", InvalidValue: "invalid-value", MissingArgument: "missing-argument", InvalidArgument: "invalid-argument");
+
 /// Divoom API error.
 /// Since the Divoom service and device APIs are http servers, it can fail due to many reasons. Hence we have a few categories of errors here.
 #[derive(Debug, Error)]
 pub enum DivoomAPIError {
-    #[error("Invalid parameter.")]
+    #[error("Invalid parameter: {0}")]
     ParameterError(String),
 
+    #[error("Unsupported command: {0}")]
+    UnsupportedCommandError(String),
+
+    /// DSL command that cannot be parsed, e.g. unknown commands, unknown arguments or invalid values.
+    #[error("Invalid command ({kind}): {message}")]
+    CommandParseError {
+        kind: DivoomCommandParseErrorKind,
+
+        /// The word in the command that causes the error, e.g. the unknown subcommand or the invalid value.
+        word: Option<String>,
+
+        message: String,
+    },
+
     #[error("Failed to load resource")]
     ResourceLoadError {
         #[from]
         source: io::Error,
     },
 
-    #[error("Failed to decode resource")]
+    #[error("Failed to decode resource: {0}")]
     ResourceDecodeError(String),
 
     #[error("Failed to send request")]
@@ -76,9 +149,167 @@ pub enum DivoomAPIError {
         source: serde_json::Error,
     },
 
-    #[error("Service or device responded failure")]
+    #[error("Service or device responded failure: {0}")]
     ServerError(DivoomServerErrorInfo),
+
+    /// Error of a specific command in a batch, so we can tell which command failed.
+    #[error("Command {command_index} ({command}) failed: {source}")]
+    CommandError {
+        /// Index of the command in the batch, starting from 0.
+        command_index: usize,
+
+        /// API name of the command, e.g. "set_device_brightness".
+        command: String,
+
+        source: Box<DivoomAPIError>,
+    },
+
+    /// Error of a DSL operation, so scripts can tell which operation failed.
+    #[error("Operation at line {line_number} ({operation}) failed: {source}")]
+    OperationError {
+        /// Line of the operation in the script, or its position in the operation list, starting from 1.
+        line_number: usize,
+
+        /// Name of the DSL command, e.g. "system set-rotation-angle".
+        operation: String,
+
+        source: Box<DivoomAPIError>,
+    },
+}
+
+impl DivoomAPIError {
+    /// Stable machine-readable type of the error, e.g. "parameter-error". For errors of a command in a batch, the type
+    /// of the inner error is returned.
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            DivoomAPIError::ParameterError(_) => "parameter-error",
+            DivoomAPIError::UnsupportedCommandError(_) => "unsupported-command-error",
+            DivoomAPIError::CommandParseError { .. } => "command-parse-error",
+            DivoomAPIError::ResourceLoadError { .. } => "resource-load-error",
+            DivoomAPIError::ResourceDecodeError(_) => "resource-decode-error",
+            DivoomAPIError::RequestError { .. } => "request-error",
+            DivoomAPIError::ResponseDeserializationError { .. } => "response-deserialization-error",
+            DivoomAPIError::ServerError(_) => "server-error",
+            DivoomAPIError::CommandError { source, .. } => source.error_type(),
+            DivoomAPIError::OperationError { source, .. } => source.error_type(),
+        }
+    }
+
+    pub fn category(&self) -> DivoomErrorCategory {
+        match self {
+            DivoomAPIError::ParameterError(_) => DivoomErrorCategory::InvalidInput,
+            DivoomAPIError::UnsupportedCommandError(_) => DivoomErrorCategory::Unsupported,
+            DivoomAPIError::CommandParseError { .. } => DivoomErrorCategory::InvalidInput,
+            DivoomAPIError::ResourceLoadError { .. } => DivoomErrorCategory::InvalidInput,
+            DivoomAPIError::ResourceDecodeError(_) => DivoomErrorCategory::InvalidInput,
+            DivoomAPIError::RequestError { source } => {
+                if source.is_builder() {
+                    DivoomErrorCategory::InvalidInput
+                } else {
+                    DivoomErrorCategory::Transient
+                }
+            }
+            DivoomAPIError::ResponseDeserializationError { .. } => DivoomErrorCategory::Unknown,
+            DivoomAPIError::ServerError(e) => e.category(),
+            DivoomAPIError::CommandError { source, .. } => source.category(),
+            DivoomAPIError::OperationError { source, .. } => source.category(),
+        }
+    }
+
+    /// Index (starting from 0) and API name of the command that failed within a batch.
+    pub fn failed_command(&self) -> Option<(usize, &str)> {
+        match self {
+            DivoomAPIError::CommandError {
+                command_index,
+                command,
+                ..
+            } => Some((*command_index, command.as_str())),
+            DivoomAPIError::OperationError { source, .. } => source.failed_command(),
+            _ => None,
+        }
+    }
+
+    /// Line number (starting from 1) and name of the DSL operation that failed within a script.
+    pub fn failed_operation(&self) -> Option<(usize, &str)> {
+        match self {
+            DivoomAPIError::OperationError {
+                line_number,
+                operation,
+                ..
+            } => Some((*line_number, operation.as_str())),
+            _ => None,
+        }
+    }
+
+    /// The error without the context of the failed command or DSL operation.
+    pub fn root_error(&self) -> &DivoomAPIError {
+        match self {
+            DivoomAPIError::CommandError { source, .. } => source.root_error(),
+            DivoomAPIError::OperationError { source, .. } => source.root_error(),
+            e => e,
+        }
+    }
+
+    pub fn server_error_info(&self) -> Option<&DivoomServerErrorInfo> {
+        match self.root_error() {
+            DivoomAPIError::ServerError(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 /// Result that wraps the error.
 pub type DivoomAPIResult<T> = std::result::Result<T, DivoomAPIError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_error_should_report_category_and_failed_command() {
+        let error = DivoomAPIError::CommandError {
+            command_index: 2,
+            command: "set_device_brightness".into(),
+            source: Box::new(DivoomAPIError::ServerError(
+                DivoomServerErrorInfo::http_error(503),
+            )),
+        };
+
+        assert_eq!(error.category(), DivoomErrorCategory::DeviceBusy);
+        assert_eq!(error.error_type(), "server-error");
+        assert_eq!(error.failed_command(), Some((2, "set_device_brightness")));
+        assert_eq!(error.failed_operation(), None);
+        assert_eq!(error.server_error_info().unwrap().http_status_code, 503);
+        assert_eq!(
+            error.to_string(),
+            "Command 2 (set_device_brightness) failed: Service or device responded failure: HTTP status code = 503 \
+            (service-unavailable: The device or service is busy or restarting.)"
+        );
+    }
+
+    #[test]
+    fn server_error_with_unknown_code_should_be_unknown() {
+        let error = DivoomServerErrorInfo::server_error(12345);
+        assert!(error.error_code_info().is_none());
+        assert_eq!(error.category(), DivoomErrorCategory::Unknown);
+        assert_eq!(error.to_string(), "Error code = 12345");
+    }
+
+    #[test]
+    fn operation_error_should_report_failed_operation_and_command_separately() {
+        let error = DivoomAPIError::OperationError {
+            line_number: 3,
+            operation: "system set-brightness".into(),
+            source: Box::new(DivoomAPIError::CommandError {
+                command_index: 0,
+                command: "set_device_brightness".into(),
+                source: Box::new(DivoomAPIError::ServerError(
+                    DivoomServerErrorInfo::server_error(1),
+                )),
+            }),
+        };
+
+        assert_eq!(error.failed_operation(), Some((3, "system set-brightness")));
+        assert_eq!(error.failed_command(), Some((0, "set_device_brightness")));
+    }
+}
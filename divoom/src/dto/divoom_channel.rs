@@ -17,6 +17,9 @@ pub enum DivoomChannelType {
 
 impl_divoom_dto_enum_traits!(DivoomChannelType, Clock: "clock", CloudChannel: "cloud", Visualizer: "visualizer", CustomPage: "customPage");
 
+/// Max number of custom pages on the devices, and the page index starts from 0.
+pub const DIVOOM_MAX_CUSTOM_PAGE_COUNT: i32 = 3;
+
 /// Clock info list that returned from Divoom service (not device).
 /// The result will be paginated, hence we have to `total_num` field here to help query with pagination.
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,236 @@
+use crate::{
+    DivoomAPIError, DivoomAPIResult, DivoomImageAnimation, DivoomTextAnimation,
+    DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT, DIVOOM_MAX_CUSTOM_PAGE_COUNT,
+    DIVOOM_TEXT_ANIMATION_FONT_COUNT, DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Divoom device models. Each model comes with a capability profile, which is used to validate the commands before
+/// sending them to the device.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Default)]
+pub enum DivoomDeviceModel {
+    Pixoo16,
+    #[default]
+    Pixoo64,
+    TimeboxEvo,
+    Ditoo,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomDeviceModel, Pixoo16: "pixoo-16", Pixoo64: "pixoo-64", TimeboxEvo: "timebox-evo", Ditoo: "ditoo");
+
+/// Capability profile of a device model, such as the native resolution, the limits of the commands and the commands
+/// that are not supported.
+#[derive(Debug, PartialEq)]
+pub struct DivoomDeviceModelProfile {
+    pub model: DivoomDeviceModel,
+
+    /// Native resolution of the screen, which is the default canvas size of the animations.
+    pub native_resolution: u32,
+
+    /// Canvas sizes of the image animations that the device can play.
+    pub supported_canvas_sizes: &'static [u32],
+
+    /// Max number of frames in a single image animation.
+    pub max_animation_frame_count: usize,
+
+    /// Max text id of the text animations.
+    pub max_text_id: i32,
+
+    /// Number of fonts for text animations, and the font index starts from 0.
+    pub text_font_count: i32,
+
+    /// Number of custom pages, and the page index starts from 0.
+    pub custom_page_count: i32,
+
+    /// Commands that are not supported, named by the API names in `PixooClient`, e.g. "play_buzzer".
+    pub unsupported_commands: &'static [&'static str],
+}
+
+// Divoom only documents the limits of the Pixoo-64 API (http://doc.divoom-gz.com/web/#/12), so the limits of the other models
+// follow it, except the screen size and the features that the model doesn't have in the Divoom app.
+
+/// Pixoo-64: 64x64 screen, and all limits come from the Pixoo-64 API document.
+const PIXOO_64_PROFILE: DivoomDeviceModelProfile = DivoomDeviceModelProfile {
+    model: DivoomDeviceModel::Pixoo64,
+    native_resolution: 64,
+    supported_canvas_sizes: &[16, 32, 64],
+    max_animation_frame_count: DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT,
+    max_text_id: DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID,
+    text_font_count: DIVOOM_TEXT_ANIMATION_FONT_COUNT,
+    custom_page_count: DIVOOM_MAX_CUSTOM_PAGE_COUNT,
+    unsupported_commands: &[],
+};
+
+/// Pixoo-16: 16x16 screen, which has no buzzer and no white balance setting in the Divoom app.
+const PIXOO_16_PROFILE: DivoomDeviceModelProfile = DivoomDeviceModelProfile {
+    model: DivoomDeviceModel::Pixoo16,
+    native_resolution: 16,
+    supported_canvas_sizes: &[16],
+    max_animation_frame_count: DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT,
+    max_text_id: DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID,
+    text_font_count: DIVOOM_TEXT_ANIMATION_FONT_COUNT,
+    custom_page_count: DIVOOM_MAX_CUSTOM_PAGE_COUNT,
+    unsupported_commands: &["play_buzzer", "set_device_white_balance"],
+};
+
+/// Timebox Evo: 16x16 screen, which has no custom pages, and no high light mode or white balance setting in the Divoom app.
+const TIMEBOX_EVO_PROFILE: DivoomDeviceModelProfile = DivoomDeviceModelProfile {
+    model: DivoomDeviceModel::TimeboxEvo,
+    native_resolution: 16,
+    supported_canvas_sizes: &[16],
+    max_animation_frame_count: DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT,
+    max_text_id: DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID,
+    text_font_count: DIVOOM_TEXT_ANIMATION_FONT_COUNT,
+    custom_page_count: 0,
+    unsupported_commands: &[
+        "select_custom_page",
+        "set_device_high_light_mode",
+        "set_device_white_balance",
+    ],
+};
+
+/// Ditoo: 16x16 screen, which has no cloud channel and no custom pages, and no high light mode, mirror mode or white balance
+/// setting in the Divoom app.
+const DITOO_PROFILE: DivoomDeviceModelProfile = DivoomDeviceModelProfile {
+    model: DivoomDeviceModel::Ditoo,
+    native_resolution: 16,
+    supported_canvas_sizes: &[16],
+    max_animation_frame_count: DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT,
+    max_text_id: DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID,
+    text_font_count: DIVOOM_TEXT_ANIMATION_FONT_COUNT,
+    custom_page_count: 0,
+    unsupported_commands: &[
+        "select_cloud_channel",
+        "select_custom_page",
+        "set_device_high_light_mode",
+        "set_device_mirror_mode",
+        "set_device_white_balance",
+    ],
+};
+
+impl DivoomDeviceModel {
+    /// Capability profile of this model.
+    pub fn profile(&self) -> &'static DivoomDeviceModelProfile {
+        match self {
+            DivoomDeviceModel::Pixoo16 => &PIXOO_16_PROFILE,
+            DivoomDeviceModel::Pixoo64 => &PIXOO_64_PROFILE,
+            DivoomDeviceModel::TimeboxEvo => &TIMEBOX_EVO_PROFILE,
+            DivoomDeviceModel::Ditoo => &DITOO_PROFILE,
+        }
+    }
+}
+
+/// Validations
+impl DivoomDeviceModelProfile {
+    pub fn supports_command(&self, api_name: &str) -> bool {
+        !self.unsupported_commands.contains(&api_name)
+    }
+
+    pub fn validate_command(&self, api_name: &str) -> DivoomAPIResult<()> {
+        if !self.supports_command(api_name) {
+            return Err(DivoomAPIError::UnsupportedCommandError(format!(
+                "Command {} is not supported by device model {}.",
+                api_name, self.model
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_canvas_size(&self, canvas_size: u32) -> DivoomAPIResult<()> {
+        if !self.supported_canvas_sizes.contains(&canvas_size) {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid canvas size: {}. Device model {} only supports {:?}.",
+                canvas_size, self.model, self.supported_canvas_sizes
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_custom_page_index(&self, custom_page_index: &i32) -> DivoomAPIResult<()> {
+        if *custom_page_index < 0 || *custom_page_index >= self.custom_page_count {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid custom page index: {}. Device model {} only has {} custom pages.",
+                custom_page_index, self.model, self.custom_page_count
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_text_animation(&self, animation: &DivoomTextAnimation) -> DivoomAPIResult<()> {
+        if animation.text_id < 0 || animation.text_id > self.max_text_id {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid text id: {}. Device model {} only supports text id 0-{}.",
+                animation.text_id, self.model, self.max_text_id
+            )));
+        }
+
+        if animation.font_index < 0 || animation.font_index >= self.text_font_count {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Invalid font index: {}. Device model {} only has {} fonts.",
+                animation.font_index, self.model, self.text_font_count
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_image_animation(
+        &self,
+        _id: &i32,
+        animation: &DivoomImageAnimation,
+    ) -> DivoomAPIResult<()> {
+        self.validate_canvas_size(animation.size)?;
+
+        if animation.frame_count > self.max_animation_frame_count {
+            return Err(DivoomAPIError::ParameterError(format!(
+                "Too many frames: {}. Device model {} only supports {} frames in an animation.",
+                animation.frame_count, self.model, self.max_animation_frame_count
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_model_can_be_parsed_from_string() {
+        assert_eq!(
+            "pixoo-16".parse::<DivoomDeviceModel>().unwrap(),
+            DivoomDeviceModel::Pixoo16
+        );
+        assert_eq!(
+            "timebox-evo".parse::<DivoomDeviceModel>().unwrap(),
+            DivoomDeviceModel::TimeboxEvo
+        );
+        assert_eq!(DivoomDeviceModel::default().to_string(), "pixoo-64");
+        assert!("pixoo-32".parse::<DivoomDeviceModel>().is_err());
+    }
+
+    #[test]
+    fn device_model_profile_should_validate_limits() {
+        let profile = DivoomDeviceModel::Pixoo16.profile();
+        assert_eq!(profile.native_resolution, 16);
+        assert!(profile.validate_command("select_clock").is_ok());
+        assert!(profile.validate_command("play_buzzer").is_err());
+        assert!(profile.validate_canvas_size(16).is_ok());
+        assert!(profile.validate_canvas_size(64).is_err());
+
+        let profile = DivoomDeviceModel::Ditoo.profile();
+        assert!(profile.validate_command("select_custom_page").is_err());
+        assert!(profile.validate_custom_page_index(&0).is_err());
+        assert!(profile.validate_canvas_size(32).is_err());
+
+        let profile = DivoomDeviceModel::Pixoo64.profile();
+        assert!(profile.validate_custom_page_index(&2).is_ok());
+        assert!(profile.validate_custom_page_index(&3).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Category of the errors, which tells the caller what can be done about the error, e.g. retrying the transient errors
+/// or fixing the input.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub enum DivoomErrorCategory {
+    /// The error might go away by retrying, e.g. the device is offline or the request timed out.
+    Transient,
+
+    /// The device is handling too many requests. Retrying later with lower rate should work.
+    DeviceBusy,
+
+    /// The request is invalid, e.g. parameters out of range or failing to load the resources.
+    InvalidInput,
+
+    /// The command is not supported by the device model or the firmware.
+    Unsupported,
+
+    /// Unknown errors, e.g. unknown error codes from the device or unexpected responses.
+    Unknown,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomErrorCategory, Transient: "transient", DeviceBusy: "device-busy", InvalidInput: "invalid-input", Unsupported: "unsupported", Unknown: "unknown");
+
+/// Where the error code comes from, since the HTTP status codes and the Divoom error codes are in different spaces.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub enum DivoomErrorCodeSource {
+    /// HTTP status code returned from Divoom service or device.
+    Http,
+
+    /// Error code in the response body, e.g. "error_code" from devices or "ReturnCode" from Divoom online service.
+    Divoom,
+}
+
+/// Known meaning of an error code.
+#[derive(Debug, PartialEq)]
+pub struct DivoomErrorCodeInfo {
+    pub source: DivoomErrorCodeSource,
+    pub code: i32,
+
+    /// Stable machine-readable name of the error code, e.g. "too-many-requests".
+    pub name: &'static str,
+
+    pub description: &'static str,
+    pub category: DivoomErrorCategory,
+}
+
+macro_rules! divoom_error_code {
+    ($source:ident, $code:literal, $name:literal, $category:ident, $description:literal) => {
+        DivoomErrorCodeInfo {
+            source: DivoomErrorCodeSource::$source,
+            code: $code,
+            name: $name,
+            description: $description,
+            category: DivoomErrorCategory::$category,
+        }
+    };
+}
+
+/// Error codes with known meanings, which are the standard HTTP status codes plus the generic failure code in the
+/// "error_code" field of Pixoo responses. Divoom doesn't document its error codes, so the other codes are reported as
+/// unknown errors with the raw code.
+pub const DIVOOM_KNOWN_ERROR_CODES: &[DivoomErrorCodeInfo] = &[
+    divoom_error_code!(
+        Http,
+        400,
+        "bad-request",
+        InvalidInput,
+        "The request is rejected as malformed."
+    ),
+    divoom_error_code!(
+        Http,
+        404,
+        "not-found",
+        Unsupported,
+        "The API is not found, usually because the device firmware doesn't support it."
+    ),
+    divoom_error_code!(
+        Http,
+        408,
+        "request-timeout",
+        Transient,
+        "The device or service didn't receive the request in time."
+    ),
+    divoom_error_code!(
+        Http,
+        429,
+        "too-many-requests",
+        DeviceBusy,
+        "Too many requests are sent to the device or service."
+    ),
+    divoom_error_code!(
+        Http,
+        500,
+        "internal-error",
+        Transient,
+        "The device or service failed to handle the request."
+    ),
+    divoom_error_code!(
+        Http,
+        502,
+        "bad-gateway",
+        Transient,
+        "The service is not reachable via the gateway."
+    ),
+    divoom_error_code!(
+        Http,
+        503,
+        "service-unavailable",
+        DeviceBusy,
+        "The device or service is busy or restarting."
+    ),
+    divoom_error_code!(
+        Http,
+        504,
+        "gateway-timeout",
+        Transient,
+        "The service didn't respond in time."
+    ),
+    divoom_error_code!(
+        Divoom,
+        1,
+        "general-failure",
+        Unknown,
+        "The device rejected the request without telling the reason."
+    ),
+];
+
+impl DivoomErrorCodeInfo {
+    /// Look up the known meaning of the error code.
+    pub fn lookup(
+        source: DivoomErrorCodeSource,
+        code: i32,
+    ) -> Option<&'static DivoomErrorCodeInfo> {
+        DIVOOM_KNOWN_ERROR_CODES
+            .iter()
+            .find(|x| x.source == source && x.code == code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_error_codes_should_be_looked_up_by_source_and_code() {
+        let info = DivoomErrorCodeInfo::lookup(DivoomErrorCodeSource::Http, 429).unwrap();
+        assert_eq!(info.name, "too-many-requests");
+        assert_eq!(info.category, DivoomErrorCategory::DeviceBusy);
+
+        assert!(DivoomErrorCodeInfo::lookup(DivoomErrorCodeSource::Divoom, 429).is_none());
+        assert_eq!(
+            DivoomErrorCategory::InvalidInput.to_string(),
+            "invalid-input"
+        );
+    }
+}
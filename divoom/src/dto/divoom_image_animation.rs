@@ -1,13 +1,17 @@
 use image::codecs::gif::{GifEncoder, Repeat};
 use image::{Frame, RgbaImage};
+use rgb::RGB8;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
 use std::fmt;
-use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 use std::str::FromStr;
 
 /// Definition of image animations.
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DivoomImageAnimation {
     /// Size of canvas. Only 16, 32, 64 are supported
@@ -26,6 +30,9 @@ pub struct DivoomImageAnimation {
 
 pub const DIVOOM_IMAGE_ANIMATION_ID_AUTO: i32 = -1;
 
+/// Max number of frames in a single image animation, which is the limit of `PicNum` in the official doc.
+pub const DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT: usize = 60;
+
 /// The data of this frame.
 ///
 /// The data format looks like below, which goes row by row and column by column, from left to right and top to down,
@@ -48,6 +55,62 @@ pub enum DivoomFileAnimationSourceType {
 
 impl_divoom_dto_enum_traits!(DivoomFileAnimationSourceType, LocalFile: "file", LocalFolder: "folder", Url: "url");
 
+/// File formats that image animations can be exported to.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+pub enum DivoomImageAnimationFileFormat {
+    /// Animated GIF.
+    Gif,
+
+    /// Animated PNG.
+    Apng,
+
+    /// One PNG file per frame in a folder, named as `frame_<frame offset>.png`.
+    PngFrames,
+
+    /// All frames laid out from left to right in a single PNG file.
+    SpriteSheet,
+}
+
+impl_divoom_dto_enum_traits_without_raw!(DivoomImageAnimationFileFormat, Gif: "gif", Apng: "apng", PngFrames: "png-frames", SpriteSheet: "sprite-sheet");
+
+/// Options for generating the upscaled preview of image animations.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DivoomImageAnimationPreviewOptions {
+    /// Number of pixels in the preview for each pixel on the device.
+    pub scale: u32,
+
+    /// Simulate the LED panel by drawing every pixel as a round dot on top of the grid color.
+    pub led_grid: bool,
+
+    /// Color of the gaps between the LED dots.
+    pub grid_color: RGB8,
+}
+
+impl Default for DivoomImageAnimationPreviewOptions {
+    fn default() -> Self {
+        DivoomImageAnimationPreviewOptions {
+            scale: 8,
+            led_grid: false,
+            grid_color: RGB8::new(0, 0, 0),
+        }
+    }
+}
+
+impl DivoomImageAnimationPreviewOptions {
+    pub fn new(scale: u32) -> Self {
+        DivoomImageAnimationPreviewOptions {
+            scale,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_led_grid(mut self, grid_color: RGB8) -> Self {
+        self.led_grid = true;
+        self.grid_color = grid_color;
+        self
+    }
+}
+
 impl DivoomImageAnimation {
     pub fn save_gif<W: Write>(&self, image: W) -> anyhow::Result<()> {
         let mut encoder = GifEncoder::new(image);
@@ -69,4 +132,253 @@ impl DivoomImageAnimation {
 
         Ok(())
     }
+
+    /// Save the animation as APNG. All frames are played with the animation speed and looped forever.
+    pub fn save_apng<W: Write>(&self, image: W) -> anyhow::Result<()> {
+        let mut encoder = png::Encoder::new(image, self.size, self.size);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        encoder.set_frame_delay(self.speed_in_ms.clamp(0, u16::MAX as i32) as u16, 1000)?;
+
+        let mut writer = encoder.write_header()?;
+        for frame_data in self.frames.values() {
+            writer.write_image_data(frame_data)?;
+        }
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Save a single frame as PNG.
+    pub fn save_png_frame<W: Write>(&self, frame_offset: u32, image: W) -> anyhow::Result<()> {
+        let frame_data = self
+            .frames
+            .get(&frame_offset)
+            .ok_or_else(|| anyhow::anyhow!("Frame not found: Offset = {}", frame_offset))?;
+        save_rgb_png(image, self.size, self.size, frame_data)
+    }
+
+    /// Save all frames as PNG files into the folder, named as `frame_<frame offset>.png`. The folder will be created if it
+    /// doesn't exist.
+    pub fn save_png_frames<P: AsRef<Path>>(&self, folder_path: P) -> anyhow::Result<()> {
+        fs::create_dir_all(&folder_path)?;
+
+        for frame_offset in self.frames.keys() {
+            let frame_path = folder_path
+                .as_ref()
+                .join(format!("frame_{:03}.png", frame_offset));
+            self.save_png_frame(*frame_offset, BufWriter::new(File::create(frame_path)?))?;
+        }
+
+        Ok(())
+    }
+
+    /// Save all frames into a single PNG file as sprite sheet.
+    ///
+    /// The frames are laid out from left to right, then top to bottom. When column count is not specified, all frames
+    /// will be put in a single row. The unused cells at the end are left black.
+    pub fn save_sprite_sheet<W: Write>(
+        &self,
+        image: W,
+        column_count: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let frame_count = self.frames.len().max(1) as u32;
+        let column_count = column_count.unwrap_or(frame_count).clamp(1, frame_count);
+        let row_count = frame_count.div_ceil(column_count);
+
+        let (sheet_width, sheet_height) = (self.size * column_count, self.size * row_count);
+        let mut sheet_data = vec![0u8; (sheet_width * sheet_height * 3) as usize];
+        let row_size = (self.size * 3) as usize;
+        for (index, frame_data) in self.frames.values().enumerate() {
+            let (column, row) = (index as u32 % column_count, index as u32 / column_count);
+            for y in 0..self.size {
+                let source_start = y as usize * row_size;
+                let target_start =
+                    (((row * self.size + y) * sheet_width + column * self.size) * 3) as usize;
+                sheet_data[target_start..target_start + row_size]
+                    .copy_from_slice(&frame_data[source_start..source_start + row_size]);
+            }
+        }
+
+        save_rgb_png(image, sheet_width, sheet_height, &sheet_data)
+    }
+
+    /// Save the animation into a file with the specified format. For PNG frames, the file path is used as the folder path.
+    pub fn save_file<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        format: DivoomImageAnimationFileFormat,
+    ) -> anyhow::Result<()> {
+        if format == DivoomImageAnimationFileFormat::PngFrames {
+            return self.save_png_frames(file_path);
+        }
+
+        let mut file = BufWriter::new(File::create(file_path)?);
+        match format {
+            DivoomImageAnimationFileFormat::Gif => self.save_gif(&mut file)?,
+            DivoomImageAnimationFileFormat::Apng => self.save_apng(&mut file)?,
+            DivoomImageAnimationFileFormat::SpriteSheet => {
+                self.save_sprite_sheet(&mut file, None)?
+            }
+            DivoomImageAnimationFileFormat::PngFrames => unreachable!(),
+        }
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Generate an upscaled preview of the animation, which can be saved in any supported format.
+    ///
+    /// Every pixel is scaled up with nearest neighbour, so the pixel art stays sharp. With LED grid enabled, every pixel
+    /// is drawn as a round dot to simulate how it looks on the device. The LED grid is skipped when scale is less than 3,
+    /// because there is no room to draw the gaps.
+    pub fn to_preview(&self, options: &DivoomImageAnimationPreviewOptions) -> DivoomImageAnimation {
+        let scale = options.scale.max(1);
+        let preview_size = self.size * scale;
+
+        // Precompute which pixels in a scaled cell are lit, since it is the same for all cells.
+        let led_radius = scale as f32 * 0.4;
+        let cell_mask: Vec<bool> = (0..scale * scale)
+            .map(|index| {
+                if !options.led_grid || scale < 3 {
+                    return true;
+                }
+
+                let dx = (index % scale) as f32 + 0.5 - scale as f32 / 2.0;
+                let dy = (index / scale) as f32 + 0.5 - scale as f32 / 2.0;
+                dx * dx + dy * dy <= led_radius * led_radius
+            })
+            .collect();
+
+        let grid_color = [
+            options.grid_color.r,
+            options.grid_color.g,
+            options.grid_color.b,
+        ];
+        let frames = self
+            .frames
+            .iter()
+            .map(|(frame_offset, frame_data)| {
+                let mut preview_data =
+                    Vec::with_capacity((preview_size * preview_size * 3) as usize);
+                for y in 0..preview_size {
+                    for x in 0..preview_size {
+                        if cell_mask[((y % scale) * scale + x % scale) as usize] {
+                            let pixel_start = 3 * (x / scale + y / scale * self.size) as usize;
+                            preview_data
+                                .extend_from_slice(&frame_data[pixel_start..pixel_start + 3]);
+                        } else {
+                            preview_data.extend_from_slice(&grid_color);
+                        }
+                    }
+                }
+                (*frame_offset, preview_data)
+            })
+            .collect();
+
+        DivoomImageAnimation {
+            size: preview_size,
+            frame_count: self.frame_count,
+            speed_in_ms: self.speed_in_ms,
+            frames,
+        }
+    }
+}
+
+fn save_rgb_png<W: Write>(image: W, width: u32, height: u32, data: &[u8]) -> anyhow::Result<()> {
+    let mut encoder = png::Encoder::new(image, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DivoomAnimationResourceLoader;
+    use std::time::Duration;
+
+    fn new_test_animation() -> DivoomImageAnimation {
+        let colors = [[255u8, 0, 0], [0, 255, 0], [0, 0, 255]];
+        DivoomImageAnimation {
+            size: 4,
+            frame_count: colors.len(),
+            speed_in_ms: 200,
+            frames: colors
+                .iter()
+                .enumerate()
+                .map(|(index, color)| (index as u32, color.repeat(16)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn image_animation_can_be_saved_as_apng() {
+        let animation = new_test_animation();
+        let mut apng: Vec<u8> = Vec::new();
+        animation.save_apng(&mut apng).unwrap();
+
+        let sequence = DivoomAnimationResourceLoader::from_apng_buf(&apng).unwrap();
+        assert_eq!(sequence.frames.len(), 3);
+        assert_eq!(sequence.delays, vec![Duration::from_millis(200); 3]);
+        assert_eq!(sequence.loop_count, 0);
+
+        let pixel = sequence.frames[2].pixel(1, 1).unwrap();
+        assert_eq!([pixel.red(), pixel.green(), pixel.blue()], [0, 0, 255]);
+    }
+
+    #[test]
+    fn image_animation_can_be_saved_as_sprite_sheet() {
+        let animation = new_test_animation();
+        let mut sprite_sheet: Vec<u8> = Vec::new();
+        animation
+            .save_sprite_sheet(&mut sprite_sheet, Some(2))
+            .unwrap();
+
+        let sheet = DivoomAnimationResourceLoader::from_image_buf(&sprite_sheet).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (8, 8));
+
+        let frames =
+            DivoomAnimationResourceLoader::from_sprite_sheet_buf(&sprite_sheet, 4, 4, None)
+                .unwrap();
+        let colors: Vec<[u8; 3]> = frames
+            .iter()
+            .map(|frame| {
+                let pixel = frame.pixel(3, 3).unwrap();
+                [pixel.red(), pixel.green(), pixel.blue()]
+            })
+            .collect();
+        assert_eq!(
+            colors,
+            vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], [0, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn image_animation_can_generate_preview_with_led_grid() {
+        let animation = new_test_animation();
+
+        let preview = animation.to_preview(&DivoomImageAnimationPreviewOptions::new(8));
+        assert_eq!(preview.size, 32);
+        assert_eq!(preview.frames.len(), 3);
+        assert!(preview.frames[&0].chunks(3).all(|x| x == [255, 0, 0]));
+
+        let preview = animation.to_preview(
+            &DivoomImageAnimationPreviewOptions::new(8).with_led_grid(RGB8::new(16, 16, 16)),
+        );
+        let pixel_at = |x: u32, y: u32| {
+            let pixel_start = 3 * (x + y * preview.size) as usize;
+            &preview.frames[&1][pixel_start..pixel_start + 3]
+        };
+        assert_eq!(pixel_at(4, 4), [0, 255, 0]);
+        assert_eq!(pixel_at(0, 0), [16, 16, 16]);
+        assert_eq!(pixel_at(8, 0), [16, 16, 16]);
+        assert_eq!(pixel_at(12, 12), [0, 255, 0]);
+    }
 }
@@ -0,0 +1,229 @@
+use crate::{
+    DivoomAPIError, DivoomAPIResult, DIVOOM_MAX_CUSTOM_PAGE_COUNT,
+    DIVOOM_TEXT_ANIMATION_FONT_COUNT, DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Integer parameter with a valid range. The ranges are defined once in the parameter types, so the other places that
+/// need them, e.g. the API schema of the gateway, can read them from here.
+pub trait DivoomRangedParameter: Sized {
+    /// Name of the field in error messages, e.g. "brightness".
+    const FIELD_NAME: &'static str;
+    const MIN: i32;
+    const MAX: i32;
+
+    fn new(value: i32) -> DivoomAPIResult<Self>;
+    fn value(&self) -> i32;
+}
+
+/// Define a newtype for an integer parameter with a valid range. The value can only be created via the validated
+/// constructors, so the parameters are checked before anything is sent to the device, and the error carries the field
+/// name, e.g. "Invalid brightness: 120. It must be between 0 and 100.".
+macro_rules! define_divoom_ranged_parameter {
+    ($(#[$meta:meta])* $name:ident, $field_name:literal, $min:expr, $max:expr) => (
+        $(#[$meta])*
+        #[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
+        pub struct $name(i32);
+
+        impl DivoomRangedParameter for $name {
+            const FIELD_NAME: &'static str = $field_name;
+            const MIN: i32 = $min;
+            const MAX: i32 = $max;
+
+            fn new(value: i32) -> DivoomAPIResult<Self> {
+                $name::new(value)
+            }
+
+            fn value(&self) -> i32 {
+                self.0
+            }
+        }
+
+        impl $name {
+            pub fn new(value: i32) -> DivoomAPIResult<Self> {
+                $name::new_for_field($field_name, value)
+            }
+
+            /// Same as `new`, but report the error with the given field name, e.g. "r" for white balance.
+            pub fn new_for_field(field_name: &str, value: i32) -> DivoomAPIResult<Self> {
+                validate_parameter_range(
+                    field_name,
+                    value,
+                    <$name as DivoomRangedParameter>::MIN,
+                    <$name as DivoomRangedParameter>::MAX,
+                )?;
+                Ok($name(value))
+            }
+
+            pub fn value(&self) -> i32 {
+                self.0
+            }
+        }
+
+        impl TryFrom<i32> for $name {
+            type Error = DivoomAPIError;
+
+            fn try_from(value: i32) -> Result<Self, Self::Error> {
+                $name::new(value)
+            }
+        }
+
+        impl From<$name> for i32 {
+            fn from(v: $name) -> Self {
+                v.0
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+            fn from_str(v: &str) -> Result<Self, Self::Err> {
+                let parsed = v
+                    .parse::<i32>()
+                    .map_err(|x| format!("Invalid value for {}: {}", $field_name, x))?;
+                $name::new(parsed).map_err(|e| match e {
+                    DivoomAPIError::ParameterError(message) => message,
+                    e => e.to_string(),
+                })
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_i32(self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let value = i32::deserialize(deserializer)?;
+                $name::new(value).map_err(|e| match e {
+                    DivoomAPIError::ParameterError(message) => de::Error::custom(message),
+                    e => de::Error::custom(e),
+                })
+            }
+        }
+    )
+}
+
+define_divoom_ranged_parameter!(
+    /// Device brightness, 0-100.
+    DivoomBrightness, "brightness", 0, 100
+);
+
+define_divoom_ranged_parameter!(
+    /// Custom page index. The range covers the custom pages of all device models, and the device model profile checks
+    /// the page count of each model.
+    DivoomCustomPageIndex, "custom_page_index", 0, DIVOOM_MAX_CUSTOM_PAGE_COUNT - 1
+);
+
+define_divoom_ranged_parameter!(
+    /// Level of a color channel in white balance, 0-255.
+    DivoomWhiteBalanceLevel, "white_balance", 0, 255
+);
+
+define_divoom_ranged_parameter!(
+    /// Minutes of the countdown tool, 0-59.
+    DivoomCountdownMinute, "minute", 0, 59
+);
+
+define_divoom_ranged_parameter!(
+    /// Seconds of the countdown tool, 0-59.
+    DivoomCountdownSecond, "second", 0, 59
+);
+
+define_divoom_ranged_parameter!(
+    /// Text id of the text animation, 0-20.
+    DivoomTextId, "text_id", 0, DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID
+);
+
+define_divoom_ranged_parameter!(
+    /// Font index of the text animation, 0-7, since Divoom only has 8 fonts.
+    DivoomTextFontIndex, "font_index", 0, DIVOOM_TEXT_ANIMATION_FONT_COUNT - 1
+);
+
+define_divoom_ranged_parameter!(
+    /// Text width of the text animation, 16-64.
+    DivoomTextWidth, "text_width", 16, 64
+);
+
+define_divoom_ranged_parameter!(
+    /// Time of the buzzer in milliseconds, which cannot be negative.
+    DivoomBuzzerTime, "buzzer_time", 0, i32::MAX
+);
+
+fn validate_parameter_range(
+    field_name: &str,
+    value: i32,
+    min: i32,
+    max: i32,
+) -> DivoomAPIResult<()> {
+    if value >= min && value <= max {
+        return Ok(());
+    }
+
+    let message = if max == i32::MAX {
+        format!("Invalid {}: {}. It must be >= {}.", field_name, value, min)
+    } else {
+        format!(
+            "Invalid {}: {}. It must be between {} and {}.",
+            field_name, value, min, max
+        )
+    };
+    Err(DivoomAPIError::ParameterError(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranged_parameter_should_validate_range() {
+        assert_eq!(DivoomBrightness::new(100).unwrap().value(), 100);
+        assert_eq!(i32::from(DivoomTextWidth::new(16).unwrap()), 16);
+
+        match DivoomBrightness::new(120) {
+            Err(DivoomAPIError::ParameterError(message)) => assert_eq!(
+                message,
+                "Invalid brightness: 120. It must be between 0 and 100."
+            ),
+            result => panic!("Brightness should be invalid: {:?}", result),
+        }
+
+        match DivoomWhiteBalanceLevel::new_for_field("g", -1) {
+            Err(DivoomAPIError::ParameterError(message)) => {
+                assert_eq!(message, "Invalid g: -1. It must be between 0 and 255.")
+            }
+            result => panic!("White balance should be invalid: {:?}", result),
+        }
+
+        assert!(DivoomBuzzerTime::try_from(-1).is_err());
+        assert_eq!(<DivoomCustomPageIndex as DivoomRangedParameter>::MAX, 2);
+    }
+
+    #[test]
+    fn ranged_parameter_should_validate_when_parsing() {
+        assert_eq!("20".parse::<DivoomTextId>().unwrap().value(), 20);
+        assert!("21".parse::<DivoomTextId>().is_err());
+        assert_eq!(
+            serde_json::from_str::<DivoomTextId>("3").unwrap().value(),
+            3
+        );
+
+        let error = serde_json::from_str::<DivoomTextWidth>("8").unwrap_err();
+        assert!(error.to_string().contains("text_width"), "{}", error);
+    }
+}
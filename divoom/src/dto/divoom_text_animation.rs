@@ -1,9 +1,17 @@
 use super::divoom_dto_common::*;
+use crate::dto::{DivoomTextFontIndex, DivoomTextId, DivoomTextWidth};
+use crate::DivoomAPIResult;
 use rgb::RGB8;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::str::FromStr;
 
+/// Max text id of the text animations.
+pub const DIVOOM_TEXT_ANIMATION_MAX_TEXT_ID: i32 = 20;
+
+/// Number of fonts for the text animations, and the font index starts from 0.
+pub const DIVOOM_TEXT_ANIMATION_FONT_COUNT: i32 = 8;
+
 /// Font types
 #[derive(Debug, PartialOrd, PartialEq, Copy, Clone)]
 pub enum DivoomFontType {
@@ -99,4 +107,12 @@ impl DivoomTextAnimation {
             align: DivoomTextAnimationAlign::Left,
         }
     }
+
+    /// Validate the text id, font index and text width, so invalid animations are rejected before sending them.
+    pub fn validate(&self) -> DivoomAPIResult<()> {
+        DivoomTextId::new(self.text_id)?;
+        DivoomTextFontIndex::new(self.font_index)?;
+        DivoomTextWidth::new(self.text_width)?;
+        Ok(())
+    }
 }
@@ -3,13 +3,19 @@ mod divoom_dto_common;
 mod divoom_api_error;
 mod divoom_channel;
 mod divoom_device;
+mod divoom_device_model;
+mod divoom_error_code;
 mod divoom_image_animation;
+mod divoom_parameter;
 mod divoom_text_animation;
 mod divoom_tool;
 
 pub use divoom_api_error::*;
 pub use divoom_channel::*;
 pub use divoom_device::*;
+pub use divoom_device_model::*;
+pub use divoom_error_code::*;
 pub use divoom_image_animation::*;
+pub use divoom_parameter::*;
 pub use divoom_text_animation::*;
 pub use divoom_tool::*;
@@ -1,8 +1,176 @@
-use serde::{Deserialize, Serialize};
+use crate::dsl::DivoomDeviceCommand;
+use serde::de::value::{EnumAccessDeserializer, MapAccessDeserializer};
+use serde::de::{EnumAccess, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DivoomScheduleConfigCronJob {
     pub cron: String,
+
+    /// DSL commands to run. In the config files, each operation can be written as either a DSL command string, e.g.
+    /// "channel set-clock 100", or a structured command object, e.g. `channel: { set-clock: { clock-id: 100 } }`, which
+    /// is converted to the canonical DSL command string when loading. Please see `DivoomDeviceCommand` for the format of
+    /// the structured commands, in which the options can be omitted to use their default values.
+    #[serde(default, deserialize_with = "deserialize_operations")]
     pub operations: Vec<String>,
+
+    /// DSL script that runs after the operations, which supports variables, conditionals, loops and sleeps.
+    /// Please see `DivoomDslScript` for the syntax.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+}
+
+fn deserialize_operations<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_seq(DivoomScheduleConfigOperationsVisitor)
+}
+
+struct DivoomScheduleConfigOperationsVisitor;
+
+impl<'de> Visitor<'de> for DivoomScheduleConfigOperationsVisitor {
+    type Value = Vec<String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of DSL command strings or structured command objects")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut operations = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(operation) = seq.next_element::<DivoomScheduleConfigOperation>()? {
+            operations.push(operation.0);
+        }
+        Ok(operations)
+    }
+}
+
+struct DivoomScheduleConfigOperation(String);
+
+impl<'de> Deserialize<'de> for DivoomScheduleConfigOperation {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DivoomScheduleConfigOperationVisitor)
+    }
+}
+
+// Unlike the untagged enums, the visitor forwards the structured commands to `DivoomDeviceCommand` directly, so the
+// errors point to the invalid field, instead of saying nothing matches. The nested commands are also accepted as single
+// key maps, which YAML only allows as tags by default.
+struct DivoomScheduleConfigOperationVisitor;
+
+impl<'de> Visitor<'de> for DivoomScheduleConfigOperationVisitor {
+    type Value = DivoomScheduleConfigOperation;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a DSL command string or a structured command object")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(DivoomScheduleConfigOperation(v.to_string()))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let command: DivoomDeviceCommand = serde_yaml::with::singleton_map_recursive::deserialize(
+            MapAccessDeserializer::new(map),
+        )?;
+        Ok(DivoomScheduleConfigOperation(command.to_string()))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let command: DivoomDeviceCommand = serde_yaml::with::singleton_map_recursive::deserialize(
+            EnumAccessDeserializer::new(data),
+        )?;
+        Ok(DivoomScheduleConfigOperation(command.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::DivoomDslParser;
+
+    #[test]
+    fn schedule_config_can_parse_structured_operations() {
+        let config: DivoomScheduleConfigCronJob = serde_yaml::from_str(
+            r#"
+cron: "0 0 * * * *"
+operations:
+  - "channel set clock"
+  - channel:
+      set-clock:
+        clock-id: 100
+  - system: get-settings
+  - animation:
+      text:
+        set:
+          text-id: 1
+          text-string: "Hello world"
+          r: 0
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.operations,
+            vec![
+                "channel set clock",
+                "channel set-clock 100",
+                "system get-settings",
+                "animation text set 1 'Hello world' -r=0",
+            ]
+        );
+
+        for operation in &config.operations {
+            DivoomDslParser::parse(operation).unwrap();
+        }
+    }
+
+    #[test]
+    fn schedule_config_can_round_trip_operations() {
+        let config = DivoomScheduleConfigCronJob {
+            cron: "0 0 * * * *".into(),
+            operations: vec![
+                "channel set clock".into(),
+                "tool countdown start 1 30".into(),
+            ],
+            script: None,
+        };
+
+        let serialized = serde_yaml::to_string(&config).unwrap();
+        let deserialized: DivoomScheduleConfigCronJob = serde_yaml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, config);
+    }
+
+    #[test]
+    fn schedule_config_should_report_invalid_structured_operations() {
+        let result = serde_yaml::from_str::<DivoomScheduleConfigCronJob>(
+            r#"
+cron: "0 0 * * * *"
+operations:
+  - channel:
+      set-clock:
+        clock: 100
+"#,
+        );
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("clock-id"), "{}", error);
+    }
 }
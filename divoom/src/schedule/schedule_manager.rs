@@ -1,6 +1,6 @@
-use crate::dsl::{DivoomDslOperation, DivoomDslParser, DivoomDslRunner};
+use crate::dsl::{DivoomDslOperation, DivoomDslParser, DivoomDslRunner, DivoomDslScript};
 use crate::schedule::schedule_config::*;
-use crate::{DivoomAPIResult, PixooClient};
+use crate::{DivoomAPIResult, DivoomDeviceModel, PixooClient};
 use log::error;
 use std::future::Future;
 use std::pin::Pin;
@@ -13,10 +13,12 @@ use crate::DivoomAnimationTemplateManager;
 pub struct DivoomScheduledJob {
     cron: String,
     operations: Vec<DivoomDslOperation>,
+    script: Option<DivoomDslScript>,
 }
 
 pub struct DivoomScheduleManager {
     device_address: String,
+    device_model: DivoomDeviceModel,
     jobs: Vec<Arc<DivoomScheduledJob>>,
     job_scheduler: JobScheduler,
 
@@ -39,26 +41,39 @@ impl DivoomScheduleManager {
                 .iter()
                 .map(|x| DivoomDslParser::parse(x))
                 .collect();
+            let parsed_script = match &schedule.script {
+                None => None,
+                Some(script) => Some(DivoomDslScript::parse(script)?),
+            };
             jobs.push(Arc::new(DivoomScheduledJob {
                 cron: schedule.cron,
                 operations: parsed_operations?,
+                script: parsed_script,
             }));
         }
 
         Ok(DivoomScheduleManager {
             device_address,
+            device_model: DivoomDeviceModel::default(),
             jobs,
             job_scheduler: JobScheduler::new().await.unwrap(),
             template_manager,
         })
     }
 
+    /// Set the device model, which is used to validate the scheduled commands before sending them to the device.
+    pub fn with_device_model(mut self, device_model: DivoomDeviceModel) -> Self {
+        self.device_model = device_model;
+        self
+    }
+
     #[cfg(feature = "animation-builder")]
     pub async fn start(&mut self) {
         for job in &self.jobs {
             let cron = job.cron.clone();
 
             let device_address_for_closure = self.device_address.clone();
+            let device_model = self.device_model;
             let job_for_closure = job.clone();
             let template_manager_for_closure = self.template_manager.clone();
 
@@ -75,7 +90,7 @@ impl DivoomScheduleManager {
                             );
                             return;
                         }
-                        Ok(v) => v,
+                        Ok(v) => v.with_device_model(device_model),
                     };
 
                     let mut dsl_runner = DivoomDslRunner::new(&pixoo, template_manager_for_async);
@@ -84,7 +99,11 @@ impl DivoomScheduleManager {
                         return;
                     }
 
-                    if let Err(e) = dsl_runner.execute().await {
+                    let result = match &job_for_async.script {
+                        None => dsl_runner.execute().await,
+                        Some(script) => dsl_runner.run_script(script).await,
+                    };
+                    if let Err(e) = result {
                         error!("Failing to execute all operations: Error = {:?}", e);
                     }
                 })
@@ -92,7 +111,8 @@ impl DivoomScheduleManager {
 
             self.job_scheduler
                 .add(Job::new_async(cron.as_ref(), job_closure).unwrap())
-                .await.unwrap();
+                .await
+                .unwrap();
         }
 
         self.job_scheduler.start().await.unwrap();
@@ -111,14 +131,20 @@ impl DivoomScheduleManager {
                 .iter()
                 .map(|x| DivoomDslParser::parse(x))
                 .collect();
+            let parsed_script = match &schedule.script {
+                None => None,
+                Some(script) => Some(DivoomDslScript::parse(script)?),
+            };
             jobs.push(Arc::new(DivoomScheduledJob {
                 cron: schedule.cron,
                 operations: parsed_operations?,
+                script: parsed_script,
             }));
         }
 
         Ok(DivoomScheduleManager {
             device_address,
+            device_model: DivoomDeviceModel::default(),
             jobs,
             job_scheduler: JobScheduler::new().unwrap(),
         })
@@ -130,6 +156,7 @@ impl DivoomScheduleManager {
             let cron = job.cron.clone();
 
             let device_address_for_closure = self.device_address.clone();
+            let device_model = self.device_model;
             let job_for_closure = job.clone();
 
             let job_closure = move |_, _| -> Pin<Box<dyn Future<Output = ()> + Send>> {
@@ -144,7 +171,7 @@ impl DivoomScheduleManager {
                             );
                             return;
                         }
-                        Ok(v) => v,
+                        Ok(v) => v.with_device_model(device_model),
                     };
 
                     let mut dsl_runner = DivoomDslRunner::new(&pixoo);
@@ -153,7 +180,11 @@ impl DivoomScheduleManager {
                         return;
                     }
 
-                    if let Err(e) = dsl_runner.execute().await {
+                    let result = match &job_for_async.script {
+                        None => dsl_runner.execute().await,
+                        Some(script) => dsl_runner.run_script(script).await,
+                    };
+                    if let Err(e) = result {
                         error!("Failing to execute all operations: Error = {:?}", e);
                     }
                 })
@@ -2,7 +2,9 @@ use crate::DivoomImageAnimation;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fmt::Debug;
-use std::{env, fs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, process};
 
 #[allow(dead_code)]
 pub fn assert_object_equal_with_baseline<T: Serialize + DeserializeOwned + PartialEq + Debug>(
@@ -53,3 +55,32 @@ pub fn assert_animation_equal_with_baseline(
     let expected_gif = fs::read(reference_file_path).unwrap();
     assert_eq!(actual_gif, expected_gif);
 }
+
+/// Mock the Pixoo command API for the requests whose body matches the pattern. Since mockito uses a global server,
+/// please make the pattern unique across the tests.
+#[allow(dead_code)]
+pub fn mock_pixoo_command(
+    body_pattern: &str,
+    response: &str,
+    expected_hits: usize,
+) -> mockito::Mock {
+    mockito::mock("POST", "/post")
+        .match_body(mockito::Matcher::Regex(body_pattern.to_string()))
+        .with_status(200)
+        .with_header("Content-Type", "application/json; charset=UTF-8")
+        .with_body(response)
+        .expect(expected_hits)
+        .create()
+}
+
+/// Create an empty folder under the temp folder for the test. The process id and a counter are added to the name, so
+/// the tests running in parallel, or in other test processes, never share the same folder.
+#[allow(dead_code)]
+pub fn create_test_temp_dir(name: &str) -> PathBuf {
+    static NEXT_TEST_TEMP_DIR_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_TEST_TEMP_DIR_ID.fetch_add(1, Ordering::SeqCst);
+    let dir = env::temp_dir().join(format!("{}_{}_{}", name, process::id(), id));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -4,6 +4,10 @@ use crate::opt::*;
 use clap::Parser;
 use divoom::*;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::Arc;
 use std::time::Duration;
 use tiny_skia::BlendMode;
 
@@ -40,6 +44,8 @@ async fn main() -> DivoomAPIResult<()> {
             handle_batch_api(&opts.common, batch_command).await
         }
 
+        DivoomCliSubCommand::Run(run_opts) => run_script(&opts.common, run_opts).await,
+
         DivoomCliSubCommand::Raw { request } => {
             let pixoo = new_pixoo_client(&opts.common)?;
             let response = pixoo.send_raw_request(request).await?;
@@ -190,6 +196,18 @@ async fn handle_animation_api(
             handle_gif_animation_api(common, gif_animation_command).await
         }
 
+        DivoomCliAnimationCommand::Image(DivoomCliImageAnimationCommand::Preview(preview)) => {
+            preview_image_animation(preview)
+        }
+
+        DivoomCliAnimationCommand::Image(DivoomCliImageAnimationCommand::Templates {
+            template_dir,
+        }) => {
+            let template_manager = DivoomAnimationTemplateManager::from_dir(&template_dir)?;
+            serialize_to_console(template_manager.templates(), common.output);
+            Ok(())
+        }
+
         DivoomCliAnimationCommand::Image(image_animation_command) => {
             handle_image_animation_api(common, image_animation_command).await
         }
@@ -253,22 +271,99 @@ async fn handle_image_animation_api(
             fit,
             rotation,
             opacity,
+            keep_timing,
         } => {
-            pixoo
-                .render_gif_as_animation_with_options(
-                    size,
-                    Duration::from_millis(speed_in_ms),
-                    &file_path,
-                    fit,
-                    rotation,
-                    opacity,
-                    BlendMode::default(),
-                )
-                .await
+            if keep_timing {
+                pixoo
+                    .render_gif_as_animation_with_original_timing(
+                        size,
+                        Duration::from_millis(speed_in_ms),
+                        &file_path,
+                        fit,
+                        rotation,
+                        opacity,
+                        BlendMode::default(),
+                    )
+                    .await
+            } else {
+                pixoo
+                    .render_gif_as_animation_with_options(
+                        size,
+                        Duration::from_millis(speed_in_ms),
+                        &file_path,
+                        fit,
+                        rotation,
+                        opacity,
+                        BlendMode::default(),
+                    )
+                    .await
+            }
+        }
+
+        DivoomCliImageAnimationCommand::Preview(_)
+        | DivoomCliImageAnimationCommand::Templates { .. } => {
+            unreachable!(
+                "Preview and templates don't need a device, so they are handled before creating the client."
+            )
         }
     }
 }
 
+fn preview_image_animation(preview: DivoomCliPreviewImageAnimationOpts) -> DivoomAPIResult<()> {
+    let animation = if let Some(gif_file) = preview.gif {
+        let animation_builder =
+            DivoomAnimationBuilder::new(preview.size, Duration::from_millis(preview.speed_in_ms))?;
+        if preview.keep_timing {
+            let gif = DivoomAnimationResourceLoader::from_gif_sequence_file(&gif_file)?;
+            animation_builder
+                .with_max_frame_count(DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT)
+                .draw_frame_sequence_fit(
+                    &gif,
+                    0,
+                    DivoomDrawFitMode::Center,
+                    0.0,
+                    1.0,
+                    BlendMode::default(),
+                )
+                .build()
+        } else {
+            let gif = DivoomAnimationResourceLoader::from_gif_file(&gif_file)?;
+            animation_builder
+                .draw_frames_fit(
+                    &gif,
+                    0,
+                    DivoomDrawFitMode::Center,
+                    0.0,
+                    1.0,
+                    BlendMode::default(),
+                )
+                .build()
+        }
+    } else if let Some(template_name) = preview.template {
+        let parameters: HashMap<String, String> = serde_json::from_str(&preview.parameters)?;
+        let per_frame_parameters: HashMap<usize, HashMap<String, String>> =
+            serde_json::from_str(&preview.per_frame_parameters)?;
+        let template_manager = DivoomAnimationTemplateManager::from_dir(&preview.template_dir)?;
+        template_manager.render_template(&template_name, &parameters, &per_frame_parameters)?
+    } else if let Some(request_file) = preview.request {
+        let request_body = fs::read_to_string(request_file)?;
+        DivoomImageAnimation::from_pixoo_request(&request_body)?
+    } else {
+        return Err(DivoomAPIError::ParameterError(
+            "The source of preview is not set! Please specify gif, template or request.".into(),
+        ));
+    };
+
+    let mut preview_options = DivoomImageAnimationPreviewOptions::new(preview.scale);
+    preview_options.led_grid = preview.led_grid;
+    animation
+        .to_preview(&preview_options)
+        .save_file(&preview.output_path, preview.format)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    Ok(())
+}
+
 async fn handle_text_animation_api(
     common: &DivoomCliDeviceCommandCommonOpts,
     text_animation_command: DivoomCliTextAnimationCommand,
@@ -297,11 +392,56 @@ async fn handle_batch_api(
     }
 }
 
+async fn run_script(
+    common: &DivoomCliDeviceCommandCommonOpts,
+    run_opts: DivoomCliRunScriptOpts,
+) -> DivoomAPIResult<()> {
+    let script = DivoomDslScript::from_file(&run_opts.script_file)?;
+    let variables: HashMap<String, String> = serde_json::from_str(&run_opts.variables)?;
+    let template_manager = Arc::new(match &run_opts.template_dir {
+        Some(template_dir) => DivoomAnimationTemplateManager::from_dir(template_dir)?,
+        None => DivoomAnimationTemplateManager::new(".")?,
+    });
+
+    // Dry run never talks to the device, so the device address is optional.
+    let pixoo = if run_opts.dry_run && common.device_address.is_none() {
+        PixooClient::with_options("127.0.0.1", common.timeout.map(Duration::from_millis))?
+            .with_device_model(common.device_model)
+    } else {
+        new_pixoo_client(common)?
+    };
+    let mut dsl_runner = DivoomDslRunner::new(&pixoo, template_manager);
+    if run_opts.dry_run {
+        dsl_runner = dsl_runner.with_dry_run();
+    }
+    if run_opts.single_command_fallback {
+        dsl_runner = dsl_runner.with_single_command_fallback();
+    }
+
+    dsl_runner
+        .run_script_with_variables(&script, variables)
+        .await?;
+
+    if run_opts.dry_run {
+        let payloads: Vec<serde_json::Value> = dsl_runner
+            .dry_run_payloads()
+            .iter()
+            .map(|x| serde_json::from_str(x))
+            .collect::<Result<_, _>>()?;
+        serialize_to_console(payloads, common.output);
+    } else if !dsl_runner.read_results().is_empty() {
+        serialize_to_console(dsl_runner.read_results(), common.output);
+    }
+
+    Ok(())
+}
+
 fn new_pixoo_client(common: &DivoomCliDeviceCommandCommonOpts) -> DivoomAPIResult<PixooClient> {
-    PixooClient::with_options(
+    Ok(PixooClient::with_options(
         common.device_address.as_ref().unwrap_or(&"".to_string()),
         common.timeout.map(Duration::from_millis),
-    )
+    )?
+    .with_device_model(common.device_model))
 }
 
 fn serialize_to_console<Data: Serialize>(v: Data, format: DivoomCliOutputFormat) {
@@ -28,6 +28,13 @@ pub struct DivoomCliDeviceCommandCommonOpts {
 
     #[clap(short, long, help = "Timeout in milliseconds.")]
     pub timeout: Option<u64>,
+
+    #[clap(
+        long,
+        default_value = "pixoo-64",
+        help = "Device model, which is used to validate the commands before sending them. Can be pixoo-16, pixoo-64, timebox-evo and ditoo."
+    )]
+    pub device_model: DivoomDeviceModel,
 }
 
 #[derive(Subcommand, Debug, Copy, Clone)]
@@ -69,6 +76,11 @@ pub enum DivoomCliSubCommand {
     #[clap(subcommand, about = "Batch related APIs")]
     Batch(DivoomCliBatchCommand),
 
+    #[clap(
+        about = "Run DSL script file, which supports variables, conditionals, loops, sleeps and includes"
+    )]
+    Run(DivoomCliRunScriptOpts),
+
     #[clap(about = "Sending raw request")]
     Raw {
         #[clap(
@@ -349,7 +361,111 @@ pub enum DivoomCliImageAnimationCommand {
             help = "Animation opacity"
         )]
         opacity: f32,
+
+        #[clap(
+            long = "keep-timing",
+            help = "Resample the GIF frames onto the speed to keep the original timing of GIFs with variable delays. Frames exceeding the max frame count of the device are dropped"
+        )]
+        keep_timing: bool,
+    },
+
+    #[clap(about = "List animation templates and the parameters they accept")]
+    Templates {
+        #[clap(
+            long,
+            default_value = ".",
+            help = "Folder to load the templates and their resources"
+        )]
+        template_dir: String,
     },
+
+    #[clap(
+        about = "Render template, gif or captured request into a file for preview, without sending it to the device. Output can be gif, apng, png-frames or sprite-sheet."
+    )]
+    Preview(DivoomCliPreviewImageAnimationOpts),
+}
+
+#[derive(Args, Debug)]
+#[clap(rename_all = "kebab-case")]
+pub struct DivoomCliPreviewImageAnimationOpts {
+    #[clap(help = "Output file path. When format is png-frames, it is used as the folder path.")]
+    pub output_path: String,
+
+    #[clap(long, help = "Gif file to render")]
+    pub gif: Option<String>,
+
+    #[clap(
+        long,
+        help = "Template name to render. Use \"animation image templates\" command to list the templates and their parameters"
+    )]
+    pub template: Option<String>,
+
+    #[clap(
+        long,
+        help = "Captured request body file to render, which can be a Draw/SendHttpGif command or a Draw/CommandList batch"
+    )]
+    pub request: Option<String>,
+
+    #[clap(
+        long,
+        default_value = ".",
+        help = "Folder to load the templates and their resources"
+    )]
+    pub template_dir: String,
+
+    #[clap(
+        short,
+        long,
+        default_value = "{}",
+        help = "Template parameters. We use a string to string json dictionary as the format. E.g. {\"foo\":\"bar\"}"
+    )]
+    pub parameters: String,
+
+    #[clap(
+        long,
+        default_value = "{}",
+        help = "Per frame template parameters. We use a 0-indexed int to string to string json dictionary as the format. E.g. {0:{\"foo\":\"bar\"},1:{\"foo\":\"bar2\"}}"
+    )]
+    pub per_frame_parameters: String,
+
+    #[clap(
+        long,
+        default_value = "64",
+        help = "Animation size in pixels when rendering gif. Only 16 and 32 and 64 are allowed."
+    )]
+    pub size: u32,
+
+    #[clap(
+        long = "speed",
+        default_value = "100",
+        help = "Animation play speed in milliseconds when rendering gif"
+    )]
+    pub speed_in_ms: u64,
+
+    #[clap(
+        long,
+        requires = "gif",
+        help = "Resample the GIF frames onto the speed to keep the original timing of GIFs with variable delays"
+    )]
+    pub keep_timing: bool,
+
+    #[clap(
+        short,
+        long,
+        default_value = "gif",
+        help = "Output format. Can be gif, apng, png-frames and sprite-sheet"
+    )]
+    pub format: DivoomImageAnimationFileFormat,
+
+    #[clap(
+        long,
+        default_value = "8",
+        help = "Number of pixels in the preview for each pixel on the device. Use 1 to keep the device resolution."
+    )]
+    pub scale: u32,
+
+    #[clap(long, help = "Simulate the LED dots on the device")]
+    pub led_grid: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -447,3 +563,36 @@ pub enum DivoomCliBatchCommand {
         command_url: String,
     },
 }
+
+#[derive(Args, Debug)]
+#[clap(rename_all = "kebab-case")]
+pub struct DivoomCliRunScriptOpts {
+    #[clap(help = "Script file path")]
+    pub script_file: String,
+
+    #[clap(
+        short,
+        long,
+        default_value = "{}",
+        help = "Variables to set before running the script, which can also be used to provide device state in dry run mode. We use a string to string json dictionary as the format. E.g. {\"channel\":\"clock\"}"
+    )]
+    pub variables: String,
+
+    #[clap(
+        long,
+        help = "Folder to load the templates used by \"animation image render-template\" commands"
+    )]
+    pub template_dir: Option<String>,
+
+    #[clap(
+        long,
+        help = "Print the JSON payloads of each batch instead of sending them to the device"
+    )]
+    pub dry_run: bool,
+
+    #[clap(
+        long,
+        help = "When a batch is rejected by the device, send its commands one by one to find the failed operation. The commands before the failed one are sent again"
+    )]
+    pub single_command_fallback: bool,
+}
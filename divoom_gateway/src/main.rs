@@ -1,5 +1,7 @@
+mod pixel_stream;
 mod server;
 
+use crate::pixel_stream::*;
 use crate::server::*;
 use clap::Parser;
 use divoom::*;
@@ -15,6 +17,14 @@ struct CliOptions {
     #[clap(help = "Device address.", value_parser)]
     device_address: String,
 
+    #[clap(
+        short = 'm',
+        long = "device-model",
+        help = "Device model, which is used to validate the commands before sending them. Can be pixoo-16, pixoo-64, timebox-evo and ditoo.",
+        value_parser
+    )]
+    device_model: Option<DivoomDeviceModel>,
+
     #[clap(short = 's', long = "server", help = "Server address.", value_parser)]
     server_address: Option<String>,
 
@@ -44,6 +54,9 @@ pub struct DivoomGatewayConfig {
     #[serde(default)]
     pub device_address: String,
 
+    #[serde(default)]
+    pub device_model: DivoomDeviceModel,
+
     #[serde(default)]
     pub server_address: String,
 
@@ -55,10 +68,16 @@ pub struct DivoomGatewayConfig {
 
     #[serde(default)]
     pub animation_template_dir: String,
+
+    #[serde(default)]
+    pub animation_template_cache: Option<DivoomAnimationTemplateCacheOptions>,
+
+    #[serde(default)]
+    pub pixel_streams: Vec<DivoomGatewayPixelStreamConfig>,
 }
 
 impl DivoomGatewayConfig {
-    pub fn fill_default(&mut self) {
+    pub fn fill_default(&mut self) -> std::io::Result<()> {
         if self.server_address.is_empty() {
             self.server_address = "127.0.0.1".to_string();
         }
@@ -70,6 +89,12 @@ impl DivoomGatewayConfig {
         if self.animation_template_dir.is_empty() {
             self.animation_template_dir = "./animation-templates".to_string();
         }
+
+        for pixel_stream in &mut self.pixel_streams {
+            pixel_stream.fill_default()?;
+        }
+
+        Ok(())
     }
 }
 
@@ -88,7 +113,9 @@ async fn main() -> Result<(), std::io::Error> {
             config.schedules,
             animation_template_manager.clone(),
         )
-        .await.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?
+        .with_device_model(config.device_model);
 
         println!(
             "Found {} schedules in gateway config, starting divoom scheduler device {}.",
@@ -98,6 +125,19 @@ async fn main() -> Result<(), std::io::Error> {
         schedule_manager.start().await;
     }
 
+    for pixel_stream_config in config.pixel_streams {
+        let pixel_stream_server = PixelStreamServer::new(
+            pixel_stream_config,
+            config.device_address.clone(),
+            config.device_model,
+        );
+        let pixel_stream_address = pixel_stream_server.start().await?;
+        println!(
+            "Pixel stream listener started on: {} for device {}.",
+            pixel_stream_address, config.device_address
+        );
+    }
+
     let url = format!("http://{}:{}", config.server_address, config.server_port);
     println!(
         "Starting divoom gateway on: {} for device {}.",
@@ -112,6 +152,7 @@ async fn main() -> Result<(), std::io::Error> {
         config.server_address,
         config.server_port,
         config.device_address,
+        config.device_model,
         animation_template_manager,
     );
     api_server.start().await
@@ -124,6 +165,10 @@ fn load_gateway_config() -> std::io::Result<DivoomGatewayConfig> {
 
     config.device_address = args.device_address;
 
+    if let Some(device_model) = args.device_model {
+        config.device_model = device_model;
+    }
+
     if let Some(server_address) = args.server_address {
         config.server_address = server_address;
     }
@@ -136,7 +181,7 @@ fn load_gateway_config() -> std::io::Result<DivoomGatewayConfig> {
         config.animation_template_dir = animation_template_dir;
     }
 
-    config.fill_default();
+    config.fill_default()?;
 
     Ok(config)
 }
@@ -147,10 +192,13 @@ fn load_gateway_config_from_file(
     let config = match config_file_path {
         None => DivoomGatewayConfig {
             device_address: "".to_string(),
+            device_model: DivoomDeviceModel::default(),
             server_address: "".to_string(),
             server_port: 0,
             schedules: vec![],
             animation_template_dir: "".to_string(),
+            animation_template_cache: None,
+            pixel_streams: vec![],
         },
 
         Some(path) => {
@@ -167,20 +215,27 @@ fn load_gateway_config_from_file(
 fn create_animation_template_manager(
     config: &DivoomGatewayConfig,
 ) -> std::io::Result<Arc<DivoomAnimationTemplateManager>> {
-    if Path::new(&config.animation_template_dir).is_dir() {
-        Ok(Arc::new(
-            DivoomAnimationTemplateManager::from_dir(&config.animation_template_dir).map_err(
-                |e| {
-                    std::io::Error::new(
-                        ErrorKind::NotFound,
-                        format!("Failed to load templates: Error = {:?}", e),
-                    )
-                },
-            )?,
-        ))
+    let mut animation_template_manager = if Path::new(&config.animation_template_dir).is_dir() {
+        DivoomAnimationTemplateManager::from_dir(&config.animation_template_dir).map_err(|e| {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("Failed to load templates: Error = {:?}", e),
+            )
+        })?
     } else {
-        Ok(Arc::new(
-            DivoomAnimationTemplateManager::new(&config.animation_template_dir).unwrap(),
-        ))
+        DivoomAnimationTemplateManager::new(&config.animation_template_dir).unwrap()
+    };
+
+    if let Some(cache_options) = &config.animation_template_cache {
+        animation_template_manager
+            .enable_cache(cache_options.clone())
+            .map_err(|e| {
+                std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Failed to enable animation template cache: Error = {:?}", e),
+                )
+            })?;
     }
+
+    Ok(Arc::new(animation_template_manager))
 }
@@ -0,0 +1,6 @@
+mod pixel_stream_config;
+mod pixel_stream_decoder;
+mod pixel_stream_server;
+
+pub use pixel_stream_config::*;
+pub use pixel_stream_server::*;
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+
+/// Protocols that pixel stream listeners can accept.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivoomGatewayPixelStreamProtocol {
+    /// TPM2.net over UDP, or TPM2 serial framing over TCP.
+    Tpm2Net,
+
+    /// Raw RGB bytes. Every UDP datagram, or every `canvas-size * canvas-size * 3` bytes on TCP, is one frame.
+    RawRgb,
+}
+
+/// Transport that pixel stream listeners are listening on.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivoomGatewayPixelStreamTransport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, PartialOrd, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DivoomGatewayPixelStreamConfig {
+    pub protocol: DivoomGatewayPixelStreamProtocol,
+
+    #[serde(default)]
+    pub transport: DivoomGatewayPixelStreamTransport,
+
+    #[serde(default)]
+    pub address: String,
+
+    #[serde(default)]
+    pub port: u16,
+
+    #[serde(default)]
+    pub canvas_size: u32,
+
+    #[serde(default)]
+    pub min_interval_in_ms: u64,
+}
+
+impl DivoomGatewayPixelStreamConfig {
+    pub fn fill_default(&mut self) -> std::io::Result<()> {
+        if self.address.is_empty() {
+            self.address = "0.0.0.0".to_string();
+        }
+
+        if self.port == 0 {
+            self.port = match self.protocol {
+                DivoomGatewayPixelStreamProtocol::Tpm2Net => 65506,
+                DivoomGatewayPixelStreamProtocol::RawRgb => 20822,
+            };
+        }
+
+        if self.canvas_size == 0 {
+            self.canvas_size = 64;
+        }
+
+        if ![16, 32, 64].contains(&self.canvas_size) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Invalid pixel stream canvas size: {}. Only 16, 32 and 64 are allowed.",
+                    self.canvas_size
+                ),
+            ));
+        }
+
+        // Pixoo devices cannot take frames too fast, otherwise they will stop responding. So we throttle it by default.
+        if self.min_interval_in_ms == 0 {
+            self.min_interval_in_ms = 100;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_test_config(canvas_size: u32) -> DivoomGatewayPixelStreamConfig {
+        DivoomGatewayPixelStreamConfig {
+            protocol: DivoomGatewayPixelStreamProtocol::Tpm2Net,
+            transport: DivoomGatewayPixelStreamTransport::Udp,
+            address: "".to_string(),
+            port: 0,
+            canvas_size,
+            min_interval_in_ms: 0,
+        }
+    }
+
+    #[test]
+    fn pixel_stream_config_should_fill_default_canvas_size() {
+        let mut config = new_test_config(0);
+        config.fill_default().unwrap();
+        assert_eq!(config.canvas_size, 64);
+    }
+
+    #[test]
+    fn pixel_stream_config_should_reject_invalid_canvas_size() {
+        for canvas_size in [16, 32, 64] {
+            assert!(new_test_config(canvas_size).fill_default().is_ok());
+        }
+
+        let error = new_test_config(48).fill_default().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
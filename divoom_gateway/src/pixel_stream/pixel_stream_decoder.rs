@@ -0,0 +1,252 @@
+use std::io::{Error, ErrorKind};
+
+const TPM2_NET_BLOCK_START: u8 = 0x9C;
+const TPM2_SERIAL_BLOCK_START: u8 = 0xC9;
+const TPM2_PACKET_TYPE_DATA: u8 = 0xDA;
+const TPM2_BLOCK_END: u8 = 0x36;
+
+/// TPM2.net frame assembler.
+///
+/// A TPM2.net frame can be split into multiple UDP packets, and each packet carries its packet number (1-based) and the total packet count.
+/// We keep the packets until all of them are received, then concat them into a single frame. UDP packets can arrive out of order, so a
+/// new frame is only started when the packet count changes or a packet that is already received arrives again.
+pub(crate) struct DivoomGatewayTpm2NetFrameAssembler {
+    packets: Vec<Option<Vec<u8>>>,
+}
+
+impl DivoomGatewayTpm2NetFrameAssembler {
+    pub fn new() -> Self {
+        DivoomGatewayTpm2NetFrameAssembler {
+            packets: Vec::new(),
+        }
+    }
+
+    /// Feed a UDP packet to the assembler, and return the frame data once all packets of the frame are received.
+    pub fn on_packet(&mut self, packet: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        if packet.len() < 7 || packet[0] != TPM2_NET_BLOCK_START {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid TPM2.net packet header.",
+            ));
+        }
+
+        // Command and response packets are not supported, but they should not break the stream.
+        if packet[1] != TPM2_PACKET_TYPE_DATA {
+            return Ok(None);
+        }
+
+        let payload_size = ((packet[2] as usize) << 8) | packet[3] as usize;
+        if packet.len() < payload_size + 7 || packet[payload_size + 6] != TPM2_BLOCK_END {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid TPM2.net packet size.",
+            ));
+        }
+
+        // Some senders use 0 for both packet number and total count when the frame is not split.
+        let total_packet_count = std::cmp::max(packet[5] as usize, 1);
+        let packet_index = std::cmp::max(packet[4] as usize, 1) - 1;
+        if packet_index >= total_packet_count {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Invalid TPM2.net packet number.",
+            ));
+        }
+
+        // A different packet count or a duplicated packet means a new frame is started, so we drop whatever is left from the
+        // previous one.
+        if self.packets.len() != total_packet_count || self.packets[packet_index].is_some() {
+            self.packets = vec![None; total_packet_count];
+        }
+
+        self.packets[packet_index] = Some(packet[6..payload_size + 6].to_vec());
+        if self.packets.iter().any(|x| x.is_none()) {
+            return Ok(None);
+        }
+
+        let frame = self.packets.drain(..).flatten().flatten().collect();
+        Ok(Some(frame))
+    }
+}
+
+/// TPM2 serial frame decoder, which is used for TPM2 over TCP.
+///
+/// TPM2 serial frames are not split into multiple packets, but they could be split into multiple TCP reads,
+/// so we buffer the data until a complete frame is received.
+pub(crate) struct DivoomGatewayTpm2StreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl DivoomGatewayTpm2StreamDecoder {
+    pub fn new() -> Self {
+        DivoomGatewayTpm2StreamDecoder { buffer: Vec::new() }
+    }
+
+    /// Feed the data from the stream, and return all completed frames.
+    pub fn on_data(&mut self, data: &[u8]) -> std::io::Result<Vec<Vec<u8>>> {
+        self.buffer.extend_from_slice(data);
+
+        let mut frames = Vec::new();
+        loop {
+            if self.buffer.len() < 4 {
+                break;
+            }
+
+            if self.buffer[0] != TPM2_SERIAL_BLOCK_START {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Invalid TPM2 frame header.",
+                ));
+            }
+
+            let payload_size = ((self.buffer[2] as usize) << 8) | self.buffer[3] as usize;
+            if self.buffer.len() < payload_size + 5 {
+                break;
+            }
+
+            if self.buffer[payload_size + 4] != TPM2_BLOCK_END {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Invalid TPM2 frame size.",
+                ));
+            }
+
+            let block: Vec<u8> = self.buffer.drain(..payload_size + 5).collect();
+            if block[1] == TPM2_PACKET_TYPE_DATA {
+                frames.push(block[4..payload_size + 4].to_vec());
+            }
+        }
+
+        Ok(frames)
+    }
+}
+
+/// Convert the received RGB data into the frame data of the animation.
+///
+/// The senders don't always know the exact size of the device, hence we truncate the data when it is too long and fill the
+/// rest with black when it is too short.
+pub(crate) fn normalize_rgb_frame_data(canvas_size: u32, mut data: Vec<u8>) -> Vec<u8> {
+    data.resize((canvas_size * canvas_size * 3) as usize, 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tpm2_net_packet(packet_number: u8, total_packet_count: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![
+            TPM2_NET_BLOCK_START,
+            TPM2_PACKET_TYPE_DATA,
+            (payload.len() >> 8) as u8,
+            payload.len() as u8,
+            packet_number,
+            total_packet_count,
+        ];
+        packet.extend_from_slice(payload);
+        packet.push(TPM2_BLOCK_END);
+        packet
+    }
+
+    #[test]
+    fn tpm2_net_assembler_can_decode_single_packet_frame() {
+        let mut assembler = DivoomGatewayTpm2NetFrameAssembler::new();
+        let frame = assembler
+            .on_packet(&make_tpm2_net_packet(1, 1, &[1, 2, 3]))
+            .unwrap();
+        assert_eq!(frame, Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn tpm2_net_assembler_can_decode_multi_packet_frame() {
+        let mut assembler = DivoomGatewayTpm2NetFrameAssembler::new();
+        assert_eq!(
+            assembler
+                .on_packet(&make_tpm2_net_packet(1, 2, &[1, 2, 3]))
+                .unwrap(),
+            None
+        );
+
+        let frame = assembler
+            .on_packet(&make_tpm2_net_packet(2, 2, &[4, 5, 6]))
+            .unwrap();
+        assert_eq!(frame, Some(vec![1, 2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn tpm2_net_assembler_can_decode_out_of_order_packets() {
+        let mut assembler = DivoomGatewayTpm2NetFrameAssembler::new();
+        for packet in [
+            make_tpm2_net_packet(2, 3, &[4, 5, 6]),
+            make_tpm2_net_packet(1, 3, &[1, 2, 3]),
+        ] {
+            assert_eq!(assembler.on_packet(&packet).unwrap(), None);
+        }
+
+        let frame = assembler
+            .on_packet(&make_tpm2_net_packet(3, 3, &[7, 8, 9]))
+            .unwrap();
+        assert_eq!(frame, Some(vec![1, 2, 3, 4, 5, 6, 7, 8, 9]));
+    }
+
+    #[test]
+    fn tpm2_net_assembler_should_start_new_frame_on_duplicated_packet() {
+        let mut assembler = DivoomGatewayTpm2NetFrameAssembler::new();
+        for packet in [
+            make_tpm2_net_packet(1, 2, &[1, 2, 3]),
+            make_tpm2_net_packet(1, 2, &[7, 8, 9]),
+        ] {
+            assert_eq!(assembler.on_packet(&packet).unwrap(), None);
+        }
+
+        let frame = assembler
+            .on_packet(&make_tpm2_net_packet(2, 2, &[4, 5, 6]))
+            .unwrap();
+        assert_eq!(frame, Some(vec![7, 8, 9, 4, 5, 6]));
+    }
+
+    #[test]
+    fn tpm2_net_assembler_should_reject_invalid_packet() {
+        let mut assembler = DivoomGatewayTpm2NetFrameAssembler::new();
+        let mut packet = make_tpm2_net_packet(1, 1, &[1, 2, 3]);
+        packet.pop();
+        assert!(assembler.on_packet(&packet).is_err());
+    }
+
+    #[test]
+    fn tpm2_stream_decoder_can_decode_frames_across_reads() {
+        let mut decoder = DivoomGatewayTpm2StreamDecoder::new();
+        let stream = [
+            TPM2_SERIAL_BLOCK_START,
+            TPM2_PACKET_TYPE_DATA,
+            0,
+            3,
+            1,
+            2,
+            3,
+            TPM2_BLOCK_END,
+            TPM2_SERIAL_BLOCK_START,
+            TPM2_PACKET_TYPE_DATA,
+            0,
+            1,
+            4,
+            TPM2_BLOCK_END,
+        ];
+
+        assert!(decoder.on_data(&stream[..5]).unwrap().is_empty());
+        let frames = decoder.on_data(&stream[5..]).unwrap();
+        assert_eq!(frames, vec![vec![1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn rgb_frame_data_should_be_normalized_to_canvas_size() {
+        assert_eq!(
+            normalize_rgb_frame_data(16, vec![1, 2, 3]).len(),
+            16 * 16 * 3
+        );
+        assert_eq!(
+            normalize_rgb_frame_data(16, vec![1; 2000]).len(),
+            16 * 16 * 3
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use super::pixel_stream_config::*;
+use super::pixel_stream_decoder::*;
+use divoom::*;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::watch;
+
+/// Pixel stream server listens on the LED matrix protocols and forwards every frame it received to the device as a single frame animation.
+///
+/// Devices cannot take frames as fast as LED walls, so only the latest frame is kept, and frames that are received within the minimum
+/// interval are dropped.
+pub struct PixelStreamServer {
+    config: DivoomGatewayPixelStreamConfig,
+    device_address: String,
+    device_model: DivoomDeviceModel,
+}
+
+impl PixelStreamServer {
+    pub fn new(
+        config: DivoomGatewayPixelStreamConfig,
+        device_address: String,
+        device_model: DivoomDeviceModel,
+    ) -> PixelStreamServer {
+        PixelStreamServer {
+            config,
+            device_address,
+            device_model,
+        }
+    }
+
+    /// Start listening and forwarding frames in background, and return the address that we are listening on.
+    pub async fn start(&self) -> std::io::Result<SocketAddr> {
+        let server_endpoint = format!("{}:{}", self.config.address, self.config.port);
+        let (frame_sender, frame_receiver) = watch::channel::<Option<Vec<u8>>>(None);
+
+        let local_address = match self.config.transport {
+            DivoomGatewayPixelStreamTransport::Udp => {
+                let socket = UdpSocket::bind(server_endpoint).await?;
+                let local_address = socket.local_addr()?;
+                tokio::spawn(run_udp_listener(
+                    socket,
+                    self.config.protocol,
+                    self.config.canvas_size,
+                    frame_sender,
+                ));
+                local_address
+            }
+
+            DivoomGatewayPixelStreamTransport::Tcp => {
+                let listener = TcpListener::bind(server_endpoint).await?;
+                let local_address = listener.local_addr()?;
+                tokio::spawn(run_tcp_listener(
+                    listener,
+                    self.config.protocol,
+                    self.config.canvas_size,
+                    frame_sender,
+                ));
+                local_address
+            }
+        };
+
+        tokio::spawn(run_frame_forwarder(
+            self.device_address.clone(),
+            self.device_model,
+            self.config.canvas_size,
+            Duration::from_millis(self.config.min_interval_in_ms),
+            frame_receiver,
+        ));
+
+        info!(
+            "Pixel stream server started: Protocol = {:?}, Transport = {:?}, Address = {}",
+            self.config.protocol, self.config.transport, local_address
+        );
+
+        Ok(local_address)
+    }
+}
+
+async fn run_udp_listener(
+    socket: UdpSocket,
+    protocol: DivoomGatewayPixelStreamProtocol,
+    canvas_size: u32,
+    frame_sender: watch::Sender<Option<Vec<u8>>>,
+) {
+    let mut tpm2_net_assembler = DivoomGatewayTpm2NetFrameAssembler::new();
+    let mut buffer = vec![0u8; 65536];
+
+    loop {
+        let (size, peer) = match socket.recv_from(&mut buffer).await {
+            Err(e) => {
+                warn!("Failed to receive pixel stream packet: Error = {:?}", e);
+                continue;
+            }
+            Ok(v) => v,
+        };
+
+        let frame = match protocol {
+            DivoomGatewayPixelStreamProtocol::Tpm2Net => {
+                match tpm2_net_assembler.on_packet(&buffer[..size]) {
+                    Err(e) => {
+                        debug!(
+                            "Dropping invalid TPM2.net packet: Peer = {}, Error = {:?}",
+                            peer, e
+                        );
+                        continue;
+                    }
+                    Ok(v) => v,
+                }
+            }
+
+            DivoomGatewayPixelStreamProtocol::RawRgb => Some(buffer[..size].to_vec()),
+        };
+
+        if let Some(frame) = frame {
+            if frame_sender
+                .send(Some(normalize_rgb_frame_data(canvas_size, frame)))
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+async fn run_tcp_listener(
+    listener: TcpListener,
+    protocol: DivoomGatewayPixelStreamProtocol,
+    canvas_size: u32,
+    frame_sender: watch::Sender<Option<Vec<u8>>>,
+) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Err(e) => {
+                warn!("Failed to accept pixel stream connection: Error = {:?}", e);
+                continue;
+            }
+            Ok(v) => v,
+        };
+
+        debug!("Pixel stream connection accepted: Peer = {}", peer);
+        tokio::spawn(run_tcp_connection(
+            stream,
+            protocol,
+            canvas_size,
+            frame_sender.clone(),
+        ));
+    }
+}
+
+async fn run_tcp_connection(
+    mut stream: TcpStream,
+    protocol: DivoomGatewayPixelStreamProtocol,
+    canvas_size: u32,
+    frame_sender: watch::Sender<Option<Vec<u8>>>,
+) {
+    let mut tpm2_decoder = DivoomGatewayTpm2StreamDecoder::new();
+    let mut buffer = vec![0u8; (canvas_size * canvas_size * 3) as usize];
+
+    loop {
+        let frames = match protocol {
+            DivoomGatewayPixelStreamProtocol::Tpm2Net => {
+                let size = match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(v) => v,
+                };
+
+                match tpm2_decoder.on_data(&buffer[..size]) {
+                    Err(e) => {
+                        debug!(
+                            "Closing pixel stream connection on invalid data: Error = {:?}",
+                            e
+                        );
+                        return;
+                    }
+                    Ok(v) => v,
+                }
+            }
+
+            DivoomGatewayPixelStreamProtocol::RawRgb => {
+                if stream.read_exact(&mut buffer).await.is_err() {
+                    return;
+                }
+
+                vec![buffer.clone()]
+            }
+        };
+
+        for frame in frames {
+            if frame_sender
+                .send(Some(normalize_rgb_frame_data(canvas_size, frame)))
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+async fn run_frame_forwarder(
+    device_address: String,
+    device_model: DivoomDeviceModel,
+    canvas_size: u32,
+    min_interval: Duration,
+    mut frame_receiver: watch::Receiver<Option<Vec<u8>>>,
+) {
+    let pixoo = match PixooClient::new(&device_address) {
+        Err(e) => {
+            warn!(
+                "Failing to create device client: DeviceAddress = {}, Error = {:?}",
+                device_address, e
+            );
+            return;
+        }
+        Ok(v) => v.with_device_model(device_model),
+    };
+
+    while frame_receiver.changed().await.is_ok() {
+        let frame = match frame_receiver.borrow_and_update().clone() {
+            None => continue,
+            Some(v) => v,
+        };
+
+        let animation = DivoomImageAnimation {
+            size: canvas_size,
+            frame_count: 1,
+            speed_in_ms: min_interval.as_millis() as i32,
+            frames: BTreeMap::from([(0, frame)]),
+        };
+
+        if let Err(e) = pixoo.send_image_animation(animation).await {
+            warn!("Failing to forward pixel stream frame: Error = {:?}", e);
+        }
+
+        tokio::time::sleep(min_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    fn new_test_config(
+        protocol: DivoomGatewayPixelStreamProtocol,
+        transport: DivoomGatewayPixelStreamTransport,
+    ) -> DivoomGatewayPixelStreamConfig {
+        DivoomGatewayPixelStreamConfig {
+            protocol,
+            transport,
+            address: "127.0.0.1".to_string(),
+            port: 0,
+            canvas_size: 16,
+            min_interval_in_ms: 10,
+        }
+    }
+
+    fn mock_device() -> (mockito::Mock, mockito::Mock) {
+        let get_id_mock = mockito::mock("POST", "/post")
+            .match_body(mockito::Matcher::PartialJsonString(
+                "{\"Command\": \"Draw/GetHttpGifId\"}".into(),
+            ))
+            .with_status(200)
+            .with_body("{\"error_code\": 0, \"PicId\": 1}")
+            .create();
+
+        let send_gif_mock = mockito::mock("POST", "/post")
+            .match_body(mockito::Matcher::Regex("Draw/SendHttpGif".into()))
+            .with_status(200)
+            .with_body("{\"error_code\": 0}")
+            .create();
+
+        (get_id_mock, send_gif_mock)
+    }
+
+    async fn wait_for_mock(mock: &mockito::Mock) {
+        for _ in 0..100 {
+            if mock.matched() {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        mock.assert();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn pixel_stream_server_should_forward_tpm2_net_frames() {
+        let (_get_id_mock, send_gif_mock) = mock_device();
+
+        let server = PixelStreamServer::new(
+            new_test_config(
+                DivoomGatewayPixelStreamProtocol::Tpm2Net,
+                DivoomGatewayPixelStreamTransport::Udp,
+            ),
+            mockito::server_address().to_string(),
+            DivoomDeviceModel::Pixoo16,
+        );
+        let server_address = server.start().await.unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let packet = [0x9C, 0xDA, 0, 3, 1, 1, 255, 0, 0, 0x36];
+        socket.send_to(&packet, server_address).await.unwrap();
+
+        wait_for_mock(&send_gif_mock).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn pixel_stream_server_should_forward_raw_rgb_frames_over_tcp() {
+        let (_get_id_mock, send_gif_mock) = mock_device();
+
+        let server = PixelStreamServer::new(
+            new_test_config(
+                DivoomGatewayPixelStreamProtocol::RawRgb,
+                DivoomGatewayPixelStreamTransport::Tcp,
+            ),
+            mockito::server_address().to_string(),
+            DivoomDeviceModel::Pixoo16,
+        );
+        let server_address = server.start().await.unwrap();
+
+        let mut stream = TcpStream::connect(server_address).await.unwrap();
+        stream.write_all(&[128u8; 16 * 16 * 3]).await.unwrap();
+
+        wait_for_mock(&send_gif_mock).await;
+    }
+}
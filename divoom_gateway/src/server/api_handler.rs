@@ -2,13 +2,13 @@ use super::api_server_dto::*;
 use divoom::*;
 use poem_openapi::payload::Json;
 use poem_openapi::{OpenApi, Tags};
-use std::io::Cursor;
 use std::sync::Arc;
 use std::time::Duration;
 use tiny_skia::BlendMode;
 
 pub struct ApiHandler {
     device_address: String,
+    device_model: DivoomDeviceModel,
     animation_template_manager: Arc<DivoomAnimationTemplateManager>,
 }
 
@@ -73,14 +73,14 @@ macro_rules! parse_gateway_api_arg {
 
 macro_rules! invoke_pixoo_api_no_response {
     ($self:ident, $api_name:ident) => (
-        match PixooClient::new(&$self.device_address).unwrap().$api_name().await {
+        match $self.new_pixoo_client().$api_name().await {
             Err(e) => return e.into(),
             Ok(_) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok())),
         }
     );
 
     ($self:ident, $api_name:ident, $($api_arg:ident),*) => (
-        match PixooClient::new(&$self.device_address).unwrap().$api_name($($api_arg),*).await {
+        match $self.new_pixoo_client().$api_name($($api_arg),*).await {
             Err(e) => return e.into(),
             Ok(_) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok())),
         }
@@ -89,14 +89,14 @@ macro_rules! invoke_pixoo_api_no_response {
 
 macro_rules! invoke_pixoo_api_respond_string {
     ($self:ident, $api_name:ident) => (
-        match PixooClient::new(&$self.device_address).unwrap().$api_name().await {
+        match $self.new_pixoo_client().$api_name().await {
             Err(e) => return e.into(),
             Ok(result) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(result.to_string()))),
         }
     );
 
     ($self:ident, $api_name:ident, $($api_arg:ident),*) => (
-        match PixooClient::new(&$self.device_address).unwrap().$api_name($($api_arg),*).await {
+        match $self.new_pixoo_client().$api_name($($api_arg),*).await {
             Err(e) => return e.into(),
             Ok(result) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(result.to_string()))),
         }
@@ -105,28 +105,38 @@ macro_rules! invoke_pixoo_api_respond_string {
 
 macro_rules! invoke_pixoo_api_respond_object {
     ($self:ident, $api_name:ident) => (
-        match PixooClient::new(&$self.device_address).unwrap().$api_name().await {
+        match $self.new_pixoo_client().$api_name().await {
             Err(e) => return e.into(),
             Ok(result) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(result.into()))),
         }
     );
 
     ($self:ident, $api_name:ident, $($api_arg:ident),*) => (
-        match PixooClient::new(&$self.device_address).unwrap().$api_name($($api_arg),*).await {
+        match $self.new_pixoo_client().$api_name($($api_arg),*).await {
             Err(e) => return e.into(),
             Ok(result) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(result.into()))),
         }
     )
 }
 
+impl ApiHandler {
+    fn new_pixoo_client(&self) -> PixooClient {
+        PixooClient::new(&self.device_address)
+            .unwrap()
+            .with_device_model(self.device_model)
+    }
+}
+
 #[OpenApi]
 impl ApiHandler {
     pub fn new(
         device_address: String,
+        device_model: DivoomDeviceModel,
         animation_template_manager: Arc<DivoomAnimationTemplateManager>,
     ) -> ApiHandler {
         ApiHandler {
             device_address,
+            device_model,
             animation_template_manager,
         }
     }
@@ -184,7 +194,7 @@ impl ApiHandler {
         &self,
         request: Json<DivoomGatewaySelectCustomPageRequest>,
     ) -> DivoomGatewayResponse<String> {
-        let DivoomGatewaySelectCustomPageRequest { id } = request.0;
+        let id = request.0.id.value();
         return invoke_pixoo_api_no_response!(self, select_custom_page, id);
     }
 
@@ -209,7 +219,7 @@ impl ApiHandler {
         &self,
         request: Json<DivoomGatewaySetDeviceBrightnessRequest>,
     ) -> DivoomGatewayResponse<String> {
-        let DivoomGatewaySetDeviceBrightnessRequest { brightness } = request.0;
+        let brightness = request.0.brightness.value();
         return invoke_pixoo_api_no_response!(self, set_device_brightness, brightness);
     }
 
@@ -350,6 +360,7 @@ impl ApiHandler {
         request: Json<DivoomGatewaySetDeviceWhiteBalanceRequest>,
     ) -> DivoomGatewayResponse<String> {
         let DivoomGatewaySetDeviceWhiteBalanceRequest { r, g, b } = request.0;
+        let (r, g, b) = (r.value(), g.value(), b.value());
         return invoke_pixoo_api_no_response!(self, set_device_white_balance, r, g, b);
     }
 
@@ -360,6 +371,7 @@ impl ApiHandler {
     ) -> DivoomGatewayResponse<String> {
         let parsed_action = parse_gateway_api_arg!(request, action, DivoomToolCountdownAction);
         let DivoomGatewaySetCountdownToolRequest { minute, second, .. } = request.0;
+        let (minute, second) = (minute.value(), second.value());
         return invoke_pixoo_api_no_response!(
             self,
             set_countdown_tool,
@@ -436,25 +448,33 @@ impl ApiHandler {
             request.canvas_size,
             Duration::from_millis(request.speed_in_ms),
         ) {
-            Err(e) => {
-                return DivoomGatewayResponse::BadRequest(Json(
-                    DivoomGatewayResponsePayload::error(format!("{:?}", e)),
-                ))
-            }
-            Ok(v) => v,
-        };
-
-        let gif = match DivoomAnimationResourceLoader::from_gif(Cursor::new(gif_data)) {
-            Err(e) => {
-                return DivoomGatewayResponse::BadRequest(Json(
-                    DivoomGatewayResponsePayload::error(format!("Invalid uploaded file: {:?}", e)),
-                ))
-            }
+            Err(e) => return e.into(),
             Ok(v) => v,
         };
 
-        let animation = animation_builder
-            .draw_frames_fit(
+        let animation_builder = if request.keep_timing {
+            let gif = match DivoomAnimationResourceLoader::from_gif_sequence_buf(&gif_data) {
+                Err(e) => return e.into(),
+                Ok(v) => v,
+            };
+
+            animation_builder
+                .with_max_frame_count(DIVOOM_IMAGE_ANIMATION_MAX_FRAME_COUNT)
+                .draw_frame_sequence_fit(
+                    &gif,
+                    0,
+                    DivoomDrawFitMode::Center,
+                    0.0,
+                    1.0,
+                    BlendMode::default(),
+                )
+        } else {
+            let gif = match DivoomAnimationResourceLoader::from_gif_buf(&gif_data) {
+                Err(e) => return e.into(),
+                Ok(v) => v,
+            };
+
+            animation_builder.draw_frames_fit(
                 &gif,
                 0,
                 DivoomDrawFitMode::Center,
@@ -462,9 +482,11 @@ impl ApiHandler {
                 1.0,
                 BlendMode::default(),
             )
-            .build();
+        };
 
-        let pixoo = PixooClient::new(&self.device_address).unwrap();
+        let animation = animation_builder.build();
+
+        let pixoo = self.new_pixoo_client();
         match pixoo.send_image_animation(animation).await {
             Err(e) => e.into(),
             Ok(_) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok())),
@@ -480,25 +502,53 @@ impl ApiHandler {
         &self,
         request: Json<DivoomGatewayRenderTemplateAsAnimationRequest>,
     ) -> DivoomGatewayResponse<String> {
-        let animation = match self
-            .animation_template_manager
-            .render_template(&request.name, &request.parameters, &request.per_frame_parameters)
-        {
-            Err(e) => {
-                return DivoomGatewayResponse::BadRequest(Json(
-                    DivoomGatewayResponsePayload::error(format!("{:?}", e)),
-                ))
-            }
+        let animation = match self.animation_template_manager.render_template(
+            &request.name,
+            &request.parameters,
+            &request.per_frame_parameters,
+        ) {
+            Err(e) => return e.into(),
             Ok(v) => v,
         };
 
-        let pixoo = PixooClient::new(&self.device_address).unwrap();
+        let pixoo = self.new_pixoo_client();
         match pixoo.send_image_animation(animation).await {
             Err(e) => e.into(),
             Ok(_) => DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok())),
         }
     }
 
+    #[oai(
+        path = "/animation/templates",
+        method = "get",
+        tag = "ApiTags::Animation"
+    )]
+    async fn get_animation_templates(
+        &self,
+    ) -> DivoomGatewayResponse<Vec<DivoomGatewayAnimationTemplateResponse>> {
+        let templates = self
+            .animation_template_manager
+            .templates()
+            .into_iter()
+            .map(|x| x.into())
+            .collect();
+        DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(templates)))
+    }
+
+    #[oai(
+        path = "/animation/template-cache-stats",
+        method = "get",
+        tag = "ApiTags::Animation"
+    )]
+    async fn get_animation_template_cache_stats(
+        &self,
+    ) -> DivoomGatewayResponse<DivoomGatewayGetAnimationTemplateCacheStatsResponse> {
+        let stats = self.animation_template_manager.cache_stats();
+        DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(
+            stats.into(),
+        )))
+    }
+
     #[oai(
         path = "/animation/next-id",
         method = "get",
@@ -557,6 +607,11 @@ impl ApiHandler {
             active_time_in_cycle,
             off_time_in_cycle,
         } = request.0;
+        let (play_total_time, active_time_in_cycle, off_time_in_cycle) = (
+            play_total_time.value(),
+            active_time_in_cycle.value(),
+            off_time_in_cycle.value(),
+        );
         return invoke_pixoo_api_no_response!(
             self,
             play_buzzer,
@@ -578,4 +633,50 @@ impl ApiHandler {
         let DivoomGatewayExecuteCommandsFromUrlRequest { url } = request.0;
         return invoke_pixoo_api_no_response!(self, execute_commands_from_url, url);
     }
+
+    #[oai(path = "/batch/run-script", method = "post", tag = "ApiTags::Batch")]
+    async fn run_script(
+        &self,
+        request: Json<DivoomGatewayRunScriptRequest>,
+    ) -> DivoomGatewayResponse<DivoomGatewayRunScriptResponse> {
+        let DivoomGatewayRunScriptRequest {
+            script,
+            variables,
+            dry_run,
+            single_command_fallback,
+        } = request.0;
+
+        // Scripts from requests are parsed without files, so includes are not supported here.
+        let script = match DivoomDslScript::parse(&script) {
+            Err(e) => return e.into(),
+            Ok(v) => v,
+        };
+
+        let pixoo = self.new_pixoo_client();
+        let mut dsl_runner = DivoomDslRunner::new(&pixoo, self.animation_template_manager.clone());
+        if dry_run {
+            dsl_runner = dsl_runner.with_dry_run();
+        }
+        if single_command_fallback {
+            dsl_runner = dsl_runner.with_single_command_fallback();
+        }
+
+        if let Err(e) = dsl_runner
+            .run_script_with_variables(&script, variables)
+            .await
+        {
+            return e.into();
+        }
+
+        DivoomGatewayResponse::Ok(Json(DivoomGatewayResponsePayload::ok_with_data(
+            DivoomGatewayRunScriptResponse {
+                dry_run_payloads: dsl_runner.dry_run_payloads().to_vec(),
+                read_results: dsl_runner
+                    .read_results()
+                    .iter()
+                    .map(|x| x.clone().into())
+                    .collect(),
+            },
+        )))
+    }
 }
@@ -1,6 +1,9 @@
 use super::api_handler::*;
-use divoom::DivoomAnimationTemplateManager;
-use poem::{listener::TcpListener, Route, handler};
+use super::api_spec::*;
+use divoom::{DivoomAnimationTemplateManager, DivoomDeviceModel};
+use poem::endpoint::make_sync;
+use poem::web::Html;
+use poem::{handler, listener::TcpListener, Response, Route};
 use poem_openapi::OpenApiService;
 use std::sync::Arc;
 
@@ -8,6 +11,7 @@ pub struct ApiServer {
     server_address: String,
     server_port: u16,
     device_address: String,
+    device_model: DivoomDeviceModel,
     animation_template_manager: Arc<DivoomAnimationTemplateManager>,
 }
 
@@ -16,20 +20,60 @@ impl ApiServer {
         server_address: String,
         server_port: u16,
         device_address: String,
+        device_model: DivoomDeviceModel,
         animation_template_manager: Arc<DivoomAnimationTemplateManager>,
     ) -> ApiServer {
         ApiServer {
             server_address,
             server_port,
             device_address,
+            device_model,
             animation_template_manager,
         }
     }
 
     pub async fn start(&self) -> std::io::Result<()> {
-        let api_service = OpenApiService::new(
+        let api_service = self.build_api_service();
+
+        // The request schema of each animation template is only known at runtime, so we patch it into the generated spec,
+        // and serve both spec and swagger UI with the patched one.
+        let generated_spec = api_service.spec();
+        let spec = self.spec();
+        let ui_html = api_service
+            .swagger_ui_html()
+            .replacen(&generated_spec, &spec, 1);
+
+        let app = Route::new()
+            .nest("/api", api_service)
+            .at(
+                "/openapi.json",
+                make_sync(move |_| {
+                    Response::builder()
+                        .content_type("application/json")
+                        .body(spec.clone())
+                }),
+            )
+            .at("/", make_sync(move |_| Html(ui_html.clone())))
+            .at("/probe", poem::get(probe));
+
+        let server_endpoint = format!("{}:{}", self.server_address, self.server_port);
+        let server_listener = TcpListener::bind(server_endpoint);
+        poem::Server::new(server_listener).run(app).await
+    }
+
+    /// OpenAPI spec of the gateway, with the parameters of all loaded animation templates.
+    pub fn spec(&self) -> String {
+        patch_api_spec_with_templates(
+            &self.build_api_service().spec(),
+            &self.animation_template_manager.templates(),
+        )
+    }
+
+    fn build_api_service(&self) -> OpenApiService<ApiHandler, ()> {
+        OpenApiService::new(
             ApiHandler::new(
                 self.device_address.clone(),
+                self.device_model,
                 self.animation_template_manager.clone(),
             ),
             "Divoom Gateway",
@@ -38,19 +82,7 @@ impl ApiServer {
         .server(format!(
             "http://{}:{}/api",
             self.server_address, self.server_port
-        ));
-
-        let ui = api_service.swagger_ui();
-        let spec = api_service.spec_endpoint();
-        let app = Route::new()
-            .nest("/api", api_service)
-            .nest("/openapi.json", spec)
-            .nest("/", ui)
-            .at("/probe", poem::get(probe));
-
-        let server_endpoint = format!("{}:{}", self.server_address, self.server_port);
-        let server_listener = TcpListener::bind(server_endpoint);
-        poem::Server::new(server_listener).run(app).await
+        ))
     }
 }
 
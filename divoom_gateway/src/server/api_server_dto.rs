@@ -1,10 +1,12 @@
 use divoom::*;
 use poem::Error;
 use poem_openapi::payload::Json;
+use poem_openapi::registry::{MetaSchema, MetaSchemaRef};
 use poem_openapi::types::multipart::Upload;
-use poem_openapi::types::{ParseFromJSON, ToJSON};
-use poem_openapi::{ApiResponse, Multipart, Object};
+use poem_openapi::types::{ParseError, ParseFromJSON, ParseResult, ToJSON, Type};
+use poem_openapi::{ApiResponse, Enum, Multipart, Object};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::str::FromStr;
 
@@ -13,15 +15,114 @@ pub struct DivoomGatewayResponsePayload<T: ParseFromJSON + ToJSON + Send + Sync>
     error: String,
     server_status_code: i32,
     server_error_code: i32,
+    error_details: Option<DivoomGatewayErrorDetails>,
     data: Option<T>,
 }
 
+/// Machine-readable details of the error, which stay stable across versions.
+#[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
+pub struct DivoomGatewayErrorDetails {
+    /// Type of the error, e.g. "parameter-error", "server-error" or "invalid-request".
+    pub error_type: String,
+
+    pub category: DivoomGatewayErrorCategory,
+
+    pub message: String,
+
+    /// Index of the failed command in the batch, starting from 0, when a command in a batch failed.
+    pub failed_command_index: Option<u32>,
+
+    /// API name of the failed command, when a command in a batch failed, e.g. "set_device_brightness".
+    pub failed_command: Option<String>,
+
+    /// Line of the failed operation in the script, starting from 1, when a DSL operation failed.
+    pub failed_operation_line: Option<u32>,
+
+    /// Name of the failed DSL operation, e.g. "system set-brightness".
+    pub failed_operation: Option<String>,
+
+    /// Name of the error code returned from the device or service, if the code is known, e.g. "too-many-requests".
+    pub server_error_name: Option<String>,
+
+    /// Meaning of the error code returned from the device or service, if the code is known.
+    pub server_error_description: Option<String>,
+}
+
+/// Category of the error, which tells the caller what can be done about the error.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "kebab-case")]
+#[oai(rename_all = "kebab-case")]
+pub enum DivoomGatewayErrorCategory {
+    /// The error might go away by retrying, e.g. the device is offline or the request timed out.
+    Transient,
+
+    /// The device is handling too many requests. Retrying later with lower rate should work.
+    DeviceBusy,
+
+    /// The request is invalid, e.g. parameters out of range or failing to load the resources.
+    InvalidInput,
+
+    /// The command is not supported by the device model or the firmware.
+    Unsupported,
+
+    Unknown,
+}
+
+impl From<DivoomErrorCategory> for DivoomGatewayErrorCategory {
+    fn from(v: DivoomErrorCategory) -> Self {
+        match v {
+            DivoomErrorCategory::Transient => DivoomGatewayErrorCategory::Transient,
+            DivoomErrorCategory::DeviceBusy => DivoomGatewayErrorCategory::DeviceBusy,
+            DivoomErrorCategory::InvalidInput => DivoomGatewayErrorCategory::InvalidInput,
+            DivoomErrorCategory::Unsupported => DivoomGatewayErrorCategory::Unsupported,
+            DivoomErrorCategory::Unknown => DivoomGatewayErrorCategory::Unknown,
+        }
+    }
+}
+
+impl DivoomGatewayErrorDetails {
+    /// Details of the errors found by the gateway itself, e.g. the request body cannot be parsed.
+    pub fn invalid_request(message: String) -> Self {
+        Self {
+            error_type: "invalid-request".to_string(),
+            category: DivoomGatewayErrorCategory::InvalidInput,
+            message,
+            failed_command_index: None,
+            failed_command: None,
+            failed_operation_line: None,
+            failed_operation: None,
+            server_error_name: None,
+            server_error_description: None,
+        }
+    }
+}
+
+impl From<&DivoomAPIError> for DivoomGatewayErrorDetails {
+    fn from(err: &DivoomAPIError) -> Self {
+        let failed_command = err.failed_command();
+        let failed_operation = err.failed_operation();
+        let server_error_code_info = err.server_error_info().and_then(|x| x.error_code_info());
+        Self {
+            error_type: err.error_type().to_string(),
+            category: err.category().into(),
+            message: err.to_string(),
+            failed_command_index: failed_command.map(|x| x.0 as u32),
+            failed_command: failed_command.map(|x| x.1.to_string()),
+            failed_operation_line: failed_operation.map(|x| x.0 as u32),
+            failed_operation: failed_operation.map(|x| x.1.to_string()),
+            server_error_name: server_error_code_info.map(|x| x.name.to_string()),
+            server_error_description: server_error_code_info.map(|x| x.description.to_string()),
+        }
+    }
+}
+
 impl<T: ParseFromJSON + ToJSON + Send + Sync> DivoomGatewayResponsePayload<T> {
     pub fn ok() -> Self {
         Self {
             error: "OK".to_string(),
             server_status_code: 200,
             server_error_code: 0,
+            error_details: None,
             data: None,
         }
     }
@@ -31,12 +132,14 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> DivoomGatewayResponsePayload<T> {
             error: "OK".to_string(),
             server_status_code: 200,
             server_error_code: 0,
+            error_details: None,
             data: Some(data),
         }
     }
 
     pub fn error(message: String) -> Self {
         Self {
+            error_details: Some(DivoomGatewayErrorDetails::invalid_request(message.clone())),
             error: message,
             server_status_code: 0,
             server_error_code: 0,
@@ -44,11 +147,13 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> DivoomGatewayResponsePayload<T> {
         }
     }
 
-    pub fn server_error(status_code: i32, server_error: i32, message: String) -> Self {
+    pub fn api_error(err: &DivoomAPIError) -> Self {
+        let server_error_info = err.server_error_info();
         Self {
-            error: message,
-            server_status_code: status_code,
-            server_error_code: server_error,
+            error: err.to_string(),
+            server_status_code: server_error_info.map_or(0, |x| x.http_status_code as i32),
+            server_error_code: server_error_info.map_or(0, |x| x.error_code),
+            error_details: Some(err.into()),
             data: None,
         }
     }
@@ -81,33 +186,20 @@ pub fn gateway_bad_request_handler<T: ParseFromJSON + ToJSON + Send + Sync>(
 
 impl<T: ParseFromJSON + ToJSON + Send + Sync> From<DivoomAPIError> for DivoomGatewayResponse<T> {
     fn from(err: DivoomAPIError) -> Self {
-        match err {
-            DivoomAPIError::ParameterError(e) => DivoomGatewayResponse::BadRequest(Json(
-                DivoomGatewayResponsePayload::error(format!("Invalid parameter: {}", e)),
-            )),
-            DivoomAPIError::ResourceLoadError { source: e } => DivoomGatewayResponse::BadRequest(
-                Json(DivoomGatewayResponsePayload::error(e.to_string())),
-            ),
-            DivoomAPIError::ResourceDecodeError(e) => {
-                DivoomGatewayResponse::BadRequest(Json(DivoomGatewayResponsePayload::error(e)))
-            }
-            DivoomAPIError::RequestError { source: e } => {
-                DivoomGatewayResponse::ServiceUnavailable(Json(
-                    DivoomGatewayResponsePayload::error(e.to_string()),
-                ))
-            }
-            DivoomAPIError::ResponseDeserializationError { source: e } => {
-                DivoomGatewayResponse::InternalServerError(Json(
-                    DivoomGatewayResponsePayload::error(e.to_string()),
-                ))
-            }
-            DivoomAPIError::ServerError(e) => {
-                DivoomGatewayResponse::BadRequest(Json(DivoomGatewayResponsePayload::server_error(
-                    e.http_status_code as i32,
-                    e.error_code,
-                    e.error_message,
-                )))
+        let payload = Json(DivoomGatewayResponsePayload::api_error(&err));
+
+        // The root error never carries the command or operation context, so only the inner errors are matched here.
+        match err.root_error() {
+            DivoomAPIError::ParameterError(_)
+            | DivoomAPIError::UnsupportedCommandError(_)
+            | DivoomAPIError::CommandParseError { .. }
+            | DivoomAPIError::ResourceLoadError { .. }
+            | DivoomAPIError::ResourceDecodeError(_)
+            | DivoomAPIError::ServerError(_) => DivoomGatewayResponse::BadRequest(payload),
+            DivoomAPIError::RequestError { .. } => {
+                DivoomGatewayResponse::ServiceUnavailable(payload)
             }
+            _ => DivoomGatewayResponse::InternalServerError(payload),
         }
     }
 }
@@ -185,14 +277,74 @@ pub struct DivoomGatewaySelectVisualizerRequest {
     pub id: i32,
 }
 
+/// Integer parameter in the requests, whose range comes from the parameter types in the divoom crate, so the schema and
+/// the validation always match the client.
+#[derive(Debug, PartialOrd, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DivoomGatewayParameter<T>(pub T);
+
+impl<T: DivoomRangedParameter> DivoomGatewayParameter<T> {
+    pub fn value(&self) -> i32 {
+        self.0.value()
+    }
+}
+
+impl<T: DivoomRangedParameter + Send + Sync> Type for DivoomGatewayParameter<T> {
+    const IS_REQUIRED: bool = true;
+
+    type RawValueType = Self;
+
+    type RawElementValueType = Self;
+
+    fn name() -> Cow<'static, str> {
+        "integer(int32)".into()
+    }
+
+    fn schema_ref() -> MetaSchemaRef {
+        let mut schema = MetaSchema::new_with_format("integer", "int32");
+        schema.minimum = Some(T::MIN as f64);
+        if T::MAX != i32::MAX {
+            schema.maximum = Some(T::MAX as f64);
+        }
+        MetaSchemaRef::Inline(Box::new(schema))
+    }
+
+    fn as_raw_value(&self) -> Option<&Self::RawValueType> {
+        Some(self)
+    }
+
+    fn raw_element_iter<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = &'a Self::RawElementValueType> + 'a> {
+        Box::new(self.as_raw_value().into_iter())
+    }
+}
+
+impl<T: DivoomRangedParameter + Send + Sync> ParseFromJSON for DivoomGatewayParameter<T> {
+    fn parse_from_json(value: Option<serde_json::Value>) -> ParseResult<Self> {
+        let value = i32::parse_from_json(value).map_err(ParseError::propagate)?;
+        match T::new(value) {
+            Ok(v) => Ok(DivoomGatewayParameter(v)),
+            Err(DivoomAPIError::ParameterError(message)) => Err(ParseError::custom(message)),
+            Err(e) => Err(ParseError::custom(e)),
+        }
+    }
+}
+
+impl<T: DivoomRangedParameter + Send + Sync> ToJSON for DivoomGatewayParameter<T> {
+    fn to_json(&self) -> Option<serde_json::Value> {
+        Some(self.value().into())
+    }
+}
+
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewaySelectCustomPageRequest {
-    pub id: i32,
+    pub id: DivoomGatewayParameter<DivoomCustomPageIndex>,
 }
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewaySetDeviceBrightnessRequest {
-    pub brightness: i32,
+    pub brightness: DivoomGatewayParameter<DivoomBrightness>,
 }
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
@@ -243,15 +395,15 @@ pub struct DivoomGatewaySetDeviceWeatherAreaRequest {
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewaySetDeviceWhiteBalanceRequest {
-    pub r: i32,
-    pub g: i32,
-    pub b: i32,
+    pub r: DivoomGatewayParameter<DivoomWhiteBalanceLevel>,
+    pub g: DivoomGatewayParameter<DivoomWhiteBalanceLevel>,
+    pub b: DivoomGatewayParameter<DivoomWhiteBalanceLevel>,
 }
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewaySetCountdownToolRequest {
-    pub minute: i32,
-    pub second: i32,
+    pub minute: DivoomGatewayParameter<DivoomCountdownMinute>,
+    pub second: DivoomGatewayParameter<DivoomCountdownSecond>,
     pub action: String,
 }
 
@@ -276,6 +428,20 @@ pub struct DivoomGatewayExecuteCommandsFromUrlRequest {
     pub url: String,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize, Object)]
+pub struct DivoomGatewayRunScriptRequest {
+    pub script: String,
+    #[oai(default)]
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    #[oai(default)]
+    #[serde(default)]
+    pub dry_run: bool,
+    #[oai(default)]
+    #[serde(default)]
+    pub single_command_fallback: bool,
+}
+
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewayPlayGifRequest {
     pub file_type: String,
@@ -284,19 +450,19 @@ pub struct DivoomGatewayPlayGifRequest {
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewayPlayBuzzerRequest {
-    pub play_total_time: i32,
-    pub active_time_in_cycle: i32,
-    pub off_time_in_cycle: i32,
+    pub play_total_time: DivoomGatewayParameter<DivoomBuzzerTime>,
+    pub active_time_in_cycle: DivoomGatewayParameter<DivoomBuzzerTime>,
+    pub off_time_in_cycle: DivoomGatewayParameter<DivoomBuzzerTime>,
 }
 
 #[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
 pub struct DivoomGatewaySendTextAnimationRequest {
-    pub text_id: i32,
+    pub text_id: DivoomGatewayParameter<DivoomTextId>,
     pub x: i32,
     pub y: i32,
     pub scroll_direction: String,
-    pub font_index: i32,
-    pub text_width: i32,
+    pub font_index: DivoomGatewayParameter<DivoomTextFontIndex>,
+    pub text_width: DivoomGatewayParameter<DivoomTextWidth>,
     pub speed_in_ms: i32,
     pub text_string: String,
     pub color: String,
@@ -306,12 +472,12 @@ pub struct DivoomGatewaySendTextAnimationRequest {
 impl DivoomGatewaySendTextAnimationRequest {
     pub fn into(self) -> Result<DivoomTextAnimation, String> {
         Ok(DivoomTextAnimation {
-            text_id: self.text_id,
+            text_id: self.text_id.value(),
             x: self.x,
             y: self.y,
             scroll_direction: DivoomTextAnimationScrollDirection::from_str(&self.scroll_direction)?,
-            font_index: self.font_index,
-            text_width: self.text_width,
+            font_index: self.font_index.value(),
+            text_width: self.text_width.value(),
             speed_in_ms: self.speed_in_ms,
             text_string: self.text_string,
             color: Default::default(),
@@ -325,6 +491,10 @@ pub struct DivoomGatewayRenderGifAsAnimationRequest {
     pub canvas_size: u32,
     pub speed_in_ms: u64,
     pub file: Upload,
+
+    /// Resample the GIF frames onto the speed to keep the original timing of GIFs with variable delays.
+    #[oai(default)]
+    pub keep_timing: bool,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Object)]
@@ -333,3 +503,185 @@ pub struct DivoomGatewayRenderTemplateAsAnimationRequest {
     pub parameters: HashMap<String, String>,
     pub per_frame_parameters: HashMap<usize, HashMap<String, String>>,
 }
+
+#[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
+#[serde(rename_all = "camelCase")]
+pub struct DivoomGatewayGetAnimationTemplateCacheStatsResponse {
+    pub enabled: bool,
+    pub memory_hit_count: u64,
+    pub disk_hit_count: u64,
+    pub miss_count: u64,
+    pub hit_rate: f64,
+    pub entry_count: u64,
+    pub capacity: u64,
+}
+
+impl From<Option<DivoomAnimationTemplateCacheStats>>
+    for DivoomGatewayGetAnimationTemplateCacheStatsResponse
+{
+    fn from(v: Option<DivoomAnimationTemplateCacheStats>) -> Self {
+        let stats = v.unwrap_or_default();
+        DivoomGatewayGetAnimationTemplateCacheStatsResponse {
+            enabled: v.is_some(),
+            memory_hit_count: stats.memory_hit_count,
+            disk_hit_count: stats.disk_hit_count,
+            miss_count: stats.miss_count,
+            hit_rate: stats.hit_rate(),
+            entry_count: stats.entry_count as u64,
+            capacity: stats.capacity as u64,
+        }
+    }
+}
+
+#[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
+#[serde(rename_all = "camelCase")]
+pub struct DivoomGatewayAnimationTemplateParameterResponse {
+    pub name: String,
+    #[oai(rename = "type")]
+    #[serde(rename = "type")]
+    pub parameter_type: String,
+    pub default: Option<String>,
+    pub allowed_values: Vec<String>,
+    pub description: String,
+}
+
+impl From<DivoomAnimationTemplateParameterConfig>
+    for DivoomGatewayAnimationTemplateParameterResponse
+{
+    fn from(v: DivoomAnimationTemplateParameterConfig) -> Self {
+        DivoomGatewayAnimationTemplateParameterResponse {
+            name: v.name,
+            parameter_type: serde_json::to_value(v.parameter_type)
+                .ok()
+                .and_then(|x| x.as_str().map(|x| x.to_string()))
+                .unwrap_or_default(),
+            default: v.default,
+            allowed_values: v.allowed_values,
+            description: v.description,
+        }
+    }
+}
+
+#[derive(Debug, PartialOrd, PartialEq, Serialize, Deserialize, Object)]
+#[serde(rename_all = "camelCase")]
+pub struct DivoomGatewayAnimationTemplateResponse {
+    pub name: String,
+    pub canvas_size: u32,
+    pub speed_in_ms: u64,
+    pub frame_count: u64,
+    pub frame_count_expression: Option<String>,
+    pub parameters: Vec<DivoomGatewayAnimationTemplateParameterResponse>,
+}
+
+impl From<DivoomAnimationTemplateInfo> for DivoomGatewayAnimationTemplateResponse {
+    fn from(v: DivoomAnimationTemplateInfo) -> Self {
+        DivoomGatewayAnimationTemplateResponse {
+            name: v.name,
+            canvas_size: v.canvas_size,
+            speed_in_ms: v.speed_in_ms,
+            frame_count: v.frame_count as u64,
+            frame_count_expression: v.frame_count_expression,
+            parameters: v.parameters.into_iter().map(|x| x.into()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Object)]
+#[serde(rename_all = "camelCase")]
+pub struct DivoomGatewayDslReadResultResponse {
+    pub command: String,
+    pub variable: Option<String>,
+    pub value: serde_json::Value,
+}
+
+impl From<DivoomDslReadResult> for DivoomGatewayDslReadResultResponse {
+    fn from(v: DivoomDslReadResult) -> Self {
+        DivoomGatewayDslReadResultResponse {
+            command: v.command,
+            variable: v.variable,
+            value: v.value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Object)]
+#[serde(rename_all = "camelCase")]
+pub struct DivoomGatewayRunScriptResponse {
+    pub dry_run_payloads: Vec<String>,
+    pub read_results: Vec<DivoomGatewayDslReadResultResponse>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn api_error_should_be_converted_to_structured_error_payload() {
+        let error = DivoomAPIError::OperationError {
+            line_number: 3,
+            operation: "system set-brightness".to_string(),
+            source: Box::new(DivoomAPIError::CommandError {
+                command_index: 1,
+                command: "set_device_brightness".to_string(),
+                source: Box::new(DivoomAPIError::ServerError(
+                    DivoomServerErrorInfo::server_error(1),
+                )),
+            }),
+        };
+
+        let response: DivoomGatewayResponse<String> = error.into();
+        let payload = match response {
+            DivoomGatewayResponse::BadRequest(Json(payload)) => payload,
+            _ => panic!("Server error should be a bad request."),
+        };
+
+        let payload = serde_json::to_value(&payload).unwrap();
+        assert_eq!(payload["server_error_code"], json!(1));
+        assert_eq!(
+            payload["error_details"],
+            json!({
+                "error_type": "server-error",
+                "category": "unknown",
+                "message": payload["error"],
+                "failed_command_index": 1,
+                "failed_command": "set_device_brightness",
+                "failed_operation_line": 3,
+                "failed_operation": "system set-brightness",
+                "server_error_name": "general-failure",
+                "server_error_description": "The device rejected the request without telling the reason.",
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(DivoomGatewayErrorCategory::from(
+                DivoomErrorCategory::DeviceBusy
+            ))
+            .unwrap(),
+            json!("device-busy")
+        );
+    }
+
+    #[test]
+    fn gateway_parameter_should_use_range_of_divoom_parameter() {
+        match DivoomGatewayParameter::<DivoomBrightness>::schema_ref() {
+            MetaSchemaRef::Inline(schema) => {
+                assert_eq!(schema.minimum, Some(0.0));
+                assert_eq!(schema.maximum, Some(100.0));
+            }
+            _ => panic!("Parameter schema should be inline."),
+        }
+
+        let request = DivoomGatewaySetDeviceBrightnessRequest::parse_from_json(Some(
+            json!({ "brightness": 30 }),
+        ))
+        .unwrap();
+        assert_eq!(request.brightness.value(), 30);
+
+        let error = DivoomGatewaySetDeviceBrightnessRequest::parse_from_json(Some(
+            json!({ "brightness": 120 }),
+        ))
+        .unwrap_err()
+        .into_message();
+        assert!(error.contains("It must be between 0 and 100"), "{}", error);
+    }
+}
@@ -0,0 +1,169 @@
+use divoom::{DivoomAnimationTemplateInfo, DivoomAnimationTemplateParameterType};
+use serde_json::{json, Map, Value};
+
+const RENDER_TEMPLATE_REQUEST_SCHEMA_NAME: &str = "DivoomGatewayRenderTemplateAsAnimationRequest";
+const TEMPLATE_PARAMETERS_SCHEMA_NAME_PREFIX: &str = "DivoomGatewayAnimationTemplateParameters_";
+
+/// Patch the OpenAPI spec generated by poem with the parameters of all loaded animation templates.
+///
+/// The templates are only known after the gateway starts, so they cannot be described by the static DTOs. Here we
+/// generate one schema per template, and use them for the `parameters` field of the render template request, so UI can
+/// generate the input form for each template.
+pub fn patch_api_spec_with_templates(
+    spec: &str,
+    templates: &[DivoomAnimationTemplateInfo],
+) -> String {
+    let mut spec: Value = match serde_json::from_str(spec) {
+        Ok(v) => v,
+        Err(_) => return spec.to_string(),
+    };
+
+    if !templates.is_empty() {
+        patch_render_template_request_schema(&mut spec, templates);
+    }
+
+    serde_json::to_string_pretty(&spec).unwrap()
+}
+
+fn patch_render_template_request_schema(
+    spec: &mut Value,
+    templates: &[DivoomAnimationTemplateInfo],
+) {
+    let schemas = match spec
+        .pointer_mut("/components/schemas")
+        .and_then(|x| x.as_object_mut())
+    {
+        Some(v) => v,
+        None => return,
+    };
+
+    let mut template_parameter_schema_refs = vec![];
+    for template in templates {
+        let schema_name = format!(
+            "{}{}",
+            TEMPLATE_PARAMETERS_SCHEMA_NAME_PREFIX, template.name
+        );
+        template_parameter_schema_refs
+            .push(json!({ "$ref": format!("#/components/schemas/{}", schema_name) }));
+        schemas.insert(schema_name, new_template_parameters_schema(template));
+    }
+
+    let request_properties = match schemas
+        .get_mut(RENDER_TEMPLATE_REQUEST_SCHEMA_NAME)
+        .and_then(|x| x.get_mut("properties"))
+        .and_then(|x| x.as_object_mut())
+    {
+        Some(v) => v,
+        None => return,
+    };
+
+    request_properties.insert(
+        "name".into(),
+        json!({
+            "type": "string",
+            "enum": templates.iter().map(|x| x.name.as_str()).collect::<Vec<&str>>(),
+        }),
+    );
+    request_properties.insert(
+        "parameters".into(),
+        json!({ "anyOf": template_parameter_schema_refs }),
+    );
+}
+
+fn new_template_parameters_schema(template: &DivoomAnimationTemplateInfo) -> Value {
+    let mut properties = Map::new();
+    let mut required = vec![];
+    for parameter in &template.parameters {
+        // Parameters are always passed as strings, so the type is exposed as format, which UI can use as input hint.
+        let mut property = json!({ "type": "string" });
+        if parameter.parameter_type != DivoomAnimationTemplateParameterType::String {
+            property["format"] = serde_json::to_value(parameter.parameter_type).unwrap();
+        }
+        if !parameter.description.is_empty() {
+            property["description"] = json!(parameter.description);
+        }
+        match &parameter.default {
+            Some(default) => property["default"] = json!(default),
+            None => required.push(parameter.name.clone()),
+        }
+        if !parameter.allowed_values.is_empty() {
+            property["enum"] = json!(parameter.allowed_values);
+        }
+
+        properties.insert(parameter.name.clone(), property);
+    }
+
+    // Templates can still take parameters that are not declared, e.g. the ones used by per frame parameters.
+    let mut schema = json!({
+        "type": "object",
+        "title": template.name,
+        "properties": properties,
+        "additionalProperties": { "type": "string" },
+    });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use divoom::DivoomAnimationTemplateManager;
+
+    #[test]
+    fn api_spec_should_be_patched_with_template_parameters() {
+        let spec = json!({
+            "components": {
+                "schemas": {
+                    RENDER_TEMPLATE_REQUEST_SCHEMA_NAME: {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "parameters": { "type": "object", "additionalProperties": { "type": "string" } },
+                        },
+                    },
+                },
+            },
+        });
+
+        let test_data_dir = "../divoom/test_data/animation_template_tests/input";
+        let mut manager = DivoomAnimationTemplateManager::new(test_data_dir).unwrap();
+        manager
+            .add_template_file(&format!("{}/template_bar_chart.yaml", test_data_dir).into())
+            .unwrap();
+        let templates = manager.templates();
+        let patched: Value = serde_json::from_str(&patch_api_spec_with_templates(
+            &spec.to_string(),
+            &templates,
+        ))
+        .unwrap();
+
+        let request_schema = &patched["components"]["schemas"][RENDER_TEMPLATE_REQUEST_SCHEMA_NAME];
+        assert_eq!(
+            request_schema["properties"]["name"]["enum"],
+            json!(templates
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect::<Vec<&str>>())
+        );
+        assert_eq!(
+            request_schema["properties"]["parameters"]["anyOf"]
+                .as_array()
+                .unwrap()
+                .len(),
+            templates.len()
+        );
+
+        let bar_chart_schema = &patched["components"]["schemas"][format!(
+            "{}template_bar_chart",
+            TEMPLATE_PARAMETERS_SCHEMA_NAME_PREFIX
+        )];
+        assert_eq!(bar_chart_schema["required"], json!(["values"]));
+        assert_eq!(
+            bar_chart_schema["properties"]["color"],
+            json!({ "type": "string", "format": "color", "default": "#008000", "description": "Color of the bars under threshold." })
+        );
+    }
+}
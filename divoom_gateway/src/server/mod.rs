@@ -1,5 +1,6 @@
 mod api_handler;
 mod api_server;
 mod api_server_dto;
+mod api_spec;
 
 pub use api_server::*;